@@ -1,5 +1,6 @@
 use std::boxed::Box;
-use trouble_host::prelude::{Packet, PacketPool};
+
+use trouble_host::prelude::{packet_freed, Packet, PacketPool};
 
 const MTU: usize = 2510;
 
@@ -36,3 +37,10 @@ impl AsMut<[u8]> for BigBuf {
 }
 
 impl Packet for BigBuf {}
+
+impl Drop for BigBuf {
+    fn drop(&mut self) {
+        // Wake tasks waiting in the default `PacketPool::poll_allocate`
+        packet_freed();
+    }
+}
@@ -72,6 +72,12 @@ pub struct CharacteristicArgs {
     pub uuid: TokenStream,
     /// Starting value for this characteristic.
     pub default_value: Option<syn::Expr>,
+    /// Application provided value storage (`&'static impl AttributeProvider`).
+    /// Mutually exclusive with the default value, as the provider owns the value.
+    pub provider: Option<syn::Expr>,
+    /// If true, each connection reads and writes its own copy of the value.
+    /// The number of connections is the `connections_max` of the server.
+    pub per_connection: bool,
     /// Descriptors for the characteristic.
     /// Descriptors are optional and can be used to add additional metadata to the characteristic.
     /// Parsed in super::check_for_characteristic.
@@ -79,6 +85,21 @@ pub struct CharacteristicArgs {
     /// Any '///' comments on each field, parsed in super::check_for_characteristic.
     pub doc_string: String,
     pub access: AccessArgs,
+    /// If true, the reliable write extended property is set.
+    pub reliable_write: bool,
+    /// If true, the writable auxiliaries extended property is set and the user description can be written.
+    pub writable_auxiliaries: bool,
+    /// Initial value of the Characteristic User Description descriptor.
+    pub user_description: Option<syn::Expr>,
+    /// Characteristic Presentation Format descriptors.
+    /// More than one format also adds a Characteristic Aggregate Format descriptor.
+    pub presentation_formats: Vec<syn::Expr>,
+    /// Valid range of the characteristic value, as a `(min, max)` tuple.
+    pub valid_range: Option<syn::Expr>,
+    /// Function called when a client writes the value, which can reject the write.
+    /// It is called as `f(&ConnectionContext, offset, &[u8])` and returns `Result<(), E>`,
+    /// where `E` converts into an `AttErrorCode`.
+    pub on_write: Option<syn::Expr>,
 }
 
 /// Check if this bool type has been specified more than once.
@@ -124,7 +145,15 @@ impl CharacteristicArgs {
         let mut notify: Option<bool> = None;
         let mut indicate: Option<bool> = None;
         let mut default_value: Option<syn::Expr> = None;
+        let mut provider: Option<syn::Expr> = None;
+        let mut per_connection: Option<bool> = None;
         let mut write_without_response: Option<bool> = None;
+        let mut reliable_write: Option<bool> = None;
+        let mut writable_auxiliaries: Option<bool> = None;
+        let mut user_description: Option<syn::Expr> = None;
+        let mut presentation_format: Option<syn::Expr> = None;
+        let mut valid_range: Option<syn::Expr> = None;
+        let mut on_write: Option<syn::Expr> = None;
         attribute.parse_nested_meta(|meta| {
             match meta.path.get_ident().ok_or(meta.error("no ident"))?.to_string().as_str() {
                 "uuid" => check_multi(&mut uuid, "uuid", &meta, parse_uuid(&meta)?)?,
@@ -139,21 +168,80 @@ impl CharacteristicArgs {
                         .map_err(|_| meta.error("'value' must be followed by '= [data]'.  i.e. value = \"42\""))?;
                     check_multi(&mut default_value, "value", &meta, value.parse()?)?
                 }
+                "reliable_write" => check_multi(&mut reliable_write, "reliable_write", &meta, true)?,
+                "writable_auxiliaries" => check_multi(&mut writable_auxiliaries, "writable_auxiliaries", &meta, true)?,
+                "user_description" => {
+                    let value = meta
+                        .value()
+                        .map_err(|_| meta.error("'user_description' must be followed by '= [text]'.  i.e. user_description = \"Temperature\""))?;
+                    check_multi(&mut user_description, "user_description", &meta, value.parse()?)?
+                }
+                "presentation_format" => {
+                    let value = meta
+                        .value()
+                        .map_err(|_| meta.error("'presentation_format' must be followed by '= [format]'.  i.e. presentation_format = PresentationFormat::new(0x04, 0, 0x27ad, 0x01, 0)"))?;
+                    check_multi(&mut presentation_format, "presentation_format", &meta, value.parse()?)?
+                }
+                "valid_range" => {
+                    let value = meta
+                        .value()
+                        .map_err(|_| meta.error("'valid_range' must be followed by '= (min, max)'.  i.e. valid_range = (0, 100)"))?;
+                    check_multi(&mut valid_range, "valid_range", &meta, value.parse()?)?
+                }
+                "on_write" => {
+                    let value = meta
+                        .value()
+                        .map_err(|_| meta.error("'on_write' must be followed by '= [function]'.  i.e. on_write = check_control_point"))?;
+                    check_multi(&mut on_write, "on_write", &meta, value.parse()?)?
+                }
+                "provider" => {
+                    let value = meta
+                        .value()
+                        .map_err(|_| meta.error("'provider' must be followed by '= [provider]'.  i.e. provider = &MY_PROVIDER"))?;
+                    check_multi(&mut provider, "provider", &meta, value.parse()?)?
+                }
+                "per_connection" => {
+                    if meta.input.peek(syn::Token![=]) {
+                        return Err(meta.error("'per_connection' does not take a value, values are kept for the 'connections_max' of the server"));
+                    }
+                    check_multi(&mut per_connection, "per_connection", &meta, true)?
+                }
                 "default_value" => return Err(meta.error("Use 'value' for default value")),
                 "descriptor" => return Err(meta.error("Descriptors are added as separate tags i.e. #[descriptor(uuid = \"1234\", value = 42, read, write, notify, indicate)]")),
                 other => return Err(
                     meta.error(
                         format!(
-                            "Unsupported characteristic property: '{other}'.\nSupported properties are:\nuuid, read, write, write_without_response, notify, indicate, value\n"
+                            "Unsupported characteristic property: '{other}'.\nSupported properties are:\nuuid, read, write, write_without_response, notify, indicate, value, provider, per_connection,\nreliable_write, writable_auxiliaries, user_description, presentation_format, valid_range, on_write\n"
                         ))),
             };
             Ok(())
         })?;
+        if default_value.is_some() && provider.is_some() {
+            return Err(Error::custom("'value' cannot be used together with 'provider'").into());
+        }
+        let per_connection = per_connection.unwrap_or_default();
+        if per_connection && provider.is_some() {
+            return Err(Error::custom("'per_connection' cannot be used together with 'provider'").into());
+        }
+        if on_write.is_some() && provider.is_some() {
+            return Err(Error::custom(
+                "'on_write' cannot be used together with 'provider', the provider handles writes",
+            )
+            .into());
+        }
+        // A list of formats is described by an aggregate format
+        let presentation_formats = match presentation_format {
+            Some(syn::Expr::Array(array)) => array.elems.into_iter().collect(),
+            Some(format) => vec![format],
+            None => Vec::new(),
+        };
         Ok(Self {
             uuid: uuid.ok_or(Error::custom("Characteristic must have a UUID"))?,
             doc_string: String::new(),
             descriptors: Vec::new(),
             default_value,
+            provider,
+            per_connection,
             access: AccessArgs {
                 write_without_response: write_without_response.unwrap_or_default(),
                 indicate: indicate.unwrap_or_default(),
@@ -161,6 +249,12 @@ impl CharacteristicArgs {
                 write: write.unwrap_or_default(),
                 read: read.unwrap_or_default(),
             },
+            reliable_write: reliable_write.unwrap_or_default(),
+            writable_auxiliaries: writable_auxiliaries.unwrap_or_default(),
+            user_description,
+            presentation_formats,
+            valid_range,
+            on_write,
         })
     }
 }
@@ -170,10 +264,10 @@ impl DescriptorArgs {
         let mut uuid: Option<_> = None;
         let mut name: Option<LitStr> = None;
         let mut read: Option<bool> = None;
-        // let mut write: Option<bool> = None;
-        // let mut capacity: Option<syn::Expr> = None;
+        let mut write: Option<bool> = None;
+        let mut capacity: Option<syn::Expr> = None;
         let mut default_value: Option<syn::Expr> = None;
-        // let mut write_without_response: Option<bool> = None;
+        let mut write_without_response: Option<bool> = None;
         attribute.parse_nested_meta(|meta| {
             match meta
                 .path
@@ -190,22 +284,22 @@ impl DescriptorArgs {
                     check_multi(&mut name, "name", &meta, value.parse()?)?
                 }
                 "read" => check_multi(&mut read, "read", &meta, true)?,
-                // "write" => check_multi(&mut write, "write", &meta, true)?,
-                // "write_without_response" => check_multi(&mut write_without_response, "write_without_response", &meta, true)?,
+                "write" => check_multi(&mut write, "write", &meta, true)?,
+                "write_without_response" => check_multi(&mut write_without_response, "write_without_response", &meta, true)?,
                 "value" => {
                     let value = meta.value().map_err(|_| {
                         meta.error("'value' must be followed by '= [data]'.  i.e. value = \"Hello World\"")
                     })?;
                     check_multi(&mut default_value, "value", &meta, value.parse()?)?
                 }
-                // "capacity" => {
-                //     let value = meta.value().map_err(|_| meta.error("'capacity' must be followed by '= [data]'.  i.e. value = 100"))?;
-                //     check_multi(&mut capacity, "capacity", &meta, value.parse()?)?
-                //     }
+                "capacity" => {
+                    let value = meta.value().map_err(|_| meta.error("'capacity' must be followed by '= [data]'.  i.e. value = 100"))?;
+                    check_multi(&mut capacity, "capacity", &meta, value.parse()?)?
+                }
                 "default_value" => return Err(meta.error("use 'value' for default value")),
                 other => {
                     return Err(meta.error(format!(
-                        "Unsupported descriptor property: '{other}'.\nSupported properties are: uuid, name, read, value"
+                        "Unsupported descriptor property: '{other}'.\nSupported properties are: uuid, name, read, write, write_without_response, value, capacity"
                     )));
                 }
             };
@@ -216,13 +310,13 @@ impl DescriptorArgs {
             uuid: uuid.ok_or(Error::custom("Descriptor must have a UUID"))?,
             name,
             default_value,
-            capacity: None,
+            capacity,
             access: AccessArgs {
                 indicate: false, // not possible for descriptor
                 notify: false,   // not possible for descriptor
                 read: read.unwrap_or_default(),
-                write_without_response: false,
-                write: false,
+                write_without_response: write_without_response.unwrap_or_default(),
+                write: write.unwrap_or_default(),
             },
         })
     }
@@ -0,0 +1,158 @@
+//! Gatt Client Service Builder
+//!
+//! This module generates a client proxy from a service struct definition. The proxy discovers the service
+//! on a connected server and offers typed access to the characteristics declared in the struct.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote, quote_spanned};
+
+use crate::characteristic::Characteristic;
+
+pub(crate) struct ClientServiceBuilder {
+    properties: syn::ItemStruct,
+    uuid: TokenStream2,
+    code_fields: TokenStream2,
+    code_discover: TokenStream2,
+    code_struct_init: TokenStream2,
+    code_impl: TokenStream2,
+}
+
+impl ClientServiceBuilder {
+    pub fn new(properties: syn::ItemStruct, uuid: TokenStream2) -> Self {
+        Self {
+            properties,
+            uuid,
+            code_fields: TokenStream2::new(),
+            code_discover: TokenStream2::new(),
+            code_struct_init: TokenStream2::new(),
+            code_impl: TokenStream2::new(),
+        }
+    }
+
+    /// Generate the field, the discovery and the access methods of each characteristic.
+    pub fn process_characteristics(mut self, characteristics: Vec<Characteristic>) -> Self {
+        for characteristic in characteristics {
+            self.construct_characteristic(characteristic);
+        }
+        self
+    }
+
+    fn construct_characteristic(&mut self, characteristic: Characteristic) {
+        let char_name = format_ident!("{}", characteristic.name);
+        let ty = &characteristic.ty;
+        let vis = &characteristic.vis;
+        let uuid = &characteristic.args.uuid;
+        let access = &characteristic.args.access;
+        let docs: TokenStream2 = characteristic
+            .args
+            .doc_string
+            .lines()
+            .map(|line| quote_spanned!(characteristic.span=> #[doc = #line]))
+            .collect();
+
+        self.code_fields.extend(quote_spanned! {characteristic.span=>
+            #docs
+            #vis #char_name: trouble_host::attribute::Characteristic<#ty>,
+        });
+        self.code_discover.extend(quote_spanned! {characteristic.span=>
+            let #char_name = client.characteristic_by_uuid::<#ty>(&service, &#uuid).await?;
+        });
+        self.code_struct_init.extend(quote_spanned! {characteristic.span=>
+            #char_name,
+        });
+
+        if access.read {
+            let read = format_ident!("read_{}", characteristic.name);
+            self.code_impl.extend(quote_spanned! {characteristic.span=>
+                /// Read the value of the characteristic.
+                #vis async fn #read(&self) -> Result<#ty, trouble_host::BleHostError<C::Error>> {
+                    let mut buf = [0; <#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE];
+                    let len = self.client.read_characteristic(&self.#char_name, &mut buf).await?;
+                    <#ty as trouble_host::types::gatt_traits::FromGatt>::from_gatt(&buf[..len])
+                        .map_err(|_| trouble_host::Error::InvalidValue.into())
+                }
+            });
+        }
+        if access.write {
+            let write = format_ident!("write_{}", characteristic.name);
+            self.code_impl.extend(quote_spanned! {characteristic.span=>
+                /// Write the value of the characteristic.
+                #vis async fn #write(&self, value: &#ty) -> Result<(), trouble_host::BleHostError<C::Error>> {
+                    let value = trouble_host::types::gatt_traits::AsGatt::as_gatt(value);
+                    self.client.write_characteristic(&self.#char_name, value).await
+                }
+            });
+        }
+        if access.write_without_response {
+            let write = format_ident!("write_{}_without_response", characteristic.name);
+            self.code_impl.extend(quote_spanned! {characteristic.span=>
+                /// Write the value of the characteristic, without waiting for a response.
+                #vis async fn #write(&self, value: &#ty) -> Result<(), trouble_host::BleHostError<C::Error>> {
+                    let value = trouble_host::types::gatt_traits::AsGatt::as_gatt(value);
+                    self.client.write_characteristic_without_response(&self.#char_name, value).await
+                }
+            });
+        }
+        if access.notify || access.indicate {
+            let subscribe = format_ident!("subscribe_{}", characteristic.name);
+            let indication = !access.notify;
+            self.code_impl.extend(quote_spanned! {characteristic.span=>
+                /// Subscribe to the notifications or indications of the characteristic.
+                #vis async fn #subscribe(&self) -> Result<trouble_host::gatt::NotificationListener<'c, P>, trouble_host::BleHostError<C::Error>> {
+                    self.client.subscribe(&self.#char_name, #indication).await
+                }
+            });
+        }
+    }
+
+    /// Construct the macro blueprint for the client proxy struct.
+    pub fn build(self) -> TokenStream2 {
+        let visibility = &self.properties.vis;
+        let struct_name = format_ident!("{}Client", self.properties.ident);
+        let doc = format!(
+            "Client proxy of [`{}`], for use with a `GattClient`.",
+            self.properties.ident
+        );
+        let uuid = self.uuid;
+        let fields = self.code_fields;
+        let code_discover = self.code_discover;
+        let code_struct_init = self.code_struct_init;
+        let code_impl = self.code_impl;
+        quote! {
+            #[doc = #doc]
+            #visibility struct #struct_name<'c, 'reference, C: trouble_host::Controller, P: trouble_host::PacketPool, const MAX_SERVICES: usize> {
+                client: &'c trouble_host::gatt::GattClient<'reference, C, P, MAX_SERVICES>,
+                service: trouble_host::gatt::ServiceHandle,
+                #fields
+            }
+
+            #[allow(unused)]
+            impl<'c, 'reference, C: trouble_host::Controller, P: trouble_host::PacketPool, const MAX_SERVICES: usize> #struct_name<'c, 'reference, C, P, MAX_SERVICES> {
+                /// Discover the service and its characteristics on the server.
+                ///
+                /// Returns `Error::NotFound` if the server does not have the service.
+                #visibility async fn discover(client: &'c trouble_host::gatt::GattClient<'reference, C, P, MAX_SERVICES>) -> Result<Self, trouble_host::BleHostError<C::Error>> {
+                    let uuid: trouble_host::types::uuid::Uuid = #uuid;
+                    let service = client
+                        .services_by_uuid(&uuid)
+                        .await?
+                        .first()
+                        .cloned()
+                        .ok_or(trouble_host::Error::NotFound)?;
+                    #code_discover
+                    Ok(Self {
+                        client,
+                        service,
+                        #code_struct_init
+                    })
+                }
+
+                /// The discovered service.
+                #visibility fn service(&self) -> &trouble_host::gatt::ServiceHandle {
+                    &self.service
+                }
+                #code_impl
+            }
+        }
+    }
+}
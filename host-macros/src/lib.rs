@@ -6,12 +6,14 @@
 extern crate proc_macro;
 
 mod characteristic;
+mod client;
 mod ctxt;
 mod server;
 mod service;
 mod uuid;
 
 use characteristic::{Characteristic, CharacteristicArgs, DescriptorArgs};
+use client::ClientServiceBuilder;
 use ctxt::Ctxt;
 use proc_macro::TokenStream;
 use server::{ServerArgs, ServerBuilder};
@@ -21,7 +23,8 @@ use syn::{parse_macro_input, Error};
 
 /// Gatt Service attribute macro.
 ///
-///
+/// Services are added to the attribute table in the order of the fields. A service can include
+/// other services declared before it with `#[include(...)]`.
 ///
 /// # Example
 /// ```rust no_run
@@ -29,8 +32,9 @@ use syn::{parse_macro_input, Error};
 ///
 /// #[gatt_server]
 /// struct MyGattServer {
-///     hrs: HeartRateService,
 ///     bas: BatteryService,
+///     #[include(bas)]
+///     hrs: HeartRateService,
 /// }
 ///
 /// ```
@@ -56,6 +60,17 @@ pub fn gatt_server(args: TokenStream, item: TokenStream) -> TokenStream {
 
 /// Gatt Service attribute macro.
 ///
+/// Add `secondary` to the arguments to declare a secondary service, which is only reachable
+/// through an include from another service, i.e. `#[gatt_service(uuid = "180f", secondary)]`.
+///
+/// Add `per_connection` to a characteristic to keep a separate value for each connection,
+/// i.e. `#[characteristic(uuid = "2a39", read, write, per_connection)]`. Within a `gatt_server`
+/// values are kept for up to `connections_max` connections.
+///
+/// Add `on_write = f` to a characteristic to check values written by clients before they are stored,
+/// where `f` is called as `f(&ConnectionContext, offset, &[u8])` and returns a `Result<(), E>` with `E`
+/// converting into an `AttErrorCode`. This allows rejecting writes with application error codes.
+///
 /// # Example
 ///
 /// ```rust no_run
@@ -78,6 +93,9 @@ pub fn gatt_server(args: TokenStream, item: TokenStream) -> TokenStream {
 ///    control: u8,
 ///    #[characteristic(uuid = "2a63", read, notify)]
 ///    energy_expended: u16,
+///    /// Standard descriptors can be added through the characteristic attribute
+///    #[characteristic(uuid = "2a6e", read, write, user_description = "Temperature", writable_auxiliaries, valid_range = (-40, 85))]
+///    temperature: i16,
 /// }
 /// ```
 #[proc_macro_attribute]
@@ -128,6 +146,113 @@ pub fn gatt_service(args: TokenStream, item: TokenStream) -> TokenStream {
     }
 }
 
+/// Gatt Client Service attribute macro.
+///
+/// Generates a client proxy named `<Service>Client` from a service struct definition. The proxy discovers the
+/// service and its characteristics through a `GattClient`, and offers typed methods for each characteristic field
+/// according to its properties: `read_<field>`, `write_<field>`, `write_<field>_without_response` and `subscribe_<field>`.
+///
+/// Placed above `#[gatt_service]`, the UUID is taken from the service and the struct is shared by the server
+/// and the client. Used on its own, the UUID must be given and the struct is emitted as declared, along with the client proxy.
+///
+/// # Example
+///
+/// ```rust no_run
+/// use trouble_host::prelude::*;
+///
+/// #[gatt_client_service]
+/// #[gatt_service(uuid = service::BATTERY)]
+/// struct BatteryService {
+///     #[characteristic(uuid = characteristic::BATTERY_LEVEL, read, notify, value = 10)]
+///     level: u8,
+/// }
+///
+/// async fn read_level<C: Controller>(client: &GattClient<'_, C, DefaultPacketPool, 10>) -> Result<u8, BleHostError<C::Error>> {
+///     let battery = BatteryServiceClient::discover(client).await?;
+///     battery.read_level().await
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn gatt_client_service(args: TokenStream, item: TokenStream) -> TokenStream {
+    let service_arguments = if args.is_empty() {
+        None
+    } else {
+        Some(parse_macro_input!(args as ServiceArgs))
+    };
+    let mut service_props = parse_macro_input!(item as syn::ItemStruct);
+    let mut original = service_props.clone();
+
+    let ctxt = Ctxt::new();
+
+    // Share the definition with a server service declared on the same struct
+    let server_service = service_props
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("gatt_service"))
+        .cloned();
+    let uuid = match (service_arguments, &server_service) {
+        (Some(args), _) => args.uuid,
+        (None, Some(attr)) => match attr.parse_args::<ServiceArgs>() {
+            Ok(args) => args.uuid,
+            Err(e) => return e.into_compile_error().into(),
+        },
+        (None, None) => {
+            ctxt.error_spanned_by(
+                &service_props.ident,
+                "gatt_client_service must have a UUID (i.e. `#[gatt_client_service(uuid = '1234')]`) or be placed above a gatt_service attribute",
+            );
+            return ctxt.check().unwrap_err().into();
+        }
+    };
+
+    let fields: Vec<syn::Field> = match &mut service_props.fields {
+        syn::Fields::Named(n) => n.named.iter().cloned().collect(),
+        _ => {
+            let s = service_props.ident;
+            ctxt.error_spanned_by(s, "gatt_client_service structs must have named fields, not tuples.");
+            return ctxt.check().unwrap_err().into();
+        }
+    };
+
+    let mut characteristics: Vec<Characteristic> = Vec::new();
+    let mut err: Option<syn::Error> = None;
+    for field in fields.iter() {
+        check_for_characteristic(field, &mut err, &mut characteristics);
+    }
+    if let Some(err) = err {
+        let desc = err.to_string();
+        ctxt.error_spanned_by(
+            err.into_compile_error(),
+            format!("Parsing characteristics was unsuccessful:\n{}", desc),
+        );
+        return ctxt.check().unwrap_err().into();
+    }
+
+    let client = ClientServiceBuilder::new(service_props, uuid)
+        .process_characteristics(characteristics)
+        .build();
+
+    // The server service macro processes the struct itself, otherwise it is kept without the field attributes
+    if server_service.is_none() {
+        if let syn::Fields::Named(fields) = &mut original.fields {
+            for field in fields.named.iter_mut() {
+                field
+                    .attrs
+                    .retain(|attr| !attr.path().is_ident("characteristic") && !attr.path().is_ident("descriptor"));
+            }
+        }
+    }
+    let result = quote::quote! {
+        #original
+        #client
+    };
+
+    match ctxt.check() {
+        Ok(()) => result.into(),
+        Err(e) => e.into(),
+    }
+}
+
 /// Check if a field has a characteristic attribute and parse it.
 ///
 /// If so also check if that field has descriptors and/or docstrings.
@@ -5,10 +5,11 @@
 
 use darling::Error;
 use proc_macro2::TokenStream as TokenStream2;
-use quote::{quote, quote_spanned};
+use quote::{format_ident, quote, quote_spanned};
 use syn::meta::ParseNestedMeta;
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
-use syn::{Expr, Result, parse_quote};
+use syn::{parse_quote, Expr, Result, Token};
 
 #[derive(Default)]
 pub(crate) struct ServerArgs {
@@ -102,6 +103,7 @@ impl ServerBuilder {
         let mut code_server_populate = TokenStream2::new();
         let mut code_attribute_summation = TokenStream2::new();
         let mut code_cccd_summation = TokenStream2::new();
+        let mut code_service_stores = TokenStream2::new();
         for service in &self.properties.fields {
             let vis = &service.vis;
             let service_span = service.span();
@@ -112,8 +114,33 @@ impl ServerBuilder {
                 #vis #service_name: #service_type,
             });
 
+            // Services to include, referenced by the name of a preceding field.
+            let mut includes = Vec::new();
+            for attr in service.attrs.iter().filter(|attr| attr.path().is_ident("include")) {
+                match attr.parse_args_with(Punctuated::<syn::Ident, Token![,]>::parse_terminated) {
+                    Ok(list) => includes.extend(list),
+                    Err(e) => return e.to_compile_error(),
+                }
+            }
+            let include_count = includes.len();
+
+            // Per-connection characteristics hold values for every connection of the server.
+            let store = format_ident!("_{}_PER_CONNECTION_STORE", service_name.to_string().to_uppercase());
+            code_service_stores.extend(quote_spanned! {service_span=>
+                static #store: static_cell::StaticCell<[u8; #service_type::per_connection_store_size(_CONNECTIONS_MAX)]> = static_cell::StaticCell::new();
+            });
+
+            // Included services are preceding fields, which were added to the same table already, and
+            // the store is sized for the service.
             code_service_init.extend(quote_spanned! {service_span=>
-                let #service_name = #service_type::new(&mut table);
+                let #service_name = {
+                    const SIZE: usize = #service_type::per_connection_store_size(_CONNECTIONS_MAX);
+                    let store: &'static mut [u8] = if SIZE == 0 { &mut [] } else { #store.init([0; SIZE]) };
+                    match #service_type::new_with_store(&mut table, &[#(#includes.handle()),*], _CONNECTIONS_MAX, store) {
+                        Ok(service) => service,
+                        Err(_) => unreachable!(),
+                    }
+                };
             });
 
             code_server_populate.extend(quote_spanned! {service_span=>
@@ -121,7 +148,7 @@ impl ServerBuilder {
             });
 
             code_attribute_summation.extend(quote_spanned! {service_span=>
-               + #service_type::ATTRIBUTE_COUNT
+               + #service_type::ATTRIBUTE_COUNT + #include_count
             });
 
             code_cccd_summation.extend(quote_spanned! {service_span=>
@@ -155,6 +182,7 @@ impl ServerBuilder {
             };
             const _CCCD_TABLE_SIZE: usize = #cccd_table_size;
             const _CONNECTIONS_MAX: usize = #connections_max;
+            #code_service_stores
 
             #visibility struct #name<'values>
             {
@@ -220,6 +248,14 @@ impl ServerBuilder {
                     self.server.table().set(attribute_handle, input)
                 }
 
+                #visibility fn get_for_connection<T: trouble_host::attribute::AttributeHandle<Value = V>, V: FromGatt>(&self, connection: &trouble_host::connection::Connection<'_, #packet_type>, attribute_handle: &T) -> Result<T::Value, trouble_host::Error> {
+                    self.server.table().get_for_connection(connection, attribute_handle)
+                }
+
+                #visibility fn set_for_connection<T: trouble_host::attribute::AttributeHandle>(&self, connection: &trouble_host::connection::Connection<'_, #packet_type>, attribute_handle: &T, input: &T::Value) -> Result<(), trouble_host::Error> {
+                    self.server.table().set_for_connection(connection, attribute_handle, input)
+                }
+
                 #visibility fn get_cccd_table(&self, connection: &trouble_host::connection::Connection<'_, #packet_type>) -> Option<trouble_host::prelude::CccdTable<_CCCD_TABLE_SIZE>> {
                     self.server.get_cccd_table(connection)
                 }
@@ -4,8 +4,8 @@
 //! The struct definition is used to define the characteristics of the service, and the ServiceBuilder is used to
 //! generate the code required to create the service.
 
-use darling::{Error, FromMeta};
 use convert_case::{Case, Casing};
+use darling::{Error, FromMeta};
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote, quote_spanned, ToTokens};
 use syn::parse::Result;
@@ -15,9 +15,14 @@ use syn::{Expr, Meta, Token};
 use crate::characteristic::{AccessArgs, Characteristic};
 use crate::uuid::Uuid;
 
+/// Capacity of a writable user description, unless the initial description is longer.
+const WRITABLE_USER_DESCRIPTION_CAPACITY: usize = 32;
+
 #[derive(Debug)]
 pub(crate) struct ServiceArgs {
     pub uuid: TokenStream2,
+    /// If true, the service is added as a secondary service.
+    pub secondary: bool,
 }
 
 /// Parse the UUID argument of the service attribute.
@@ -62,6 +67,7 @@ fn parse_arg_uuid(value: &Expr) -> Result<TokenStream2> {
 impl syn::parse::Parse for ServiceArgs {
     fn parse(input: syn::parse::ParseStream) -> Result<Self> {
         let mut uuid: Option<_> = None;
+        let mut secondary = false;
 
         while !input.is_empty() {
             let meta = input.parse()?;
@@ -85,13 +91,21 @@ impl syn::parse::Parse for ServiceArgs {
                         }
                         other => {
                             return Err(Error::unknown_field(&format!(
-                                "Unsupported service property: '{other}'.\nSupported properties are: uuid"
+                                "Unsupported service property: '{other}'.\nSupported properties are: uuid, secondary"
                             ))
                             .with_span(&name_value.span())
                             .into())
                         }
                     }
                 }
+                Meta::Path(path) if path.is_ident("secondary") => {
+                    if secondary {
+                        return Err(Error::custom("'secondary' should not be specified more than once")
+                            .with_span(&path.span())
+                            .into());
+                    }
+                    secondary = true;
+                }
                 _ => return Err(Error::custom("Unexpected argument").with_span(&meta.span()).into()),
             }
             let _ = input.parse::<Token![,]>();
@@ -101,6 +115,7 @@ impl syn::parse::Parse for ServiceArgs {
             uuid: uuid.ok_or(Error::custom(
                 "Service must have a UUID (i.e. `#[gatt_service(uuid = '1234')]` or `#[gatt_service(uuid = service::BATTERY)]`)",
             ))?,
+            secondary,
         })
     }
 }
@@ -114,6 +129,8 @@ pub(crate) struct ServiceBuilder {
     code_build_chars: TokenStream2,
     code_struct_init: TokenStream2,
     code_fields: TokenStream2,
+    /// Store sizes of the per-connection characteristics, for `connections` connections.
+    code_per_connection_sizes: Vec<TokenStream2>,
 }
 
 impl ServiceBuilder {
@@ -127,6 +144,7 @@ impl ServiceBuilder {
             code_impl: TokenStream2::new(),
             code_fields: TokenStream2::new(),
             code_build_chars: TokenStream2::new(),
+            code_per_connection_sizes: Vec::new(),
         }
     }
     /// Increment the number of access arguments required for this characteristic
@@ -156,8 +174,23 @@ impl ServiceBuilder {
         let fields = self.code_fields;
         let code_build_chars = self.code_build_chars;
         let uuid = self.args.uuid;
+        let add_service = if self.args.secondary {
+            quote!(add_secondary_service)
+        } else {
+            quote!(add_service)
+        };
         let attribute_count = self.attribute_count;
         let cccd_count = self.cccd_count;
+        let per_connection_sizes = self.code_per_connection_sizes;
+        let default_store = if per_connection_sizes.is_empty() {
+            quote!(&mut [])
+        } else {
+            quote! {
+                const SIZE: usize = #struct_name::per_connection_store_size(1);
+                static STORE: static_cell::StaticCell<[u8; SIZE]> = static_cell::StaticCell::new();
+                STORE.init([0; SIZE])
+            }
+        };
         quote! {
             #visibility struct #struct_name {
                 #fields
@@ -169,11 +202,83 @@ impl ServiceBuilder {
                 #visibility const ATTRIBUTE_COUNT: usize = #attribute_count;
                 #visibility const CCCD_COUNT: usize = #cccd_count;
 
+                /// Size of the store needed by [`Self::new_with_store`] for the per-connection characteristics
+                /// of the service, when holding values for the given number of connections.
+                #visibility const fn per_connection_store_size(connections: usize) -> usize {
+                    0 #(+ #per_connection_sizes)*
+                }
+
+                /// Create the service.
+                ///
+                /// Per-connection characteristics hold a value for a single connection, use [`Self::new_with_store`]
+                /// to hold values for more connections.
                 #visibility fn new<M, const MAX_ATTRIBUTES: usize>(table: &mut trouble_host::attribute::AttributeTable<'_, M, MAX_ATTRIBUTES>) -> Self
                 where
                     M: embassy_sync::blocking_mutex::raw::RawMutex,
                 {
-                    let mut service = table.add_service(trouble_host::attribute::Service::new(#uuid));
+                    let service = table.#add_service(trouble_host::attribute::Service::new(#uuid));
+                    Self::build_service(service, 1, Self::default_store())
+                }
+
+                /// Create the service, including the services with the given handles.
+                ///
+                /// The included services must already have been added to the table, otherwise
+                /// `Error::NotFound` is returned and nothing is added to the table.
+                ///
+                /// Per-connection characteristics hold a value for a single connection, use [`Self::new_with_store`]
+                /// to hold values for more connections.
+                #visibility fn new_with_includes<M, const MAX_ATTRIBUTES: usize>(table: &mut trouble_host::attribute::AttributeTable<'_, M, MAX_ATTRIBUTES>, includes: &[u16]) -> Result<Self, trouble_host::Error>
+                where
+                    M: embassy_sync::blocking_mutex::raw::RawMutex,
+                {
+                    // Checked before taking the store, so it is still available when retrying
+                    Self::check_includes(table, includes)?;
+                    Self::new_with_store(table, includes, 1, Self::default_store())
+                }
+
+                /// Create the service, including the services with the given handles, with per-connection
+                /// characteristics holding values for up to `connections` connections in the given store.
+                ///
+                /// The store must be at least [`Self::per_connection_store_size`] bytes long, otherwise
+                /// `Error::InsufficientSpace` is returned. The included services must already have been added
+                /// to the table, otherwise `Error::NotFound` is returned. Nothing is added to the table on errors.
+                #visibility fn new_with_store<'d, M, const MAX_ATTRIBUTES: usize>(table: &mut trouble_host::attribute::AttributeTable<'d, M, MAX_ATTRIBUTES>, includes: &[u16], connections: usize, store: &'d mut [u8]) -> Result<Self, trouble_host::Error>
+                where
+                    M: embassy_sync::blocking_mutex::raw::RawMutex,
+                {
+                    if store.len() < Self::per_connection_store_size(connections) {
+                        return Err(trouble_host::Error::InsufficientSpace);
+                    }
+                    Self::check_includes(table, includes)?;
+                    let mut service = table.#add_service(trouble_host::attribute::Service::new(#uuid));
+                    for include in includes {
+                        service.include(*include)?;
+                    }
+                    Ok(Self::build_service(service, connections, store))
+                }
+
+                /// Store for per-connection characteristics holding a value for a single connection.
+                fn default_store() -> &'static mut [u8] {
+                    #default_store
+                }
+
+                fn check_includes<M, const MAX_ATTRIBUTES: usize>(table: &trouble_host::attribute::AttributeTable<'_, M, MAX_ATTRIBUTES>, includes: &[u16]) -> Result<(), trouble_host::Error>
+                where
+                    M: embassy_sync::blocking_mutex::raw::RawMutex,
+                {
+                    for include in includes {
+                        if !table.is_service(*include) {
+                            return Err(trouble_host::Error::NotFound);
+                        }
+                    }
+                    Ok(())
+                }
+
+                fn build_service<'d, M, const MAX_ATTRIBUTES: usize>(mut service: trouble_host::attribute::ServiceBuilder<'_, 'd, M, MAX_ATTRIBUTES>, connections: usize, per_connection_store: &'d mut [u8]) -> Self
+                where
+                    M: embassy_sync::blocking_mutex::raw::RawMutex,
+                {
+                    let mut per_connection_store = per_connection_store;
                     #code_build_chars
 
                     Self {
@@ -181,6 +286,11 @@ impl ServiceBuilder {
                         #code_struct_init
                     }
                 }
+
+                /// Handle of the service declaration.
+                #visibility fn handle(&self) -> u16 {
+                    self.handle
+                }
                 #code_impl
             }
         }
@@ -188,6 +298,7 @@ impl ServiceBuilder {
 
     /// Construct instructions for adding a characteristic to the service, with static storage.
     fn construct_characteristic_static(&mut self, characteristic: Characteristic) {
+        let code_standard_descriptors = self.build_standard_descriptors(&characteristic);
         let (code_descriptors, named_descriptors) = self.build_descriptors(&characteristic);
         let name_screaming = format_ident!("{}", characteristic.name.as_str().to_case(Case::Constant));
         let char_name = format_ident!("{}", characteristic.name);
@@ -200,12 +311,36 @@ impl ServiceBuilder {
             None => quote_spanned!(characteristic.span => <#ty>::default()), // or default otherwise
         };
 
-        self.code_build_chars.extend(quote_spanned! {characteristic.span=>
-            let (#char_name, #(#named_descriptors),*) = {
+        let add_characteristic = match (characteristic.args.provider, characteristic.args.per_connection) {
+            (Some(provider), _) => quote_spanned! {characteristic.span=>
+                let mut builder = service
+                    .add_characteristic_provider::<#ty, _>(#uuid, &[#(#properties),*], #provider);
+            },
+            (None, true) => quote_spanned! {characteristic.span=>
+                let size = trouble_host::attribute::per_connection_store_size(<#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE, connections);
+                let (store, rest) = core::mem::take(&mut per_connection_store).split_at_mut(size);
+                per_connection_store = rest;
+                let mut builder = service
+                    .add_characteristic_per_connection(#uuid, &[#(#properties),*], #default_value, connections, store);
+            },
+            (None, false) => quote_spanned! {characteristic.span=>
                 static #name_screaming: static_cell::StaticCell<[u8; <#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE]> = static_cell::StaticCell::new();
                 let store = #name_screaming.init([0; <#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE]);
                 let mut builder = service
                     .add_characteristic(#uuid, &[#(#properties),*], #default_value, store);
+            },
+        };
+
+        if characteristic.args.per_connection {
+            self.code_per_connection_sizes.push(quote_spanned! {characteristic.span=>
+                trouble_host::attribute::per_connection_store_size(<#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE, connections)
+            });
+        }
+
+        self.code_build_chars.extend(quote_spanned! {characteristic.span=>
+            let (#char_name, #(#named_descriptors),*) = {
+                #add_characteristic
+                #code_standard_descriptors
                 #code_descriptors
 
                 (builder.build(), #(#named_descriptors),*)
@@ -277,6 +412,114 @@ impl ServiceBuilder {
         self
     }
 
+    /// Generate token stream for the standard descriptors configured through the characteristic attribute.
+    fn build_standard_descriptors(&mut self, characteristic: &Characteristic) -> TokenStream2 {
+        let args = &characteristic.args;
+        let ty = &characteristic.ty;
+        let name_screaming = characteristic.name.as_str().to_case(Case::Constant);
+        let mut code = TokenStream2::new();
+
+        let mut extended_properties = Vec::new();
+        if args.reliable_write {
+            extended_properties.push(quote!(
+                trouble_host::attribute::CharacteristicExtendedProp::ReliableWrite
+            ));
+        }
+        if args.writable_auxiliaries {
+            extended_properties.push(quote!(
+                trouble_host::attribute::CharacteristicExtendedProp::WritableAuxiliaries
+            ));
+        }
+        if !extended_properties.is_empty() {
+            self.attribute_count += 1;
+            code.extend(quote_spanned! {characteristic.span=>
+                builder.add_extended_properties(&[#(#extended_properties),*]);
+            });
+        }
+
+        if let Some(description) = &args.user_description {
+            self.attribute_count += 1;
+            let store_name = format_ident!("USER_DESCRIPTION_{}", name_screaming);
+            let writable = args.writable_auxiliaries;
+            // Writable descriptions can be replaced by longer ones, up to the given capacity
+            let capacity = if writable {
+                quote!({ if #description.len() > #WRITABLE_USER_DESCRIPTION_CAPACITY { #description.len() } else { #WRITABLE_USER_DESCRIPTION_CAPACITY } })
+            } else {
+                quote!(#description.len())
+            };
+            code.extend(quote_spanned! {characteristic.span=>
+                {
+                    static #store_name: static_cell::StaticCell<[u8; #capacity]> = static_cell::StaticCell::new();
+                    let store = #store_name.init([0; #capacity]);
+                    builder.add_user_description(#description, #writable, store).unwrap();
+                }
+            });
+        }
+
+        if !args.presentation_formats.is_empty() {
+            let format_names: Vec<_> = (0..args.presentation_formats.len())
+                .map(|index| format_ident!("PRESENTATION_FORMAT_{index}_{}", name_screaming))
+                .collect();
+            let formats = &args.presentation_formats;
+            self.attribute_count += formats.len();
+            let code_formats = quote_spanned! {characteristic.span=>
+                #(
+                    static #format_names: trouble_host::attribute::PresentationFormat = #formats;
+                )*
+                let formats = [#(builder.add_presentation_format(&#format_names)),*];
+            };
+            if formats.len() > 1 {
+                self.attribute_count += 1;
+                let store_name = format_ident!("AGGREGATE_FORMAT_{}", name_screaming);
+                let capacity = formats.len() * 2;
+                code.extend(quote_spanned! {characteristic.span=>
+                    {
+                        #code_formats
+                        static #store_name: static_cell::StaticCell<[u8; #capacity]> = static_cell::StaticCell::new();
+                        let store = #store_name.init([0; #capacity]);
+                        builder.add_aggregate_format(&formats, store).unwrap();
+                    }
+                });
+            } else {
+                code.extend(quote_spanned! {characteristic.span=>
+                    {
+                        #code_formats
+                    }
+                });
+            }
+        }
+
+        if let Some(range) = &args.valid_range {
+            self.attribute_count += 1;
+            let store_name = format_ident!("VALID_RANGE_{}", name_screaming);
+            code.extend(quote_spanned! {characteristic.span=>
+                {
+                    let (min, max): (#ty, #ty) = #range;
+                    static #store_name: static_cell::StaticCell<[u8; 2 * <#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE]> = static_cell::StaticCell::new();
+                    let store = #store_name.init([0; 2 * <#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE]);
+                    builder.add_valid_range(&min, &max, store);
+                }
+            });
+        }
+
+        if let Some(on_write) = &args.on_write {
+            code.extend(quote_spanned! {characteristic.span=>
+                {
+                    fn on_write(
+                        ctx: &trouble_host::attribute::ConnectionContext,
+                        offset: usize,
+                        data: &[u8],
+                    ) -> Result<(), trouble_host::att::AttErrorCode> {
+                        (#on_write)(ctx, offset, data).map_err(Into::into)
+                    }
+                    builder.set_write_handler(on_write);
+                }
+            });
+        }
+
+        code
+    }
+
     /// Generate token stream for any descriptors tagged against this characteristic.
     fn build_descriptors(&mut self, characteristic: &Characteristic) -> (TokenStream2, Vec<TokenStream2>) {
         let mut named_descriptors = Vec::<TokenStream2>::new();
@@ -9,8 +9,11 @@ static CONFIGS: &[(&str, usize)] = &[
     ("CONNECTION_EVENT_QUEUE_SIZE", 2),
     ("L2CAP_RX_QUEUE_SIZE", 8),
     ("L2CAP_TX_QUEUE_SIZE", 8),
+    ("L2CAP_LISTENERS_MAX", 4),
+    ("L2CAP_FIXED_CHANNELS_MAX", 2),
     ("DEFAULT_PACKET_POOL_SIZE", 16),
     ("DEFAULT_PACKET_POOL_MTU", 251),
+    ("GATT_SERVER_PREPARE_WRITE_QUEUE_SIZE", 256),
     ("GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS", 1),
     ("GATT_CLIENT_NOTIFICATION_QUEUE_SIZE", 1),
     // END AUTOGENERATED CONFIG FEATURES
@@ -46,12 +46,21 @@ feature("l2cap_rx_queue_size",
 feature("l2cap_tx_queue_size",
         "Controls the size of the L2CAP outbound queue per channel.",
         default=8, min=1, max=64, pow2=True)
+feature("l2cap_listeners_max",
+        "Controls how many L2CAP listeners can be registered at the same time.",
+        default=4, min=1, max=16, pow2=True)
+feature("l2cap_fixed_channels_max",
+        "Controls how many user fixed L2CAP channels can be registered at the same time.",
+        default=2, min=1, max=16, pow2=True)
 feature("default_packet_pool_size",
         "Controls the pool size of the default packet pool, if enabled.",
         default=16, min=1, max=128, pow2=True)
 feature("default_packet_pool_mtu",
         "Controls the packet MTU of the default packet pool, if enabled.",
         default=251, vals = [27, 48, 64, 128, 251, 255, 512, 1024])
+feature("gatt_server_prepare_write_queue_size",
+        "Controls the size in bytes of the GATT server queue of prepared writes for each connection.",
+        default=256, min=64, max=4096, pow2=True)
 feature("gatt_client_notification_max_subscribers",
         "When using the GATT client, this controls how many subscribers can be created.",
         default=1, min=1, max=512, pow2=True)
@@ -26,8 +26,10 @@ pub(crate) const ATT_PREPARE_WRITE_REQ: u8 = 0x16;
 pub(crate) const ATT_PREPARE_WRITE_RSP: u8 = 0x17;
 pub(crate) const ATT_EXECUTE_WRITE_REQ: u8 = 0x18;
 pub(crate) const ATT_EXECUTE_WRITE_RSP: u8 = 0x19;
-pub(crate) const ATT_READ_MULTIPLE_REQ: u8 = 0x20;
-pub(crate) const ATT_READ_MULTIPLE_RSP: u8 = 0x21;
+pub(crate) const ATT_READ_MULTIPLE_REQ: u8 = 0x0e;
+pub(crate) const ATT_READ_MULTIPLE_RSP: u8 = 0x0f;
+pub(crate) const ATT_READ_MULTIPLE_VARIABLE_REQ: u8 = 0x20;
+pub(crate) const ATT_READ_MULTIPLE_VARIABLE_RSP: u8 = 0x21;
 pub(crate) const ATT_READ_BLOB_REQ: u8 = 0x0c;
 pub(crate) const ATT_READ_BLOB_RSP: u8 = 0x0d;
 pub(crate) const ATT_HANDLE_VALUE_NTF: u8 = 0x1b;
@@ -39,6 +41,29 @@ pub(crate) const ATT_HANDLE_VALUE_CMF: u8 = 0x1e;
 /// This enum type describes the `ATT_ERROR_RSP` PDU from the Bluetooth Core Specification
 /// Version 6.0 | Vol 3, Part F (page 1491)
 /// See also: Core Specification Supplement, Part B: Common Profile and Service Error Codes
+///
+/// Error codes in the range 0x80 to 0x9F are defined by profile and service specifications,
+/// and can be created with [`AttErrorCode::application`]. A service specific error type can be
+/// returned anywhere an `AttErrorCode` is expected by implementing `From<E> for AttErrorCode`:
+///
+/// ```rust
+/// use trouble_host::prelude::AttErrorCode;
+///
+/// enum ControlPointError {
+///     OpCodeNotSupported,
+///     InvalidParameter,
+/// }
+///
+/// impl From<ControlPointError> for AttErrorCode {
+///     fn from(error: ControlPointError) -> Self {
+///         let code = match error {
+///             ControlPointError::OpCodeNotSupported => 0x80,
+///             ControlPointError::InvalidParameter => 0x81,
+///         };
+///         AttErrorCode::application(code).unwrap()
+///     }
+/// }
+/// ```
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct AttErrorCode {
@@ -94,6 +119,26 @@ impl AttErrorCode {
     pub const PROCEDURE_ALREADY_IN_PROGRESS: Self = Self { value: 0xFE };
     /// The attribute value is out of range as defined by a profile or service specification
     pub const OUT_OF_RANGE: Self = Self { value: 0xFF };
+
+    /// Create an application error code, defined by a profile or service specification.
+    ///
+    /// Returns `None` if the code is outside of the application error range 0x80 to 0x9F.
+    pub const fn application(code: u8) -> Option<Self> {
+        match code {
+            0x80..=0x9F => Some(Self { value: code }),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this is an application error code, defined by a profile or service specification.
+    pub const fn is_application(&self) -> bool {
+        matches!(self.value, 0x80..=0x9F)
+    }
+
+    /// Returns the raw value of the error code.
+    pub const fn value(&self) -> u8 {
+        self.value
+    }
 }
 
 impl Display for AttErrorCode {
@@ -127,7 +172,7 @@ impl Display for AttErrorCode {
             &Self::DATABASE_OUT_OF_SYNC => f.write_str("the server requests the client to rediscover the database"),
             &Self::VALUE_NOT_ALLOWED => f.write_str("value not allowed: the attribute parameter value was not allowed"),
 
-            &Self{value: 0x80..=0x9F} => write!(f, "application error code {}: check the application documentation of the device which produced this error code", self.value),
+            &Self{value: 0x80..=0x9F} => write!(f, "application error code {:#04x}: check the application documentation of the device which produced this error code", self.value),
 
             &Self::WRITE_REQUEST_REJECTED => f.write_str("write request rejected: the write request could not be fulfilled for reasons other than permissions"),
             &Self::CCCD_IMPROPERLY_CONFIGURED => f.write_str("CCCD improperly configured: the client characteristic configuration descriptor (CCCD) is not configured according to the requirements of the profile or service"),
@@ -248,6 +293,11 @@ pub enum AttReq<'d> {
         /// Attribute handles
         handles: &'d [u8],
     },
+    /// Read Multiple Variable Length Request
+    ReadMultipleVariable {
+        /// Attribute handles
+        handles: &'d [u8],
+    },
     /// Read Blob Request
     ReadBlob {
         /// Attribute handle
@@ -316,6 +366,16 @@ pub enum AttRsp<'d> {
         /// Iterator over the found handles
         it: ReadByTypeIter<'d>,
     },
+    /// Read By Group Type Response
+    ReadByGroupType {
+        /// Iterator over the found groups
+        it: ReadByGroupTypeIter<'d>,
+    },
+    /// Find Information Response
+    FindInformation {
+        /// Iterator over the found handles and types
+        it: FindInformationIter<'d>,
+    },
     /// Read Response
     Read {
         /// Attribute value
@@ -323,6 +383,32 @@ pub enum AttRsp<'d> {
     },
     /// Write Response
     Write,
+    /// Read Blob Response
+    ReadBlob {
+        /// Part of the attribute value
+        data: &'d [u8],
+    },
+    /// Prepare Write Response
+    PrepareWrite {
+        /// Attribute handle
+        handle: u16,
+        /// Attribute offset
+        offset: u16,
+        /// Part of the attribute value, as queued by the server
+        value: &'d [u8],
+    },
+    /// Execute Write Response
+    ExecuteWrite,
+    /// Read Multiple Response
+    ReadMultiple {
+        /// Concatenated attribute values
+        data: &'d [u8],
+    },
+    /// Read Multiple Variable Length Response
+    ReadMultipleVariable {
+        /// Iterator over the attribute values
+        it: ReadMultipleVariableIter<'d>,
+    },
 }
 
 /// ATT Unsolicited PDU
@@ -408,6 +494,95 @@ impl<'d> ReadByTypeIter<'d> {
     }
 }
 
+/// Attribute handle, end group handle and attribute data of a group in a Read By Group Type response
+pub type AttributeGroup<'d> = (u16, u16, &'d [u8]);
+
+/// An Iterator-like type for iterating over the found attribute groups
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Debug)]
+pub struct ReadByGroupTypeIter<'d> {
+    item_len: usize,
+    cursor: ReadCursor<'d>,
+}
+
+impl<'d> ReadByGroupTypeIter<'d> {
+    /// Get the next group as attribute handle, end group handle and attribute data
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<AttributeGroup<'d>, crate::Error>> {
+        if self.item_len >= 4 && self.cursor.available() >= self.item_len {
+            let res = (|| {
+                let handle: u16 = self.cursor.read()?;
+                let end: u16 = self.cursor.read()?;
+                let item = self.cursor.slice(self.item_len - 4)?;
+                Ok((handle, end, item))
+            })();
+            Some(res)
+        } else {
+            None
+        }
+    }
+}
+
+/// An Iterator-like type for iterating over the values of a Read Multiple Variable Length response
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Debug)]
+pub struct ReadMultipleVariableIter<'d> {
+    cursor: ReadCursor<'d>,
+}
+
+impl<'d> ReadMultipleVariableIter<'d> {
+    /// Get the next attribute value
+    ///
+    /// The last value may be truncated if the response did not fit in the MTU.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<&'d [u8], crate::Error>> {
+        if self.cursor.available() >= 2 {
+            let res = (|| {
+                let len: u16 = self.cursor.read()?;
+                let len = (len as usize).min(self.cursor.available());
+                Ok(self.cursor.slice(len)?)
+            })();
+            Some(res)
+        } else {
+            None
+        }
+    }
+}
+
+/// An Iterator-like type for iterating over the found handles and attribute types
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Debug)]
+pub struct FindInformationIter<'d> {
+    format: u8,
+    cursor: ReadCursor<'d>,
+}
+
+impl FindInformationIter<'_> {
+    fn uuid_len(&self) -> usize {
+        if self.format == 0x02 {
+            16
+        } else {
+            2
+        }
+    }
+
+    /// Get the next pair of attribute handle and attribute type
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<(u16, Uuid), crate::Error>> {
+        let uuid_len = self.uuid_len();
+        if self.cursor.available() >= 2 + uuid_len {
+            let res = (|| {
+                let handle: u16 = self.cursor.read()?;
+                let uuid = Uuid::try_from(self.cursor.slice(uuid_len)?)?;
+                Ok((handle, uuid))
+            })();
+            Some(res)
+        } else {
+            None
+        }
+    }
+}
+
 impl<'d> AttServer<'d> {
     fn size(&self) -> usize {
         match self {
@@ -440,7 +615,14 @@ impl<'d> AttRsp<'d> {
             Self::Error { .. } => 4,
             Self::Read { data } => data.len(),
             Self::ReadByType { it } => it.cursor.len(),
+            Self::ReadByGroupType { it } => it.cursor.len(),
+            Self::FindInformation { it } => it.cursor.len(),
             Self::Write => 0,
+            Self::ReadBlob { data } => data.len(),
+            Self::PrepareWrite { value, .. } => 4 + value.len(),
+            Self::ExecuteWrite => 0,
+            Self::ReadMultiple { data } => data.len(),
+            Self::ReadMultipleVariable { it } => it.cursor.len(),
         }
     }
 
@@ -474,6 +656,25 @@ impl<'d> AttRsp<'d> {
                     w.append(item)?;
                 }
             }
+            Self::ReadByGroupType { it } => {
+                w.write(ATT_READ_BY_GROUP_TYPE_RSP)?;
+                w.write(it.item_len as u8)?;
+                let mut it = it.clone();
+                while let Some(Ok((handle, end, item))) = it.next() {
+                    w.write(handle)?;
+                    w.write(end)?;
+                    w.append(item)?;
+                }
+            }
+            Self::FindInformation { it } => {
+                w.write(ATT_FIND_INFORMATION_RSP)?;
+                w.write(it.format)?;
+                let mut it = it.clone();
+                while let Some(Ok((handle, uuid))) = it.next() {
+                    w.write(handle)?;
+                    w.append(uuid.as_raw())?;
+                }
+            }
             Self::Read { data } => {
                 w.write(ATT_READ_RSP)?;
                 w.append(data)?;
@@ -481,6 +682,31 @@ impl<'d> AttRsp<'d> {
             Self::Write => {
                 w.write(ATT_WRITE_RSP)?;
             }
+            Self::ReadBlob { data } => {
+                w.write(ATT_READ_BLOB_RSP)?;
+                w.append(data)?;
+            }
+            Self::PrepareWrite { handle, offset, value } => {
+                w.write(ATT_PREPARE_WRITE_RSP)?;
+                w.write(*handle)?;
+                w.write(*offset)?;
+                w.append(value)?;
+            }
+            Self::ExecuteWrite => {
+                w.write(ATT_EXECUTE_WRITE_RSP)?;
+            }
+            Self::ReadMultiple { data } => {
+                w.write(ATT_READ_MULTIPLE_RSP)?;
+                w.append(data)?;
+            }
+            Self::ReadMultipleVariable { it } => {
+                w.write(ATT_READ_MULTIPLE_VARIABLE_RSP)?;
+                let mut it = it.clone();
+                while let Some(Ok(value)) = it.next() {
+                    w.write(value.len() as u16)?;
+                    w.append(value)?;
+                }
+            }
         }
         Ok(())
     }
@@ -510,7 +736,37 @@ impl<'d> AttRsp<'d> {
                     },
                 })
             }
+            ATT_READ_BY_GROUP_TYPE_RSP => {
+                let item_len: u8 = r.read()?;
+                Ok(Self::ReadByGroupType {
+                    it: ReadByGroupTypeIter {
+                        item_len: item_len as usize,
+                        cursor: r,
+                    },
+                })
+            }
+            ATT_FIND_INFORMATION_RSP => {
+                let format: u8 = r.read()?;
+                Ok(Self::FindInformation {
+                    it: FindInformationIter { format, cursor: r },
+                })
+            }
             ATT_WRITE_RSP => Ok(Self::Write),
+            ATT_READ_BLOB_RSP => Ok(Self::ReadBlob { data: r.remaining() }),
+            ATT_PREPARE_WRITE_RSP => {
+                let handle = r.read()?;
+                let offset = r.read()?;
+                Ok(Self::PrepareWrite {
+                    handle,
+                    offset,
+                    value: r.remaining(),
+                })
+            }
+            ATT_EXECUTE_WRITE_RSP => Ok(Self::ExecuteWrite),
+            ATT_READ_MULTIPLE_RSP => Ok(Self::ReadMultiple { data: r.remaining() }),
+            ATT_READ_MULTIPLE_VARIABLE_RSP => Ok(Self::ReadMultipleVariable {
+                it: ReadMultipleVariableIter { cursor: r },
+            }),
             _ => Err(codec::Error::InvalidValue),
         }
     }
@@ -604,9 +860,15 @@ impl<'d> AttReq<'d> {
                 end,
                 attribute_type,
             } => 4 + attribute_type.as_raw().len(),
+            Self::ReadByGroupType { group_type, .. } => 4 + group_type.as_raw().len(),
+            Self::FindInformation { .. } => 4,
             Self::Read { .. } => 2,
             Self::Write { handle, data } => 2 + data.len(),
-            _ => unimplemented!(),
+            Self::ReadBlob { .. } => 4,
+            Self::PrepareWrite { value, .. } => 4 + value.len(),
+            Self::ExecuteWrite { .. } => 1,
+            Self::ReadMultiple { handles } => handles.len(),
+            Self::ReadMultipleVariable { handles } => handles.len(),
         }
     }
     fn encode(&self, dest: &mut [u8]) -> Result<(), codec::Error> {
@@ -638,6 +900,20 @@ impl<'d> AttReq<'d> {
                 w.write(*end)?;
                 w.write_ref(attribute_type)?;
             }
+            Self::ReadByGroupType { start, end, group_type } => {
+                w.write(ATT_READ_BY_GROUP_TYPE_REQ)?;
+                w.write(*start)?;
+                w.write(*end)?;
+                w.write_ref(group_type)?;
+            }
+            Self::FindInformation {
+                start_handle,
+                end_handle,
+            } => {
+                w.write(ATT_FIND_INFORMATION_REQ)?;
+                w.write(*start_handle)?;
+                w.write(*end_handle)?;
+            }
             Self::Read { handle } => {
                 w.write(ATT_READ_REQ)?;
                 w.write(*handle)?;
@@ -647,7 +923,29 @@ impl<'d> AttReq<'d> {
                 w.write(*handle)?;
                 w.append(data)?;
             }
-            _ => unimplemented!(),
+            Self::ReadBlob { handle, offset } => {
+                w.write(ATT_READ_BLOB_REQ)?;
+                w.write(*handle)?;
+                w.write(*offset)?;
+            }
+            Self::PrepareWrite { handle, offset, value } => {
+                w.write(ATT_PREPARE_WRITE_REQ)?;
+                w.write(*handle)?;
+                w.write(*offset)?;
+                w.append(value)?;
+            }
+            Self::ExecuteWrite { flags } => {
+                w.write(ATT_EXECUTE_WRITE_REQ)?;
+                w.write(*flags)?;
+            }
+            Self::ReadMultiple { handles } => {
+                w.write(ATT_READ_MULTIPLE_REQ)?;
+                w.append(handles)?;
+            }
+            Self::ReadMultipleVariable { handles } => {
+                w.write(ATT_READ_MULTIPLE_VARIABLE_REQ)?;
+                w.append(handles)?;
+            }
         }
         Ok(())
     }
@@ -662,7 +960,7 @@ impl<'d> AttReq<'d> {
                 let group_type = if payload.len() == 6 {
                     Uuid::Uuid16([payload[4], payload[5]])
                 } else if payload.len() == 20 {
-                    let uuid = payload[4..21].try_into().map_err(|_| codec::Error::InvalidValue)?;
+                    let uuid = payload[4..20].try_into().map_err(|_| codec::Error::InvalidValue)?;
                     Uuid::Uuid128(uuid)
                 } else {
                     return Err(codec::Error::InvalidValue);
@@ -744,6 +1042,7 @@ impl<'d> AttReq<'d> {
                 Ok(Self::ExecuteWrite { flags })
             }
             ATT_READ_MULTIPLE_REQ => Ok(Self::ReadMultiple { handles: payload }),
+            ATT_READ_MULTIPLE_VARIABLE_REQ => Ok(Self::ReadMultipleVariable { handles: payload }),
             ATT_READ_BLOB_REQ => {
                 let handle = (payload[0] as u16) + ((payload[1] as u16) << 8);
                 let offset = (payload[2] as u16) + ((payload[3] as u16) << 8);
@@ -874,3 +1173,51 @@ impl<'d> codec::Decode<'d> for Att<'d> {
         Self::decode(data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use super::*;
+
+    #[test]
+    fn application_error_codes() {
+        assert_eq!(AttErrorCode::application(0x7F), None);
+        assert_eq!(AttErrorCode::application(0xA0), None);
+
+        let first = AttErrorCode::application(0x80).unwrap();
+        let last = AttErrorCode::application(0x9F).unwrap();
+        assert_eq!(first.value(), 0x80);
+        assert_eq!(last.value(), 0x9F);
+        assert!(first.is_application());
+        assert!(last.is_application());
+
+        assert!(!AttErrorCode::INVALID_HANDLE.is_application());
+        assert!(!AttErrorCode::INSUFFICIENT_RESOURCES.is_application());
+        assert!(!AttErrorCode::WRITE_REQUEST_REJECTED.is_application());
+        assert!(!AttErrorCode::OUT_OF_RANGE.is_application());
+
+        let mut s: heapless::String<128> = heapless::String::new();
+        write!(s, "{}", first).unwrap();
+        assert!(s.starts_with("application error code 0x80"));
+    }
+
+    #[test]
+    fn application_error_from_service_error() {
+        enum ControlPointError {
+            InvalidParameter,
+        }
+
+        impl From<ControlPointError> for AttErrorCode {
+            fn from(error: ControlPointError) -> Self {
+                match error {
+                    ControlPointError::InvalidParameter => AttErrorCode::application(0x81).unwrap(),
+                }
+            }
+        }
+
+        let code: AttErrorCode = ControlPointError::InvalidParameter.into();
+        assert_eq!(code.value(), 0x81);
+        assert!(code.is_application());
+    }
+}
@@ -3,8 +3,14 @@ use core::cell::RefCell;
 use core::fmt;
 use core::marker::PhantomData;
 
-use bt_hci::uuid::declarations::{CHARACTERISTIC, PRIMARY_SERVICE};
-use bt_hci::uuid::descriptors::CLIENT_CHARACTERISTIC_CONFIGURATION;
+use bt_hci::controller::Controller;
+use bt_hci::param::ConnHandle;
+use bt_hci::uuid::characteristic::CLIENT_SUPPORTED_FEATURES;
+use bt_hci::uuid::declarations::{CHARACTERISTIC, INCLUDE, PRIMARY_SERVICE, SECONDARY_SERVICE};
+use bt_hci::uuid::descriptors::{
+    CHARACTERISTIC_AGGREGATE_FORMAT, CHARACTERISTIC_EXTENDED_PROPERTIES, CHARACTERISTIC_PRESENTATION_FORMAT,
+    CHARACTERISTIC_USER_DESCRIPTION, CLIENT_CHARACTERISTIC_CONFIGURATION, VALID_RANGE,
+};
 use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::blocking_mutex::Mutex;
 use heapless::Vec;
@@ -12,10 +18,12 @@ use heapless::Vec;
 use crate::att::AttErrorCode;
 use crate::attribute_server::AttributeServer;
 use crate::cursor::{ReadCursor, WriteCursor};
-use crate::prelude::{AsGatt, FixedGattValue, FromGatt, GattConnection};
+use crate::l2cap::L2capChannel;
+use crate::pdu::TxSdu;
+use crate::prelude::{AsGatt, Connection, FixedGattValue, FromGatt, GattConnection};
 use crate::types::gatt_traits::FromGattError;
 pub use crate::types::uuid::Uuid;
-use crate::{Error, PacketPool, MAX_INVALID_DATA_LEN};
+use crate::{BleHostError, Error, Identity, PacketPool, Stack, MAX_INVALID_DATA_LEN};
 
 /// Characteristic properties
 #[derive(Debug, Clone, Copy)]
@@ -39,6 +47,16 @@ pub enum CharacteristicProp {
     Extended = 0x80,
 }
 
+/// Characteristic extended properties
+#[derive(Debug, Clone, Copy)]
+#[repr(u16)]
+pub enum CharacteristicExtendedProp {
+    /// Reliable write
+    ReliableWrite = 0x0001,
+    /// Writable auxiliaries
+    WritableAuxiliaries = 0x0002,
+}
+
 /// Attribute metadata.
 pub struct Attribute<'a> {
     pub(crate) uuid: Uuid,
@@ -50,19 +68,90 @@ pub struct Attribute<'a> {
 impl<'a> Attribute<'a> {
     const EMPTY: Option<Attribute<'a>> = None;
 
-    pub(crate) fn read(&self, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
+    pub(crate) fn read(&self, ctx: &ConnectionContext, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
         if !self.data.readable() {
             return Err(AttErrorCode::READ_NOT_PERMITTED);
         }
-        self.data.read(offset, data)
+        self.data.read(ctx, offset, data)
     }
 
-    pub(crate) fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), AttErrorCode> {
+    pub(crate) fn write(&mut self, ctx: &ConnectionContext, offset: usize, data: &[u8]) -> Result<(), AttErrorCode> {
         if !self.data.writable() {
             return Err(AttErrorCode::WRITE_NOT_PERMITTED);
         }
 
-        self.data.write(offset, data)
+        self.data.write(ctx, offset, data)
+    }
+}
+
+/// Information about the connection on whose behalf an attribute is accessed.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionContext {
+    handle: ConnHandle,
+    peer_identity: Identity,
+    att_mtu: u16,
+    encrypted: bool,
+}
+
+impl ConnectionContext {
+    pub(crate) fn new<P: PacketPool>(connection: &Connection<'_, P>) -> Self {
+        Self {
+            handle: connection.handle(),
+            peer_identity: connection.peer_identity(),
+            att_mtu: connection.att_mtu(),
+            encrypted: connection.encrypted(),
+        }
+    }
+
+    /// Connection handle of the peer accessing the attribute.
+    pub fn handle(&self) -> ConnHandle {
+        self.handle
+    }
+
+    /// Identity of the peer accessing the attribute.
+    pub fn peer_identity(&self) -> &Identity {
+        &self.peer_identity
+    }
+
+    /// ATT MTU negotiated for the connection.
+    pub fn att_mtu(&self) -> u16 {
+        self.att_mtu
+    }
+
+    /// Whether the link is encrypted.
+    pub fn encrypted(&self) -> bool {
+        self.encrypted
+    }
+}
+
+/// Handler called when a client writes a characteristic value, before the value is stored.
+///
+/// Receives the offset and data of the write. Returning an error rejects the write with that error code,
+/// which may be an application error code created with [`AttErrorCode::application`].
+pub type WriteHandler = fn(&ConnectionContext, usize, &[u8]) -> Result<(), AttErrorCode>;
+
+/// Application provided storage for an attribute value.
+///
+/// A provider is consulted every time a peer reads or writes the attribute, instead of the value being
+/// copied into a buffer owned by the attribute table. This is useful for values that are large or computed
+/// on demand, such as log buffers or live sensor readings.
+///
+/// The provider is called synchronously while the attribute table is locked, so implementations must not
+/// block or access the attribute table.
+pub trait AttributeProvider {
+    /// Read the value starting at `offset` into `data`, returning the number of bytes written.
+    ///
+    /// Returning fewer bytes than `data.len()` signals the end of the value. An `offset` past the end
+    /// of the value should return `AttErrorCode::INVALID_OFFSET`.
+    fn read(&self, ctx: &ConnectionContext, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode>;
+
+    /// Write `data` into the value starting at `offset`.
+    ///
+    /// The default implementation rejects all writes.
+    fn write(&self, ctx: &ConnectionContext, offset: usize, data: &[u8]) -> Result<(), AttErrorCode> {
+        let _ = (ctx, offset, data);
+        Err(AttErrorCode::WRITE_NOT_PERMITTED)
     }
 }
 
@@ -79,29 +168,56 @@ pub(crate) enum AttributeData<'d> {
         variable_len: bool,
         len: u16,
         value: &'d mut [u8],
+        range: Option<ValidRange<'d>>,
+        on_write: Option<WriteHandler>,
     },
     Declaration {
         props: CharacteristicProps,
         handle: u16,
         uuid: Uuid,
     },
+    IncludedService {
+        handle: u16,
+        last_handle_in_group: u16,
+        uuid: Uuid,
+    },
+    ExtendedProperties {
+        value: u16,
+    },
     Cccd {
         notifications: bool,
         indications: bool,
     },
+    /// Client Supported Features, held by the attribute server for each connected client like the CCCDs.
+    ClientFeatures {
+        value: u8,
+    },
+    Provider {
+        props: CharacteristicProps,
+        provider: &'d dyn AttributeProvider,
+    },
+    PerConnection {
+        props: CharacteristicProps,
+        variable_len: bool,
+        values: PerConnectionValues<'d>,
+        range: Option<ValidRange<'d>>,
+        on_write: Option<WriteHandler>,
+    },
 }
 
 impl AttributeData<'_> {
     pub(crate) fn readable(&self) -> bool {
         match self {
-            Self::Data { props, .. } => props.0 & (CharacteristicProp::Read as u8) != 0,
+            Self::Data { props, .. } | Self::Provider { props, .. } | Self::PerConnection { props, .. } => {
+                props.0 & (CharacteristicProp::Read as u8) != 0
+            }
             _ => true,
         }
     }
 
     pub(crate) fn writable(&self) -> bool {
         match self {
-            Self::Data { props, .. } => {
+            Self::Data { props, .. } | Self::Provider { props, .. } | Self::PerConnection { props, .. } => {
                 props.0
                     & (CharacteristicProp::Write as u8
                         | CharacteristicProp::WriteWithoutResponse as u8
@@ -112,11 +228,12 @@ impl AttributeData<'_> {
                 notifications,
                 indications,
             } => true,
+            Self::ClientFeatures { .. } => true,
             _ => false,
         }
     }
 
-    fn read(&self, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
+    fn read(&self, ctx: &ConnectionContext, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
         if !self.readable() {
             return Err(AttErrorCode::READ_NOT_PERMITTED);
         }
@@ -131,12 +248,7 @@ impl AttributeData<'_> {
                 }
                 Ok(len)
             }
-            Self::Data {
-                props,
-                value,
-                variable_len,
-                len,
-            } => {
+            Self::Data { value, len, .. } => {
                 let value = &value[..*len as usize];
                 if offset > value.len() {
                     return Ok(0);
@@ -179,6 +291,17 @@ impl AttributeData<'_> {
                 data[0] = v;
                 Ok(2)
             }
+            Self::ClientFeatures { value } => {
+                let val = [*value];
+                if offset > val.len() {
+                    return Ok(0);
+                }
+                let len = data.len().min(val.len() - offset);
+                if len > 0 {
+                    data[..len].copy_from_slice(&val[offset..offset + len]);
+                }
+                Ok(len)
+            }
             Self::Declaration { props, handle, uuid } => {
                 let val = uuid.as_raw();
                 if offset > val.len() + 3 {
@@ -201,23 +324,87 @@ impl AttributeData<'_> {
                 }
                 Ok(w.len())
             }
+            Self::IncludedService {
+                handle,
+                last_handle_in_group,
+                uuid,
+            } => {
+                let mut val = [0; 6];
+                val[..2].copy_from_slice(&handle.to_le_bytes());
+                val[2..4].copy_from_slice(&last_handle_in_group.to_le_bytes());
+                // The service UUID is only part of the declaration if it is a 16-bit UUID
+                let val = match uuid {
+                    Uuid::Uuid16(raw) => {
+                        val[4..].copy_from_slice(raw);
+                        &val[..]
+                    }
+                    Uuid::Uuid128(_) => &val[..4],
+                };
+                if offset > val.len() {
+                    return Ok(0);
+                }
+                let len = data.len().min(val.len() - offset);
+                if len > 0 {
+                    data[..len].copy_from_slice(&val[offset..offset + len]);
+                }
+                Ok(len)
+            }
+            Self::ExtendedProperties { value } => {
+                let val = value.to_le_bytes();
+                if offset > val.len() {
+                    return Ok(0);
+                }
+                let len = data.len().min(val.len() - offset);
+                if len > 0 {
+                    data[..len].copy_from_slice(&val[offset..offset + len]);
+                }
+                Ok(len)
+            }
+            Self::Provider { provider, .. } => provider.read(ctx, offset, data),
+            Self::PerConnection { values, .. } => {
+                let value = values.value(values.find(ctx.handle()).unwrap_or(0));
+                if offset > value.len() {
+                    return Ok(0);
+                }
+                let len = data.len().min(value.len() - offset);
+                if len > 0 {
+                    data[..len].copy_from_slice(&value[offset..offset + len]);
+                }
+                Ok(len)
+            }
         }
     }
 
-    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), AttErrorCode> {
+    fn write(&mut self, ctx: &ConnectionContext, offset: usize, data: &[u8]) -> Result<(), AttErrorCode> {
         let writable = self.writable();
 
         match self {
             Self::Data {
                 value,
-                props,
-                variable_len,
                 len,
+                range,
+                on_write,
+                ..
             } => {
                 if !writable {
                     return Err(AttErrorCode::WRITE_NOT_PERMITTED);
                 }
 
+                if let Some(range) = range {
+                    // The range can only be validated against a complete value. Prepared writes continuing
+                    // each other are merged by the server, so a long write of the value starts at offset 0.
+                    if offset > 0 {
+                        return Err(AttErrorCode::INVALID_OFFSET);
+                    }
+                    if !range.contains(data) {
+                        return Err(AttErrorCode::OUT_OF_RANGE);
+                    }
+                }
+
+                if let Some(on_write) = on_write {
+                    on_write(ctx, offset, data)?;
+                }
+
                 if offset + data.len() <= value.len() {
                     value[offset..offset + data.len()].copy_from_slice(data);
                     *len = (offset + data.len()) as u16;
@@ -242,6 +429,56 @@ impl AttributeData<'_> {
                 *indications = data[0] & 0x02 != 0;
                 Ok(())
             }
+            Self::ClientFeatures { value } => {
+                if offset > 0 {
+                    return Err(AttErrorCode::INVALID_OFFSET);
+                }
+                // Only the first octet is defined, and a client may not clear a feature it has enabled.
+                let features = data.first().copied().unwrap_or(0);
+                if features & *value != *value {
+                    return Err(AttErrorCode::VALUE_NOT_ALLOWED);
+                }
+                *value = features;
+                Ok(())
+            }
+            Self::Provider { provider, .. } => {
+                if !writable {
+                    return Err(AttErrorCode::WRITE_NOT_PERMITTED);
+                }
+                provider.write(ctx, offset, data)
+            }
+            Self::PerConnection {
+                values,
+                range,
+                on_write,
+                ..
+            } => {
+                if !writable {
+                    return Err(AttErrorCode::WRITE_NOT_PERMITTED);
+                }
+
+                if let Some(range) = range {
+                    if offset > 0 {
+                        return Err(AttErrorCode::INVALID_OFFSET);
+                    }
+                    if !range.contains(data) {
+                        return Err(AttErrorCode::OUT_OF_RANGE);
+                    }
+                }
+
+                if let Some(on_write) = on_write {
+                    on_write(ctx, offset, data)?;
+                }
+
+                let slot = values
+                    .slot_for(ctx.handle())
+                    .ok_or(AttErrorCode::INSUFFICIENT_RESOURCES)?;
+                if values.write(slot, offset, data) {
+                    Ok(())
+                } else {
+                    Err(AttErrorCode::INVALID_OFFSET)
+                }
+            }
             _ => Err(AttErrorCode::WRITE_NOT_PERMITTED),
         }
     }
@@ -348,10 +585,35 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
 
     /// Add a service to the attribute table (group of characteristics)
     pub fn add_service(&mut self, service: Service) -> ServiceBuilder<'_, 'd, M, MAX> {
+        self.add_service_internal(PRIMARY_SERVICE.into(), service)
+    }
+
+    /// Add a secondary service to the attribute table.
+    ///
+    /// Secondary services are not discovered by clients on their own, and are only
+    /// reachable when included from another service using [`ServiceBuilder::include`].
+    pub fn add_secondary_service(&mut self, service: Service) -> ServiceBuilder<'_, 'd, M, MAX> {
+        self.add_service_internal(SECONDARY_SERVICE.into(), service)
+    }
+
+    /// Returns true if the handle is the declaration of a service in this table, which can be included
+    /// from other services.
+    pub fn is_service(&self, handle: u16) -> bool {
+        self.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == handle {
+                    return matches!(att.data, AttributeData::Service { .. });
+                }
+            }
+            false
+        })
+    }
+
+    fn add_service_internal(&mut self, declaration: Uuid, service: Service) -> ServiceBuilder<'_, 'd, M, MAX> {
         let len = self.inner.lock(|i| i.borrow().attributes.len());
         let handle = self.handle;
         self.push(Attribute {
-            uuid: PRIMARY_SERVICE.into(),
+            uuid: declaration,
             handle: 0,
             last_handle_in_group: 0,
             data: AttributeData::Service { uuid: service.uuid },
@@ -364,14 +626,27 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
     }
 
     pub(crate) fn set_raw(&self, attribute: u16, input: &[u8]) -> Result<(), Error> {
+        self.set_raw_for(None, attribute, input)
+    }
+
+    /// Set the raw value of an attribute.
+    ///
+    /// For per-connection characteristics the value of the given connection is set, or the
+    /// initial value if no connection is given. All other attributes ignore the connection.
+    pub(crate) fn set_raw_for(
+        &self,
+        connection: Option<ConnHandle>,
+        attribute: u16,
+        input: &[u8],
+    ) -> Result<(), Error> {
         self.iterate(|mut it| {
             while let Some(att) = it.next() {
                 if att.handle == attribute {
                     if let AttributeData::Data {
-                        props: _,
                         value,
                         variable_len,
                         len,
+                        ..
                     } = &mut att.data
                     {
                         let expected_len = value.len();
@@ -390,6 +665,27 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
                                 actual: actual_len,
                             });
                         }
+                    } else if let AttributeData::Provider { .. } = &att.data {
+                        // The value is owned by the provider and must be changed there.
+                        return Err(Error::NotSupported);
+                    } else if let AttributeData::PerConnection {
+                        values, variable_len, ..
+                    } = &mut att.data
+                    {
+                        let expected_len = values.size;
+                        let actual_len = input.len();
+                        if actual_len > expected_len || (!*variable_len && actual_len != expected_len) {
+                            return Err(Error::UnexpectedDataLength {
+                                expected: expected_len,
+                                actual: actual_len,
+                            });
+                        }
+                        let slot = match connection {
+                            Some(connection) => values.slot_for(connection).ok_or(Error::ConnectionLimitReached)?,
+                            None => 0,
+                        };
+                        values.write(slot, 0, input);
+                        return Ok(());
                     }
                 }
             }
@@ -403,40 +699,89 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
     /// otherwise this function will panic.
     ///
     /// If the characteristic for the handle cannot be found, or the shape of the data does not match the type of the characterstic,
-    /// an error is returned
+    /// an error is returned. Characteristics served by an [`AttributeProvider`] return `Error::NotSupported`.
     pub fn set<T: AttributeHandle>(&self, attribute_handle: &T, input: &T::Value) -> Result<(), Error> {
         let gatt_value = input.as_gatt();
         self.set_raw(attribute_handle.handle(), gatt_value)
     }
 
+    /// Set the value of a characteristic as seen by the given connection.
+    ///
+    /// For characteristics with per-connection storage only the value of this connection is changed,
+    /// for all other characteristics this is the same as [`AttributeTable::set`].
+    ///
+    /// If no storage is left for the connection, `Error::ConnectionLimitReached` is returned.
+    pub fn set_for_connection<T: AttributeHandle, P: PacketPool>(
+        &self,
+        connection: &Connection<'_, P>,
+        attribute_handle: &T,
+        input: &T::Value,
+    ) -> Result<(), Error> {
+        let gatt_value = input.as_gatt();
+        self.set_raw_for(Some(connection.handle()), attribute_handle.handle(), gatt_value)
+    }
+
     /// Read the value of the characteristic and pass the value to the provided closure.
     ///
     /// The return value of the closure is returned in this function and is assumed to be infallible.
     ///
-    /// If the characteristic for the handle cannot be found, an error is returned.
+    /// For characteristics with per-connection storage, the initial value for new connections is returned.
+    ///
+    /// If the characteristic for the handle cannot be found, an error is returned. Characteristics served by an
+    /// [`AttributeProvider`] return `Error::NotSupported`.
     pub fn get<T: AttributeHandle<Value = V>, V: FromGatt>(&self, attribute_handle: &T) -> Result<T::Value, Error> {
+        self.get_for(None, attribute_handle)
+    }
+
+    /// Read the value of the characteristic as seen by the given connection.
+    ///
+    /// For characteristics with per-connection storage this is the value of this connection,
+    /// for all other characteristics this is the same as [`AttributeTable::get`].
+    pub fn get_for_connection<T: AttributeHandle<Value = V>, V: FromGatt, P: PacketPool>(
+        &self,
+        connection: &Connection<'_, P>,
+        attribute_handle: &T,
+    ) -> Result<T::Value, Error> {
+        self.get_for(Some(connection.handle()), attribute_handle)
+    }
+
+    fn get_for<T: AttributeHandle<Value = V>, V: FromGatt>(
+        &self,
+        connection: Option<ConnHandle>,
+        attribute_handle: &T,
+    ) -> Result<T::Value, Error> {
         self.iterate(|mut it| {
             while let Some(att) = it.next() {
                 if att.handle == attribute_handle.handle() {
-                    if let AttributeData::Data {
-                        props,
-                        value,
-                        variable_len,
-                        len,
-                    } = &mut att.data
-                    {
-                        let value_slice = if *variable_len { &value[..*len as usize] } else { value };
-
-                        match T::Value::from_gatt(value_slice) {
-                            Ok(v) => return Ok(v),
-                            Err(_) => {
-                                let mut invalid_data = [0u8; MAX_INVALID_DATA_LEN];
-                                let len_to_copy = value_slice.len().min(MAX_INVALID_DATA_LEN);
-                                invalid_data[..len_to_copy].copy_from_slice(&value_slice[..len_to_copy]);
-
-                                return Err(Error::CannotConstructGattValue(invalid_data));
+                    let value_slice = match &att.data {
+                        AttributeData::Data {
+                            value,
+                            variable_len,
+                            len,
+                            ..
+                        } => {
+                            if *variable_len {
+                                &value[..*len as usize]
+                            } else {
+                                &value[..]
                             }
                         }
+                        AttributeData::PerConnection { values, .. } => {
+                            values.value(connection.and_then(|c| values.find(c)).unwrap_or(0))
+                        }
+                        // The value is owned by the provider and must be read from there.
+                        AttributeData::Provider { .. } => return Err(Error::NotSupported),
+                        _ => continue,
+                    };
+                    match T::Value::from_gatt(value_slice) {
+                        Ok(v) => return Ok(v),
+                        Err(_) => {
+                            let mut invalid_data = [0u8; MAX_INVALID_DATA_LEN];
+                            let len_to_copy = value_slice.len().min(MAX_INVALID_DATA_LEN);
+                            invalid_data[..len_to_copy].copy_from_slice(&value_slice[..len_to_copy]);
+
+                            return Err(Error::CannotConstructGattValue(invalid_data));
+                        }
                     }
                 }
             }
@@ -444,6 +789,17 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
         })
     }
 
+    /// Release the per-connection values held for a connection, so the storage can be reused.
+    pub(crate) fn release_connection(&self, connection: ConnHandle) {
+        self.with_inner(|inner| {
+            for att in inner.attributes.iter_mut() {
+                if let AttributeData::PerConnection { values, .. } = &mut att.data {
+                    values.release(connection);
+                }
+            }
+        });
+    }
+
     /// Return the characteristic which corresponds to the supplied value handle
     ///
     /// If no characteristic corresponding to the given value handle was found, returns an error
@@ -509,6 +865,37 @@ pub struct ServiceBuilder<'r, 'd, M: RawMutex, const MAX: usize> {
 }
 
 impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
+    /// Include another service in this service.
+    ///
+    /// The included service is referenced by the handle returned from [`ServiceBuilder::build`], and
+    /// must have been added to the table before this service. Includes should be added before any
+    /// characteristics of this service.
+    ///
+    /// Returns the handle of the include declaration.
+    pub fn include(&mut self, service: u16) -> Result<u16, Error> {
+        let (last_handle_in_group, uuid) = self.table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == service {
+                    if let AttributeData::Service { uuid } = &att.data {
+                        return Ok((att.last_handle_in_group, uuid.clone()));
+                    }
+                    break;
+                }
+            }
+            Err(Error::NotFound)
+        })?;
+        Ok(self.table.push(Attribute {
+            uuid: INCLUDE.into(),
+            handle: 0,
+            last_handle_in_group: 0,
+            data: AttributeData::IncludedService {
+                handle: service,
+                last_handle_in_group,
+                uuid,
+            },
+        }))
+    }
+
     fn add_characteristic_internal<T: AsGatt>(
         &mut self,
         uuid: Uuid,
@@ -553,12 +940,25 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
             None
         };
 
+        // Add extended properties descriptor, the properties can be set through the builder
+        let extended_properties = if props.any(&[CharacteristicProp::Extended]) {
+            Some(self.table.push(Attribute {
+                uuid: CHARACTERISTIC_EXTENDED_PROPERTIES.into(),
+                handle: 0,
+                last_handle_in_group: 0,
+                data: AttributeData::ExtendedProperties { value: 0 },
+            }))
+        } else {
+            None
+        };
+
         CharacteristicBuilder {
             handle: Characteristic {
                 handle: next,
                 cccd_handle,
                 phantom: PhantomData,
             },
+            extended_properties,
             table: self.table,
         }
     }
@@ -584,10 +984,22 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
                 value: store,
                 variable_len,
                 len,
+                range: None,
+                on_write: None,
             },
         )
     }
 
+    /// Add the Client Supported Features characteristic, whose value is held by the attribute server for each client.
+    pub(crate) fn add_client_supported_features(&mut self) -> CharacteristicBuilder<'_, 'd, u8, M, MAX> {
+        let props = [CharacteristicProp::Read, CharacteristicProp::Write].into();
+        self.add_characteristic_internal(
+            CLIENT_SUPPORTED_FEATURES.into(),
+            props,
+            AttributeData::ClientFeatures { value: 0 },
+        )
+    }
+
     /// Add a characteristic to this service with a refererence to an immutable storage buffer.
     pub fn add_characteristic_ro<T: AsGatt, U: Into<Uuid>>(
         &mut self,
@@ -605,6 +1017,53 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
         )
     }
 
+    /// Add a characteristic to this service which holds a separate value for each connection.
+    ///
+    /// Every connection starts out with the provided value, and reads, writes and notifications act on
+    /// the value of that connection only. The store holds the initial value and the values of up to
+    /// `connections` connections, and must be at least [`per_connection_store_size`] bytes long.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the store is too small for the given number of connections.
+    pub fn add_characteristic_per_connection<T: AsGatt, U: Into<Uuid>>(
+        &mut self,
+        uuid: U,
+        props: &[CharacteristicProp],
+        value: T,
+        connections: usize,
+        store: &'d mut [u8],
+    ) -> CharacteristicBuilder<'_, 'd, T, M, MAX> {
+        let props = props.into();
+        let variable_len = T::MAX_SIZE != T::MIN_SIZE;
+        self.add_characteristic_internal(
+            uuid.into(),
+            props,
+            AttributeData::PerConnection {
+                props,
+                variable_len,
+                values: PerConnectionValues::new(value.as_gatt(), T::MAX_SIZE, connections, store),
+                range: None,
+                on_write: None,
+            },
+        )
+    }
+
+    /// Add a characteristic to this service whose value is served by an application provided [`AttributeProvider`].
+    ///
+    /// Reads and writes from peers are forwarded to the provider. The value cannot be read or set through the
+    /// attribute table, which returns `Error::NotSupported`, but notifications can still be sent with
+    /// [`Characteristic::notify`].
+    pub fn add_characteristic_provider<T: AsGatt, U: Into<Uuid>>(
+        &mut self,
+        uuid: U,
+        props: &[CharacteristicProp],
+        provider: &'d dyn AttributeProvider,
+    ) -> CharacteristicBuilder<'_, 'd, T, M, MAX> {
+        let props = props.into();
+        self.add_characteristic_internal(uuid.into(), props, AttributeData::Provider { props, provider })
+    }
+
     /// Finish construction of the service and return a handle.
     pub fn build(self) -> u16 {
         self.handle
@@ -639,22 +1098,20 @@ pub struct Characteristic<T: AsGatt> {
 impl<T: FromGatt> Characteristic<T> {
     /// Write a value to a characteristic, and notify a connection with the new value of the characteristic.
     ///
+    /// For characteristics with per-connection storage, only the value of the notified connection is written.
+    ///
     /// If the provided connection has not subscribed for this characteristic, it will not be notified.
     ///
     /// If the characteristic does not support notifications, an error is returned.
     pub async fn notify<P: PacketPool>(&self, connection: &GattConnection<'_, '_, P>, value: &T) -> Result<(), Error> {
         let value = value.as_gatt();
-        let server = connection.server;
-        server.set(self.handle, value)?;
-
-        let cccd_handle = self.cccd_handle.ok_or(Error::NotFound)?;
-        let connection = connection.raw();
-        if !server.should_notify(connection, cccd_handle) {
+        if !self.set_notified(connection, value)? {
             // No reason to fail?
             return Ok(());
         }
+        let connection = connection.raw();
 
-        let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
+        let mut tx = P::allocate_async().await;
         let mut w = WriteCursor::new(tx.as_mut());
         let (mut header, mut data) = w.split(4)?;
         data.write(crate::att::ATT_HANDLE_VALUE_NTF)?;
@@ -670,6 +1127,48 @@ impl<T: FromGatt> Characteristic<T> {
         Ok(())
     }
 
+    /// Write a value to a characteristic, and notify a connection with the new value of the characteristic
+    /// over an Enhanced ATT bearer.
+    ///
+    /// Behaves like [`Characteristic::notify`], except that the notification is sent on the provided bearer,
+    /// and is truncated to the MTU of the bearer.
+    pub async fn notify_eatt<C: Controller, P: PacketPool>(
+        &self,
+        connection: &GattConnection<'_, '_, P>,
+        stack: &Stack<'_, C, P>,
+        bearer: &mut L2capChannel<'_, P>,
+        value: &T,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let value = value.as_gatt();
+        if !self.set_notified(connection, value)? {
+            return Ok(());
+        }
+
+        let mtu = stack.host.channels.send_mtu(bearer.index())? as usize;
+        let mut sdu = TxSdu::<P>::allocate_async().await;
+        let mut w = WriteCursor::new(sdu.buffer_mut());
+        w.write(crate::att::ATT_HANDLE_VALUE_NTF)?;
+        w.write(self.handle)?;
+        w.append(&value[..value.len().min(mtu.saturating_sub(3))])?;
+        let len = w.len();
+        sdu.set_len(len)?;
+        bearer.send_sdu(stack, sdu).await
+    }
+
+    /// Write the notified value, and check if the connection has subscribed to notifications.
+    fn set_notified<P: PacketPool>(&self, connection: &GattConnection<'_, '_, P>, value: &[u8]) -> Result<bool, Error> {
+        let server = connection.server;
+        let connection = connection.raw();
+        match server.set(connection, self.handle, value) {
+            // Values served by a provider are not stored in the table, but can still be notified.
+            Ok(()) | Err(Error::NotSupported) => {}
+            Err(e) => return Err(e),
+        }
+
+        let cccd_handle = self.cccd_handle.ok_or(Error::NotFound)?;
+        Ok(server.should_notify(connection, cccd_handle))
+    }
+
     /// Set the value of the characteristic in the provided attribute server.
     pub fn set<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
         &self,
@@ -712,6 +1211,7 @@ impl AttributeHandle for CharacteristicPropertiesHandle {
 /// Builder for characteristics.
 pub struct CharacteristicBuilder<'r, 'd, T: AsGatt, M: RawMutex, const MAX: usize> {
     handle: Characteristic<T>,
+    extended_properties: Option<u16>,
     table: &'r mut AttributeTable<'d, M, MAX>,
 }
 
@@ -753,6 +1253,8 @@ impl<'d, T: AsGatt, M: RawMutex, const MAX: usize> CharacteristicBuilder<'_, 'd,
                 value: data,
                 variable_len: false,
                 len,
+                range: None,
+                on_write: None,
             },
         )
     }
@@ -763,12 +1265,308 @@ impl<'d, T: AsGatt, M: RawMutex, const MAX: usize> CharacteristicBuilder<'_, 'd,
         self.add_descriptor_internal(uuid.into(), props, AttributeData::ReadOnlyData { props, value: data })
     }
 
+    /// Set extended properties for this characteristic.
+    ///
+    /// The Characteristic Extended Properties descriptor is added if not already present, and the
+    /// extended properties bit is set in the characteristic declaration. Properties are added to
+    /// any previously set properties.
+    pub fn add_extended_properties(&mut self, props: &[CharacteristicExtendedProp]) -> Descriptor<u16> {
+        let bits = props.iter().fold(0, |bits, prop| bits | *prop as u16);
+        let handle = match self.extended_properties {
+            Some(handle) => handle,
+            None => {
+                let handle = self.table.push(Attribute {
+                    uuid: CHARACTERISTIC_EXTENDED_PROPERTIES.into(),
+                    handle: 0,
+                    last_handle_in_group: 0,
+                    data: AttributeData::ExtendedProperties { value: 0 },
+                });
+                self.extended_properties = Some(handle);
+                handle
+            }
+        };
+
+        let declaration = self.handle.handle - 1;
+        self.table.with_inner(|inner| {
+            for att in inner.attributes.iter_mut() {
+                if att.handle == declaration {
+                    if let AttributeData::Declaration { props, .. } = &mut att.data {
+                        props.0 |= CharacteristicProp::Extended as u8;
+                    }
+                } else if att.handle == handle {
+                    if let AttributeData::ExtendedProperties { value } = &mut att.data {
+                        *value |= bits;
+                    }
+                }
+            }
+        });
+
+        Descriptor {
+            handle,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Add a Characteristic User Description descriptor for this characteristic.
+    ///
+    /// The store must be large enough to hold the description, and any description written by a client
+    /// if `writable` is set. Writable descriptions also set the writable auxiliaries extended property.
+    ///
+    /// Returns `Error::InsufficientSpace` if the description does not fit in the store.
+    pub fn add_user_description(
+        &mut self,
+        description: &str,
+        writable: bool,
+        store: &'d mut [u8],
+    ) -> Result<Descriptor<&'static [u8]>, Error> {
+        if description.len() > store.len() {
+            return Err(Error::InsufficientSpace);
+        }
+        if writable {
+            self.add_extended_properties(&[CharacteristicExtendedProp::WritableAuxiliaries]);
+        }
+
+        let props: CharacteristicProps = if writable {
+            [CharacteristicProp::Read, CharacteristicProp::Write].into()
+        } else {
+            [CharacteristicProp::Read].into()
+        };
+        store[..description.len()].copy_from_slice(description.as_bytes());
+        let len = description.len() as u16;
+        Ok(self.add_descriptor_internal(
+            CHARACTERISTIC_USER_DESCRIPTION.into(),
+            props,
+            AttributeData::Data {
+                props,
+                value: store,
+                variable_len: true,
+                len,
+                range: None,
+                on_write: None,
+            },
+        ))
+    }
+
+    /// Add a Characteristic Presentation Format descriptor for this characteristic.
+    pub fn add_presentation_format(&mut self, format: &'d PresentationFormat) -> Descriptor<PresentationFormat> {
+        let props = [CharacteristicProp::Read].into();
+        self.add_descriptor_internal(
+            CHARACTERISTIC_PRESENTATION_FORMAT.into(),
+            props,
+            AttributeData::ReadOnlyData {
+                props,
+                value: FixedGattValue::as_gatt(format),
+            },
+        )
+    }
+
+    /// Add a Characteristic Aggregate Format descriptor for this characteristic.
+    ///
+    /// The store must hold 2 bytes for every presentation format, otherwise `Error::InsufficientSpace` is returned.
+    pub fn add_aggregate_format(
+        &mut self,
+        formats: &[Descriptor<PresentationFormat>],
+        store: &'d mut [u8],
+    ) -> Result<Descriptor<&'static [u8]>, Error> {
+        let len = formats.len() * 2;
+        if len > store.len() {
+            return Err(Error::InsufficientSpace);
+        }
+        for (format, chunk) in formats.iter().zip(store[..len].chunks_exact_mut(2)) {
+            chunk.copy_from_slice(&format.handle.to_le_bytes());
+        }
+        let props = [CharacteristicProp::Read].into();
+        Ok(self.add_descriptor_internal(
+            CHARACTERISTIC_AGGREGATE_FORMAT.into(),
+            props,
+            AttributeData::ReadOnlyData {
+                props,
+                value: &store[..len],
+            },
+        ))
+    }
+
+    /// Add a Valid Range descriptor for this characteristic.
+    ///
+    /// Writes from clients with values outside of the range are rejected with `AttErrorCode::OUT_OF_RANGE`.
+    /// The store must be large enough to hold both the minimum and maximum value.
+    pub fn add_valid_range(&mut self, min: &T, max: &T, store: &'d mut [u8]) -> Descriptor<&'static [u8]>
+    where
+        T: FromGatt + PartialOrd,
+    {
+        let min = min.as_gatt();
+        let max = max.as_gatt();
+        let len = min.len() + max.len();
+        store[..min.len()].copy_from_slice(min);
+        store[min.len()..len].copy_from_slice(max);
+        let value: &'d [u8] = &store[..len];
+        let range = ValidRange {
+            min: &value[..min.len()],
+            max: &value[min.len()..],
+            check: ValidRange::check::<T>,
+        };
+
+        let handle = self.handle.handle;
+        self.table.with_inner(|inner| {
+            for att in inner.attributes.iter_mut() {
+                if att.handle == handle {
+                    if let AttributeData::Data { range: r, .. } | AttributeData::PerConnection { range: r, .. } =
+                        &mut att.data
+                    {
+                        *r = Some(range);
+                    }
+                }
+            }
+        });
+
+        let props = [CharacteristicProp::Read].into();
+        self.add_descriptor_internal(VALID_RANGE.into(), props, AttributeData::ReadOnlyData { props, value })
+    }
+
+    /// Set a handler called when a client writes the characteristic value.
+    ///
+    /// The handler is called after the value has been checked against the valid range, if any, and the
+    /// value is only stored if the handler accepts it. Has no effect on characteristics using a provider.
+    pub fn set_write_handler(&mut self, handler: WriteHandler) {
+        let handle = self.handle.handle;
+        self.table.with_inner(|inner| {
+            for att in inner.attributes.iter_mut() {
+                if att.handle == handle {
+                    if let AttributeData::Data { on_write, .. } | AttributeData::PerConnection { on_write, .. } =
+                        &mut att.data
+                    {
+                        *on_write = Some(handler);
+                    }
+                }
+            }
+        });
+    }
+
     /// Return the built characteristic.
     pub fn build(self) -> Characteristic<T> {
         self.handle
     }
 }
 
+/// Valid range of a characteristic value, checked when written by a client.
+#[derive(Clone, Copy)]
+pub(crate) struct ValidRange<'d> {
+    min: &'d [u8],
+    max: &'d [u8],
+    check: fn(&[u8], &[u8], &[u8]) -> bool,
+}
+
+impl ValidRange<'_> {
+    fn contains(&self, value: &[u8]) -> bool {
+        (self.check)(self.min, self.max, value)
+    }
+
+    fn check<T: FromGatt + PartialOrd>(min: &[u8], max: &[u8], value: &[u8]) -> bool {
+        match (T::from_gatt(min), T::from_gatt(max), T::from_gatt(value)) {
+            (Ok(min), Ok(max), Ok(value)) => min <= value && value <= max,
+            _ => false,
+        }
+    }
+}
+
+/// Size of the header in front of each per-connection value: the connection handle and the value length.
+const PER_CONNECTION_HEADER_LEN: usize = 4;
+/// Connection handle marking a per-connection value as unused, outside of the valid range of handles.
+const PER_CONNECTION_UNUSED: u16 = 0xffff;
+
+/// Returns the size of the store needed for a per-connection characteristic.
+///
+/// `size` is the maximum size of the value, `CONN_MAX` the number of connections which can hold a value.
+pub const fn per_connection_store_size(size: usize, conn_max: usize) -> usize {
+    (conn_max + 1) * (PER_CONNECTION_HEADER_LEN + size)
+}
+
+/// Values of a characteristic held per connection.
+///
+/// The store is split in slots of equal size, each holding the owning connection handle, the value length
+/// and the value. The first slot holds the initial value for new connections.
+pub(crate) struct PerConnectionValues<'d> {
+    size: usize,
+    store: &'d mut [u8],
+}
+
+impl<'d> PerConnectionValues<'d> {
+    fn new(initial: &[u8], size: usize, connections: usize, store: &'d mut [u8]) -> Self {
+        let len = per_connection_store_size(size, connections);
+        assert!(store.len() >= len, "per-connection store too small");
+        let mut values = Self {
+            size,
+            store: &mut store[..len],
+        };
+        for slot in values.store.chunks_exact_mut(PER_CONNECTION_HEADER_LEN + size) {
+            slot[..2].copy_from_slice(&PER_CONNECTION_UNUSED.to_le_bytes());
+            slot[2..4].copy_from_slice(&0u16.to_le_bytes());
+        }
+        values.write(0, 0, initial);
+        values
+    }
+
+    fn slot_len(&self) -> usize {
+        PER_CONNECTION_HEADER_LEN + self.size
+    }
+
+    fn slots(&self) -> usize {
+        self.store.len() / self.slot_len()
+    }
+
+    fn owner(&self, slot: usize) -> u16 {
+        let start = slot * self.slot_len();
+        u16::from_le_bytes([self.store[start], self.store[start + 1]])
+    }
+
+    /// Find the slot holding the value of a connection.
+    fn find(&self, connection: ConnHandle) -> Option<usize> {
+        (1..self.slots()).find(|slot| self.owner(*slot) == connection.raw())
+    }
+
+    /// Find the slot holding the value of a connection, or assign a free slot starting out with the initial value.
+    fn slot_for(&mut self, connection: ConnHandle) -> Option<usize> {
+        if let Some(slot) = self.find(connection) {
+            return Some(slot);
+        }
+        let slot = (1..self.slots()).find(|slot| self.owner(*slot) == PER_CONNECTION_UNUSED)?;
+        let len = self.slot_len();
+        let start = slot * len;
+        self.store.copy_within(0..len, start);
+        self.store[start..start + 2].copy_from_slice(&connection.raw().to_le_bytes());
+        Some(slot)
+    }
+
+    fn release(&mut self, connection: ConnHandle) {
+        if let Some(slot) = self.find(connection) {
+            let start = slot * self.slot_len();
+            self.store[start..start + 2].copy_from_slice(&PER_CONNECTION_UNUSED.to_le_bytes());
+        }
+    }
+
+    fn value(&self, slot: usize) -> &[u8] {
+        let start = slot * self.slot_len();
+        let len = u16::from_le_bytes([self.store[start + 2], self.store[start + 3]]) as usize;
+        let start = start + PER_CONNECTION_HEADER_LEN;
+        &self.store[start..start + len]
+    }
+
+    /// Write to the value in a slot, the value ends after the written data.
+    ///
+    /// Returns false if the data does not fit in the value.
+    fn write(&mut self, slot: usize, offset: usize, data: &[u8]) -> bool {
+        if offset + data.len() > self.size {
+            return false;
+        }
+        let start = slot * self.slot_len();
+        let len = (offset + data.len()) as u16;
+        self.store[start + 2..start + 4].copy_from_slice(&len.to_le_bytes());
+        let start = start + PER_CONNECTION_HEADER_LEN + offset;
+        self.store[start..start + data.len()].copy_from_slice(data);
+        true
+    }
+}
+
 /// Characteristic descriptor handle.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Clone, Copy, Debug)]
@@ -818,7 +1616,8 @@ impl Service {
 }
 
 /// Properties of a characteristic.
-#[derive(Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct CharacteristicProps(u8);
 
 impl<'a> From<&'a [CharacteristicProp]> for CharacteristicProps {
@@ -869,6 +1668,67 @@ impl FixedGattValue for CharacteristicProps {
     }
 }
 
+/// Value of a Characteristic Presentation Format descriptor.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PresentationFormat([u8; 7]);
+
+impl PresentationFormat {
+    /// Create a new presentation format.
+    ///
+    /// The format, unit, namespace and description values are assigned numbers defined by the Bluetooth SIG.
+    pub const fn new(format: u8, exponent: i8, unit: u16, namespace: u8, description: u16) -> Self {
+        let unit = unit.to_le_bytes();
+        let description = description.to_le_bytes();
+        Self([
+            format,
+            exponent as u8,
+            unit[0],
+            unit[1],
+            namespace,
+            description[0],
+            description[1],
+        ])
+    }
+
+    /// Format of the characteristic value.
+    pub fn format(&self) -> u8 {
+        self.0[0]
+    }
+
+    /// Exponent applied to integer characteristic values.
+    pub fn exponent(&self) -> i8 {
+        self.0[1] as i8
+    }
+
+    /// Unit of the characteristic value.
+    pub fn unit(&self) -> u16 {
+        u16::from_le_bytes([self.0[2], self.0[3]])
+    }
+
+    /// Namespace of the description.
+    pub fn namespace(&self) -> u8 {
+        self.0[4]
+    }
+
+    /// Description of the characteristic value within the namespace.
+    pub fn description(&self) -> u16 {
+        u16::from_le_bytes([self.0[5], self.0[6]])
+    }
+}
+
+impl FixedGattValue for PresentationFormat {
+    const SIZE: usize = 7;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        data.try_into().map(Self).map_err(|_| FromGattError::InvalidLength)
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
 /// A value of an attribute.
 pub struct AttributeValue<'d, M: RawMutex> {
     value: Mutex<M, &'d mut [u8]>,
@@ -1,20 +1,33 @@
 use core::cell::RefCell;
 use core::marker::PhantomData;
 
+use bt_hci::param::ConnHandle;
+use bt_hci::uuid::declarations::{PRIMARY_SERVICE, SECONDARY_SERVICE};
 use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::blocking_mutex::Mutex;
+use heapless::Vec;
 
 use crate::att::{self, AttClient, AttCmd, AttErrorCode, AttReq};
-use crate::attribute::{Attribute, AttributeData, AttributeTable, CCCD};
+use crate::attribute::{Attribute, AttributeData, AttributeTable, ConnectionContext, CCCD};
+use crate::config::GATT_SERVER_PREPARE_WRITE_QUEUE_SIZE;
 use crate::cursor::WriteCursor;
 use crate::prelude::Connection;
 use crate::types::uuid::Uuid;
 use crate::{codec, Error, Identity, PacketPool};
 
+/// Maximum length of a single value in a Read By Type response, limited by the one byte length field.
+const MAX_READ_BY_TYPE_VALUE_LEN: usize = 255 - 2;
+/// Maximum length of a single value in a Read By Group Type response, limited by the one byte length field.
+const MAX_READ_BY_GROUP_TYPE_VALUE_LEN: usize = 255 - 4;
+/// Size of the header in front of each prepared write: the attribute handle, the offset and the value length.
+const PREPARED_WRITE_HEADER_LEN: usize = 6;
+
 #[derive(Default)]
 struct Client {
     identity: Identity,
     is_connected: bool,
+    /// Value of the Client Supported Features characteristic written by the client.
+    features: u8,
 }
 
 impl Client {
@@ -145,6 +158,7 @@ impl<M: RawMutex, const CCCD_MAX: usize, const CONN_MAX: usize> CccdTables<M, CC
                     client.set_identity(*peer_identity);
                     // erase the previous client's config
                     table.disable_all();
+                    client.features = 0;
                     return Ok(());
                 }
             }
@@ -203,6 +217,30 @@ impl<M: RawMutex, const CCCD_MAX: usize, const CONN_MAX: usize> CccdTables<M, CC
         })
     }
 
+    fn client_features(&self, peer_identity: &Identity) -> u8 {
+        self.state.lock(|n| {
+            let n = n.borrow();
+            for (client, _) in n.iter() {
+                if client.identity.match_identity(peer_identity) {
+                    return client.features;
+                }
+            }
+            0
+        })
+    }
+
+    fn set_client_features(&self, peer_identity: &Identity, features: u8) {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            for (client, _) in n.iter_mut() {
+                if client.identity.match_identity(peer_identity) {
+                    client.features = features;
+                    break;
+                }
+            }
+        })
+    }
+
     fn get_cccd_table(&self, peer_identity: &Identity) -> Option<CccdTable<CCCD_MAX>> {
         self.state.lock(|n| {
             let n = n.borrow();
@@ -242,6 +280,138 @@ impl<M: RawMutex, const CCCD_MAX: usize, const CONN_MAX: usize> CccdTables<M, CC
     }
 }
 
+/// Writes prepared by a client, waiting to be executed.
+///
+/// Each write is stored as the attribute handle, offset and value length followed by the value. A write
+/// which continues where the previous write to the same attribute ended is merged into it, so the value
+/// of a long write is applied at once.
+#[derive(Default)]
+struct PreparedWrites {
+    connection: Option<ConnHandle>,
+    data: Vec<u8, GATT_SERVER_PREPARE_WRITE_QUEUE_SIZE>,
+    last: Option<usize>,
+}
+
+impl PreparedWrites {
+    fn header(&self, pos: usize) -> (u16, u16, usize) {
+        let d = &self.data[pos..pos + PREPARED_WRITE_HEADER_LEN];
+        (
+            u16::from_le_bytes([d[0], d[1]]),
+            u16::from_le_bytes([d[2], d[3]]),
+            u16::from_le_bytes([d[4], d[5]]) as usize,
+        )
+    }
+
+    fn push(&mut self, handle: u16, offset: u16, value: &[u8]) -> Result<(), AttErrorCode> {
+        if let Some(last) = self.last {
+            let (last_handle, last_offset, len) = self.header(last);
+            if last_handle == handle && last_offset as usize + len == offset as usize {
+                self.data
+                    .extend_from_slice(value)
+                    .map_err(|_| AttErrorCode::PREPARE_QUEUE_FULL)?;
+                let len = (len + value.len()) as u16;
+                self.data[last + 4..last + 6].copy_from_slice(&len.to_le_bytes());
+                return Ok(());
+            }
+        }
+        if self.data.capacity() - self.data.len() < PREPARED_WRITE_HEADER_LEN + value.len() {
+            return Err(AttErrorCode::PREPARE_QUEUE_FULL);
+        }
+        let pos = self.data.len();
+        for field in [handle, offset, value.len() as u16] {
+            self.data
+                .extend_from_slice(&field.to_le_bytes())
+                .map_err(|_| AttErrorCode::PREPARE_QUEUE_FULL)?;
+        }
+        self.data
+            .extend_from_slice(value)
+            .map_err(|_| AttErrorCode::PREPARE_QUEUE_FULL)?;
+        self.last = Some(pos);
+        Ok(())
+    }
+
+    /// Apply the prepared writes in order, stopping at the first write that fails.
+    fn apply<F: FnMut(u16, u16, &[u8]) -> Result<(), AttErrorCode>>(
+        &self,
+        mut f: F,
+    ) -> Result<(), (u16, AttErrorCode)> {
+        let mut pos = 0;
+        while pos < self.data.len() {
+            let (handle, offset, len) = self.header(pos);
+            let start = pos + PREPARED_WRITE_HEADER_LEN;
+            f(handle, offset, &self.data[start..start + len]).map_err(|e| (handle, e))?;
+            pos = start + len;
+        }
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        self.connection = None;
+        self.data.clear();
+        self.last = None;
+    }
+}
+
+/// Prepared write queues of the connected clients.
+struct PrepareWriteQueues<M: RawMutex, const CONN_MAX: usize> {
+    state: Mutex<M, RefCell<[PreparedWrites; CONN_MAX]>>,
+}
+
+impl<M: RawMutex, const CONN_MAX: usize> PrepareWriteQueues<M, CONN_MAX> {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(core::array::from_fn(|_| PreparedWrites::default()))),
+        }
+    }
+
+    fn prepare(&self, connection: ConnHandle, handle: u16, offset: u16, value: &[u8]) -> Result<(), AttErrorCode> {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            let queue = match n.iter().position(|q| q.connection == Some(connection)) {
+                Some(i) => &mut n[i],
+                None => {
+                    let queue = n
+                        .iter_mut()
+                        .find(|q| q.connection.is_none())
+                        .ok_or(AttErrorCode::PREPARE_QUEUE_FULL)?;
+                    queue.connection = Some(connection);
+                    queue
+                }
+            };
+            queue.push(handle, offset, value)
+        })
+    }
+
+    /// Apply the prepared writes of a connection, and remove them from the queue.
+    fn execute<F: FnMut(u16, u16, &[u8]) -> Result<(), AttErrorCode>>(
+        &self,
+        connection: ConnHandle,
+        f: F,
+    ) -> Result<(), (u16, AttErrorCode)> {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            match n.iter_mut().find(|q| q.connection == Some(connection)) {
+                Some(queue) => {
+                    let result = queue.apply(f);
+                    queue.release();
+                    result
+                }
+                None => Ok(()),
+            }
+        })
+    }
+
+    /// Discard the prepared writes of a connection.
+    fn cancel(&self, connection: ConnHandle) {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            if let Some(queue) = n.iter_mut().find(|q| q.connection == Some(connection)) {
+                queue.release();
+            }
+        })
+    }
+}
+
 /// A GATT server capable of processing the GATT protocol using the provided table of attributes.
 pub struct AttributeServer<
     'values,
@@ -253,6 +423,7 @@ pub struct AttributeServer<
 > {
     att_table: AttributeTable<'values, M, ATT_MAX>,
     cccd_tables: CccdTables<M, CCCD_MAX, CONN_MAX>,
+    prepared_writes: PrepareWriteQueues<M, CONN_MAX>,
     _p: PhantomData<P>,
 }
 
@@ -267,9 +438,10 @@ pub(crate) mod sealed {
             connection: &Connection<'_, P>,
             packet: &AttClient,
             rx: &mut [u8],
+            mtu: u16,
         ) -> Result<Option<usize>, Error>;
         fn should_notify(&self, connection: &Connection<'_, P>, cccd_handle: u16) -> bool;
-        fn set(&self, characteristic: u16, input: &[u8]) -> Result<(), Error>;
+        fn set(&self, connection: &Connection<'_, P>, characteristic: u16, input: &[u8]) -> Result<(), Error>;
         fn update_identity(&self, identity: Identity) -> Result<(), Error>;
     }
 }
@@ -290,6 +462,8 @@ impl<M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX: usize, co
 
     fn disconnect(&self, connection: &Connection<'_, P>) {
         self.cccd_tables.disconnect(&connection.peer_identity());
+        self.att_table.release_connection(connection.handle());
+        self.prepared_writes.cancel(connection.handle());
     }
 
     fn process(
@@ -297,8 +471,9 @@ impl<M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX: usize, co
         connection: &Connection<'_, P>,
         packet: &AttClient,
         rx: &mut [u8],
+        mtu: u16,
     ) -> Result<Option<usize>, Error> {
-        let res = AttributeServer::process(self, connection, packet, rx)?;
+        let res = AttributeServer::process_with_mtu(self, connection, packet, rx, mtu)?;
         Ok(res)
     }
 
@@ -306,8 +481,9 @@ impl<M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX: usize, co
         AttributeServer::should_notify(self, connection, cccd_handle)
     }
 
-    fn set(&self, characteristic: u16, input: &[u8]) -> Result<(), Error> {
-        self.att_table.set_raw(characteristic, input)
+    fn set(&self, connection: &Connection<'_, P>, characteristic: u16, input: &[u8]) -> Result<(), Error> {
+        self.att_table
+            .set_raw_for(Some(connection.handle()), characteristic, input)
     }
 
     fn update_identity(&self, identity: Identity) -> Result<(), Error> {
@@ -326,6 +502,7 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         AttributeServer {
             att_table,
             cccd_tables,
+            prepared_writes: PrepareWriteQueues::new(),
             _p: PhantomData,
         }
     }
@@ -345,15 +522,19 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         att: &mut Attribute<'values>,
         data: &mut [u8],
     ) -> Result<usize, AttErrorCode> {
+        let ctx = ConnectionContext::new(connection);
         if let AttributeData::Cccd { .. } = att.data {
             // CCCD values for each connected client are held in the CCCD tables:
             // the value is written back into att.data so att.read() has the final
             // say when parsing at the requested offset.
-            if let Some(value) = self.cccd_tables.get_value(&connection.peer_identity(), att.handle) {
-                let _ = att.write(0, value.as_slice());
+            if let Some(value) = self.cccd_tables.get_value(ctx.peer_identity(), att.handle) {
+                let _ = att.write(&ctx, 0, value.as_slice());
             }
         }
-        att.read(offset, data)
+        if let AttributeData::ClientFeatures { value } = &mut att.data {
+            *value = self.cccd_tables.client_features(ctx.peer_identity());
+        }
+        att.read(&ctx, offset, data)
     }
 
     fn write_attribute_data(
@@ -363,7 +544,12 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         att: &mut Attribute<'values>,
         data: &[u8],
     ) -> Result<(), AttErrorCode> {
-        let err = att.write(offset, data);
+        let ctx = ConnectionContext::new(connection);
+        // The features enabled by this client are checked against the new value
+        if let AttributeData::ClientFeatures { value } = &mut att.data {
+            *value = self.cccd_tables.client_features(ctx.peer_identity());
+        }
+        let err = att.write(&ctx, offset, data);
         if err.is_ok() {
             if let AttributeData::Cccd {
                 notifications,
@@ -373,6 +559,9 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
                 self.cccd_tables
                     .set_notify(&connection.peer_identity(), att.handle, notifications);
             }
+            if let AttributeData::ClientFeatures { value } = att.data {
+                self.cccd_tables.set_client_features(ctx.peer_identity(), value);
+            }
         }
         err
     }
@@ -381,12 +570,14 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         &self,
         connection: &Connection<'_, P>,
         buf: &mut [u8],
+        mtu: u16,
         start: u16,
         end: u16,
         attribute_type: &Uuid,
     ) -> Result<usize, codec::Error> {
         let mut handle = start;
-        let mut data = WriteCursor::new(buf);
+        let mtu = buf.len().min(mtu as usize);
+        let mut data = WriteCursor::new(&mut buf[..mtu]);
 
         let (mut header, mut body) = data.split(2)?;
         let err = self.att_table.iterate(|mut it| {
@@ -394,16 +585,39 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
             while let Some(att) = it.next() {
                 // trace!("[read_by_type] Check attribute {:?} {}", att.uuid, att.handle);
                 if &att.uuid == attribute_type && att.handle >= start && att.handle <= end {
+                    // All entries in the response must have the same length, so subsequent
+                    // entries get one spare byte to detect a longer value.
+                    let limit = match err {
+                        Ok(len) => len + 1,
+                        Err(_) => MAX_READ_BY_TYPE_VALUE_LEN,
+                    };
+                    if err.is_ok() && body.available() < 2 + limit {
+                        break;
+                    }
+                    let pos = body.len();
                     body.write(att.handle)?;
-                    handle = att.handle;
 
-                    err = self.read_attribute_data(connection, 0, att, body.write_buf());
-                    if let Ok(len) = err {
-                        body.commit(len)?;
+                    let buf = body.write_buf();
+                    let limit = limit.min(buf.len());
+                    match self.read_attribute_data(connection, 0, att, &mut buf[..limit]) {
+                        Ok(len) if err.is_err() || err == Ok(len) => {
+                            body.commit(len)?;
+                            err = Ok(len);
+                        }
+                        Ok(_) => {
+                            body.truncate(pos);
+                            break;
+                        }
+                        Err(e) => {
+                            body.truncate(pos);
+                            if err.is_err() {
+                                handle = att.handle;
+                                err = Err(e);
+                            }
+                            break;
+                        }
                     }
-
                     // debug!("[read_by_type] found! {:?} {}", att.uuid, att.handle);
-                    break;
                 }
             }
             err
@@ -423,13 +637,24 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         &self,
         connection: &Connection<'_, P>,
         buf: &mut [u8],
+        mtu: u16,
         start: u16,
         end: u16,
         group_type: &Uuid,
     ) -> Result<usize, codec::Error> {
-        // TODO respond with all finds - not just one
         let mut handle = start;
-        let mut data = WriteCursor::new(buf);
+        let mtu = buf.len().min(mtu as usize);
+        let mut data = WriteCursor::new(&mut buf[..mtu]);
+
+        // Only services are grouping attributes
+        if *group_type != Uuid::from(PRIMARY_SERVICE) && *group_type != Uuid::from(SECONDARY_SERVICE) {
+            return Self::error_response(
+                data,
+                att::ATT_READ_BY_GROUP_TYPE_REQ,
+                start,
+                AttErrorCode::UNSUPPORTED_GROUP_TYPE,
+            );
+        }
 
         let (mut header, mut body) = data.split(2)?;
         let err = self.att_table.iterate(|mut it| {
@@ -438,15 +663,37 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
                 // trace!("[read_by_group] Check attribute {:x} {}", att.uuid, att.handle);
                 if &att.uuid == group_type && att.handle >= start && att.handle <= end {
                     // debug!("[read_by_group] found! {:x} {}", att.uuid, att.handle);
-                    handle = att.handle;
-
+                    let limit = match err {
+                        Ok(len) => len + 1,
+                        Err(_) => MAX_READ_BY_GROUP_TYPE_VALUE_LEN,
+                    };
+                    if err.is_ok() && body.available() < 4 + limit {
+                        break;
+                    }
+                    let pos = body.len();
                     body.write(att.handle)?;
                     body.write(att.last_handle_in_group)?;
-                    err = self.read_attribute_data(connection, 0, att, body.write_buf());
-                    if let Ok(len) = err {
-                        body.commit(len)?;
+
+                    let buf = body.write_buf();
+                    let limit = limit.min(buf.len());
+                    match self.read_attribute_data(connection, 0, att, &mut buf[..limit]) {
+                        Ok(len) if err.is_err() || err == Ok(len) => {
+                            body.commit(len)?;
+                            err = Ok(len);
+                        }
+                        Ok(_) => {
+                            body.truncate(pos);
+                            break;
+                        }
+                        Err(e) => {
+                            body.truncate(pos);
+                            if err.is_err() {
+                                handle = att.handle;
+                                err = Err(e);
+                            }
+                            break;
+                        }
                     }
-                    break;
                 }
             }
             err
@@ -642,28 +889,65 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         w.write(handle)?;
         w.write(offset)?;
 
+        // Permissions are checked when the write is prepared, the value when it is executed.
         let err = self.att_table.iterate(|mut it| {
-            let mut err = Err(AttErrorCode::ATTRIBUTE_NOT_FOUND);
             while let Some(att) = it.next() {
                 if att.handle == handle {
-                    err = self.write_attribute_data(connection, offset as usize, att, value);
-                    w.append(value)?;
-                    break;
+                    return if att.data.writable() {
+                        Ok(())
+                    } else {
+                        Err(AttErrorCode::WRITE_NOT_PERMITTED)
+                    };
                 }
             }
-            err
+            Err(AttErrorCode::ATTRIBUTE_NOT_FOUND)
         });
+        let err = err.and_then(|_| self.prepared_writes.prepare(connection.handle(), handle, offset, value));
 
         match err {
-            Ok(()) => Ok(w.len()),
+            Ok(()) => {
+                w.append(value)?;
+                Ok(w.len())
+            }
             Err(e) => Ok(Self::error_response(w, att::ATT_PREPARE_WRITE_REQ, handle, e)?),
         }
     }
 
-    fn handle_execute_write(&self, buf: &mut [u8], _flags: u8) -> Result<usize, codec::Error> {
+    fn handle_execute_write(
+        &self,
+        connection: &Connection<'_, P>,
+        buf: &mut [u8],
+        flags: u8,
+    ) -> Result<usize, codec::Error> {
         let mut w = WriteCursor::new(buf);
-        w.write(att::ATT_EXECUTE_WRITE_RSP)?;
-        Ok(w.len())
+        let write = |handle: u16, offset: u16, value: &[u8]| {
+            self.att_table.iterate(|mut it| {
+                while let Some(att) = it.next() {
+                    if att.handle == handle {
+                        return self.write_attribute_data(connection, offset as usize, att, value);
+                    }
+                }
+                Err(AttErrorCode::ATTRIBUTE_NOT_FOUND)
+            })
+        };
+        let err = match flags {
+            // Cancel all prepared writes
+            0x00 => {
+                self.prepared_writes.cancel(connection.handle());
+                Ok(())
+            }
+            // Write all prepared values
+            0x01 => self.prepared_writes.execute(connection.handle(), write),
+            _ => Err((0, AttErrorCode::INVALID_PDU)),
+        };
+
+        match err {
+            Ok(()) => {
+                w.write(att::ATT_EXECUTE_WRITE_RSP)?;
+                Ok(w.len())
+            }
+            Err((handle, e)) => Ok(Self::error_response(w, att::ATT_EXECUTE_WRITE_REQ, handle, e)?),
+        }
     }
 
     fn handle_read_blob(
@@ -696,14 +980,62 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         }
     }
 
-    fn handle_read_multiple(&self, buf: &mut [u8], handles: &[u8]) -> Result<usize, codec::Error> {
-        let w = WriteCursor::new(buf);
-        Self::error_response(
-            w,
-            att::ATT_READ_MULTIPLE_REQ,
-            u16::from_le_bytes([handles[0], handles[1]]),
-            AttErrorCode::ATTRIBUTE_NOT_FOUND,
-        )
+    /// Read the values of a set of attributes.
+    ///
+    /// With `variable` set, each value is preceded by its length (Read Multiple Variable Length),
+    /// otherwise the values are concatenated. The response is truncated to the MTU.
+    fn handle_read_multiple(
+        &self,
+        connection: &Connection<'_, P>,
+        buf: &mut [u8],
+        mtu: u16,
+        handles: &[u8],
+        variable: bool,
+    ) -> Result<usize, codec::Error> {
+        let (request, response) = if variable {
+            (att::ATT_READ_MULTIPLE_VARIABLE_REQ, att::ATT_READ_MULTIPLE_VARIABLE_RSP)
+        } else {
+            (att::ATT_READ_MULTIPLE_REQ, att::ATT_READ_MULTIPLE_RSP)
+        };
+        let mtu = buf.len().min(mtu as usize);
+        let mut w = WriteCursor::new(&mut buf[..mtu]);
+        if handles.len() < 4 || handles.len() % 2 != 0 {
+            return Self::error_response(w, request, 0, AttErrorCode::INVALID_PDU);
+        }
+        w.write(response)?;
+
+        for handle in handles.chunks_exact(2) {
+            let handle = u16::from_le_bytes([handle[0], handle[1]]);
+            let err = self.att_table.iterate(|mut it| {
+                let mut err = Err(AttErrorCode::ATTRIBUTE_NOT_FOUND);
+                while let Some(att) = it.next() {
+                    if att.handle == handle {
+                        let dest = w.write_buf();
+                        err = if variable && dest.len() >= 2 {
+                            let (len, value) = dest.split_at_mut(2);
+                            self.read_attribute_data(connection, 0, att, value).map(|n| {
+                                len.copy_from_slice(&(n as u16).to_le_bytes());
+                                n + 2
+                            })
+                        } else if variable {
+                            // No room left for the length of the value, only check that it can be read
+                            self.read_attribute_data(connection, 0, att, &mut []).map(|_| 0)
+                        } else {
+                            self.read_attribute_data(connection, 0, att, dest)
+                        };
+                        if let Ok(n) = err {
+                            w.commit(n)?;
+                        }
+                        break;
+                    }
+                }
+                err
+            });
+            if let Err(e) = err {
+                return Self::error_response(w, request, handle, e);
+            }
+        }
+        Ok(w.len())
     }
 
     /// Process an event and produce a response if necessary
@@ -712,16 +1044,27 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         connection: &Connection<'_, P>,
         packet: &AttClient,
         rx: &mut [u8],
+    ) -> Result<Option<usize>, codec::Error> {
+        self.process_with_mtu(connection, packet, rx, connection.get_att_mtu())
+    }
+
+    /// Process an event received on a bearer with the provided ATT MTU.
+    pub(crate) fn process_with_mtu(
+        &self,
+        connection: &Connection<'_, P>,
+        packet: &AttClient,
+        rx: &mut [u8],
+        mtu: u16,
     ) -> Result<Option<usize>, codec::Error> {
         let len = match packet {
             AttClient::Request(AttReq::ReadByType {
                 start,
                 end,
                 attribute_type,
-            }) => self.handle_read_by_type_req(connection, rx, *start, *end, attribute_type)?,
+            }) => self.handle_read_by_type_req(connection, rx, mtu, *start, *end, attribute_type)?,
 
             AttClient::Request(AttReq::ReadByGroupType { start, end, group_type }) => {
-                self.handle_read_by_group_type_req(connection, rx, *start, *end, group_type)?
+                self.handle_read_by_group_type_req(connection, rx, mtu, *start, *end, group_type)?
             }
             AttClient::Request(AttReq::FindInformation {
                 start_handle,
@@ -752,13 +1095,19 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
                 self.handle_prepare_write(connection, rx, *handle, *offset, value)?
             }
 
-            AttClient::Request(AttReq::ExecuteWrite { flags }) => self.handle_execute_write(rx, *flags)?,
+            AttClient::Request(AttReq::ExecuteWrite { flags }) => self.handle_execute_write(connection, rx, *flags)?,
 
             AttClient::Request(AttReq::ReadBlob { handle, offset }) => {
                 self.handle_read_blob(connection, rx, *handle, *offset)?
             }
 
-            AttClient::Request(AttReq::ReadMultiple { handles }) => self.handle_read_multiple(rx, handles)?,
+            AttClient::Request(AttReq::ReadMultiple { handles }) => {
+                self.handle_read_multiple(connection, rx, mtu, handles, false)?
+            }
+
+            AttClient::Request(AttReq::ReadMultipleVariable { handles }) => {
+                self.handle_read_multiple(connection, rx, mtu, handles, true)?
+            }
 
             AttClient::Confirmation(_) => 0,
         };
@@ -784,3 +1133,674 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         self.cccd_tables.set_cccd_table(&connection.peer_identity(), table);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use core::task::Poll;
+    use std::boxed::Box;
+
+    use bt_hci::param::{AddrKind, BdAddr, ConnHandle, LeConnRole};
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::attribute::{
+        per_connection_store_size, AttributeProvider, Characteristic, CharacteristicProp, PresentationFormat, Service,
+    };
+    use crate::connection_manager::{ConnectionManager, ConnectionStorage};
+    use crate::prelude::DefaultPacketPool;
+
+    type Server<'d> = AttributeServer<'d, NoopRawMutex, DefaultPacketPool, 16, 2, 2>;
+
+    fn setup() -> &'static ConnectionManager<'static, DefaultPacketPool> {
+        let storage = Box::leak(Box::new([const { ConnectionStorage::new() }; 3]));
+        let mgr = ConnectionManager::new(&mut storage[..], 23);
+        Box::leak(Box::new(mgr))
+    }
+
+    fn connect(
+        mgr: &'static ConnectionManager<'static, DefaultPacketPool>,
+        handle: u16,
+    ) -> Connection<'static, DefaultPacketPool> {
+        mgr.connect(
+            ConnHandle::new(handle),
+            AddrKind::RANDOM,
+            BdAddr::new([handle as u8; 6]),
+            LeConnRole::Peripheral,
+        )
+        .unwrap();
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        conn
+    }
+
+    fn request<'a>(
+        server: &Server<'_>,
+        connection: &Connection<'_, DefaultPacketPool>,
+        req: AttReq<'_>,
+        rx: &'a mut [u8],
+    ) -> &'a [u8] {
+        let len = server
+            .process(connection, &AttClient::Request(req), rx)
+            .unwrap()
+            .unwrap();
+        &rx[..len]
+    }
+
+    struct Counter {
+        value: Cell<u32>,
+    }
+
+    impl AttributeProvider for Counter {
+        fn read(&self, _ctx: &ConnectionContext, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
+            let value = self.value.get().to_le_bytes();
+            if offset > value.len() {
+                return Err(AttErrorCode::INVALID_OFFSET);
+            }
+            let len = data.len().min(value.len() - offset);
+            data[..len].copy_from_slice(&value[offset..offset + len]);
+            Ok(len)
+        }
+
+        fn write(&self, _ctx: &ConnectionContext, offset: usize, data: &[u8]) -> Result<(), AttErrorCode> {
+            let value: [u8; 4] = data
+                .try_into()
+                .map_err(|_| AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)?;
+            if offset > 0 {
+                return Err(AttErrorCode::INVALID_OFFSET);
+            }
+            self.value.set(u32::from_le_bytes(value));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn provider_read_write() {
+        let provider: &'static Counter = Box::leak(Box::new(Counter { value: Cell::new(42) }));
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        let characteristic: Characteristic<u32> = table
+            .add_service(Service::new(0x180fu16))
+            .add_characteristic_provider(
+                0x2a19u16,
+                &[CharacteristicProp::Read, CharacteristicProp::Write],
+                provider,
+            )
+            .build();
+        let server = Server::new(table);
+
+        let mgr = setup();
+        let conn = connect(mgr, 1);
+        let handle = characteristic.handle;
+        let mut rx = [0; 23];
+
+        let rsp = request(&server, &conn, AttReq::Read { handle }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_READ_RSP, 42, 0, 0, 0]);
+
+        let rsp = request(
+            &server,
+            &conn,
+            AttReq::Write {
+                handle,
+                data: &[7, 0, 0, 0],
+            },
+            &mut rx,
+        );
+        assert_eq!(rsp, &[att::ATT_WRITE_RSP]);
+        assert_eq!(provider.value.get(), 7);
+
+        let rsp = request(&server, &conn, AttReq::Read { handle }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_READ_RSP, 7, 0, 0, 0]);
+
+        // Errors from the provider are forwarded to the peer
+        let rsp = request(&server, &conn, AttReq::Write { handle, data: &[1, 2] }, &mut rx);
+        let [h0, h1] = handle.to_le_bytes();
+        assert_eq!(
+            rsp,
+            &[
+                att::ATT_ERROR_RSP,
+                att::ATT_WRITE_REQ,
+                h0,
+                h1,
+                AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.value()
+            ]
+        );
+        assert_eq!(provider.value.get(), 7);
+
+        // The value is owned by the provider, not the table
+        assert_eq!(server.table().get(&characteristic), Err(Error::NotSupported));
+        assert_eq!(server.table().set(&characteristic, &1), Err(Error::NotSupported));
+        assert_eq!(provider.value.get(), 7);
+    }
+
+    #[test]
+    fn write_handler_application_error() {
+        fn check(_ctx: &ConnectionContext, _offset: usize, data: &[u8]) -> Result<(), AttErrorCode> {
+            match data {
+                [0] => Err(AttErrorCode::application(0x80).unwrap()),
+                _ => Ok(()),
+            }
+        }
+
+        let store: &'static mut [u8] = Box::leak(Box::new([0; 1]));
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        let mut service = table.add_service(Service::new(0x180fu16));
+        let mut builder = service.add_characteristic(
+            0x2a19u16,
+            &[CharacteristicProp::Read, CharacteristicProp::Write],
+            5u8,
+            store,
+        );
+        builder.set_write_handler(check);
+        let characteristic = builder.build();
+        service.build();
+        let server = Server::new(table);
+
+        let mgr = setup();
+        let conn = connect(mgr, 1);
+        let handle = characteristic.handle;
+        let mut rx = [0; 23];
+
+        let rsp = request(&server, &conn, AttReq::Write { handle, data: &[0] }, &mut rx);
+        let [h0, h1] = handle.to_le_bytes();
+        assert_eq!(rsp, &[att::ATT_ERROR_RSP, att::ATT_WRITE_REQ, h0, h1, 0x80]);
+        assert_eq!(server.table().get(&characteristic), Ok(5));
+
+        let rsp = request(&server, &conn, AttReq::Write { handle, data: &[9] }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_WRITE_RSP]);
+        assert_eq!(server.table().get(&characteristic), Ok(9));
+    }
+
+    #[test]
+    fn read_by_type_multiple_entries() {
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        let mut service = table.add_service(Service::new(0x180fu16));
+        let mut handles = [0; 3];
+        for (i, handle) in handles.iter_mut().enumerate() {
+            let characteristic: Characteristic<u8> = service
+                .add_characteristic(
+                    0x2a19u16,
+                    &[CharacteristicProp::Read],
+                    i as u8,
+                    Box::leak(Box::new([0; 1])),
+                )
+                .build();
+            *handle = characteristic.handle;
+        }
+        // Same type, but a value of a different length
+        let other: Characteristic<u16> = service
+            .add_characteristic(
+                0x2a19u16,
+                &[CharacteristicProp::Read],
+                0x1234u16,
+                Box::leak(Box::new([0; 2])),
+            )
+            .build();
+        service.build();
+        let server = Server::new(table);
+
+        let mgr = setup();
+        let conn = connect(mgr, 1);
+        let mut rx = [0; 23];
+
+        let req = AttReq::ReadByType {
+            start: 1,
+            end: 0xffff,
+            attribute_type: 0x2a19u16.into(),
+        };
+        let rsp = request(&server, &conn, req, &mut rx);
+        let [a, b, c] = handles.map(u16::to_le_bytes);
+        assert_eq!(
+            rsp,
+            &[
+                att::ATT_READ_BY_TYPE_RSP,
+                3,
+                a[0],
+                a[1],
+                0,
+                b[0],
+                b[1],
+                1,
+                c[0],
+                c[1],
+                2
+            ]
+        );
+
+        // The entry with a different length is returned in the next response
+        let req = AttReq::ReadByType {
+            start: handles[2] + 1,
+            end: 0xffff,
+            attribute_type: 0x2a19u16.into(),
+        };
+        let rsp = request(&server, &conn, req, &mut rx);
+        let h = other.handle.to_le_bytes();
+        assert_eq!(rsp, &[att::ATT_READ_BY_TYPE_RSP, 4, h[0], h[1], 0x34, 0x12]);
+
+        let req = AttReq::ReadByType {
+            start: other.handle + 1,
+            end: 0xffff,
+            attribute_type: 0x2a19u16.into(),
+        };
+        let rsp = request(&server, &conn, req, &mut rx);
+        let h = (other.handle + 1).to_le_bytes();
+        assert_eq!(
+            rsp,
+            &[
+                att::ATT_ERROR_RSP,
+                att::ATT_READ_BY_TYPE_REQ,
+                h[0],
+                h[1],
+                AttErrorCode::ATTRIBUTE_NOT_FOUND.value()
+            ]
+        );
+    }
+
+    #[test]
+    fn read_by_group_type_multiple_entries() {
+        const LONG: [u8; 16] = [
+            0xb0, 0x93, 0xc7, 0x28, 0x10, 0x6a, 0x5f, 0xbb, 0xa1, 0x42, 0xdf, 0xb1, 0xf1, 0x1c, 0x70, 0x7e,
+        ];
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        let first = table.add_service(Service::new(0x180fu16)).build();
+        let second = table.add_service(Service::new(0x180au16)).build();
+        let long = table.add_service(Service::new(LONG)).build();
+        let secondary = table.add_secondary_service(Service::new(0x1801u16)).build();
+        let server = Server::new(table);
+
+        let mgr = setup();
+        let conn = connect(mgr, 1);
+        let mut rx = [0; 23];
+
+        // Services with 16-bit UUIDs are returned together
+        let req = AttReq::ReadByGroupType {
+            start: 1,
+            end: 0xffff,
+            group_type: PRIMARY_SERVICE.into(),
+        };
+        let rsp = request(&server, &conn, req, &mut rx);
+        assert_eq!(rsp.len(), 2 + 2 * 6);
+        assert_eq!(rsp[..2], [att::ATT_READ_BY_GROUP_TYPE_RSP, 6]);
+        assert_eq!(rsp[2..4], first.to_le_bytes());
+        assert_eq!(rsp[6..8], [0x0f, 0x18]);
+        assert_eq!(rsp[8..10], second.to_le_bytes());
+        assert_eq!(rsp[12..14], [0x0a, 0x18]);
+        let end = u16::from_le_bytes([rsp[10], rsp[11]]);
+        assert!(end >= second && end < long);
+
+        // The service with a 128-bit UUID is returned on its own
+        let req = AttReq::ReadByGroupType {
+            start: end + 1,
+            end: 0xffff,
+            group_type: PRIMARY_SERVICE.into(),
+        };
+        let rsp = request(&server, &conn, req, &mut rx);
+        assert_eq!(rsp.len(), 2 + 20);
+        assert_eq!(rsp[..2], [att::ATT_READ_BY_GROUP_TYPE_RSP, 20]);
+        assert_eq!(rsp[2..4], long.to_le_bytes());
+        assert_eq!(rsp[6..], LONG);
+
+        // Secondary services are only returned when asked for
+        let req = AttReq::ReadByGroupType {
+            start: 1,
+            end: 0xffff,
+            group_type: SECONDARY_SERVICE.into(),
+        };
+        let rsp = request(&server, &conn, req, &mut rx);
+        assert_eq!(rsp.len(), 2 + 6);
+        assert_eq!(rsp[2..4], secondary.to_le_bytes());
+        assert_eq!(rsp[6..8], [0x01, 0x18]);
+    }
+
+    #[test]
+    fn read_by_group_type_long_uuid() {
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        table.add_service(Service::new(0x180fu16)).build();
+        let server = Server::new(table);
+
+        let mgr = setup();
+        let conn = connect(mgr, 1);
+        let mut rx = [0; 23];
+
+        let mut pdu = [0; 21];
+        pdu[0] = att::ATT_READ_BY_GROUP_TYPE_REQ;
+        pdu[1..3].copy_from_slice(&1u16.to_le_bytes());
+        pdu[3..5].copy_from_slice(&0xffffu16.to_le_bytes());
+        pdu[5..].copy_from_slice(&[0xaa; 16]);
+        let Ok(att::Att::Client(client)) = att::Att::decode(&pdu) else {
+            panic!("expected a client request");
+        };
+        let AttClient::Request(AttReq::ReadByGroupType { group_type, .. }) = &client else {
+            panic!("expected a read by group type request");
+        };
+        assert_eq!(*group_type, Uuid::new_long([0xaa; 16]));
+
+        // Only services are grouping attributes
+        let len = server.process(&conn, &client, &mut rx).unwrap().unwrap();
+        assert_eq!(
+            &rx[..len],
+            &[
+                att::ATT_ERROR_RSP,
+                att::ATT_READ_BY_GROUP_TYPE_REQ,
+                1,
+                0,
+                AttErrorCode::UNSUPPORTED_GROUP_TYPE.value()
+            ]
+        );
+    }
+
+    #[test]
+    fn prepared_writes() {
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        let mut service = table.add_service(Service::new(0x180fu16));
+        let long: Characteristic<[u8; 8]> = service
+            .add_characteristic(
+                0x2a3du16,
+                &[CharacteristicProp::Write],
+                [0; 8],
+                Box::leak(Box::new([0; 8])),
+            )
+            .build();
+        let ranged: Characteristic<u32> = {
+            let mut builder = service.add_characteristic(
+                0x2a6eu16,
+                &[CharacteristicProp::Read, CharacteristicProp::Write],
+                10u32,
+                Box::leak(Box::new([0; 4])),
+            );
+            builder.add_valid_range(&0, &100, Box::leak(Box::new([0; 8])));
+            builder.build()
+        };
+        service.build();
+        let server = Server::new(table);
+
+        let mgr = setup();
+        let conn = connect(mgr, 1);
+        let mut rx = [0; 23];
+        let [h0, h1] = long.handle.to_le_bytes();
+
+        // Prepared writes are echoed but not applied
+        let rsp = request(
+            &server,
+            &conn,
+            AttReq::PrepareWrite {
+                handle: long.handle,
+                offset: 0,
+                value: &[1, 2, 3, 4],
+            },
+            &mut rx,
+        );
+        assert_eq!(rsp, &[att::ATT_PREPARE_WRITE_RSP, h0, h1, 0, 0, 1, 2, 3, 4]);
+        let rsp = request(
+            &server,
+            &conn,
+            AttReq::PrepareWrite {
+                handle: long.handle,
+                offset: 4,
+                value: &[5, 6, 7, 8],
+            },
+            &mut rx,
+        );
+        assert_eq!(rsp, &[att::ATT_PREPARE_WRITE_RSP, h0, h1, 4, 0, 5, 6, 7, 8]);
+        assert_eq!(server.table().get(&long), Ok([0; 8]));
+
+        // Cancelling discards the prepared writes
+        let rsp = request(&server, &conn, AttReq::ExecuteWrite { flags: 0 }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_EXECUTE_WRITE_RSP]);
+        let rsp = request(&server, &conn, AttReq::ExecuteWrite { flags: 1 }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_EXECUTE_WRITE_RSP]);
+        assert_eq!(server.table().get(&long), Ok([0; 8]));
+
+        // Executing applies them
+        for (offset, value) in [(0, &[1u8, 2, 3, 4]), (4, &[5u8, 6, 7, 8])] {
+            let req = AttReq::PrepareWrite {
+                handle: long.handle,
+                offset,
+                value,
+            };
+            request(&server, &conn, req, &mut rx);
+        }
+        let rsp = request(&server, &conn, AttReq::ExecuteWrite { flags: 1 }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_EXECUTE_WRITE_RSP]);
+        assert_eq!(server.table().get(&long), Ok([1, 2, 3, 4, 5, 6, 7, 8]));
+
+        // A long write of a value with a valid range is checked as a whole
+        for (offset, value) in [(0, &[50u8, 0]), (2, &[0u8, 0])] {
+            let req = AttReq::PrepareWrite {
+                handle: ranged.handle,
+                offset,
+                value,
+            };
+            request(&server, &conn, req, &mut rx);
+        }
+        let rsp = request(&server, &conn, AttReq::ExecuteWrite { flags: 1 }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_EXECUTE_WRITE_RSP]);
+        assert_eq!(server.table().get(&ranged), Ok(50));
+
+        // Values outside of the range are rejected
+        let [r0, r1] = ranged.handle.to_le_bytes();
+        for (offset, value) in [(0, &[0u8, 1]), (2, &[0u8, 0])] {
+            let req = AttReq::PrepareWrite {
+                handle: ranged.handle,
+                offset,
+                value,
+            };
+            request(&server, &conn, req, &mut rx);
+        }
+        let rsp = request(&server, &conn, AttReq::ExecuteWrite { flags: 1 }, &mut rx);
+        let out_of_range = AttErrorCode::OUT_OF_RANGE.value();
+        assert_eq!(
+            rsp,
+            &[att::ATT_ERROR_RSP, att::ATT_EXECUTE_WRITE_REQ, r0, r1, out_of_range]
+        );
+        let req = AttReq::Write {
+            handle: ranged.handle,
+            data: &[101, 0, 0, 0],
+        };
+        let rsp = request(&server, &conn, req, &mut rx);
+        assert_eq!(rsp, &[att::ATT_ERROR_RSP, att::ATT_WRITE_REQ, r0, r1, out_of_range]);
+        assert_eq!(server.table().get(&ranged), Ok(50));
+    }
+
+    #[test]
+    fn prepared_writes_per_connection() {
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        let long: Characteristic<[u8; 4]> = table
+            .add_service(Service::new(0x180fu16))
+            .add_characteristic(
+                0x2a3du16,
+                &[CharacteristicProp::Write],
+                [0; 4],
+                Box::leak(Box::new([0; 4])),
+            )
+            .build();
+        let read_only: Characteristic<u8> = table
+            .add_service(Service::new(0x180au16))
+            .add_characteristic(0x2a19u16, &[CharacteristicProp::Read], 0, Box::leak(Box::new([0; 1])))
+            .build();
+        let server = Server::new(table);
+
+        let mgr = setup();
+        let first = connect(mgr, 1);
+        let second = connect(mgr, 2);
+        let mut rx = [0; 23];
+
+        // Permissions are checked when preparing
+        let [h0, h1] = read_only.handle.to_le_bytes();
+        let req = AttReq::PrepareWrite {
+            handle: read_only.handle,
+            offset: 0,
+            value: &[1],
+        };
+        let rsp = request(&server, &first, req, &mut rx);
+        let not_permitted = AttErrorCode::WRITE_NOT_PERMITTED.value();
+        assert_eq!(
+            rsp,
+            &[att::ATT_ERROR_RSP, att::ATT_PREPARE_WRITE_REQ, h0, h1, not_permitted]
+        );
+
+        let req = AttReq::PrepareWrite {
+            handle: long.handle,
+            offset: 0,
+            value: &[1, 2, 3, 4],
+        };
+        request(&server, &first, req, &mut rx);
+        let req = AttReq::PrepareWrite {
+            handle: long.handle,
+            offset: 0,
+            value: &[5, 6, 7, 8],
+        };
+        request(&server, &second, req, &mut rx);
+
+        // Each connection only executes its own writes
+        request(&server, &second, AttReq::ExecuteWrite { flags: 0 }, &mut rx);
+        assert_eq!(server.table().get(&long), Ok([0; 4]));
+        request(&server, &first, AttReq::ExecuteWrite { flags: 1 }, &mut rx);
+        assert_eq!(server.table().get(&long), Ok([1, 2, 3, 4]));
+
+        // The queue is full when the value does not fit
+        let value = [0; GATT_SERVER_PREPARE_WRITE_QUEUE_SIZE];
+        let req = AttReq::PrepareWrite {
+            handle: long.handle,
+            offset: 0,
+            value: &value[..GATT_SERVER_PREPARE_WRITE_QUEUE_SIZE - PREPARED_WRITE_HEADER_LEN + 1],
+        };
+        let mut rx = [0; GATT_SERVER_PREPARE_WRITE_QUEUE_SIZE + 8];
+        let rsp = request(&server, &second, req, &mut rx);
+        let [h0, h1] = long.handle.to_le_bytes();
+        let full = AttErrorCode::PREPARE_QUEUE_FULL.value();
+        assert_eq!(rsp, &[att::ATT_ERROR_RSP, att::ATT_PREPARE_WRITE_REQ, h0, h1, full]);
+    }
+
+    #[test]
+    fn per_connection_values() {
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        let store = Box::leak(Box::new([0; per_connection_store_size(1, 2)]));
+        let session: Characteristic<u8> = table
+            .add_service(Service::new(0x180fu16))
+            .add_characteristic_per_connection(
+                0x2a19u16,
+                &[CharacteristicProp::Read, CharacteristicProp::Write],
+                7,
+                2,
+                store,
+            )
+            .build();
+        let server = Server::new(table);
+
+        let mgr = setup();
+        let first = connect(mgr, 1);
+        let second = connect(mgr, 2);
+        let third = connect(mgr, 3);
+        let handle = session.handle;
+        let mut rx = [0; 23];
+
+        // Every connection starts out with the initial value, and writes only change its own value
+        let rsp = request(&server, &first, AttReq::Write { handle, data: &[1] }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_WRITE_RSP]);
+        let rsp = request(&server, &second, AttReq::Read { handle }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_READ_RSP, 7]);
+        let rsp = request(&server, &second, AttReq::Write { handle, data: &[2] }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_WRITE_RSP]);
+
+        let rsp = request(&server, &first, AttReq::Read { handle }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_READ_RSP, 1]);
+        let rsp = request(&server, &second, AttReq::Read { handle }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_READ_RSP, 2]);
+        assert_eq!(server.table().get_for_connection(&first, &session), Ok(1));
+        assert_eq!(server.table().get_for_connection(&second, &session), Ok(2));
+        assert_eq!(server.table().get(&session), Ok(7));
+
+        // There is no room for the value of a third connection
+        let rsp = request(&server, &third, AttReq::Write { handle, data: &[3] }, &mut rx);
+        let [h0, h1] = handle.to_le_bytes();
+        let no_resources = AttErrorCode::INSUFFICIENT_RESOURCES.value();
+        assert_eq!(rsp, &[att::ATT_ERROR_RSP, att::ATT_WRITE_REQ, h0, h1, no_resources]);
+        let rsp = request(&server, &third, AttReq::Read { handle }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_READ_RSP, 7]);
+        assert_eq!(
+            server.table().set_for_connection(&third, &session, &3),
+            Err(Error::ConnectionLimitReached)
+        );
+
+        // The value of a disconnected connection is released
+        sealed::DynamicAttributeServer::disconnect(&server, &first);
+        let rsp = request(&server, &third, AttReq::Write { handle, data: &[3] }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_WRITE_RSP]);
+        assert_eq!(server.table().get_for_connection(&third, &session), Ok(3));
+        assert_eq!(server.table().get_for_connection(&second, &session), Ok(2));
+    }
+
+    #[test]
+    fn client_supported_features() {
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        let features: Characteristic<u8> = table
+            .add_service(Service::new(0x1801u16))
+            .add_client_supported_features()
+            .build();
+        let server = Server::new(table);
+
+        let mgr = setup();
+        let first = connect(mgr, 1);
+        let second = connect(mgr, 2);
+        server.connect(&first).unwrap();
+        server.connect(&second).unwrap();
+        let handle = features.handle;
+        let mut rx = [0; 23];
+
+        // Every client has its own features
+        let rsp = request(&server, &first, AttReq::Write { handle, data: &[0x02] }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_WRITE_RSP]);
+        let rsp = request(&server, &first, AttReq::Read { handle }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_READ_RSP, 0x02]);
+        let rsp = request(&server, &second, AttReq::Read { handle }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_READ_RSP, 0x00]);
+        let rsp = request(&server, &second, AttReq::Write { handle, data: &[0x01] }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_WRITE_RSP]);
+
+        // A client can enable more features, but not disable one
+        let rsp = request(&server, &first, AttReq::Write { handle, data: &[0x03] }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_WRITE_RSP]);
+        let rsp = request(&server, &first, AttReq::Write { handle, data: &[0x01] }, &mut rx);
+        let [h0, h1] = handle.to_le_bytes();
+        let not_allowed = AttErrorCode::VALUE_NOT_ALLOWED.value();
+        assert_eq!(rsp, &[att::ATT_ERROR_RSP, att::ATT_WRITE_REQ, h0, h1, not_allowed]);
+        let rsp = request(&server, &first, AttReq::Read { handle }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_READ_RSP, 0x03]);
+        let rsp = request(&server, &second, AttReq::Read { handle }, &mut rx);
+        assert_eq!(rsp, &[att::ATT_READ_RSP, 0x01]);
+    }
+
+    #[test]
+    #[should_panic(expected = "per-connection store too small")]
+    fn per_connection_store_too_small() {
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        let store = Box::leak(Box::new([0; per_connection_store_size(1, 1)]));
+        let _: Characteristic<u8> = table
+            .add_service(Service::new(0x180fu16))
+            .add_characteristic_per_connection(0x2a19u16, &[CharacteristicProp::Read], 7, 2, store)
+            .build();
+    }
+
+    #[test]
+    fn descriptor_store_too_small() {
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        let mut service = table.add_service(Service::new(0x180fu16));
+        let mut builder =
+            service.add_characteristic(0x2a19u16, &[CharacteristicProp::Read], 7u8, Box::leak(Box::new([0; 1])));
+        assert!(matches!(
+            builder.add_user_description("Battery", false, Box::leak(Box::new([0; 4]))),
+            Err(Error::InsufficientSpace)
+        ));
+        let format = builder.add_presentation_format(Box::leak(Box::new(PresentationFormat::new(4, 0, 0x27ad, 1, 0))));
+        assert!(matches!(
+            builder.add_aggregate_format(&[format], Box::leak(Box::new([0; 1]))),
+            Err(Error::InsufficientSpace)
+        ));
+        assert!(builder
+            .add_user_description("Battery", false, Box::leak(Box::new([0; 7])))
+            .is_ok());
+    }
+}
@@ -147,7 +147,7 @@ impl<'stack, C: Controller, P: PacketPool> Central<'stack, C, P> {
     #[cfg(feature = "security")]
     pub async fn pairing(&self, connection: &Connection<'stack, P>) -> Result<(), BleHostError<C::Error>> {
         let sm = &self.stack.host.connections.security_manager;
-        sm.initiate(connection)?;
+        sm.initiate(connection).await?;
         let reason = sm.get_result().await;
         if reason == crate::security_manager::Reason::Success {
             Ok(())
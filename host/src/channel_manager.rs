@@ -3,27 +3,47 @@ use core::future::poll_fn;
 use core::task::{Context, Poll};
 
 use bt_hci::controller::{blocking, Controller};
-use bt_hci::param::ConnHandle;
+use bt_hci::param::{ConnHandle, LeConnRole};
 use bt_hci::FromHciBytes;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::waitqueue::WakerRegistration;
+use embassy_time::{Duration, WithTimeout};
+use heapless::Vec;
 
+use crate::codec::Decode;
+use crate::connection::{ConnectionEvent, ConnectionParamsRequest};
 use crate::connection_manager::ConnectionManager;
 use crate::cursor::WriteCursor;
 use crate::host::BleHost;
 #[cfg(not(feature = "l2cap-sdu-reassembly-optimization"))]
 use crate::l2cap::sar::PacketReassembly;
 use crate::l2cap::L2capChannel;
-use crate::pdu::{Pdu, Sdu};
+use crate::pdu::{Pdu, Sdu, TX_SDU_HEADROOM};
 use crate::prelude::L2capChannelConfig;
 use crate::types::l2cap::{
-    CommandRejectRes, ConnParamUpdateReq, ConnParamUpdateRes, DisconnectionReq, DisconnectionRes, L2capSignalCode,
-    L2capSignalHeader, LeCreditConnReq, LeCreditConnRes, LeCreditConnResultCode, LeCreditFlowInd,
+    CommandRejectRes, ConnParamUpdateReq, ConnParamUpdateRes, ConnParamUpdateResultCode, CreditConnReconfigReq,
+    CreditConnReconfigRes, CreditConnReconfigResultCode, CreditConnReq, CreditConnRes, DisconnectionReq,
+    DisconnectionRes, EchoRes, InformationReq, InformationRes, L2capSignalCode, L2capSignalHeader, LeCreditConnReq,
+    LeCreditConnRes, LeCreditConnResultCode, LeCreditFlowInd, L2CAP_CID_FIXED_END, L2CAP_CID_FIXED_START,
+    L2CAP_ECFC_MAX_CHANNELS, L2CAP_ECFC_MIN_MTU, L2CAP_ECHO_MAX_DATA, L2CAP_LE_SIG_MTU,
 };
 use crate::{config, BleHostError, Error, PacketPool};
 
+const SIGNAL_QUEUE_SIZE: usize = 4;
+
+/// Signalling responses waiting to be sent by the control runner.
+pub(crate) type SignalQueue = Channel<NoopRawMutex, PendingSignal, SIGNAL_QUEUE_SIZE>;
+
+/// Channels created together by an enhanced credit based connection request.
+pub(crate) type EnhancedChannels<'d, P> = Vec<L2capChannel<'d, P>, L2CAP_ECFC_MAX_CHANNELS>;
+
+/// How long to wait for the response to a signaling request (RTX).
+pub(crate) const L2CAP_RTX_TIMEOUT: Duration = Duration::from_secs(30);
+
 const BASE_ID: u16 = 0x40;
+/// Last CID of the LE dynamically allocated range.
+const DYNAMIC_CID_END: u16 = 0x7F;
 
 struct State<'d, P> {
     next_req_id: u8,
@@ -31,11 +51,37 @@ struct State<'d, P> {
     accept_waker: WakerRegistration,
     create_waker: WakerRegistration,
     disconnect_waker: WakerRegistration,
+    listeners: Vec<Listener, { config::L2CAP_LISTENERS_MAX }>,
+    fixed: Vec<FixedChannel<P>, { config::L2CAP_FIXED_CHANNELS_MAX }>,
+}
+
+/// A user registered fixed channel of a connection.
+struct FixedChannel<P> {
+    conn: ConnHandle,
+    cid: u16,
+    /// Cleared when the connection is closed. The entry is removed when the handle is dropped.
+    open: bool,
+    inbound: PacketChannel<P, { config::L2CAP_RX_QUEUE_SIZE }>,
+}
+
+/// First PSM of the dynamically allocated range.
+const DYNAMIC_PSM_START: u16 = 0x80;
+/// Last PSM of the dynamically allocated range.
+const DYNAMIC_PSM_END: u16 = 0xFF;
+
+/// A registered L2CAP listener.
+struct Listener {
+    psm: u16,
+    security: L2capSecurityLevel,
+    backlog: u8,
 }
 
 /// Channel manager for L2CAP channels used directly by clients.
 pub struct ChannelManager<'d, P: PacketPool> {
     state: RefCell<State<'d, P::Packet>>,
+    // Held by the host resources, as connection parameter requests handed to the application queue a reject
+    // when dropped.
+    signals: &'static SignalQueue,
 }
 
 pub(crate) struct PacketChannel<P, const QLEN: usize> {
@@ -97,7 +143,7 @@ impl<P> State<'_, P> {
 }
 
 impl<'d, P: PacketPool> ChannelManager<'d, P> {
-    pub fn new(channels: &'d mut [ChannelStorage<P::Packet>]) -> Self {
+    pub fn new(channels: &'d mut [ChannelStorage<P::Packet>], signals: &'static SignalQueue) -> Self {
         Self {
             state: RefCell::new(State {
                 next_req_id: 0,
@@ -105,14 +151,131 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                 accept_waker: WakerRegistration::new(),
                 create_waker: WakerRegistration::new(),
                 disconnect_waker: WakerRegistration::new(),
+                listeners: Vec::new(),
+                fixed: Vec::new(),
             }),
+            signals,
         }
     }
 
-    fn next_request_id(&self) -> u8 {
+    pub(crate) fn next_request_id(&self) -> u8 {
         self.state.borrow_mut().next_request_id()
     }
 
+    /// Register a listener for the given PSM, or for a free dynamic PSM if none is provided.
+    ///
+    /// Returns the PSM of the listener.
+    pub(crate) fn listen(&self, psm: Option<u16>, security: L2capSecurityLevel, backlog: u8) -> Result<u16, Error> {
+        let mut state = self.state.borrow_mut();
+        let psm = match psm {
+            Some(0) => return Err(Error::InvalidValue),
+            Some(psm) if state.listeners.iter().any(|l| l.psm == psm) => return Err(Error::InvalidState),
+            Some(psm) => psm,
+            None => (DYNAMIC_PSM_START..=DYNAMIC_PSM_END)
+                .find(|psm| !state.listeners.iter().any(|l| l.psm == *psm))
+                .ok_or(Error::OutOfMemory)?,
+        };
+        state
+            .listeners
+            .push(Listener {
+                psm,
+                security,
+                backlog: backlog.max(1),
+            })
+            .map_err(|_| Error::OutOfMemory)?;
+        Ok(psm)
+    }
+
+    /// Remove the listener for the given PSM.
+    ///
+    /// Connection requests which were queued for the listener, but not yet accepted, are refused.
+    pub(crate) fn unlisten(&self, psm: u16) {
+        let mut state = self.state.borrow_mut();
+        state.listeners.retain(|l| l.psm != psm);
+        // Requests are identified by connection and identifier, with the number of channels requested.
+        let mut refused: Vec<(ConnHandle, u8, bool, usize), SIGNAL_QUEUE_SIZE> = Vec::new();
+        for storage in state.channels.iter_mut() {
+            if let (ChannelState::PeerConnecting(req_id), Some(conn)) = (&storage.state, storage.conn) {
+                if storage.psm == psm {
+                    match refused.iter_mut().find(|(c, id, _, _)| *c == conn && id == req_id) {
+                        Some((_, _, _, n)) => *n += 1,
+                        None => {
+                            let _ = refused.push((conn, *req_id, storage.enhanced, 1));
+                        }
+                    }
+                    storage.state = ChannelState::Disconnected;
+                    storage.conn = None;
+                }
+            }
+        }
+        drop(state);
+        for (conn, identifier, enhanced, n) in refused {
+            let response = if enhanced {
+                let mut dcids = Vec::new();
+                for _ in 0..n {
+                    let _ = dcids.push(0);
+                }
+                SignalResponse::CreditConn(CreditConnRes {
+                    mtu: 0,
+                    mps: 0,
+                    credits: 0,
+                    result: LeCreditConnResultCode::SpsmNotSupported as u16,
+                    dcids,
+                })
+            } else {
+                SignalResponse::LeCreditConn(LeCreditConnRes {
+                    dcid: 0,
+                    mtu: 0,
+                    mps: 0,
+                    credits: 0,
+                    result: LeCreditConnResultCode::SpsmNotSupported,
+                })
+            };
+            let _ = self.queue_signal(conn, identifier, response);
+        }
+    }
+
+    /// Check if a connection request for the given PSM can be queued.
+    ///
+    /// When no listener is registered, all requests are queued until accepted. Enhanced ATT bearers are
+    /// accepted by the GATT server, so their requests are queued unless a listener is registered for them.
+    fn check_listener(&self, psm: u16, encrypted: bool) -> Result<(), LeCreditConnResultCode> {
+        let state = self.state.borrow();
+        if state.listeners.is_empty() {
+            return Ok(());
+        }
+        let Some(listener) = state.listeners.iter().find(|l| l.psm == psm) else {
+            #[cfg(feature = "gatt")]
+            if psm == crate::gatt::EATT_PSM {
+                return Ok(());
+            }
+            return Err(LeCreditConnResultCode::SpsmNotSupported);
+        };
+        match listener.security {
+            L2capSecurityLevel::None => {}
+            L2capSecurityLevel::Encrypted if encrypted => {}
+            L2capSecurityLevel::Encrypted => return Err(LeCreditConnResultCode::InsufficientEncryption),
+            // Only unauthenticated pairing is supported, so an authenticated link is never established.
+            L2capSecurityLevel::Authenticated => return Err(LeCreditConnResultCode::InsufficientAuthentication),
+        }
+        // Count pending requests rather than channels, as an enhanced request may open several channels.
+        let pending = state
+            .channels
+            .iter()
+            .enumerate()
+            .filter(|(idx, chan)| match chan.state {
+                ChannelState::PeerConnecting(req_id) if chan.psm == psm => !state.channels[..*idx]
+                    .iter()
+                    .any(|prev| prev.state == ChannelState::PeerConnecting(req_id) && prev.conn == chan.conn),
+                _ => false,
+            })
+            .count();
+        if pending >= listener.backlog as usize {
+            return Err(LeCreditConnResultCode::NoResources);
+        }
+        Ok(())
+    }
+
     pub(crate) fn psm(&self, index: ChannelIndex) -> u16 {
         self.with_mut(|state| {
             let chan = &mut state.channels[index.0 as usize];
@@ -122,9 +285,10 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
 
     pub(crate) fn disconnect(&self, index: ChannelIndex) {
         self.with_mut(|state| {
-            let chan = &mut state.channels[index.0 as usize];
-            if chan.state == ChannelState::Connected {
-                chan.state = ChannelState::Disconnecting;
+            if state.channels[index.0 as usize].state == ChannelState::Connected {
+                let identifier = state.next_request_id();
+                let chan = &mut state.channels[index.0 as usize];
+                chan.state = ChannelState::Disconnecting(identifier);
                 let _ = chan.inbound.close();
                 #[cfg(feature = "channel-metrics")]
                 chan.metrics.reset();
@@ -133,6 +297,35 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         })
     }
 
+    /// Disconnect the channel and wait for the peer to respond to the disconnection request.
+    ///
+    /// If no response is received within the timeout, the channel is closed anyway.
+    pub(crate) async fn disconnect_and_wait(&self, index: ChannelIndex, timeout: Duration) -> Result<(), Error> {
+        self.disconnect(index);
+        match poll_fn(|cx| self.poll_disconnected(index, cx))
+            .with_timeout(timeout)
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(_) => {
+                warn!("[l2cap] no response to disconnect request for channel {}", index.0);
+                self.with_mut(|state| state.channels[index.0 as usize].close());
+                Err(Error::Timeout)
+            }
+        }
+    }
+
+    fn poll_disconnected(&self, index: ChannelIndex, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.borrow_mut();
+        state.create_waker.register(cx.waker());
+        match state.channels[index.0 as usize].state {
+            ChannelState::Disconnecting(_)
+            | ChannelState::DisconnectRequested(_)
+            | ChannelState::PeerDisconnecting(_) => Poll::Pending,
+            _ => Poll::Ready(()),
+        }
+    }
+
     pub(crate) fn disconnected(&self, conn: ConnHandle) -> Result<(), Error> {
         let mut state = self.state.borrow_mut();
         for storage in state.channels.iter_mut() {
@@ -145,11 +338,99 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                 storage.close();
             }
         }
+        for fixed in state.fixed.iter_mut() {
+            if fixed.conn == conn {
+                fixed.open = false;
+                let _ = fixed.inbound.close();
+            }
+        }
         state.accept_waker.wake();
         state.create_waker.wake();
         Ok(())
     }
 
+    /// Register a fixed channel with the given CID on a connection.
+    pub(crate) fn register_fixed(&self, conn: ConnHandle, cid: u16) -> Result<(), Error> {
+        if !(L2CAP_CID_FIXED_START..=L2CAP_CID_FIXED_END).contains(&cid) {
+            return Err(Error::InvalidChannelId);
+        }
+        let mut state = self.state.borrow_mut();
+        if state.fixed.iter().any(|f| f.conn == conn && f.cid == cid) {
+            return Err(Error::InvalidState);
+        }
+        state
+            .fixed
+            .push(FixedChannel {
+                conn,
+                cid,
+                open: true,
+                inbound: PacketChannel::new(),
+            })
+            .map_err(|_| Error::NoChannelAvailable)
+    }
+
+    pub(crate) fn unregister_fixed(&self, conn: ConnHandle, cid: u16) {
+        self.state
+            .borrow_mut()
+            .fixed
+            .retain(|f| !(f.conn == conn && f.cid == cid));
+    }
+
+    /// Check if a fixed channel with the given CID is open on a connection.
+    pub(crate) fn is_fixed(&self, conn: ConnHandle, cid: u16) -> bool {
+        self.state
+            .borrow()
+            .fixed
+            .iter()
+            .any(|f| f.conn == conn && f.cid == cid && f.open)
+    }
+
+    /// Dispatch a B-frame payload received on a fixed channel.
+    pub(crate) fn dispatch_fixed(&self, conn: ConnHandle, cid: u16, pdu: Pdu<P::Packet>) -> Result<(), Error> {
+        let state = self.state.borrow();
+        let fixed = state
+            .fixed
+            .iter()
+            .find(|f| f.conn == conn && f.cid == cid && f.open)
+            .ok_or(Error::InvalidChannelId)?;
+        fixed.inbound.try_send(pdu)
+    }
+
+    /// Receive the payload of a B-frame on a fixed channel.
+    pub(crate) async fn receive_fixed(&self, conn: ConnHandle, cid: u16) -> Result<Pdu<P::Packet>, Error> {
+        poll_fn(|cx| {
+            let state = self.state.borrow();
+            match state.fixed.iter().find(|f| f.conn == conn && f.cid == cid) {
+                Some(fixed) if fixed.open => match fixed.inbound.poll_receive(cx) {
+                    Poll::Ready(Some(pdu)) => Poll::Ready(Ok(pdu)),
+                    Poll::Ready(None) => Poll::Ready(Err(Error::ChannelClosed)),
+                    Poll::Pending => Poll::Pending,
+                },
+                _ => Poll::Ready(Err(Error::ChannelClosed)),
+            }
+        })
+        .await
+    }
+
+    /// Send a B-frame on a fixed channel.
+    ///
+    /// The frame shares the ACL flow control of the connection.
+    pub(crate) async fn send_fixed<T: Controller>(
+        &self,
+        conn: ConnHandle,
+        cid: u16,
+        buf: &[u8],
+        p_buf: &mut [u8],
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        if !self.is_fixed(conn, cid) {
+            return Err(Error::ChannelClosed.into());
+        }
+        let len = encode(buf, p_buf, cid, None)?;
+        ble.l2cap(conn, buf.len() as u16, 1).await?.send(&p_buf[..len]).await?;
+        Ok(())
+    }
+
     fn alloc<F: FnOnce(&mut ChannelStorage<P::Packet>)>(&self, conn: ConnHandle, f: F) -> Result<ChannelIndex, Error> {
         let mut state = self.state.borrow_mut();
         for (idx, storage) in state.channels.iter_mut().enumerate() {
@@ -194,14 +475,14 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             state.accept_waker.register(cx.waker());
             for (idx, chan) in state.channels.iter_mut().enumerate() {
                 match chan.state {
-                    ChannelState::PeerConnecting(req_id) if chan.conn == Some(conn) && psm.contains(&chan.psm) => {
-                        chan.mtu = chan.mtu.min(mtu);
-                        chan.mps = chan.mps.min(mps);
+                    ChannelState::PeerConnecting(req_id)
+                        if chan.conn == Some(conn) && !chan.enhanced && psm.contains(&chan.psm) =>
+                    {
+                        chan.mtu = mtu;
+                        chan.mps = mps;
                         chan.flow_control =
                             CreditFlowControl::new(*flow_policy, initial_credits.unwrap_or(P::capacity() as u16));
                         chan.state = ChannelState::Connected;
-                        let mps = chan.mps;
-                        let mtu = chan.mtu;
                         let cid = chan.cid;
                         let available = chan.flow_control.available();
                         if chan.refcount != 0 {
@@ -286,7 +567,17 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         ble.l2cap_signal(conn, req_id, &command, &mut tx[..]).await?;
 
         // Wait until a response is accepted.
-        poll_fn(|cx| self.poll_created(conn, idx, ble, Some(cx))).await
+        match poll_fn(|cx| self.poll_created(conn, idx, ble, Some(cx)))
+            .with_timeout(L2CAP_RTX_TIMEOUT)
+            .await
+        {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("[l2cap][conn = {:?}] no response to connect request {}", conn, req_id);
+                self.with_mut(|state| state.channels[idx.0 as usize].close());
+                Err(Error::Timeout.into())
+            }
+        }
     }
 
     fn poll_created<T: Controller>(
@@ -310,9 +601,19 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         assert_eq!(Some(conn), storage.conn);
 
         match storage.state {
-            ChannelState::Disconnecting | ChannelState::PeerDisconnecting => {
+            ChannelState::Disconnecting(_)
+            | ChannelState::DisconnectRequested(_)
+            | ChannelState::PeerDisconnecting(_) => {
                 return Poll::Ready(Err(Error::Disconnected.into()));
             }
+            ChannelState::Refused => {
+                storage.close();
+                return Poll::Ready(Err(Error::Rejected.into()));
+            }
+            ChannelState::Rejected(reason) => {
+                storage.close();
+                return Poll::Ready(Err(Error::CommandRejected(reason).into()));
+            }
             ChannelState::Connected => {
                 if storage.refcount != 0 {
                     state.print(true);
@@ -327,6 +628,311 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         Poll::Pending
     }
 
+    pub(crate) async fn accept_enhanced<T: Controller>(
+        &'d self,
+        conn: ConnHandle,
+        psm: &[u16],
+        config: &L2capChannelConfig,
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<EnhancedChannels<'d, P>, BleHostError<T::Error>> {
+        let L2capChannelConfig {
+            mtu,
+            mps,
+            flow_policy,
+            initial_credits,
+        } = config;
+
+        let (mtu, mps) = Self::enhanced_params(*mtu, *mps)?;
+        let credits = initial_credits.unwrap_or(P::capacity() as u16);
+
+        // Wait until we find channels for our connection in the connecting state matching our PSM,
+        // and accept all channels of that request.
+        let (channels, req_id, dcids) = poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+            state.accept_waker.register(cx.waker());
+            let req_id = state.channels.iter().find_map(|chan| match chan.state {
+                ChannelState::PeerConnecting(req_id)
+                    if chan.conn == Some(conn) && chan.enhanced && psm.contains(&chan.psm) =>
+                {
+                    Some(req_id)
+                }
+                _ => None,
+            });
+            let Some(req_id) = req_id else {
+                return Poll::Pending;
+            };
+
+            let mut channels = Vec::new();
+            let mut dcids = Vec::new();
+            for idx in 0..state.channels.len() {
+                let chan = &mut state.channels[idx];
+                if chan.state == ChannelState::PeerConnecting(req_id) && chan.conn == Some(conn) && chan.enhanced {
+                    chan.mtu = mtu;
+                    chan.mps = mps;
+                    chan.flow_control = CreditFlowControl::new(*flow_policy, credits);
+                    chan.state = ChannelState::Connected;
+                    let _ = dcids.push(chan.cid);
+                    let index = ChannelIndex(idx as u8);
+                    state.inc_ref(index);
+                    let _ = channels.push(L2capChannel::new(index, self));
+                }
+            }
+            Poll::Ready((channels, req_id, dcids))
+        })
+        .await;
+
+        let mut tx = [0; 32];
+        ble.l2cap_signal_var(
+            conn,
+            req_id,
+            &CreditConnRes {
+                mtu,
+                mps,
+                credits,
+                result: LeCreditConnResultCode::Success as u16,
+                dcids,
+            },
+            &mut tx[..],
+        )
+        .await?;
+        Ok(channels)
+    }
+
+    pub(crate) async fn create_enhanced<T: Controller>(
+        &'d self,
+        conn: ConnHandle,
+        psm: u16,
+        count: usize,
+        config: &L2capChannelConfig,
+        ble: &BleHost<'_, T, P>,
+    ) -> Result<EnhancedChannels<'d, P>, BleHostError<T::Error>> {
+        let L2capChannelConfig {
+            mtu,
+            mps,
+            flow_policy,
+            initial_credits,
+        } = config;
+
+        if count == 0 || count > L2CAP_ECFC_MAX_CHANNELS {
+            return Err(Error::InvalidValue.into());
+        }
+        let (mtu, mps) = Self::enhanced_params(*mtu, *mps)?;
+        let credits = initial_credits.unwrap_or(P::capacity() as u16);
+        let req_id = self.next_request_id();
+
+        // Allocate space for our new channels. A reference is held on each channel until the
+        // response has been processed, so that refused channels are not reused in the meantime.
+        let mut indices: Vec<ChannelIndex, L2CAP_ECFC_MAX_CHANNELS> = Vec::new();
+        let mut scids = Vec::new();
+        for _ in 0..count {
+            let mut cid = 0;
+            let idx = match self.alloc(conn, |storage| {
+                cid = storage.cid;
+                storage.psm = psm;
+                storage.mtu = mtu;
+                storage.mps = mps;
+                storage.enhanced = true;
+                storage.flow_control = CreditFlowControl::new(*flow_policy, credits);
+                storage.state = ChannelState::Connecting(req_id);
+            }) {
+                Ok(idx) => idx,
+                Err(e) => {
+                    self.release(&indices);
+                    return Err(e.into());
+                }
+            };
+            self.inc_ref(idx);
+            let _ = indices.push(idx);
+            let _ = scids.push(cid);
+        }
+
+        let mut tx = [0; 32];
+        let command = CreditConnReq {
+            spsm: psm,
+            mtu,
+            mps,
+            credits,
+            scids,
+        };
+        if let Err(e) = ble.l2cap_signal_var(conn, req_id, &command, &mut tx[..]).await {
+            self.release(&indices);
+            return Err(e);
+        }
+
+        // Wait until a response is accepted.
+        match poll_fn(|cx| self.poll_created_enhanced(conn, req_id, &indices, ble, Some(cx)))
+            .with_timeout(L2CAP_RTX_TIMEOUT)
+            .await
+        {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("[l2cap][conn = {:?}] no response to connect request {}", conn, req_id);
+                self.release(&indices);
+                Err(Error::Timeout.into())
+            }
+        }
+    }
+
+    fn poll_created_enhanced<T: Controller>(
+        &'d self,
+        conn: ConnHandle,
+        req_id: u8,
+        indices: &[ChannelIndex],
+        ble: &BleHost<'_, T, P>,
+        cx: Option<&mut Context<'_>>,
+    ) -> Poll<Result<EnhancedChannels<'d, P>, BleHostError<T::Error>>> {
+        let mut state = self.state.borrow_mut();
+        if let Some(cx) = cx {
+            state.create_waker.register(cx.waker());
+        }
+        if !ble.connections.is_handle_connected(conn) {
+            drop(state);
+            self.release(indices);
+            return Poll::Ready(Err(Error::Disconnected.into()));
+        }
+
+        // All channels of the request are updated by the same response.
+        match state.channels[indices[0].0 as usize].state {
+            ChannelState::Connecting(id) if id == req_id => return Poll::Pending,
+            ChannelState::Rejected(reason) => {
+                drop(state);
+                self.release(indices);
+                return Poll::Ready(Err(Error::CommandRejected(reason).into()));
+            }
+            _ => {}
+        }
+        drop(state);
+
+        let mut channels = Vec::new();
+        let mut refused: Vec<ChannelIndex, L2CAP_ECFC_MAX_CHANNELS> = Vec::new();
+        for idx in indices {
+            if self.with_mut(|state| state.channels[idx.0 as usize].state == ChannelState::Connected) {
+                // The reference taken when allocating is handed over to the channel.
+                let _ = channels.push(L2capChannel::new(*idx, self));
+            } else {
+                let _ = refused.push(*idx);
+            }
+        }
+        self.release(&refused);
+
+        if channels.is_empty() {
+            return Poll::Ready(Err(Error::Rejected.into()));
+        }
+        Poll::Ready(Ok(channels))
+    }
+
+    // Close channels which were never handed to the application and drop their reference.
+    fn release(&self, indices: &[ChannelIndex]) {
+        self.with_mut(|state| {
+            for idx in indices {
+                let chan = &mut state.channels[idx.0 as usize];
+                chan.close();
+                chan.refcount = chan.refcount.saturating_sub(1);
+            }
+        })
+    }
+
+    fn enhanced_params(mtu: Option<u16>, mps: Option<u16>) -> Result<(u16, u16), Error> {
+        let mtu = mtu.unwrap_or(P::MTU as u16 - 6);
+        let mps = mps.unwrap_or(P::MTU as u16 - 4);
+        if mps > P::MTU as u16 - 4 {
+            return Err(Error::InsufficientSpace);
+        }
+        if mtu < L2CAP_ECFC_MIN_MTU || mps < L2CAP_ECFC_MIN_MTU {
+            return Err(Error::InvalidValue);
+        }
+        Ok((mtu, mps))
+    }
+
+    /// Change the MTU and MPS of one or more enhanced credit based channels of the same connection.
+    pub(crate) async fn reconfigure<T: Controller>(
+        &self,
+        indices: &[ChannelIndex],
+        mtu: u16,
+        mps: u16,
+        ble: &BleHost<'_, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        if indices.is_empty() || indices.len() > L2CAP_ECFC_MAX_CHANNELS {
+            return Err(Error::InvalidValue.into());
+        }
+        if mps > P::MTU as u16 - 4 {
+            return Err(Error::InsufficientSpace.into());
+        }
+        if mtu < L2CAP_ECFC_MIN_MTU || mps < L2CAP_ECFC_MIN_MTU {
+            return Err(Error::InvalidValue.into());
+        }
+
+        let identifier = self.next_request_id();
+        let (conn, dcids) = self.with_mut(|state| {
+            let mut conn = None;
+            let mut dcids = Vec::new();
+            for idx in indices {
+                let chan = &state.channels[idx.0 as usize];
+                if chan.state != ChannelState::Connected {
+                    return Err(Error::ChannelClosed);
+                }
+                if !chan.enhanced || chan.reconfigure != ReconfigureState::Idle {
+                    return Err(Error::InvalidState);
+                }
+                if conn.is_some() && conn != chan.conn {
+                    return Err(Error::InvalidValue);
+                }
+                // The MTU may never be reduced, and the MPS only if a single channel is reconfigured.
+                if mtu < chan.mtu || (indices.len() > 1 && mps < chan.mps) {
+                    return Err(Error::InvalidValue);
+                }
+                conn = chan.conn;
+                let _ = dcids.push(chan.cid);
+            }
+            for idx in indices {
+                state.channels[idx.0 as usize].reconfigure = ReconfigureState::Pending { identifier, mtu, mps };
+            }
+            Ok((unwrap!(conn), dcids))
+        })?;
+
+        let mut tx = [0; 32];
+        let command = CreditConnReconfigReq { mtu, mps, dcids };
+        if let Err(e) = ble.l2cap_signal_var(conn, identifier, &command, &mut tx[..]).await {
+            self.reconfigured(indices);
+            return Err(e);
+        }
+
+        let result = poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+            state.create_waker.register(cx.waker());
+            let chan = &state.channels[indices[0].0 as usize];
+            if chan.state != ChannelState::Connected {
+                return Poll::Ready(Err(Error::ChannelClosed));
+            }
+            match chan.reconfigure {
+                ReconfigureState::Done(result) => Poll::Ready(Ok(result)),
+                ReconfigureState::Rejected(reason) => Poll::Ready(Err(Error::CommandRejected(reason))),
+                _ => Poll::Pending,
+            }
+        })
+        .with_timeout(L2CAP_RTX_TIMEOUT)
+        .await
+        .unwrap_or(Err(Error::Timeout));
+        self.reconfigured(indices);
+
+        match result? {
+            r if r == CreditConnReconfigResultCode::Success as u16 => Ok(()),
+            r if r == CreditConnReconfigResultCode::InvalidDestinationCid as u16 => Err(Error::InvalidChannelId.into()),
+            r => {
+                warn!("[l2cap] reconfigure request refused: {}", r);
+                Err(Error::InvalidValue.into())
+            }
+        }
+    }
+
+    fn reconfigured(&self, indices: &[ChannelIndex]) {
+        self.with_mut(|state| {
+            for idx in indices {
+                state.channels[idx.0 as usize].reconfigure = ReconfigureState::Idle;
+            }
+        })
+    }
+
     pub(crate) fn received(&self, channel: u16, credits: u16) -> Result<(), Error> {
         if channel < BASE_ID {
             return Err(Error::InvalidChannelId);
@@ -429,8 +1035,43 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
     }
 
     /// Handle incoming L2CAP signal
-    pub(crate) fn signal(&self, conn: ConnHandle, data: &[u8]) -> Result<(), Error> {
+    pub(crate) fn signal(
+        &self,
+        conn: ConnHandle,
+        data: &[u8],
+        connections: &ConnectionManager<'_, P>,
+    ) -> Result<(), Error> {
+        if data.len() > L2CAP_LE_SIG_MTU {
+            warn!(
+                "[l2cap][conn = {:?}] signal of {} bytes exceeds the signaling MTU",
+                conn,
+                data.len()
+            );
+            let identifier = data.get(1).copied().unwrap_or(0);
+            return self.queue_signal(
+                conn,
+                identifier,
+                SignalResponse::CommandReject(CommandRejectRes::mtu_exceeded(L2CAP_LE_SIG_MTU as u16)),
+            );
+        }
+        // The header holds the code as an enum, so unknown codes must be rejected before decoding it.
+        if data.len() >= 4 && L2capSignalCode::try_from(data[0]).is_err() {
+            warn!("[l2cap][conn = {:?}] unknown signal code {}", conn, data[0]);
+            return self.queue_signal(
+                conn,
+                data[1],
+                SignalResponse::CommandReject(CommandRejectRes::not_understood()),
+            );
+        }
         let (header, data) = L2capSignalHeader::from_hci_bytes(data)?;
+        if header.length as usize > data.len() {
+            warn!("[l2cap][conn = {:?}] fragmented signal exceeds the signaling MTU", conn);
+            return self.queue_signal(
+                conn,
+                header.identifier,
+                SignalResponse::CommandReject(CommandRejectRes::mtu_exceeded(L2CAP_LE_SIG_MTU as u16)),
+            );
+        }
         //trace!(
         //    "[l2cap][conn = {:?}] received signal (req {}) code {:?}",
         //    conn,
@@ -440,37 +1081,58 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         match header.code {
             L2capSignalCode::LeCreditConnReq => {
                 let req = LeCreditConnReq::from_hci_bytes_complete(data)?;
-                self.handle_connect_request(conn, header.identifier, &req)?;
+                let encrypted = connections.is_handle_encrypted(conn);
+                self.handle_connect_request(conn, header.identifier, &req, encrypted)?;
             }
             L2capSignalCode::LeCreditConnRes => {
                 let res = LeCreditConnRes::from_hci_bytes_complete(data)?;
                 self.handle_connect_response(conn, header.identifier, &res)?;
             }
+            L2capSignalCode::CreditConnReq => {
+                let req = CreditConnReq::decode(data)?;
+                let encrypted = connections.is_handle_encrypted(conn);
+                self.handle_enhanced_connect_request(conn, header.identifier, &req, encrypted)?;
+            }
+            L2capSignalCode::CreditConnRes => {
+                let res = CreditConnRes::decode(data)?;
+                self.handle_enhanced_connect_response(conn, header.identifier, &res)?;
+            }
+            L2capSignalCode::CreditConnReconfigReq => {
+                let req = CreditConnReconfigReq::decode(data)?;
+                trace!("[l2cap][conn = {:?}] reconfigure request: {:?}", conn, req);
+                self.handle_reconfigure_request(conn, header.identifier, &req)?;
+            }
+            L2capSignalCode::CreditConnReconfigRes => {
+                let res = CreditConnReconfigRes::from_hci_bytes_complete(data)?;
+                self.handle_reconfigure_response(conn, header.identifier, &res)?;
+            }
             L2capSignalCode::LeCreditFlowInd => {
                 let req = LeCreditFlowInd::from_hci_bytes_complete(data)?;
                 //trace!("[l2cap] credit flow: {:?}", req);
                 self.handle_credit_flow(conn, &req)?;
             }
             L2capSignalCode::CommandRejectRes => {
-                let (reject, _) = CommandRejectRes::from_hci_bytes(data)?;
+                let reject = CommandRejectRes::decode(data)?;
+                warn!(
+                    "[l2cap][conn = {:?}] request {} rejected: {:?}",
+                    conn, header.identifier, reject
+                );
+                self.handle_command_reject(conn, header.identifier, reject.reason, connections);
             }
             L2capSignalCode::DisconnectionReq => {
                 let req = DisconnectionReq::from_hci_bytes_complete(data)?;
                 trace!("[l2cap][conn = {:?}, cid = {}] disconnect request", conn, req.dcid);
-                self.handle_disconnect_request(req.dcid)?;
+                self.handle_disconnect_request(conn, header.identifier, &req)?;
             }
             L2capSignalCode::DisconnectionRes => {
                 let res = DisconnectionRes::from_hci_bytes_complete(data)?;
                 trace!("[l2cap][conn = {:?}, cid = {}] disconnect response", conn, res.scid);
-                self.handle_disconnect_response(res.scid)?;
+                self.handle_disconnect_response(conn, header.identifier, &res)?;
             }
             L2capSignalCode::ConnParamUpdateReq => {
                 let req = ConnParamUpdateReq::from_hci_bytes_complete(data)?;
-                trace!(
-                    "[l2cap][conn = {:?}] connection param update request: {:?}, ignored",
-                    conn,
-                    req
-                );
+                trace!("[l2cap][conn = {:?}] connection param update request: {:?}", conn, req);
+                self.handle_conn_param_update_request(conn, header.identifier, req, connections)?;
             }
             L2capSignalCode::ConnParamUpdateRes => {
                 let res = ConnParamUpdateRes::from_hci_bytes_complete(data)?;
@@ -479,25 +1141,105 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                     conn,
                     res.result,
                 );
+                connections.param_update_response(conn, header.identifier, res.result)?;
+            }
+            L2capSignalCode::EchoReq => {
+                let res = EchoRes {
+                    data: Vec::from_slice(&data[..data.len().min(L2CAP_ECHO_MAX_DATA)]).unwrap_or_default(),
+                };
+                self.queue_signal(conn, header.identifier, SignalResponse::Echo(res))?;
+            }
+            L2capSignalCode::InformationReq => {
+                let req = InformationReq::from_hci_bytes_complete(data)?;
+                let res = InformationRes::new(req.info_type);
+                self.queue_signal(conn, header.identifier, SignalResponse::Information(res))?;
             }
             r => {
                 warn!("[l2cap][conn = {:?}] unsupported signal: {:?}", conn, r);
-                return Err(Error::NotSupported);
+                self.queue_signal(
+                    conn,
+                    header.identifier,
+                    SignalResponse::CommandReject(CommandRejectRes::not_understood()),
+                )?;
             }
         }
         Ok(())
     }
 
-    fn handle_connect_request(&self, conn: ConnHandle, identifier: u8, req: &LeCreditConnReq) -> Result<(), Error> {
-        self.alloc(conn, |storage| {
-            storage.conn = Some(conn);
-            storage.psm = req.psm;
-            storage.peer_cid = req.scid;
-            storage.peer_credits = req.credits;
-            storage.mps = req.mps;
-            storage.mtu = req.mtu;
-            storage.state = ChannelState::PeerConnecting(identifier);
-        })?;
+    /// Fail the outstanding request which was rejected by the peer.
+    fn handle_command_reject(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        reason: u16,
+        connections: &ConnectionManager<'_, P>,
+    ) {
+        let mut state = self.state.borrow_mut();
+        for storage in state.channels.iter_mut().filter(|storage| storage.conn == Some(conn)) {
+            if storage.state == ChannelState::Connecting(identifier) {
+                storage.state = ChannelState::Rejected(reason);
+            }
+            // The peer does not know the channel anymore, so it is closed.
+            if storage.state == ChannelState::DisconnectRequested(identifier) {
+                storage.close();
+            }
+            if let ReconfigureState::Pending { identifier: id, .. } = storage.reconfigure {
+                if id == identifier {
+                    storage.reconfigure = ReconfigureState::Rejected(reason);
+                }
+            }
+        }
+        state.create_waker.wake();
+        drop(state);
+        let _ = connections.param_update_response(conn, identifier, ConnParamUpdateResultCode::Rejected as u16);
+    }
+
+    fn handle_connect_request(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        req: &LeCreditConnReq,
+        encrypted: bool,
+    ) -> Result<(), Error> {
+        if let Err(result) = self.check_listener(req.psm, encrypted) {
+            warn!(
+                "[l2cap][conn = {:?}] refusing connection request for psm {}: {:?}",
+                conn, req.psm, result
+            );
+            return self.queue_signal(
+                conn,
+                identifier,
+                SignalResponse::LeCreditConn(LeCreditConnRes {
+                    dcid: 0,
+                    mtu: 0,
+                    mps: 0,
+                    credits: 0,
+                    result,
+                }),
+            );
+        }
+        let allocated = self.alloc(conn, |storage| {
+            storage.conn = Some(conn);
+            storage.psm = req.psm;
+            storage.peer_cid = req.scid;
+            storage.peer_credits = req.credits;
+            storage.peer_mps = req.mps;
+            storage.peer_mtu = req.mtu;
+            storage.state = ChannelState::PeerConnecting(identifier);
+        });
+        if allocated.is_err() {
+            return self.queue_signal(
+                conn,
+                identifier,
+                SignalResponse::LeCreditConn(LeCreditConnRes {
+                    dcid: 0,
+                    mtu: 0,
+                    mps: 0,
+                    credits: 0,
+                    result: LeCreditConnResultCode::NoResources,
+                }),
+            );
+        }
         self.state.borrow_mut().accept_waker.wake();
         Ok(())
     }
@@ -512,8 +1254,8 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                         ChannelState::Connecting(req_id) if identifier == req_id && Some(conn) == storage.conn => {
                             storage.peer_cid = res.dcid;
                             storage.peer_credits = res.credits;
-                            storage.mps = storage.mps.min(res.mps);
-                            storage.mtu = storage.mtu.min(res.mtu);
+                            storage.peer_mps = res.mps;
+                            storage.peer_mtu = res.mtu;
                             storage.state = ChannelState::Connected;
                             state.create_waker.wake();
                             return Ok(());
@@ -530,9 +1272,278 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             }
             other => {
                 warn!("Channel open request failed: {:?}", other);
-                Err(Error::NotSupported)
+                let mut state = self.state.borrow_mut();
+                for storage in state.channels.iter_mut() {
+                    if storage.state == ChannelState::Connecting(identifier) && Some(conn) == storage.conn {
+                        storage.state = ChannelState::Refused;
+                        state.create_waker.wake();
+                        return Ok(());
+                    }
+                }
+                Err(Error::NotFound)
+            }
+        }
+    }
+
+    fn handle_enhanced_connect_request(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        req: &CreditConnReq,
+        encrypted: bool,
+    ) -> Result<(), Error> {
+        let refuse = |result: LeCreditConnResultCode| {
+            let mut dcids = Vec::new();
+            for _ in req.scids.iter() {
+                let _ = dcids.push(0);
+            }
+            self.queue_signal(
+                conn,
+                identifier,
+                SignalResponse::CreditConn(CreditConnRes {
+                    mtu: 0,
+                    mps: 0,
+                    credits: 0,
+                    result: result as u16,
+                    dcids,
+                }),
+            )
+        };
+
+        if let Err(result) = self.check_listener(req.spsm, encrypted) {
+            warn!(
+                "[l2cap][conn = {:?}] refusing enhanced connection request for psm {}: {:?}",
+                conn, req.spsm, result
+            );
+            return refuse(result);
+        }
+
+        if req.mtu < L2CAP_ECFC_MIN_MTU || req.mps < L2CAP_ECFC_MIN_MTU {
+            return refuse(LeCreditConnResultCode::UnacceptableParameters);
+        }
+
+        if req.scids.iter().any(|scid| !(BASE_ID..=DYNAMIC_CID_END).contains(scid)) {
+            warn!(
+                "[l2cap][conn = {:?}] refusing enhanced connection request with invalid source cids",
+                conn
+            );
+            return refuse(LeCreditConnResultCode::InvalidSourceId);
+        }
+
+        // A source CID can neither be repeated in the request nor be in use by another channel of the connection.
+        let allocated = self.with_mut(|state| {
+            req.scids.iter().enumerate().any(|(i, scid)| {
+                req.scids[..i].contains(scid)
+                    || state
+                        .channels
+                        .iter()
+                        .any(|storage| storage.conn == Some(conn) && storage.peer_cid == *scid)
+            })
+        });
+        if allocated {
+            warn!(
+                "[l2cap][conn = {:?}] refusing enhanced connection request with allocated source cids",
+                conn
+            );
+            return refuse(LeCreditConnResultCode::ScidAlreadyAllocated);
+        }
+
+        // Either all channels of the request are allocated, or none of them.
+        let available = self
+            .state
+            .borrow()
+            .channels
+            .iter()
+            .filter(|storage| storage.state == ChannelState::Disconnected && storage.refcount == 0)
+            .count();
+        if available < req.scids.len() {
+            warn!(
+                "[l2cap][conn = {:?}] no room for {} enhanced channels",
+                conn,
+                req.scids.len()
+            );
+            return refuse(LeCreditConnResultCode::NoResources);
+        }
+
+        for scid in req.scids.iter() {
+            self.alloc(conn, |storage| {
+                storage.psm = req.spsm;
+                storage.peer_cid = *scid;
+                storage.peer_credits = req.credits;
+                storage.peer_mps = req.mps;
+                storage.peer_mtu = req.mtu;
+                storage.enhanced = true;
+                storage.state = ChannelState::PeerConnecting(identifier);
+            })?;
+        }
+        self.state.borrow_mut().accept_waker.wake();
+        Ok(())
+    }
+
+    fn handle_enhanced_connect_response(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        res: &CreditConnRes,
+    ) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        let mut dcids = res.dcids.iter();
+        let mut found = false;
+        // Channels of a request are allocated in order, so they match the order of the destination CIDs.
+        for storage in state.channels.iter_mut() {
+            match storage.state {
+                ChannelState::Connecting(req_id) if identifier == req_id && Some(conn) == storage.conn => {
+                    found = true;
+                    match dcids.next() {
+                        Some(dcid) if *dcid != 0 => {
+                            storage.peer_cid = *dcid;
+                            storage.peer_credits = res.credits;
+                            storage.peer_mps = res.mps;
+                            storage.peer_mtu = res.mtu;
+                            storage.state = ChannelState::Connected;
+                        }
+                        _ => {
+                            storage.close();
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !found {
+            trace!(
+                "[l2cap][handle_enhanced_connect_response][link = {}] request with id {} not found",
+                conn.raw(),
+                identifier
+            );
+            return Err(Error::NotFound);
+        }
+        if res.result != LeCreditConnResultCode::Success as u16 {
+            warn!("[l2cap] enhanced channel request (partially) refused: {}", res.result);
+        }
+        state.create_waker.wake();
+        Ok(())
+    }
+
+    fn handle_reconfigure_request(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        req: &CreditConnReconfigReq,
+    ) -> Result<(), Error> {
+        let result = self.with_mut(|state| {
+            if req.mtu < L2CAP_ECFC_MIN_MTU || req.mps < L2CAP_ECFC_MIN_MTU {
+                return CreditConnReconfigResultCode::UnacceptableParameters;
+            }
+            // Validate all channels before changing any of them.
+            for dcid in req.dcids.iter() {
+                let Some(storage) = state.channels.iter().find(|storage| {
+                    storage.state == ChannelState::Connected
+                        && storage.enhanced
+                        && storage.conn == Some(conn)
+                        && storage.peer_cid == *dcid
+                }) else {
+                    return CreditConnReconfigResultCode::InvalidDestinationCid;
+                };
+                if req.mtu < storage.peer_mtu {
+                    return CreditConnReconfigResultCode::MtuReductionNotAllowed;
+                }
+                if req.dcids.len() > 1 && req.mps < storage.peer_mps {
+                    return CreditConnReconfigResultCode::MpsReductionNotAllowed;
+                }
+            }
+            for storage in state.channels.iter_mut() {
+                if storage.state == ChannelState::Connected
+                    && storage.enhanced
+                    && storage.conn == Some(conn)
+                    && req.dcids.contains(&storage.peer_cid)
+                {
+                    storage.peer_mtu = req.mtu;
+                    storage.peer_mps = req.mps;
+                }
+            }
+            CreditConnReconfigResultCode::Success
+        });
+        self.queue_signal(
+            conn,
+            identifier,
+            SignalResponse::CreditConnReconfig(CreditConnReconfigRes { result: result as u16 }),
+        )
+    }
+
+    fn handle_reconfigure_response(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        res: &CreditConnReconfigRes,
+    ) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        let mut found = false;
+        for storage in state.channels.iter_mut() {
+            match storage.reconfigure {
+                ReconfigureState::Pending {
+                    identifier: id,
+                    mtu,
+                    mps,
+                } if id == identifier && storage.conn == Some(conn) => {
+                    if res.result == CreditConnReconfigResultCode::Success as u16 {
+                        storage.mtu = mtu;
+                        storage.mps = mps;
+                    }
+                    storage.reconfigure = ReconfigureState::Done(res.result);
+                    found = true;
+                }
+                _ => {}
             }
         }
+        if !found {
+            return Err(Error::NotFound);
+        }
+        state.create_waker.wake();
+        Ok(())
+    }
+
+    fn handle_conn_param_update_request(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        req: ConnParamUpdateReq,
+        connections: &ConnectionManager<'_, P>,
+    ) -> Result<(), Error> {
+        let role = connections.with_connected_handle(conn, |storage| Ok(storage.role))?;
+        if role != Some(LeConnRole::Central) {
+            // Only the central may act on the request, a peripheral rejects the command as not understood.
+            return self.queue_signal(
+                conn,
+                identifier,
+                SignalResponse::CommandReject(CommandRejectRes::not_understood()),
+            );
+        }
+
+        if !valid_conn_params(&req) {
+            return queue_conn_params_reject(self.signals, conn, identifier);
+        }
+        let request = ConnectionParamsRequest::new(conn, identifier, req, self.signals);
+        if connections
+            .post_handle_event(conn, ConnectionEvent::RequestConnectionParams { request })
+            .is_err()
+        {
+            warn!(
+                "[l2cap][conn = {:?}] event queue full, rejecting connection param update",
+                conn
+            );
+            // The request is dropped with the event, which queues the reject.
+        }
+        Ok(())
+    }
+
+    fn queue_signal(&self, handle: ConnHandle, identifier: u8, response: SignalResponse) -> Result<(), Error> {
+        queue_signal(self.signals, handle, identifier, response)
+    }
+
+    pub(crate) fn poll_signal(&self, cx: &mut Context<'_>) -> Poll<PendingSignal> {
+        self.signals.poll_receive(cx)
     }
 
     fn handle_credit_flow(&self, conn: ConnHandle, req: &LeCreditFlowInd) -> Result<(), Error> {
@@ -557,24 +1568,38 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         Err(Error::NotFound)
     }
 
-    fn handle_disconnect_request(&self, cid: u16) -> Result<(), Error> {
+    fn handle_disconnect_request(&self, conn: ConnHandle, identifier: u8, req: &DisconnectionReq) -> Result<(), Error> {
         let mut state = self.state.borrow_mut();
-        for (idx, storage) in state.channels.iter_mut().enumerate() {
-            if cid == storage.cid {
-                storage.state = ChannelState::PeerDisconnecting;
+        for storage in state.channels.iter_mut() {
+            if Some(conn) == storage.conn && req.dcid == storage.cid && req.scid == storage.peer_cid {
+                storage.state = ChannelState::PeerDisconnecting(identifier);
                 let _ = storage.inbound.close();
                 state.disconnect_waker.wake();
-                break;
+                return Ok(());
             }
         }
-        Ok(())
+        drop(state);
+        self.queue_signal(
+            conn,
+            identifier,
+            SignalResponse::CommandReject(CommandRejectRes::invalid_cid(req.dcid, req.scid)),
+        )
     }
 
-    fn handle_disconnect_response(&self, cid: u16) -> Result<(), Error> {
+    fn handle_disconnect_response(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        res: &DisconnectionRes,
+    ) -> Result<(), Error> {
         let mut state = self.state.borrow_mut();
         for storage in state.channels.iter_mut() {
-            if storage.state == ChannelState::Disconnecting && cid == storage.cid {
+            if storage.state == ChannelState::DisconnectRequested(identifier)
+                && Some(conn) == storage.conn
+                && res.scid == storage.cid
+            {
                 storage.close();
+                state.create_waker.wake();
                 break;
             }
         }
@@ -677,6 +1702,54 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         Ok(())
     }
 
+    /// Send an SDU written in place in a packet over a given l2cap channel.
+    ///
+    /// The packet must hold the payload after `TX_SDU_HEADROOM` bytes of headroom. Each K-frame header is
+    /// written in front of its segment, overwriting data of the previous segment which was already sent,
+    /// so the payload is never copied.
+    pub(crate) async fn send_in_place<T: Controller>(
+        &self,
+        index: ChannelIndex,
+        packet: &mut [u8],
+        len: usize,
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let (conn, mps, mtu, peer_cid) = self.connected_channel_params(index)?;
+        if len > mtu as usize || TX_SDU_HEADROOM + len > packet.len() {
+            return Err(Error::InsufficientSpace.into());
+        }
+        let n_packets = (len as u16).saturating_add(2).div_ceil(mps);
+        let mut grant = poll_fn(|cx| self.poll_request_to_send(index, n_packets, Some(cx))).await?;
+
+        let mps = mps as usize;
+        let end = TX_SDU_HEADROOM + len;
+        let first = len.min(mps - 2);
+        let mut w = WriteCursor::new(&mut packet[..TX_SDU_HEADROOM]);
+        w.write(first as u16 + 2)?;
+        w.write(peer_cid)?;
+        w.write(len as u16)?;
+        ble.l2cap(conn, first as u16 + 2, 1)
+            .await?
+            .send(&packet[..TX_SDU_HEADROOM + first])
+            .await?;
+        grant.confirm(1);
+
+        let mut offset = TX_SDU_HEADROOM + first;
+        while offset < end {
+            let chunk = (end - offset).min(mps);
+            let mut w = WriteCursor::new(&mut packet[offset - 4..offset]);
+            w.write(chunk as u16)?;
+            w.write(peer_cid)?;
+            ble.l2cap(conn, chunk as u16, 1)
+                .await?
+                .send(&packet[offset - 4..offset + chunk])
+                .await?;
+            grant.confirm(1);
+            offset += chunk;
+        }
+        Ok(())
+    }
+
     /// Send the provided buffer over a given l2cap channel.
     ///
     /// The buffer must be equal to or smaller than the MTU agreed for the channel.
@@ -726,11 +1799,57 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         Ok(())
     }
 
+    /// Frame an SDU which fits in a single K-frame for the given l2cap channel, consuming one credit.
+    ///
+    /// The frame can be queued on the connection without awaiting, which is needed where the SDU is sent
+    /// from a drop handler. If the peer has not granted a credit, `Error::Busy` is returned.
+    pub(crate) fn try_frame(&self, index: ChannelIndex, sdu: &[u8]) -> Result<Pdu<P::Packet>, Error> {
+        let (_, mps, mtu, peer_cid) = self.connected_channel_params(index)?;
+        if sdu.len() > mtu as usize || sdu.len() + 2 > mps as usize {
+            return Err(Error::InsufficientSpace);
+        }
+        let mut packet = P::allocate().ok_or(Error::OutOfMemory)?;
+        let mut grant = match self.poll_request_to_send(index, 1, None) {
+            Poll::Ready(res) => res?,
+            Poll::Pending => return Err(Error::Busy),
+        };
+        let len = encode(sdu, packet.as_mut(), peer_cid, Some(sdu.len() as u16))?;
+        grant.confirm(1);
+        Ok(Pdu::new(packet, len))
+    }
+
+    /// Open a channel on a connection without signaling, with the same MTU and MPS on both sides.
+    #[cfg(test)]
+    pub(crate) fn open(&'d self, conn: ConnHandle, mtu: u16, mps: u16, credits: u16) -> L2capChannel<'d, P> {
+        let idx = unwrap!(self.alloc(conn, |storage| {
+            storage.state = ChannelState::Connected;
+            storage.mtu = mtu;
+            storage.mps = mps;
+            storage.flow_control = CreditFlowControl::new(CreditFlowPolicy::Manual, credits);
+            storage.peer_cid = 0x50;
+            storage.peer_mtu = mtu;
+            storage.peer_mps = mps;
+            storage.peer_credits = credits;
+        }));
+        self.inc_ref(idx);
+        L2capChannel::new(idx, self)
+    }
+
+    /// Largest SDU which can be sent on a connected channel.
+    pub(crate) fn send_mtu(&self, index: ChannelIndex) -> Result<u16, Error> {
+        self.connected_channel_params(index).map(|(_, _, mtu, _)| mtu)
+    }
+
     fn connected_channel_params(&self, index: ChannelIndex) -> Result<(ConnHandle, u16, u16, u16), Error> {
         let state = self.state.borrow();
         let chan = &state.channels[index.0 as usize];
         if chan.state == ChannelState::Connected {
-            return Ok((chan.conn.unwrap(), chan.mps, chan.mtu, chan.peer_cid));
+            return Ok((
+                chan.conn.unwrap(),
+                chan.mps.min(chan.peer_mps),
+                chan.mtu.min(chan.peer_mtu),
+                chan.peer_cid,
+            ));
         }
         //trace!("[l2cap][connected_channel_params] channel {} closed", index);
         Err(Error::ChannelClosed)
@@ -773,6 +1892,86 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         Ok(())
     }
 
+    /// Number of frames the peer allows us to send on a channel.
+    pub(crate) fn tx_credits(&self, index: ChannelIndex) -> u16 {
+        let state = self.state.borrow();
+        let chan = &state.channels[index.0 as usize];
+        if chan.state == ChannelState::Connected {
+            chan.peer_credits
+        } else {
+            0
+        }
+    }
+
+    /// Number of frames the peer is allowed to send on a channel.
+    pub(crate) fn rx_credits(&self, index: ChannelIndex) -> u16 {
+        let state = self.state.borrow();
+        let chan = &state.channels[index.0 as usize];
+        if chan.state == ChannelState::Connected {
+            chan.flow_control.available()
+        } else {
+            0
+        }
+    }
+
+    /// Change the credit flow policy of a channel.
+    ///
+    /// Credits owed according to the new policy are issued right away.
+    pub(crate) async fn set_flow_policy<T: Controller>(
+        &self,
+        index: ChannelIndex,
+        policy: CreditFlowPolicy,
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            if chan.state == ChannelState::Connected {
+                chan.flow_control.policy = policy;
+                return Ok(());
+            }
+            Err(Error::ChannelClosed)
+        })?;
+        let mut p_buf: [u8; 16] = [0; 16];
+        self.flow_control(index, ble, &mut p_buf).await
+    }
+
+    /// Grant credits to the peer of a channel, regardless of the credit flow policy.
+    pub(crate) async fn grant_credits<T: Controller>(
+        &self,
+        index: ChannelIndex,
+        credits: u16,
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let (conn, cid) = self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            if chan.state != ChannelState::Connected {
+                return Err(Error::ChannelClosed);
+            }
+            // The peer may never hold more than 65535 credits.
+            if chan.flow_control.available().checked_add(credits).is_none() {
+                return Err(Error::InvalidValue);
+            }
+            Ok((chan.conn.unwrap(), chan.cid))
+        })?;
+        if credits == 0 {
+            return Ok(());
+        }
+
+        let identifier = self.next_request_id();
+        let signal = LeCreditFlowInd { cid, credits };
+        let mut p_buf: [u8; 16] = [0; 16];
+        ble.l2cap_signal(conn, identifier, &signal, &mut p_buf).await?;
+        self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            if chan.state == ChannelState::Connected {
+                chan.flow_control.confirm_granted(credits);
+                return Ok(());
+            }
+            Err(Error::ChannelClosed)
+        })?;
+        Ok(())
+    }
+
     fn with_mut<F: FnOnce(&mut State<'d, P::Packet>) -> R, R>(&self, f: F) -> R {
         let mut state = self.state.borrow_mut();
         f(&mut state)
@@ -812,7 +2011,7 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         }
         for (idx, storage) in state.channels.iter().enumerate() {
             match storage.state {
-                ChannelState::Disconnecting | ChannelState::PeerDisconnecting => {
+                ChannelState::Disconnecting(_) | ChannelState::PeerDisconnecting(_) => {
                     return Poll::Ready(DisconnectRequest {
                         index: ChannelIndex(idx as u8),
                         handle: storage.conn.unwrap(),
@@ -833,14 +2032,22 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
 
     pub(crate) fn dec_ref(&self, index: ChannelIndex) {
         self.with_mut(|state| {
-            let state = &mut state.channels[index.0 as usize];
-            state.refcount = unwrap!(
-                state.refcount.checked_sub(1),
+            let chan = &mut state.channels[index.0 as usize];
+            chan.refcount = unwrap!(
+                chan.refcount.checked_sub(1),
                 "bug: dropping a channel (i = {}) with refcount 0",
                 index.0
             );
-            if state.refcount == 0 && state.state == ChannelState::Connected {
-                state.state = ChannelState::Disconnecting;
+            if chan.refcount == 0 {
+                match chan.state {
+                    ChannelState::Connected => {
+                        let identifier = state.next_request_id();
+                        state.channels[index.0 as usize].state = ChannelState::Disconnecting(identifier);
+                    }
+                    // Nobody is waiting for the disconnection response anymore.
+                    ChannelState::DisconnectRequested(_) => chan.state = ChannelState::Disconnected,
+                    _ => {}
+                }
             }
         });
     }
@@ -880,24 +2087,30 @@ impl<'a, 'd, P: PacketPool> DisconnectRequest<'a, 'd, P> {
     }
 
     pub async fn send<T: Controller>(&self, host: &BleHost<'_, T, P>) -> Result<(), BleHostError<T::Error>> {
-        let (state, conn, identifier, dcid, scid) = {
-            let mut state = self.state.borrow_mut();
-            let identifier = state.next_request_id();
+        let (state, conn, cid, peer_cid) = {
+            let state = self.state.borrow();
             let chan = &state.channels[self.index.0 as usize];
-            (chan.state.clone(), chan.conn, identifier, chan.peer_cid, chan.cid)
+            (chan.state.clone(), chan.conn, chan.cid, chan.peer_cid)
         };
 
         let mut tx = [0; 18];
         match state {
-            ChannelState::PeerDisconnecting => {
+            ChannelState::PeerDisconnecting(identifier) => {
                 assert_eq!(Some(self.handle), conn);
-                host.l2cap_signal(self.handle, identifier, &DisconnectionRes { dcid, scid }, &mut tx[..])
-                    .await?;
+                // The response echoes the channel identifiers of the request.
+                let res = DisconnectionRes {
+                    dcid: cid,
+                    scid: peer_cid,
+                };
+                host.l2cap_signal(self.handle, identifier, &res, &mut tx[..]).await?;
             }
-            ChannelState::Disconnecting => {
+            ChannelState::Disconnecting(identifier) => {
                 assert_eq!(Some(self.handle), conn);
-                host.l2cap_signal(self.handle, identifier, &DisconnectionReq { dcid, scid }, &mut tx[..])
-                    .await?;
+                let req = DisconnectionReq {
+                    dcid: peer_cid,
+                    scid: cid,
+                };
+                host.l2cap_signal(self.handle, identifier, &req, &mut tx[..]).await?;
             }
             _ => {}
         }
@@ -905,10 +2118,111 @@ impl<'a, 'd, P: PacketPool> DisconnectRequest<'a, 'd, P> {
     }
 
     pub fn confirm(self) {
-        self.state.borrow_mut().channels[self.index.0 as usize].state = ChannelState::Disconnected;
+        let mut state = self.state.borrow_mut();
+        let chan = &mut state.channels[self.index.0 as usize];
+        chan.state = match chan.state {
+            // Only wait for the response if the channel is still referenced.
+            ChannelState::Disconnecting(identifier) if chan.refcount > 0 => {
+                ChannelState::DisconnectRequested(identifier)
+            }
+            _ => ChannelState::Disconnected,
+        };
+        state.create_waker.wake();
+    }
+}
+
+/// A signalling response which is sent by the control runner, as signals are processed in the receive path.
+pub struct PendingSignal {
+    handle: ConnHandle,
+    identifier: u8,
+    response: SignalResponse,
+}
+
+enum SignalResponse {
+    CreditConn(CreditConnRes),
+    CreditConnReconfig(CreditConnReconfigRes),
+    LeCreditConn(LeCreditConnRes),
+    ConnParamUpdate(ConnParamUpdateRes),
+    CommandReject(CommandRejectRes),
+    Echo(EchoRes),
+    Information(InformationRes),
+}
+
+impl PendingSignal {
+    pub fn handle(&self) -> ConnHandle {
+        self.handle
+    }
+
+    pub async fn send<T: Controller, P: PacketPool>(
+        &self,
+        host: &BleHost<'_, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let mut tx = [0; 32];
+        match &self.response {
+            SignalResponse::CreditConn(res) => {
+                host.l2cap_signal_var(self.handle, self.identifier, res, &mut tx[..])
+                    .await
+            }
+            SignalResponse::CreditConnReconfig(res) => {
+                host.l2cap_signal(self.handle, self.identifier, res, &mut tx[..]).await
+            }
+            SignalResponse::ConnParamUpdate(res) => {
+                host.l2cap_signal(self.handle, self.identifier, res, &mut tx[..]).await
+            }
+            SignalResponse::LeCreditConn(res) => {
+                host.l2cap_signal(self.handle, self.identifier, res, &mut tx[..]).await
+            }
+            SignalResponse::CommandReject(res) => {
+                host.l2cap_signal_var(self.handle, self.identifier, res, &mut tx[..])
+                    .await
+            }
+            SignalResponse::Echo(res) => {
+                host.l2cap_signal_var(self.handle, self.identifier, res, &mut tx[..])
+                    .await
+            }
+            SignalResponse::Information(res) => {
+                host.l2cap_signal_var(self.handle, self.identifier, res, &mut tx[..])
+                    .await
+            }
+        }
     }
 }
 
+/// Queue a signaling response, to be sent by the host runner.
+fn queue_signal(
+    signals: &SignalQueue,
+    handle: ConnHandle,
+    identifier: u8,
+    response: SignalResponse,
+) -> Result<(), Error> {
+    signals
+        .try_send(PendingSignal {
+            handle,
+            identifier,
+            response,
+        })
+        .map_err(|_| Error::OutOfMemory)
+}
+
+/// Queue the reject of a connection parameter update request.
+pub(crate) fn queue_conn_params_reject(signals: &SignalQueue, handle: ConnHandle, identifier: u8) -> Result<(), Error> {
+    let reject = SignalResponse::ConnParamUpdate(ConnParamUpdateRes {
+        result: ConnParamUpdateResultCode::Rejected as u16,
+    });
+    queue_signal(signals, handle, identifier, reject)
+}
+
+/// Check connection parameters requested by the peer against the ranges allowed by the specification.
+fn valid_conn_params(req: &ConnParamUpdateReq) -> bool {
+    // Intervals are in units of 1.25 ms and the timeout in units of 10 ms.
+    (6..=3200).contains(&req.interval_min)
+        && (req.interval_min..=3200).contains(&req.interval_max)
+        && req.latency <= 499
+        && (10..=3200).contains(&req.timeout)
+        // The supervision timeout must be larger than (1 + latency) * interval_max * 2.
+        && req.timeout as u32 * 4 > (1 + req.latency as u32) * req.interval_max as u32
+}
+
 fn encode(data: &[u8], packet: &mut [u8], peer_cid: u16, header: Option<u16>) -> Result<usize, Error> {
     let mut w = WriteCursor::new(packet);
     if header.is_some() {
@@ -938,7 +2252,11 @@ pub struct ChannelStorage<P> {
 
     peer_cid: u16,
     peer_credits: u16,
+    peer_mps: u16,
+    peer_mtu: u16,
     credit_waker: WakerRegistration,
+    enhanced: bool,
+    reconfigure: ReconfigureState,
 
     inbound: PacketChannel<P, { config::L2CAP_RX_QUEUE_SIZE }>,
     #[cfg(not(feature = "l2cap-sdu-reassembly-optimization"))]
@@ -1018,6 +2336,9 @@ impl<P> core::fmt::Debug for ChannelStorage<P> {
             .field("peer_cid", &self.peer_cid)
             .field("mps", &self.mps)
             .field("mtu", &self.mtu)
+            .field("peer_mps", &self.peer_mps)
+            .field("peer_mtu", &self.peer_mtu)
+            .field("enhanced", &self.enhanced)
             .field("peer_credits", &self.peer_credits)
             .field("available", &self.flow_control.available())
             .field("refcount", &self.refcount);
@@ -1032,13 +2353,15 @@ impl<P> defmt::Format for ChannelStorage<P> {
     fn format(&self, f: defmt::Formatter<'_>) {
         defmt::write!(
             f,
-            "state = {}, c = {}, cid = {}, peer = {}, mps = {}, mtu = {}, cred out {}, cred in = {}, ref = {}",
+            "state = {}, c = {}, cid = {}, peer = {}, mps = {}/{}, mtu = {}/{}, cred out {}, cred in = {}, ref = {}",
             self.state,
             self.conn,
             self.cid,
             self.peer_cid,
             self.mps,
+            self.peer_mps,
             self.mtu,
+            self.peer_mtu,
             self.peer_credits,
             self.flow_control.available(),
             self.refcount,
@@ -1061,7 +2384,11 @@ impl<P> ChannelStorage<P> {
             flow_control: CreditFlowControl::new(CreditFlowPolicy::Every(1), 0),
             peer_cid: 0,
             peer_credits: 0,
+            peer_mps: 0,
+            peer_mtu: 0,
             credit_waker: WakerRegistration::new(),
+            enhanced: false,
+            reconfigure: ReconfigureState::Idle,
             refcount: 0,
             inbound: PacketChannel::new(),
             #[cfg(not(feature = "l2cap-sdu-reassembly-optimization"))]
@@ -1081,9 +2408,22 @@ impl<P> ChannelStorage<P> {
         self.peer_cid = 0;
         self.flow_control = CreditFlowControl::new(CreditFlowPolicy::Every(1), 0);
         self.peer_credits = 0;
+        self.peer_mps = 0;
+        self.peer_mtu = 0;
+        self.enhanced = false;
+        self.reconfigure = ReconfigureState::Idle;
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum ReconfigureState {
+    Idle,
+    Pending { identifier: u8, mtu: u16, mps: u16 },
+    Done(u16),
+    Rejected(u16),
+}
+
 #[derive(Debug, PartialEq, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ChannelState {
@@ -1091,8 +2431,16 @@ pub enum ChannelState {
     Connecting(u8),
     PeerConnecting(u8),
     Connected,
-    PeerDisconnecting,
-    Disconnecting,
+    /// The peer requested a disconnection with the given identifier, which is echoed in the response.
+    PeerDisconnecting(u8),
+    /// A disconnection request with the given identifier is waiting to be sent.
+    Disconnecting(u8),
+    /// A disconnection request with the given identifier was sent, and the channel is waiting for the response.
+    DisconnectRequested(u8),
+    /// The peer refused the connection request.
+    Refused,
+    /// The peer rejected the connection request command with the given reason.
+    Rejected(u16),
 }
 
 /// Control how credits are issued by the receiving end.
@@ -1103,6 +2451,8 @@ pub enum CreditFlowPolicy {
     Every(u16),
     /// Issue credits when below a threshold
     MinThreshold(u16),
+    /// Only issue credits when explicitly granted by the application
+    Manual,
 }
 
 impl Default for CreditFlowPolicy {
@@ -1111,6 +2461,21 @@ impl Default for CreditFlowPolicy {
     }
 }
 
+/// Security required from a connection before an L2CAP listener accepts its channels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum L2capSecurityLevel {
+    /// No security required.
+    #[default]
+    None,
+    /// The connection must be encrypted.
+    Encrypted,
+    /// The connection must be encrypted with an authenticated key.
+    ///
+    /// The host only supports unauthenticated pairing, so requests to such listeners are always refused.
+    Authenticated,
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub(crate) struct CreditFlowControl {
@@ -1159,6 +2524,7 @@ impl CreditFlowControl {
                     None
                 }
             }
+            CreditFlowPolicy::Manual => None,
         }
     }
 }
@@ -1207,10 +2573,13 @@ mod tests {
     extern crate std;
 
     use bt_hci::param::{AddrKind, BdAddr, LeConnRole, Status};
+    use embassy_futures::join::join;
 
     use super::*;
+    use crate::codec::Encode;
     use crate::mock_controller::MockController;
     use crate::prelude::DefaultPacketPool;
+    use crate::types::l2cap::{CommandRejectReason, L2CAP_EXTENDED_FEATURES};
     use crate::HostResources;
 
     #[test]
@@ -1244,4 +2613,833 @@ mod tests {
             Poll::Ready(Err(BleHostError::BleHost(Error::Disconnected)))
         ));
     }
+
+    fn signal<S: Encode>(code: L2capSignalCode, identifier: u8, signal: &S) -> std::vec::Vec<u8> {
+        let mut data = std::vec![0; 4 + signal.size()];
+        data[0] = code as u8;
+        data[1] = identifier;
+        data[2..4].copy_from_slice(&(signal.size() as u16).to_le_bytes());
+        signal.encode(&mut data[4..]).unwrap();
+        data
+    }
+
+    #[test]
+    fn enhanced_connect_request() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Peripheral)
+            .unwrap();
+
+        let req = CreditConnReq {
+            spsm: 0x80,
+            mtu: 100,
+            mps: 80,
+            credits: 10,
+            scids: Vec::from_slice(&[0x40, 0x41]).unwrap(),
+        };
+        ble.channels
+            .signal(conn, &signal(L2capSignalCode::CreditConnReq, 7, &req), &ble.connections)
+            .unwrap();
+
+        let state = ble.channels.state.borrow();
+        for (storage, scid) in state.channels.iter().zip([0x40, 0x41]) {
+            assert_eq!(storage.state, ChannelState::PeerConnecting(7));
+            assert!(storage.enhanced);
+            assert_eq!(storage.psm, 0x80);
+            assert_eq!(storage.peer_cid, scid);
+            assert_eq!(storage.peer_mtu, 100);
+            assert_eq!(storage.peer_mps, 80);
+        }
+        drop(state);
+
+        // Not enough channels left, the whole request is refused.
+        let req = CreditConnReq {
+            spsm: 0x80,
+            mtu: 100,
+            mps: 80,
+            credits: 10,
+            scids: Vec::from_slice(&[0x42]).unwrap(),
+        };
+        ble.channels
+            .signal(conn, &signal(L2capSignalCode::CreditConnReq, 8, &req), &ble.connections)
+            .unwrap();
+        let pending = ble.channels.signals.try_receive().unwrap();
+        assert_eq!(pending.identifier, 8);
+        let SignalResponse::CreditConn(res) = pending.response else {
+            panic!("unexpected response");
+        };
+        assert_eq!(res.result, LeCreditConnResultCode::NoResources as u16);
+        assert_eq!(res.dcids.as_slice(), &[0]);
+    }
+
+    #[test]
+    fn enhanced_connect_request_invalid_scids() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Peripheral)
+            .unwrap();
+
+        let refused = |identifier: u8, scids: &[u16], result: LeCreditConnResultCode| {
+            let req = CreditConnReq {
+                spsm: 0x80,
+                mtu: 100,
+                mps: 80,
+                credits: 10,
+                scids: Vec::from_slice(scids).unwrap(),
+            };
+            ble.channels
+                .signal(
+                    conn,
+                    &signal(L2capSignalCode::CreditConnReq, identifier, &req),
+                    &ble.connections,
+                )
+                .unwrap();
+            let pending = ble.channels.signals.try_receive().unwrap();
+            assert_eq!(pending.identifier, identifier);
+            let SignalResponse::CreditConn(res) = pending.response else {
+                panic!("unexpected response");
+            };
+            assert_eq!(res.result, result as u16);
+            assert_eq!(res.dcids.len(), scids.len());
+            assert!(res.dcids.iter().all(|dcid| *dcid == 0));
+        };
+
+        // Source CIDs outside of the LE dynamic range
+        refused(1, &[0x40, 0x3f], LeCreditConnResultCode::InvalidSourceId);
+        refused(2, &[0x80], LeCreditConnResultCode::InvalidSourceId);
+
+        // The same source CID twice in a request
+        refused(3, &[0x41, 0x41], LeCreditConnResultCode::ScidAlreadyAllocated);
+
+        // A source CID already used by a channel of the connection
+        let req = CreditConnReq {
+            spsm: 0x80,
+            mtu: 100,
+            mps: 80,
+            credits: 10,
+            scids: Vec::from_slice(&[0x40]).unwrap(),
+        };
+        ble.channels
+            .signal(conn, &signal(L2capSignalCode::CreditConnReq, 4, &req), &ble.connections)
+            .unwrap();
+        assert!(ble.channels.signals.try_receive().is_err());
+        refused(5, &[0x41, 0x40], LeCreditConnResultCode::ScidAlreadyAllocated);
+
+        // Nothing was allocated for the refused requests
+        let state = ble.channels.state.borrow();
+        assert_eq!(
+            state
+                .channels
+                .iter()
+                .filter(|storage| storage.state != ChannelState::Disconnected)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn enhanced_connect_partially_refused() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+
+        // Channels allocated for a request, as done by `create_enhanced`
+        let indices: std::vec::Vec<ChannelIndex> = (0..2)
+            .map(|_| {
+                let idx = ble
+                    .channels
+                    .alloc(conn, |storage| {
+                        storage.enhanced = true;
+                        storage.state = ChannelState::Connecting(9);
+                    })
+                    .unwrap();
+                ble.channels.inc_ref(idx);
+                idx
+            })
+            .collect();
+        assert!(ble
+            .channels
+            .poll_created_enhanced(conn, 9, &indices, &ble, None)
+            .is_pending());
+
+        // The peer only accepts the first channel
+        let res = CreditConnRes {
+            mtu: 100,
+            mps: 80,
+            credits: 10,
+            result: LeCreditConnResultCode::NoResources as u16,
+            dcids: Vec::from_slice(&[0x50, 0]).unwrap(),
+        };
+        ble.channels
+            .signal(conn, &signal(L2capSignalCode::CreditConnRes, 9, &res), &ble.connections)
+            .unwrap();
+
+        let Poll::Ready(Ok(channels)) = ble.channels.poll_created_enhanced(conn, 9, &indices, &ble, None) else {
+            panic!("expected the accepted channel to be created");
+        };
+        assert_eq!(channels.len(), 1);
+
+        let state = ble.channels.state.borrow();
+        let accepted = &state.channels[indices[0].0 as usize];
+        assert_eq!(accepted.state, ChannelState::Connected);
+        assert_eq!(accepted.peer_cid, 0x50);
+        assert_eq!(accepted.peer_mtu, 100);
+        assert_eq!(accepted.peer_credits, 10);
+        assert_eq!(accepted.refcount, 1);
+
+        // The refused channel is released
+        let refused = &state.channels[indices[1].0 as usize];
+        assert_eq!(refused.state, ChannelState::Disconnected);
+        assert_eq!(refused.refcount, 0);
+        drop(state);
+    }
+
+    #[test]
+    fn conn_param_update_request() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let central = ConnHandle::new(33);
+        let peripheral = ConnHandle::new(34);
+        ble.connections
+            .connect(central, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        ble.connections
+            .connect(
+                peripheral,
+                AddrKind::PUBLIC,
+                BdAddr::new([1; 6]),
+                LeConnRole::Peripheral,
+            )
+            .unwrap();
+        let Poll::Ready(connection) = ble.connections.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("connection not accepted");
+        };
+
+        let update = |conn, identifier: u8, interval: u16, timeout: u16| {
+            let mut data = std::vec![L2capSignalCode::ConnParamUpdateReq as u8, identifier, 8, 0];
+            for value in [interval, interval, 0, timeout] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            ble.channels.signal(conn, &data, &ble.connections).unwrap();
+        };
+
+        // Valid parameters are handed to the application.
+        update(central, 1, 24, 400);
+        let ConnectionEvent::RequestConnectionParams { request } = embassy_futures::block_on(connection.next()) else {
+            panic!("unexpected event");
+        };
+        let params = request.params();
+        assert_eq!(params.min_connection_interval, embassy_time::Duration::from_millis(30));
+        assert_eq!(params.max_connection_interval, embassy_time::Duration::from_millis(30));
+        assert_eq!(params.supervision_timeout, embassy_time::Duration::from_secs(4));
+        assert!(ble.channels.signals.try_receive().is_err());
+
+        // A request dropped by the application is rejected.
+        drop(request);
+        let pending = ble.channels.signals.try_receive().unwrap();
+        assert_eq!(pending.identifier, 1);
+        let SignalResponse::ConnParamUpdate(res) = pending.response else {
+            panic!("unexpected response");
+        };
+        assert_eq!(res.result, ConnParamUpdateResultCode::Rejected as u16);
+
+        // Out of range interval is rejected by the host.
+        update(central, 2, 3, 400);
+        let pending = ble.channels.signals.try_receive().unwrap();
+        assert_eq!(pending.identifier, 2);
+        let SignalResponse::ConnParamUpdate(res) = pending.response else {
+            panic!("unexpected response");
+        };
+        assert_eq!(res.result, ConnParamUpdateResultCode::Rejected as u16);
+
+        // A peripheral does not understand the request.
+        update(peripheral, 3, 24, 400);
+        let pending = ble.channels.signals.try_receive().unwrap();
+        assert_eq!(pending.identifier, 3);
+        assert!(matches!(pending.response, SignalResponse::CommandReject(_)));
+    }
+
+    #[test]
+    fn central_does_not_request_conn_params() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let stack = crate::new(MockController::new(), &mut resources);
+
+        let conn = ConnHandle::new(33);
+        stack
+            .host
+            .connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        let Poll::Ready(connection) = stack.host.connections.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("connection not accepted");
+        };
+
+        let res = embassy_futures::block_on(connection.request_connection_params(&stack, &Default::default()));
+        assert!(matches!(res, Err(BleHostError::BleHost(Error::InvalidState))));
+        assert!(stack.host.channels.signals.try_receive().is_err());
+    }
+
+    #[test]
+    fn signal_unknown_code() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+
+        // Unknown codes are not understood, whether or not they carry a payload.
+        for (identifier, data) in [
+            (4, &[0x30, 4, 0, 0][..]),
+            (5, &[0x00, 5, 2, 0, 1, 2][..]),
+            (6, &[0xff, 6, 4, 0, 1, 2, 3, 4][..]),
+        ] {
+            ble.channels.signal(conn, data, &ble.connections).unwrap();
+            let pending = ble.channels.signals.try_receive().unwrap();
+            assert_eq!(pending.identifier, identifier);
+            let SignalResponse::CommandReject(res) = pending.response else {
+                panic!("unexpected response");
+            };
+            assert_eq!(res.reason, CommandRejectReason::NotUnderstood as u16);
+            assert!(res.data.is_empty());
+        }
+        assert!(ble.channels.signals.try_receive().is_err());
+    }
+
+    #[test]
+    fn signal_rejects() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+
+        let reject = |data: &[u8]| {
+            ble.channels.signal(conn, data, &ble.connections).unwrap();
+            let pending = ble.channels.signals.try_receive().unwrap();
+            let SignalResponse::CommandReject(res) = pending.response else {
+                panic!("unexpected response");
+            };
+            (pending.identifier, res)
+        };
+
+        // Disconnect request for a channel which does not exist.
+        let (identifier, res) = reject(&[L2capSignalCode::DisconnectionReq as u8, 5, 4, 0, 0x40, 0, 0x41, 0]);
+        assert_eq!(identifier, 5);
+        assert_eq!(res.reason, CommandRejectReason::InvalidCid as u16);
+        assert_eq!(res.data.as_slice(), &[0x40, 0, 0x41, 0]);
+
+        // Command larger than the signaling MTU.
+        let mut data = std::vec![L2capSignalCode::EchoReq as u8, 6, 28, 0];
+        data.resize(32, 0);
+        let (identifier, res) = reject(&data);
+        assert_eq!(identifier, 6);
+        assert_eq!(res.reason, CommandRejectReason::MtuExceeded as u16);
+        assert_eq!(res.data.as_slice(), &[L2CAP_LE_SIG_MTU as u8, 0]);
+
+        // Information request for the extended features.
+        ble.channels
+            .signal(
+                conn,
+                &[L2capSignalCode::InformationReq as u8, 7, 2, 0, 2, 0],
+                &ble.connections,
+            )
+            .unwrap();
+        let pending = ble.channels.signals.try_receive().unwrap();
+        let SignalResponse::Information(res) = pending.response else {
+            panic!("unexpected response");
+        };
+        assert_eq!(res.result, 0);
+        assert_eq!(res.data.as_slice(), &L2CAP_EXTENDED_FEATURES.to_le_bytes());
+
+        // A rejected connect request fails the pending channel.
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.state = ChannelState::Connecting(8);
+            })
+            .unwrap();
+        ble.channels
+            .signal(
+                conn,
+                &[L2capSignalCode::CommandRejectRes as u8, 8, 2, 0, 0, 0],
+                &ble.connections,
+            )
+            .unwrap();
+        assert!(ble.channels.signals.try_receive().is_err());
+        let chan = ble.channels.poll_created(conn, idx, &ble, None);
+        assert!(matches!(
+            chan,
+            Poll::Ready(Err(BleHostError::BleHost(Error::CommandRejected(0))))
+        ));
+    }
+
+    #[test]
+    fn disconnect_identifiers() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        let connected = || {
+            let idx = ble
+                .channels
+                .alloc(conn, |storage| {
+                    storage.state = ChannelState::Connected;
+                    storage.peer_cid = 0x50;
+                })
+                .unwrap();
+            ble.channels.inc_ref(idx);
+            idx
+        };
+        let state = |idx: ChannelIndex| ble.channels.state.borrow().channels[idx.0 as usize].state.clone();
+        let response = |identifier: u8| {
+            [
+                L2capSignalCode::DisconnectionRes as u8,
+                identifier,
+                4,
+                0,
+                0x50,
+                0,
+                0x40,
+                0,
+            ]
+        };
+
+        // The identifier of a disconnection request is kept for the response.
+        let idx = connected();
+        ble.channels
+            .signal(
+                conn,
+                &[L2capSignalCode::DisconnectionReq as u8, 9, 4, 0, 0x40, 0, 0x50, 0],
+                &ble.connections,
+            )
+            .unwrap();
+        assert_eq!(state(idx), ChannelState::PeerDisconnecting(9));
+        let Poll::Ready(request) = ble.channels.poll_disconnecting(None) else {
+            panic!("no disconnection pending");
+        };
+        request.confirm();
+        assert_eq!(state(idx), ChannelState::Disconnected);
+        ble.channels.dec_ref(idx);
+
+        // A local disconnection completes when the response with the same identifier is received.
+        let idx = connected();
+        let (res, _) = embassy_futures::block_on(join(
+            ble.channels.disconnect_and_wait(idx, Duration::from_secs(1)),
+            async {
+                let Poll::Ready(request) = ble.channels.poll_disconnecting(None) else {
+                    panic!("no disconnection pending");
+                };
+                let ChannelState::Disconnecting(identifier) = state(idx) else {
+                    panic!("channel not disconnecting");
+                };
+                request.confirm();
+                assert_eq!(state(idx), ChannelState::DisconnectRequested(identifier));
+
+                // A response with another identifier is ignored.
+                ble.channels
+                    .signal(conn, &response(identifier.wrapping_add(1)), &ble.connections)
+                    .unwrap();
+                assert_eq!(state(idx), ChannelState::DisconnectRequested(identifier));
+
+                ble.channels
+                    .signal(conn, &response(identifier), &ble.connections)
+                    .unwrap();
+            },
+        ));
+        assert!(res.is_ok());
+        assert_eq!(state(idx), ChannelState::Disconnected);
+        ble.channels.dec_ref(idx);
+
+        // Without a response, the disconnection times out and the channel is closed.
+        let idx = connected();
+        let (res, _) = embassy_futures::block_on(join(
+            ble.channels.disconnect_and_wait(idx, Duration::from_millis(10)),
+            async {
+                let Poll::Ready(request) = ble.channels.poll_disconnecting(None) else {
+                    panic!("no disconnection pending");
+                };
+                request.confirm();
+            },
+        ));
+        assert!(matches!(res, Err(Error::Timeout)));
+        assert_eq!(state(idx), ChannelState::Disconnected);
+        ble.channels.dec_ref(idx);
+        assert!(ble.channels.poll_disconnecting(None).is_pending());
+    }
+
+    #[test]
+    fn listener_refuses_requests() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 4> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Peripheral)
+            .unwrap();
+
+        let open = L2capSecurityLevel::None;
+        assert_eq!(ble.channels.listen(None, open, 1), Ok(DYNAMIC_PSM_START));
+        assert_eq!(ble.channels.listen(None, open, 1), Ok(DYNAMIC_PSM_START + 1));
+        assert_eq!(
+            ble.channels.listen(Some(0x25), L2capSecurityLevel::Encrypted, 1),
+            Ok(0x25)
+        );
+        assert_eq!(ble.channels.listen(Some(0x25), open, 1), Err(Error::InvalidState));
+        ble.channels.unlisten(DYNAMIC_PSM_START + 1);
+
+        let connect = |identifier: u8, psm: u16, scid: u16| {
+            let mut data = std::vec![L2capSignalCode::LeCreditConnReq as u8, identifier, 10, 0];
+            for value in [psm, scid, 64, 64, 1] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            ble.channels.signal(conn, &data, &ble.connections).unwrap();
+            match ble.channels.signals.try_receive() {
+                Ok(PendingSignal {
+                    response: SignalResponse::LeCreditConn(res),
+                    ..
+                }) => Some(res.result as u16),
+                Ok(_) => panic!("unexpected response"),
+                Err(_) => None,
+            }
+        };
+
+        // No listener registered for the PSM.
+        assert_eq!(
+            connect(1, DYNAMIC_PSM_START + 1, 0x40),
+            Some(LeCreditConnResultCode::SpsmNotSupported as u16)
+        );
+        // The connection is not encrypted.
+        assert_eq!(
+            connect(2, 0x25, 0x41),
+            Some(LeCreditConnResultCode::InsufficientEncryption as u16)
+        );
+        // Queued until accepted, then the backlog is full.
+        assert_eq!(connect(3, DYNAMIC_PSM_START, 0x42), None);
+        assert_eq!(
+            connect(4, DYNAMIC_PSM_START, 0x43),
+            Some(LeCreditConnResultCode::NoResources as u16)
+        );
+
+        // Unregistering the listener refuses the pending request.
+        ble.channels.unlisten(DYNAMIC_PSM_START);
+        let pending = ble.channels.signals.try_receive().unwrap();
+        assert_eq!(pending.identifier, 3);
+        assert!(matches!(
+            pending.response,
+            SignalResponse::LeCreditConn(LeCreditConnRes {
+                result: LeCreditConnResultCode::SpsmNotSupported,
+                ..
+            })
+        ));
+    }
+
+    #[cfg(feature = "gatt")]
+    #[test]
+    fn listener_queues_eatt_requests() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 4> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Peripheral)
+            .unwrap();
+        assert_eq!(
+            ble.channels.listen(None, L2capSecurityLevel::None, 1),
+            Ok(DYNAMIC_PSM_START)
+        );
+
+        let connect = |identifier: u8, scid: u16| {
+            let req = CreditConnReq {
+                spsm: crate::gatt::EATT_PSM,
+                mtu: 64,
+                mps: 64,
+                credits: 1,
+                scids: Vec::from_slice(&[scid]).unwrap(),
+            };
+            ble.channels
+                .signal(
+                    conn,
+                    &signal(L2capSignalCode::CreditConnReq, identifier, &req),
+                    &ble.connections,
+                )
+                .unwrap();
+            ble.channels.signals.try_receive().ok()
+        };
+
+        // Enhanced ATT bearers are queued for the GATT server even though another listener is registered.
+        assert!(connect(1, 0x40).is_none());
+        let state = ble.channels.state.borrow();
+        assert_eq!(state.channels[0].state, ChannelState::PeerConnecting(1));
+        assert_eq!(state.channels[0].psm, crate::gatt::EATT_PSM);
+        drop(state);
+
+        // A listener registered for the Enhanced ATT PSM applies its security requirement.
+        assert_eq!(
+            ble.channels
+                .listen(Some(crate::gatt::EATT_PSM), L2capSecurityLevel::Encrypted, 1),
+            Ok(crate::gatt::EATT_PSM)
+        );
+        let pending = connect(2, 0x41).unwrap();
+        assert_eq!(pending.identifier, 2);
+        let SignalResponse::CreditConn(res) = pending.response else {
+            panic!("unexpected response");
+        };
+        assert_eq!(res.result, LeCreditConnResultCode::InsufficientEncryption as u16);
+    }
+
+    #[test]
+    fn send_in_place_segments() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+        ble.initialize(251, 16);
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        let Poll::Ready(_connection) = ble.connections.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        let channel = ble.channels.open(conn, 40, 16, 10);
+
+        let data: std::vec::Vec<u8> = (0..40).collect();
+        let mut packet = DefaultPacketPool::allocate().unwrap();
+        packet.as_mut()[TX_SDU_HEADROOM..TX_SDU_HEADROOM + data.len()].copy_from_slice(&data);
+        embassy_futures::block_on(
+            ble.channels
+                .send_in_place(channel.index(), packet.as_mut(), data.len(), &ble),
+        )
+        .unwrap();
+
+        // The first K-frame starts with the SDU length, and every K-frame is limited by the MPS.
+        let frames = ble.controller.take_acl();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0][..6], [16, 0, 0x50, 0, 40, 0]);
+        assert_eq!(frames[0][6..], data[..14]);
+        assert_eq!(frames[1][..4], [16, 0, 0x50, 0]);
+        assert_eq!(frames[1][4..], data[14..30]);
+        assert_eq!(frames[2][..4], [10, 0, 0x50, 0]);
+        assert_eq!(frames[2][4..], data[30..]);
+
+        // An SDU larger than the MTU is refused without sending anything.
+        let res = embassy_futures::block_on(ble.channels.send_in_place(channel.index(), packet.as_mut(), 41, &ble));
+        assert!(matches!(res, Err(BleHostError::BleHost(Error::InsufficientSpace))));
+        assert!(ble.controller.take_acl().is_empty());
+    }
+
+    #[test]
+    fn frame_without_awaiting() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+        ble.initialize(251, 16);
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        let Poll::Ready(_connection) = ble.connections.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        let channel = ble.channels.open(conn, 40, 16, 1);
+
+        let pdu = ble.channels.try_frame(channel.index(), &[1, 2, 3]).unwrap();
+        assert_eq!(pdu.as_ref(), [5, 0, 0x50, 0, 3, 0, 1, 2, 3]);
+
+        // The only credit was consumed by the first frame.
+        let res = ble.channels.try_frame(channel.index(), &[1, 2, 3]);
+        assert!(matches!(res, Err(Error::Busy)));
+
+        // An SDU which does not fit in a single K-frame is refused.
+        let res = ble.channels.try_frame(channel.index(), &[0; 15]);
+        assert!(matches!(res, Err(Error::InsufficientSpace)));
+    }
+
+    #[test]
+    fn fixed_channel() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+
+        assert_eq!(ble.channels.register_fixed(conn, 0x04), Err(Error::InvalidChannelId));
+        assert_eq!(ble.channels.register_fixed(conn, 0x40), Err(Error::InvalidChannelId));
+        ble.channels.register_fixed(conn, 0x20).unwrap();
+        assert_eq!(ble.channels.register_fixed(conn, 0x20), Err(Error::InvalidState));
+        assert!(ble.channels.is_fixed(conn, 0x20));
+        assert!(!ble.channels.is_fixed(ConnHandle::new(34), 0x20));
+
+        let mut packet = DefaultPacketPool::allocate().unwrap();
+        packet.as_mut()[..3].copy_from_slice(&[1, 2, 3]);
+        ble.channels.dispatch_fixed(conn, 0x20, Pdu::new(packet, 3)).unwrap();
+        let pdu = embassy_futures::block_on(ble.channels.receive_fixed(conn, 0x20)).unwrap();
+        assert_eq!(pdu.as_ref(), &[1, 2, 3]);
+
+        // Frames are no longer accepted once the connection is closed.
+        ble.channels.disconnected(conn).unwrap();
+        assert!(!ble.channels.is_fixed(conn, 0x20));
+        assert!(matches!(
+            embassy_futures::block_on(ble.channels.receive_fixed(conn, 0x20)),
+            Err(Error::ChannelClosed)
+        ));
+
+        // The CID can be registered again once unregistered.
+        ble.channels.unregister_fixed(conn, 0x20);
+        ble.channels.register_fixed(conn, 0x20).unwrap();
+    }
+
+    #[test]
+    fn credit_flow_policy() {
+        let mut flow = CreditFlowControl::new(CreditFlowPolicy::Every(2), 4);
+        flow.confirm_received(2);
+        assert_eq!(flow.process(), Some(2));
+
+        // Credits are only granted by the application with a manual policy.
+        flow.policy = CreditFlowPolicy::Manual;
+        flow.confirm_received(2);
+        assert_eq!(flow.available(), 0);
+        assert_eq!(flow.process(), None);
+        flow.confirm_granted(1);
+        assert_eq!(flow.available(), 1);
+
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.state = ChannelState::Connected;
+                storage.peer_credits = 3;
+                storage.flow_control = CreditFlowControl::new(CreditFlowPolicy::Manual, 5);
+            })
+            .unwrap();
+        assert_eq!(ble.channels.tx_credits(idx), 3);
+        assert_eq!(ble.channels.rx_credits(idx), 5);
+
+        ble.channels.disconnected(conn).unwrap();
+        assert_eq!(ble.channels.tx_credits(idx), 0);
+        assert_eq!(ble.channels.rx_credits(idx), 0);
+    }
+
+    #[test]
+    fn enhanced_reconfigure_request() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.peer_cid = 0x50;
+                storage.peer_mtu = 100;
+                storage.peer_mps = 80;
+                storage.enhanced = true;
+                storage.state = ChannelState::Connected;
+            })
+            .unwrap();
+
+        let reconfigure = |mtu, dcid| {
+            let req = CreditConnReconfigReq {
+                mtu,
+                mps: 64,
+                dcids: Vec::from_slice(&[dcid]).unwrap(),
+            };
+            ble.channels
+                .signal(
+                    conn,
+                    &signal(L2capSignalCode::CreditConnReconfigReq, 3, &req),
+                    &ble.connections,
+                )
+                .unwrap();
+            let pending = ble.channels.signals.try_receive().unwrap();
+            let SignalResponse::CreditConnReconfig(res) = pending.response else {
+                panic!("unexpected response");
+            };
+            res.result
+        };
+
+        assert_eq!(
+            reconfigure(90, 0x50),
+            CreditConnReconfigResultCode::MtuReductionNotAllowed as u16
+        );
+        assert_eq!(
+            reconfigure(200, 0x51),
+            CreditConnReconfigResultCode::InvalidDestinationCid as u16
+        );
+        assert_eq!(reconfigure(200, 0x50), CreditConnReconfigResultCode::Success as u16);
+
+        let state = ble.channels.state.borrow();
+        let storage = &state.channels[idx.0 as usize];
+        assert_eq!(storage.peer_mtu, 200);
+        assert_eq!(storage.peer_mps, 64);
+    }
 }
@@ -49,6 +49,21 @@ pub const L2CAP_TX_QUEUE_SIZE: usize = raw::L2CAP_TX_QUEUE_SIZE;
 /// Default: 8.
 pub const L2CAP_RX_QUEUE_SIZE: usize = raw::L2CAP_RX_QUEUE_SIZE;
 
+/// L2CAP listeners
+///
+/// This is the maximum number of L2CAP listeners that can be registered at the same time.
+///
+/// Default: 4.
+pub const L2CAP_LISTENERS_MAX: usize = raw::L2CAP_LISTENERS_MAX;
+
+/// L2CAP fixed channels
+///
+/// This is the maximum number of user fixed L2CAP channels that can be registered at the same time,
+/// across all connections.
+///
+/// Default: 2.
+pub const L2CAP_FIXED_CHANNELS_MAX: usize = raw::L2CAP_FIXED_CHANNELS_MAX;
+
 /// L2CAP default packet pool size
 ///
 /// This is the default packet pool size of all l2cap channels. There has to be at least
@@ -64,6 +79,14 @@ pub const DEFAULT_PACKET_POOL_SIZE: usize = raw::DEFAULT_PACKET_POOL_SIZE;
 /// Default: 251.
 pub const DEFAULT_PACKET_POOL_MTU: usize = raw::DEFAULT_PACKET_POOL_MTU;
 
+/// GATT server prepared write queue size.
+///
+/// This is the size in bytes of the queue holding prepared writes for each connection, until they are
+/// executed or cancelled by the client. Every prepared write takes 6 bytes in addition to its value.
+///
+/// Default: 256.
+pub const GATT_SERVER_PREPARE_WRITE_QUEUE_SIZE: usize = raw::GATT_SERVER_PREPARE_WRITE_QUEUE_SIZE;
+
 /// Default: 1.
 pub const GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS: usize = raw::GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS;
 
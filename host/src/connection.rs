@@ -1,6 +1,8 @@
 //! BLE connection.
 
-use bt_hci::cmd::le::{LeConnUpdate, LeReadPhy, LeSetPhy};
+use core::future::poll_fn;
+
+use bt_hci::cmd::le::{LeConnUpdate, LeReadLocalSupportedFeatures, LeReadPhy, LeSetPhy};
 use bt_hci::cmd::status::ReadRssi;
 use bt_hci::controller::{ControllerCmdAsync, ControllerCmdSync};
 use bt_hci::param::{
@@ -8,8 +10,9 @@ use bt_hci::param::{
 };
 #[cfg(feature = "gatt")]
 use embassy_sync::blocking_mutex::raw::RawMutex;
-use embassy_time::Duration;
+use embassy_time::{Duration, WithTimeout};
 
+use crate::channel_manager::{queue_conn_params_reject, SignalQueue, L2CAP_RTX_TIMEOUT};
 use crate::connection_manager::ConnectionManager;
 #[cfg(feature = "connection-metrics")]
 pub use crate::connection_manager::Metrics as ConnectionMetrics;
@@ -18,7 +21,8 @@ use crate::pdu::Pdu;
 use crate::prelude::{AttributeServer, GattConnection};
 #[cfg(feature = "security")]
 use crate::security_manager::BondInformation;
-use crate::{BleHostError, Error, Identity, PacketPool, Stack};
+use crate::types::l2cap::{ConnParamUpdateReq, ConnParamUpdateRes, ConnParamUpdateResultCode};
+use crate::{BleHostError, Controller, Error, Identity, PacketPool, Stack};
 
 /// Connection configuration.
 pub struct ConnectConfig<'d> {
@@ -116,12 +120,144 @@ pub enum ConnectionEvent {
         /// Supervision timeout.
         supervision_timeout: Duration,
     },
+    /// The ATT MTU was negotiated for this connection.
+    AttMtuUpdated {
+        /// The negotiated ATT MTU.
+        mtu: u16,
+    },
     #[cfg(feature = "security")]
     /// Bonded event.
     Bonded {
         /// Bond info for this connection
         bond_info: BondInformation,
     },
+    /// The peripheral requested new connection parameters.
+    RequestConnectionParams {
+        /// The request, which must be accepted or rejected.
+        request: ConnectionParamsRequest,
+    },
+}
+
+/// Connection parameters requested by the peripheral using the L2CAP connection parameter update procedure.
+///
+/// The peripheral waits for a response, so the request should always be either accepted or rejected. A request
+/// which is dropped without a response is rejected.
+pub struct ConnectionParamsRequest {
+    handle: ConnHandle,
+    identifier: u8,
+    req: ConnParamUpdateReq,
+    signals: &'static SignalQueue,
+    responded: bool,
+}
+
+impl core::fmt::Debug for ConnectionParamsRequest {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ConnectionParamsRequest")
+            .field("handle", &self.handle)
+            .field("identifier", &self.identifier)
+            .field("req", &self.req)
+            .finish()
+    }
+}
+
+impl ConnectionParamsRequest {
+    pub(crate) fn new(
+        handle: ConnHandle,
+        identifier: u8,
+        req: ConnParamUpdateReq,
+        signals: &'static SignalQueue,
+    ) -> Self {
+        Self {
+            handle,
+            identifier,
+            req,
+            signals,
+            responded: false,
+        }
+    }
+
+    /// The requested connection parameters.
+    pub fn params(&self) -> ConnectParams {
+        ConnectParams {
+            min_connection_interval: Duration::from_micros(self.req.interval_min as u64 * 1250),
+            max_connection_interval: Duration::from_micros(self.req.interval_max as u64 * 1250),
+            max_latency: self.req.latency,
+            event_length: Duration::from_secs(0),
+            supervision_timeout: Duration::from_millis(self.req.timeout as u64 * 10),
+        }
+    }
+
+    /// Accept the request and update the connection to the requested parameters.
+    pub async fn accept<T: Controller, P: PacketPool>(
+        mut self,
+        stack: &Stack<'_, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        self.respond(stack, ConnParamUpdateResultCode::Accepted).await?;
+        conn_update(stack, self.handle, &self.params()).await
+    }
+
+    /// Reject the request, keeping the current connection parameters.
+    pub async fn reject<T: Controller, P: PacketPool>(
+        mut self,
+        stack: &Stack<'_, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        self.respond(stack, ConnParamUpdateResultCode::Rejected).await
+    }
+
+    async fn respond<T: Controller, P: PacketPool>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        result: ConnParamUpdateResultCode,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let res = ConnParamUpdateRes { result: result as u16 };
+        let mut tx = [0; 16];
+        stack
+            .host
+            .l2cap_signal(self.handle, self.identifier, &res, &mut tx[..])
+            .await?;
+        self.responded = true;
+        Ok(())
+    }
+}
+
+impl Drop for ConnectionParamsRequest {
+    fn drop(&mut self) {
+        if !self.responded && queue_conn_params_reject(self.signals, self.handle, self.identifier).is_err() {
+            warn!(
+                "[host] unable to reject dropped connection param update request on {:?}",
+                self.handle
+            );
+        }
+    }
+}
+
+async fn conn_update<T, P: PacketPool>(
+    stack: &Stack<'_, T, P>,
+    handle: ConnHandle,
+    params: &ConnectParams,
+) -> Result<(), BleHostError<T::Error>>
+where
+    T: ControllerCmdAsync<LeConnUpdate>,
+{
+    match stack
+        .host
+        .async_command(LeConnUpdate::new(
+            handle,
+            params.min_connection_interval.into(),
+            params.max_connection_interval.into(),
+            params.max_latency,
+            params.supervision_timeout.into(),
+            params.event_length.into(),
+            params.event_length.into(),
+        ))
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(BleHostError::BleHost(crate::Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => {
+            Err(crate::Error::Disconnected.into())
+        }
+        Err(e) => Err(e),
+    }
 }
 
 impl Default for ConnectParams {
@@ -170,6 +306,10 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         self.manager.get_att_mtu(self.index)
     }
 
+    pub(crate) fn exchange_att_mtu(&self, mtu: u16) -> u16 {
+        self.manager.exchange_att_mtu(self.handle(), mtu)
+    }
+
     pub(crate) async fn send(&self, pdu: Pdu<P::Packet>) {
         self.manager.send(self.index, pdu).await
     }
@@ -192,6 +332,11 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         self.manager.next_gatt(self.index).await
     }
 
+    #[cfg(feature = "gatt")]
+    pub(crate) async fn next_gatt_client(&self) -> Pdu<P::Packet> {
+        self.manager.next_gatt_client(self.index).await
+    }
+
     /// Check if still connected
     pub fn is_connected(&self) -> bool {
         self.manager.is_connected(self.index)
@@ -306,25 +451,55 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
     where
         T: ControllerCmdAsync<LeConnUpdate>,
     {
-        let handle = self.handle();
-        match stack
+        conn_update(stack, self.handle(), params).await
+    }
+
+    /// Request new connection parameters from the central, as the peripheral of this connection.
+    ///
+    /// The link layer connection parameters request procedure is used if the controller supports it. Otherwise,
+    /// the request is sent using the L2CAP connection parameter update procedure, and this waits for the
+    /// central to respond. Returns `Error::Rejected` if the central rejected the parameters, `Error::Timeout`
+    /// if it did not respond, or `Error::InvalidState` if this is not the peripheral of the connection.
+    pub async fn request_connection_params<T>(
+        &self,
+        stack: &Stack<'_, T, P>,
+        params: &ConnectParams,
+    ) -> Result<(), BleHostError<T::Error>>
+    where
+        T: Controller + ControllerCmdSync<LeReadLocalSupportedFeatures>,
+    {
+        if self.role() != LeConnRole::Peripheral {
+            return Err(Error::InvalidState.into());
+        }
+        let features = stack.host.command(LeReadLocalSupportedFeatures::new()).await?;
+        if features.supports_conn_parameters_request_procedure() {
+            match conn_update(stack, self.handle(), params).await {
+                Err(BleHostError::BleHost(Error::Hci(bt_hci::param::Error::UNSUPPORTED_REMOTE_FEATURE))) => {}
+                r => return r,
+            }
+        }
+
+        let identifier = stack.host.channels.next_request_id();
+        let req = ConnParamUpdateReq {
+            interval_min: (params.min_connection_interval.as_micros() / 1250) as u16,
+            interval_max: (params.max_connection_interval.as_micros() / 1250) as u16,
+            latency: params.max_latency,
+            timeout: (params.supervision_timeout.as_millis() / 10) as u16,
+        };
+        self.manager.start_param_update(self.index, identifier);
+        let mut tx = [0; 16];
+        stack
             .host
-            .async_command(LeConnUpdate::new(
-                handle,
-                params.min_connection_interval.into(),
-                params.max_connection_interval.into(),
-                params.max_latency,
-                params.supervision_timeout.into(),
-                params.event_length.into(),
-                params.event_length.into(),
-            ))
+            .l2cap_signal(self.handle(), identifier, &req, &mut tx[..])
+            .await?;
+        let result = poll_fn(|cx| self.manager.poll_param_update(self.index, cx))
+            .with_timeout(L2CAP_RTX_TIMEOUT)
             .await
-        {
-            Ok(_) => Ok(()),
-            Err(BleHostError::BleHost(crate::Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => {
-                Err(crate::Error::Disconnected.into())
-            }
-            Err(e) => Err(e),
+            .map_err(|_| Error::Timeout)??;
+        if result == ConnParamUpdateResultCode::Accepted as u16 {
+            Ok(())
+        } else {
+            Err(Error::Rejected.into())
         }
     }
 
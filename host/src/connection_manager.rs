@@ -94,7 +94,19 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
     }
 
     pub(crate) async fn next(&self, index: u8) -> ConnectionEvent {
-        poll_fn(|cx| self.with_mut(|state| state.connections[index as usize].events.poll_receive(cx))).await
+        poll_fn(|cx| {
+            self.with_mut(|state| {
+                let storage = &mut state.connections[index as usize];
+                // Reported outside of the event queue, so that an update is never lost to a full queue.
+                if storage.att_mtu_updated {
+                    storage.att_mtu_updated = false;
+                    return Poll::Ready(ConnectionEvent::AttMtuUpdated { mtu: storage.att_mtu });
+                }
+                storage.att_mtu_waker.register(cx.waker());
+                storage.events.poll_receive(cx)
+            })
+        })
+        .await
     }
 
     #[cfg(feature = "gatt")]
@@ -102,6 +114,11 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         poll_fn(|cx| self.with_mut(|state| state.connections[index as usize].gatt.poll_receive(cx))).await
     }
 
+    #[cfg(feature = "gatt")]
+    pub(crate) async fn next_gatt_client(&self, index: u8) -> Pdu<P::Packet> {
+        poll_fn(|cx| self.with_mut(|state| state.connections[index as usize].gatt_client.poll_receive(cx))).await
+    }
+
     pub(crate) async fn post_event(&self, index: u8, event: ConnectionEvent) {
         poll_fn(|cx| self.with_mut(|state| state.connections[index as usize].events.poll_ready_to_send(cx))).await;
         self.with_mut(|state| state.connections[index as usize].events.try_send(event).unwrap());
@@ -132,6 +149,19 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         })
     }
 
+    #[cfg(feature = "gatt")]
+    pub(crate) fn post_gatt_client(&self, handle: ConnHandle, pdu: Pdu<P::Packet>) -> Result<(), Error> {
+        self.with_mut(|state| {
+            for entry in state.connections.iter() {
+                if entry.state == ConnectionState::Connected && Some(handle) == entry.handle {
+                    entry.gatt_client.try_send(pdu).map_err(|_| Error::OutOfMemory)?;
+                    return Ok(());
+                }
+            }
+            Err(Error::NotFound)
+        })
+    }
+
     pub(crate) fn peer_address(&self, index: u8) -> BdAddr {
         self.with_mut(|state| {
             let state = &mut state.connections[index as usize];
@@ -252,6 +282,46 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         self.with_connected_handle(h, |storage| f(&mut storage.reassembly))
     }
 
+    /// Record that a connection parameter update request with the given identifier was sent.
+    ///
+    /// A new request supersedes any request still waiting for a response.
+    pub(crate) fn start_param_update(&self, index: u8, identifier: u8) {
+        self.with_mut(|state| {
+            state.connections[index as usize].param_update = ParamUpdateState::Pending(identifier);
+        })
+    }
+
+    pub(crate) fn poll_param_update(&self, index: u8, cx: &mut Context<'_>) -> Poll<Result<u16, Error>> {
+        self.with_mut(|state| {
+            let storage = &mut state.connections[index as usize];
+            if storage.state != ConnectionState::Connected {
+                return Poll::Ready(Err(Error::Disconnected));
+            }
+            match storage.param_update {
+                ParamUpdateState::Done(result) => {
+                    storage.param_update = ParamUpdateState::Idle;
+                    Poll::Ready(Ok(result))
+                }
+                ParamUpdateState::Pending(_) => {
+                    storage.param_update_waker.register(cx.waker());
+                    Poll::Pending
+                }
+                ParamUpdateState::Idle => Poll::Ready(Err(Error::InvalidState)),
+            }
+        })
+    }
+
+    pub(crate) fn param_update_response(&self, h: ConnHandle, identifier: u8, result: u16) -> Result<(), Error> {
+        self.with_connected_handle(h, |storage| {
+            if storage.param_update != ParamUpdateState::Pending(identifier) {
+                return Err(Error::NotFound);
+            }
+            storage.param_update = ParamUpdateState::Done(result);
+            storage.param_update_waker.wake();
+            Ok(())
+        })
+    }
+
     pub(crate) fn disconnected(&self, h: ConnHandle, reason: Status) -> Result<(), Error> {
         let mut state = self.state.borrow_mut();
         for (idx, storage) in state.connections.iter_mut().enumerate() {
@@ -259,8 +329,12 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
                 storage.state = ConnectionState::Disconnected;
                 storage.reassembly.clear();
                 let _ = storage.events.try_send(ConnectionEvent::Disconnected { reason });
+                storage.param_update_waker.wake();
                 #[cfg(feature = "gatt")]
-                storage.gatt.clear();
+                {
+                    storage.gatt.clear();
+                    storage.gatt_client.clear();
+                }
                 #[cfg(feature = "connection-metrics")]
                 storage.metrics.reset();
                 #[cfg(feature = "security")]
@@ -293,6 +367,8 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
                 storage.link_credits = default_credits;
                 // Default ATT MTU is 23
                 storage.att_mtu = 23;
+                storage.att_mtu_updated = false;
+                storage.param_update = ParamUpdateState::Idle;
                 storage.handle.replace(handle);
                 storage.peer_addr_kind.replace(peer_addr_kind);
                 storage.peer_identity.replace(Identity {
@@ -503,6 +579,8 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
             match storage.state {
                 ConnectionState::Connected if storage.handle.unwrap() == conn => {
                     storage.att_mtu = default_att_mtu.min(mtu);
+                    storage.att_mtu_updated = true;
+                    storage.att_mtu_waker.wake();
                     return storage.att_mtu;
                 }
                 _ => {}
@@ -520,6 +598,19 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         false
     }
 
+    pub(crate) fn is_handle_encrypted(&self, h: ConnHandle) -> bool {
+        #[cfg(feature = "security")]
+        {
+            self.with_connected_handle(h, |storage| Ok(storage.encrypted))
+                .unwrap_or(false)
+        }
+        #[cfg(not(feature = "security"))]
+        {
+            let _ = h;
+            false
+        }
+    }
+
     pub(crate) fn handle_security_channel(&self, handle: ConnHandle, pdu: Pdu<P::Packet>) -> Result<(), Error> {
         #[cfg(feature = "security")]
         {
@@ -527,7 +618,7 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
             for storage in state.connections.iter() {
                 match storage.state {
                     ConnectionState::Connected if storage.handle.unwrap() == handle => {
-                        if let Err(error) = self.security_manager.handle(pdu, self, storage) {
+                        if let Err(error) = self.security_manager.handle(pdu, storage) {
                             error!("Failed to handle security manager packet, {:?}", error);
                             return Err(error);
                         }
@@ -640,6 +731,10 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
                 self.security_manager.cancel_timeout()?;
             }
             crate::security_manager::SecurityEventData::TimerChange => (),
+            crate::security_manager::SecurityEventData::SendPacket(handle, packet) => {
+                let pdu = packet.into_pdu::<P>().await;
+                self.outbound.send((handle, pdu)).await;
+            }
         }
         Ok(())
     }
@@ -688,8 +783,12 @@ pub struct ConnectionStorage<P> {
     pub peer_addr_kind: Option<AddrKind>,
     pub peer_identity: Option<Identity>,
     pub att_mtu: u16,
+    pub att_mtu_updated: bool,
+    pub att_mtu_waker: WakerRegistration,
     pub link_credits: usize,
     pub link_credit_waker: WakerRegistration,
+    pub param_update: ParamUpdateState,
+    pub param_update_waker: WakerRegistration,
     pub refcount: u8,
     #[cfg(feature = "connection-metrics")]
     pub metrics: Metrics,
@@ -699,6 +798,8 @@ pub struct ConnectionStorage<P> {
     pub reassembly: PacketReassembly<P>,
     #[cfg(feature = "gatt")]
     pub gatt: GattChannel<P>,
+    #[cfg(feature = "gatt")]
+    pub gatt_client: GattChannel<P>,
 }
 
 /// Connection metrics
@@ -772,8 +873,12 @@ impl<P> ConnectionStorage<P> {
             peer_addr_kind: None,
             peer_identity: None,
             att_mtu: 23,
+            att_mtu_updated: false,
+            att_mtu_waker: WakerRegistration::new(),
             link_credits: 0,
             link_credit_waker: WakerRegistration::new(),
+            param_update: ParamUpdateState::Idle,
+            param_update_waker: WakerRegistration::new(),
             refcount: 0,
             #[cfg(feature = "connection-metrics")]
             metrics: Metrics::new(),
@@ -782,6 +887,8 @@ impl<P> ConnectionStorage<P> {
             events: EventChannel::new(),
             #[cfg(feature = "gatt")]
             gatt: GattChannel::new(),
+            #[cfg(feature = "gatt")]
+            gatt_client: GattChannel::new(),
             reassembly: PacketReassembly::new(),
         }
     }
@@ -827,6 +934,15 @@ impl<P> defmt::Format for ConnectionStorage<P> {
     }
 }
 
+/// State of a connection parameter update requested over L2CAP by this device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParamUpdateState {
+    Idle,
+    Pending(u8),
+    Done(u16),
+}
+
 #[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ConnectionState {
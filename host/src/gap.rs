@@ -11,6 +11,8 @@ use embassy_sync::blocking_mutex::raw::RawMutex;
 use heapless::String;
 use static_cell::StaticCell;
 
+#[cfg(feature = "eatt")]
+use crate::gatt::SERVER_FEATURES_EATT;
 use crate::prelude::*;
 
 /// Advertising packet is limited to 31 bytes. 9 of these are used by other GAP data, leaving 22 bytes for the Device Name characteristic
@@ -23,8 +25,23 @@ const DEVICE_NAME_MAX_LENGTH: usize = 22;
 /// GATT_SERVICE:    + 1
 ///                  ---
 ///                  = 6
+#[cfg(not(feature = "eatt"))]
 pub const GAP_SERVICE_ATTRIBUTE_COUNT: usize = 6;
 
+/// The number of attributes added by the GAP and GATT services
+/// GAP_SERVICE:                     1
+/// ├── DEVICE_NAME:                 2
+/// └── APPEARANCE:                  2
+/// GATT_SERVICE:                  + 1
+/// ├── SERVER_SUPPORTED_FEATURES:   2
+/// └── CLIENT_SUPPORTED_FEATURES:   2
+///                                ---
+///                                = 10
+///
+/// The supported features characteristics are only added with the `eatt` feature.
+#[cfg(feature = "eatt")]
+pub const GAP_SERVICE_ATTRIBUTE_COUNT: usize = 10;
+
 /// Configuration for the GAP Service.
 pub enum GapConfig<'a> {
     /// Peripheral device configuration.
@@ -93,7 +110,7 @@ impl<'a> PeripheralConfig<'a> {
         gap_builder.add_characteristic_ro(characteristic::APPEARANCE, self.appearance);
         gap_builder.build();
 
-        table.add_service(Service::new(service::GATT));
+        build_gatt_service(table);
 
         Ok(())
     }
@@ -113,8 +130,23 @@ impl<'a> CentralConfig<'a> {
         gap_builder.add_characteristic_ro(characteristic::APPEARANCE, self.appearance);
         gap_builder.build();
 
-        table.add_service(Service::new(service::GATT));
+        build_gatt_service(table);
 
         Ok(())
     }
 }
+
+/// Add the GATT service.
+#[cfg(not(feature = "eatt"))]
+fn build_gatt_service<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) {
+    table.add_service(Service::new(service::GATT));
+}
+
+/// Add the GATT service, advertising Enhanced ATT support in the Server Supported Features.
+#[cfg(feature = "eatt")]
+fn build_gatt_service<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) {
+    let mut gatt_builder = table.add_service(Service::new(service::GATT));
+    gatt_builder.add_characteristic_ro(characteristic::SERVER_SUPPORTED_FEATURES, &SERVER_FEATURES_EATT);
+    gatt_builder.add_client_supported_features();
+    gatt_builder.build();
+}
@@ -1,32 +1,49 @@
 //! GATT server and client implementation.
-use core::cell::RefCell;
-use core::future::Future;
+use core::cell::{Cell, RefCell};
+use core::future::{poll_fn, Future};
 use core::marker::PhantomData;
+use core::pin::pin;
 
 use att::AttErrorCode;
 use bt_hci::controller::Controller;
 use bt_hci::param::{ConnHandle, PhyKind, Status};
-use bt_hci::uuid::declarations::{CHARACTERISTIC, PRIMARY_SERVICE};
+use bt_hci::uuid::characteristic::{
+    CLIENT_SUPPORTED_FEATURES, DATABASE_HASH, SERVER_SUPPORTED_FEATURES, SERVICE_CHANGED,
+};
+use bt_hci::uuid::declarations::{CHARACTERISTIC, INCLUDE, PRIMARY_SERVICE};
 use bt_hci::uuid::descriptors::CLIENT_CHARACTERISTIC_CONFIGURATION;
 use embassy_futures::select::{select, Either};
 use embassy_sync::blocking_mutex::raw::{NoopRawMutex, RawMutex};
-use embassy_sync::channel::{Channel, DynamicReceiver};
-use embassy_sync::pubsub::{self, PubSubChannel, WaitResult};
-use embassy_time::Duration;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, WithTimeout};
 use heapless::Vec;
 
-use crate::att::{self, Att, AttClient, AttCmd, AttReq, AttRsp, AttServer, AttUns, ATT_HANDLE_VALUE_NTF};
-use crate::attribute::{AttributeData, Characteristic, CharacteristicProp, Uuid, CCCD};
+use crate::att::{
+    self, Att, AttCfm, AttClient, AttCmd, AttReq, AttRsp, AttServer, AttUns, ReadMultipleVariableIter,
+    ATT_HANDLE_VALUE_IND, ATT_HANDLE_VALUE_NTF,
+};
+use crate::attribute::{AttributeData, CCCDFlag, Characteristic, CharacteristicProp, CharacteristicProps, Uuid, CCCD};
 use crate::attribute_server::{AttributeServer, DynamicAttributeServer};
+use crate::channel_manager::ChannelIndex;
 use crate::connection::Connection;
 use crate::cursor::{ReadCursor, WriteCursor};
-use crate::pdu::Pdu;
-use crate::prelude::ConnectionEvent;
+use crate::l2cap::{L2capChannel, L2capChannelConfig, L2CAP_ECFC_MAX_CHANNELS};
+use crate::pdu::{Pdu, TxSdu, TX_SDU_HEADROOM};
+use crate::prelude::{ConnectionEvent, ConnectionParamsRequest};
 #[cfg(feature = "security")]
 use crate::security_manager::BondInformation;
 use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
 use crate::types::l2cap::L2capHeader;
-use crate::{config, BleHostError, Error, PacketPool, Stack};
+use crate::{config, BleHostError, Error, Identity, PacketPool, Stack};
+
+/// PSM of the Enhanced ATT bearers.
+pub const EATT_PSM: u16 = 0x0027;
+
+/// Server Supported Features bit: the server supports Enhanced ATT bearers.
+pub(crate) const SERVER_FEATURES_EATT: u8 = 0x01;
+
+/// Client Supported Features bit: the client supports Enhanced ATT bearers.
+const CLIENT_FEATURES_EATT: u8 = 0x02;
 
 /// A GATT connection event.
 pub enum GattConnectionEvent<'stack, 'server, P: PacketPool> {
@@ -51,12 +68,22 @@ pub enum GattConnectionEvent<'stack, 'server, P: PacketPool> {
         /// Supervision timeout.
         supervision_timeout: Duration,
     },
+    /// The ATT MTU was negotiated for this connection.
+    AttMtuUpdated {
+        /// The negotiated ATT MTU.
+        mtu: u16,
+    },
     #[cfg(feature = "security")]
     /// Bonded event.
     Bonded {
         /// Bond info for this connection
         bond_info: BondInformation,
     },
+    /// The peripheral requested new connection parameters.
+    RequestConnectionParams {
+        /// The request, which must be accepted or rejected.
+        request: ConnectionParamsRequest,
+    },
     /// GATT event.
     Gatt {
         /// The event that was returned
@@ -111,6 +138,9 @@ impl<'stack, 'server, P: PacketPool> GattConnection<'stack, 'server, P> {
                     ConnectionEvent::PhyUpdated { tx_phy, rx_phy } => {
                         return GattConnectionEvent::PhyUpdated { tx_phy, rx_phy };
                     }
+                    ConnectionEvent::AttMtuUpdated { mtu } => {
+                        return GattConnectionEvent::AttMtuUpdated { mtu };
+                    }
                     #[cfg(feature = "security")]
                     ConnectionEvent::Bonded { bond_info } => {
                         // Update the identity of the connection
@@ -119,6 +149,9 @@ impl<'stack, 'server, P: PacketPool> GattConnection<'stack, 'server, P> {
                         }
                         return GattConnectionEvent::Bonded { bond_info };
                     }
+                    ConnectionEvent::RequestConnectionParams { request } => {
+                        return GattConnectionEvent::RequestConnectionParams { request };
+                    }
                 },
                 Either::Second(data) => {
                     let data = GattData::new(data, self.connection.clone());
@@ -134,6 +167,63 @@ impl<'stack, 'server, P: PacketPool> GattConnection<'stack, 'server, P> {
         }
     }
 
+    /// Accept the Enhanced ATT bearers opened by the client in a single request.
+    ///
+    /// Each bearer must be served with [`GattConnection::next_eatt`] for the requests on it to be processed.
+    pub async fn accept_eatt<T: Controller>(
+        &self,
+        stack: &'stack Stack<'stack, T, P>,
+    ) -> Result<Vec<L2capChannel<'stack, P>, L2CAP_ECFC_MAX_CHANNELS>, BleHostError<T::Error>> {
+        let config = L2capChannelConfig {
+            // Responses are written in place after the L2CAP headers, so they must fit in a packet with them.
+            mtu: Some((P::MTU - TX_SDU_HEADROOM) as u16),
+            ..Default::default()
+        };
+        L2capChannel::accept_enhanced(stack, &self.connection, &[EATT_PSM], &config).await
+    }
+
+    /// Wait for the next GATT event on an Enhanced ATT bearer.
+    ///
+    /// Reads and writes of characteristics produce an [`EattEvent`], which sends the response on the bearer
+    /// once accepted or rejected. Other requests are processed by the attribute server and answered directly.
+    /// Responses are limited to the MTU of the bearer.
+    ///
+    /// Returns `None` once the bearer is closed.
+    pub async fn next_eatt<T: Controller>(
+        &self,
+        stack: &'stack Stack<'stack, T, P>,
+        bearer: &mut L2capChannel<'stack, P>,
+    ) -> Result<Option<EattEvent<'stack, 'server, T, P>>, BleHostError<T::Error>> {
+        loop {
+            let sdu = match bearer.receive_sdu(stack).await {
+                Ok(sdu) => sdu,
+                Err(BleHostError::BleHost(Error::ChannelClosed)) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            let len = sdu.len();
+            let pdu = Pdu::new(sdu.into_inner(), len);
+            if !matches!(Att::decode(pdu.as_ref()), Ok(Att::Client(_))) {
+                warn!("[gatt] ignoring invalid att pdu on eatt bearer");
+                continue;
+            }
+
+            let index = bearer.index();
+            let mtu = stack.host.channels.send_mtu(index)?;
+            let mut data = GattData::new(pdu, self.connection.clone());
+            if let Some(event) = data.event(self.server) {
+                return Ok(Some(EattEvent {
+                    event,
+                    stack,
+                    bearer: index,
+                    mtu,
+                }));
+            }
+            if let Some(pdu) = data.pdu.take() {
+                respond_eatt(stack, index, mtu, &self.connection, self.server, &pdu, None).await?;
+            }
+        }
+    }
+
     /// Get a reference to the underlying BLE connection.
     pub fn raw(&self) -> &Connection<'stack, P> {
         &self.connection
@@ -189,43 +279,49 @@ impl<'stack, P: PacketPool> GattData<'stack, P> {
         mut self,
         server: &'m dyn DynamicAttributeServer<P>,
     ) -> Result<Option<GattEvent<'stack, 'm, P>>, Error> {
+        if let Some(event) = self.event(server) {
+            return Ok(Some(event));
+        }
+        // Process it now since the user will not
+        if let Some(pdu) = self.pdu.as_ref() {
+            let reply = process_accept(pdu, &self.connection, server)?;
+            reply.send().await;
+        }
+        Ok(None)
+    }
+
+    /// Take the PDU into an event if the request is a read or write of a characteristic.
+    fn event<'m>(&mut self, server: &'m dyn DynamicAttributeServer<P>) -> Option<GattEvent<'stack, 'm, P>> {
         let att = self.incoming();
         match att {
-            AttClient::Request(AttReq::Write { handle, data: _ }) => Ok(Some(GattEvent::Write(WriteEvent {
+            AttClient::Request(AttReq::Write { handle, data: _ }) => Some(GattEvent::Write(WriteEvent {
                 value_handle: handle,
                 pdu: self.pdu.take(),
                 connection: self.connection.clone(),
                 server,
-            }))),
+            })),
 
-            AttClient::Command(AttCmd::Write { handle, data: _ }) => Ok(Some(GattEvent::Write(WriteEvent {
+            AttClient::Command(AttCmd::Write { handle, data: _ }) => Some(GattEvent::Write(WriteEvent {
                 value_handle: handle,
                 pdu: self.pdu.take(),
                 connection: self.connection.clone(),
                 server,
-            }))),
+            })),
 
-            AttClient::Request(AttReq::Read { handle }) => Ok(Some(GattEvent::Read(ReadEvent {
+            AttClient::Request(AttReq::Read { handle }) => Some(GattEvent::Read(ReadEvent {
                 value_handle: handle,
                 pdu: self.pdu.take(),
                 connection: self.connection.clone(),
                 server,
-            }))),
+            })),
 
-            AttClient::Request(AttReq::ReadBlob { handle, offset }) => Ok(Some(GattEvent::Read(ReadEvent {
+            AttClient::Request(AttReq::ReadBlob { handle, offset }) => Some(GattEvent::Read(ReadEvent {
                 value_handle: handle,
                 pdu: self.pdu.take(),
                 connection: self.connection.clone(),
                 server,
-            }))),
-            _ => {
-                // Process it now since the user will not
-                if let Some(pdu) = self.pdu.as_ref() {
-                    let reply = process_accept(pdu, &self.connection, server)?;
-                    reply.send().await;
-                }
-                Ok(None)
-            }
+            })),
+            _ => None,
         }
     }
 }
@@ -239,6 +335,14 @@ pub enum GattEvent<'stack, 'server, P: PacketPool> {
 }
 
 impl<'stack, 'server, P: PacketPool> GattEvent<'stack, 'server, P> {
+    /// Characteristic handle that was read or written.
+    pub fn handle(&self) -> u16 {
+        match self {
+            Self::Read(e) => e.handle(),
+            Self::Write(e) => e.handle(),
+        }
+    }
+
     /// Accept the event, making it processed by the server.
     pub fn accept(self) -> Result<Reply<'stack, P>, Error> {
         match self {
@@ -248,7 +352,9 @@ impl<'stack, 'server, P: PacketPool> GattEvent<'stack, 'server, P> {
     }
 
     /// Reject the event with the provided error code, it will not be processed by the attribute server.
-    pub fn reject(self, err: AttErrorCode) -> Result<Reply<'stack, P>, Error> {
+    ///
+    /// Any error type which converts into an [`AttErrorCode`] can be used, such as a service specific error enum.
+    pub fn reject<E: Into<AttErrorCode>>(self, err: E) -> Result<Reply<'stack, P>, Error> {
         match self {
             Self::Read(e) => e.reject(err),
             Self::Write(e) => e.reject(err),
@@ -256,6 +362,78 @@ impl<'stack, 'server, P: PacketPool> GattEvent<'stack, 'server, P> {
     }
 }
 
+/// An event returned while processing GATT requests on an Enhanced ATT bearer.
+///
+/// The response is sent on the bearer when the event is accepted or rejected. Unlike [`GattEvent`], the
+/// event is not accepted when dropped, as the response can only be processed asynchronously. The request is
+/// rejected with `UNLIKELY_ERROR` instead, so that the bearer is not left waiting for a response.
+pub struct EattEvent<'stack, 'server, T, P: PacketPool> {
+    event: GattEvent<'stack, 'server, P>,
+    stack: &'stack Stack<'stack, T, P>,
+    bearer: ChannelIndex,
+    mtu: u16,
+}
+
+impl<'stack, 'server, T: Controller, P: PacketPool> EattEvent<'stack, 'server, T, P> {
+    /// The read or write of the characteristic.
+    pub fn event(&self) -> &GattEvent<'stack, 'server, P> {
+        &self.event
+    }
+
+    /// Accept the event, making it processed by the server, and send the response on the bearer.
+    pub async fn accept(mut self) -> Result<(), BleHostError<T::Error>> {
+        self.respond(None).await
+    }
+
+    /// Reject the event with the provided error code, it will not be processed by the attribute server.
+    ///
+    /// Any error type which converts into an [`AttErrorCode`] can be used, such as a service specific error enum.
+    pub async fn reject<E: Into<AttErrorCode>>(mut self, err: E) -> Result<(), BleHostError<T::Error>> {
+        let handle = self.event.handle();
+        self.respond(Some((handle, err.into()))).await
+    }
+
+    async fn respond(&mut self, rejected: Option<(u16, AttErrorCode)>) -> Result<(), BleHostError<T::Error>> {
+        let (pdu, connection, server) = match &mut self.event {
+            GattEvent::Read(e) => (e.pdu.take(), &e.connection, e.server),
+            GattEvent::Write(e) => (e.pdu.take(), &e.connection, e.server),
+        };
+        match pdu {
+            Some(pdu) => respond_eatt(self.stack, self.bearer, self.mtu, connection, server, &pdu, rejected).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T, P: PacketPool> Drop for EattEvent<'_, '_, T, P> {
+    fn drop(&mut self) {
+        let handle = self.event.handle();
+        let (pdu, connection) = match &mut self.event {
+            GattEvent::Read(e) => (e.pdu.take(), &e.connection),
+            GattEvent::Write(e) => (e.pdu.take(), &e.connection),
+        };
+        let Some(pdu) = pdu else {
+            return;
+        };
+        // The response must be sent on the bearer the request was received on, framed for it.
+        let rsp = AttRsp::Error {
+            request: pdu.as_ref()[0],
+            handle,
+            code: AttErrorCode::UNLIKELY_ERROR,
+        };
+        let mut buf = [0; 5];
+        let mut w = WriteCursor::new(&mut buf);
+        let sent = w
+            .write(Att::Server(AttServer::Response(rsp)))
+            .map_err(Error::from)
+            .and_then(|_| self.stack.host.channels.try_frame(self.bearer, w.finish()))
+            .and_then(|frame| connection.try_send(frame));
+        if sent.is_err() {
+            warn!("[gatt] error rejecting dropped eatt event");
+        }
+    }
+}
+
 /// An event returned while processing GATT requests.
 pub struct ReadEvent<'stack, 'server, P: PacketPool> {
     value_handle: u16,
@@ -279,9 +457,11 @@ impl<'stack, P: PacketPool> ReadEvent<'stack, '_, P> {
     }
 
     /// Reject the event with the provided error code, it will not be processed by the attribute server.
-    pub fn reject(mut self, err: AttErrorCode) -> Result<Reply<'stack, P>, Error> {
+    ///
+    /// Any error type which converts into an [`AttErrorCode`] can be used, such as a service specific error enum.
+    pub fn reject<E: Into<AttErrorCode>>(mut self, err: E) -> Result<Reply<'stack, P>, Error> {
         let handle = self.handle();
-        process(&mut self.pdu, handle, &self.connection, self.server, Err(err))
+        process(&mut self.pdu, handle, &self.connection, self.server, Err(err.into()))
     }
 }
 
@@ -327,9 +507,11 @@ impl<'stack, P: PacketPool> WriteEvent<'stack, '_, P> {
     }
 
     /// Reject the event with the provided error code, it will not be processed by the attribute server.
-    pub fn reject(mut self, err: AttErrorCode) -> Result<Reply<'stack, P>, Error> {
+    ///
+    /// Any error type which converts into an [`AttErrorCode`] can be used, such as a service specific error enum.
+    pub fn reject<E: Into<AttErrorCode>>(mut self, err: E) -> Result<Reply<'stack, P>, Error> {
         let handle = self.handle();
-        process(&mut self.pdu, handle, &self.connection, self.server, Err(err))
+        process(&mut self.pdu, handle, &self.connection, self.server, Err(err.into()))
     }
 }
 
@@ -378,8 +560,8 @@ where
     let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
     let mut w = WriteCursor::new(tx.as_mut());
     let (mut header, mut data) = w.split(4)?;
-    if let Some(written) = server.process(connection, &att, data.write_buf())? {
-        let mtu = connection.get_att_mtu();
+    let mtu = connection.get_att_mtu();
+    if let Some(written) = server.process(connection, &att, data.write_buf(), mtu)? {
         data.commit(written)?;
         data.truncate(mtu as usize);
         header.write(data.len() as u16)?;
@@ -392,6 +574,50 @@ where
     }
 }
 
+/// Process a request received on an Enhanced ATT bearer, or reject it with the provided handle and error
+/// code, and send the response on the bearer.
+async fn respond_eatt<T: Controller, P: PacketPool>(
+    stack: &Stack<'_, T, P>,
+    bearer: ChannelIndex,
+    mtu: u16,
+    connection: &Connection<'_, P>,
+    server: &dyn DynamicAttributeServer<P>,
+    pdu: &Pdu<P::Packet>,
+    rejected: Option<(u16, AttErrorCode)>,
+) -> Result<(), BleHostError<T::Error>> {
+    let mut sdu = TxSdu::<P>::allocate_async().await;
+    let len = match rejected {
+        Some((handle, code)) => {
+            let request = pdu.as_ref()[0];
+            let mut w = WriteCursor::new(sdu.buffer_mut());
+            w.write(Att::Server(AttServer::Response(AttRsp::Error {
+                request,
+                handle,
+                code,
+            })))?;
+            w.len()
+        }
+        None => {
+            // The PDU was checked to be a client PDU when it was received
+            let att = unwrap!(Att::decode(pdu.as_ref()));
+            let Att::Client(att) = att else {
+                unreachable!("Expected Att::Client, got {:?}", att)
+            };
+            match server.process(connection, &att, sdu.buffer_mut(), mtu)? {
+                Some(written) => written.min(mtu as usize),
+                None => return Ok(()),
+            }
+        }
+    };
+    sdu.set_len(len)?;
+    let (packet, len) = sdu.raw_mut();
+    stack
+        .host
+        .channels
+        .send_in_place(bearer, packet, len, &stack.host)
+        .await
+}
+
 fn process_reject<'stack, P: PacketPool>(
     pdu: &Pdu<P::Packet>,
     handle: u16,
@@ -463,52 +689,197 @@ impl<P: PacketPool> Drop for Reply<'_, P> {
 }
 
 /// Notification listener for GATT client.
-pub struct NotificationListener<'lst, const MTU: usize> {
+///
+/// The listener is unsubscribed from the notification queue when dropped.
+pub struct NotificationListener<'lst, P: PacketPool> {
     handle: u16,
-    listener: pubsub::DynSubscriber<'lst, Notification<MTU>>,
+    slot: usize,
+    listeners: &'lst NotificationListeners<P>,
 }
 
-impl<'lst, const MTU: usize> NotificationListener<'lst, MTU> {
+impl<'lst, P: PacketPool> NotificationListener<'lst, P> {
     #[allow(clippy::should_implement_trait)]
-    /// Get the next (len: u16, Packet) tuple from the rx queue
-    pub async fn next(&mut self) -> Notification<MTU> {
-        loop {
-            if let WaitResult::Message(m) = self.listener.next_message().await {
-                if m.handle == self.handle {
-                    return m;
-                }
-            }
-        }
+    /// Get the next notification or indication from the rx queue
+    pub async fn next(&mut self) -> Notification<P> {
+        self.listeners.queues[self.slot].receive().await
+    }
+
+    /// Handle of the characteristic value this listener receives notifications for.
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+}
+
+impl<P: PacketPool> Drop for NotificationListener<'_, P> {
+    fn drop(&mut self) {
+        self.listeners.handles.borrow_mut()[self.slot] = None;
+        self.listeners.queues[self.slot].clear();
     }
 }
 
 const MAX_NOTIF: usize = config::GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS;
 const NOTIF_QSIZE: usize = config::GATT_CLIENT_NOTIFICATION_QUEUE_SIZE;
 
+/// Queues of received notifications, one for each listener.
+struct NotificationListeners<P: PacketPool> {
+    handles: RefCell<[Option<u16>; MAX_NOTIF]>,
+    queues: [Channel<NoopRawMutex, Notification<P>, NOTIF_QSIZE>; MAX_NOTIF],
+}
+
+impl<P: PacketPool> NotificationListeners<P> {
+    fn new() -> Self {
+        Self {
+            handles: RefCell::new([None; MAX_NOTIF]),
+            queues: core::array::from_fn(|_| Channel::new()),
+        }
+    }
+
+    fn listen(&self, handle: u16) -> Result<NotificationListener<'_, P>, Error> {
+        let mut handles = self.handles.borrow_mut();
+        let slot = handles
+            .iter()
+            .position(|h| h.is_none())
+            .ok_or(Error::GattSubscriberLimitReached)?;
+        handles[slot] = Some(handle);
+        Ok(NotificationListener {
+            handle,
+            slot,
+            listeners: self,
+        })
+    }
+
+    /// Deliver a notification to every listener of the characteristic.
+    ///
+    /// The received packet is handed to the last listener, the others receive a copy in a packet from the pool.
+    /// If a queue is full, the oldest notification in it is dropped.
+    fn publish(&self, notification: Notification<P>) {
+        let handles = self.handles.borrow();
+        let mut remaining = handles.iter().filter(|h| **h == Some(notification.handle)).count();
+        let mut notification = Some(notification);
+        for (slot, handle) in handles.iter().enumerate() {
+            let Some(n) = notification.as_ref() else {
+                break;
+            };
+            if *handle != Some(n.handle) {
+                continue;
+            }
+            remaining -= 1;
+            let n = if remaining == 0 {
+                notification.take()
+            } else {
+                n.try_clone()
+            };
+            let Some(n) = n else {
+                warn!("[gatt] no packet available to copy notification for listener {}", slot);
+                continue;
+            };
+            let queue = &self.queues[slot];
+            if queue.is_full() {
+                let _ = queue.try_receive();
+            }
+            let _ = queue.try_send(n);
+        }
+    }
+}
+
 /// A GATT client capable of using the GATT protocol.
 pub struct GattClient<'reference, T: Controller, P: PacketPool, const MAX_SERVICES: usize> {
-    known_services: RefCell<Vec<ServiceHandle, MAX_SERVICES>>,
-    rx: DynamicReceiver<'reference, (ConnHandle, Pdu<P::Packet>)>,
     stack: &'reference Stack<'reference, T, P>,
     connection: Connection<'reference, P>,
-    response_channel: Channel<NoopRawMutex, (ConnHandle, Pdu<P::Packet>), 1>,
+    response_channel: Channel<NoopRawMutex, Pdu<P::Packet>, 1>,
+    auto_confirm: Cell<bool>,
+    notifications: NotificationListeners<P>,
+    timeout: Cell<Duration>,
+    disconnect_on_timeout: Cell<bool>,
+    failed: Cell<bool>,
+    bearers: RefCell<Vec<L2capChannel<'reference, P>, L2CAP_ECFC_MAX_CHANNELS>>,
+    free_bearers: Channel<NoopRawMutex, u8, L2CAP_ECFC_MAX_CHANNELS>,
+}
 
-    // TODO: Wait for something like https://github.com/rust-lang/rust/issues/132980 (min_generic_const_args) to allow using P::MTU
-    notifications: PubSubChannel<NoopRawMutex, Notification<512>, NOTIF_QSIZE, MAX_NOTIF, 1>,
+/// ATT transaction timeout defined by the specification.
+const ATT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An Enhanced ATT bearer reserved for a single transaction, released when dropped.
+struct BearerSlot<'a> {
+    pool: &'a Channel<NoopRawMutex, u8, L2CAP_ECFC_MAX_CHANNELS>,
+    slot: u8,
 }
 
-/// A notification payload.
-#[derive(Debug, PartialEq, Clone)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct Notification<const MTU: usize> {
+impl<'a> BearerSlot<'a> {
+    async fn take(pool: &'a Channel<NoopRawMutex, u8, L2CAP_ECFC_MAX_CHANNELS>) -> Self {
+        let slot = pool.receive().await;
+        Self { pool, slot }
+    }
+}
+
+impl Drop for BearerSlot<'_> {
+    fn drop(&mut self) {
+        let _ = self.pool.try_send(self.slot);
+    }
+}
+
+/// A notification or indication payload.
+///
+/// The payload is held in the received packet, which is returned to the packet pool when the notification is dropped.
+pub struct Notification<P: PacketPool> {
     handle: u16,
-    data: [u8; MTU],
-    len: usize,
+    pdu: Pdu<P::Packet>,
+    indication: bool,
+}
+
+impl<P: PacketPool> Notification<P> {
+    /// Offset of the value in the received ATT PDU: the opcode and the attribute handle.
+    const VALUE_OFFSET: usize = 3;
+
+    /// Copy the notification into a packet from the pool, if one is available.
+    fn try_clone(&self) -> Option<Self> {
+        let mut packet = P::allocate()?;
+        let len = self.pdu.len();
+        packet.as_mut()[..len].copy_from_slice(self.pdu.as_ref());
+        Some(Self {
+            handle: self.handle,
+            pdu: Pdu::new(packet, len),
+            indication: self.indication,
+        })
+    }
+
+    /// Handle of the characteristic value.
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// Returns true if the value was sent as an indication, which must be confirmed.
+    pub fn is_indication(&self) -> bool {
+        self.indication
+    }
 }
 
-impl<const MTU: usize> AsRef<[u8]> for Notification<MTU> {
+impl<P: PacketPool> AsRef<[u8]> for Notification<P> {
     fn as_ref(&self) -> &[u8] {
-        &self.data[..self.len]
+        &self.pdu.as_ref()[Self::VALUE_OFFSET..]
+    }
+}
+
+impl<P: PacketPool> core::fmt::Debug for Notification<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Notification")
+            .field("handle", &self.handle)
+            .field("data", &self.as_ref())
+            .field("indication", &self.indication)
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<P: PacketPool> defmt::Format for Notification<P> {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "Notification {{ handle: {}, data: {:x}, indication: {} }}",
+            self.handle,
+            self.as_ref(),
+            self.indication
+        );
     }
 }
 
@@ -521,6 +892,317 @@ pub struct ServiceHandle {
     uuid: Uuid,
 }
 
+impl ServiceHandle {
+    /// Create a service handle, for instance to restore a cached [`GattDatabase`].
+    pub fn new(start: u16, end: u16, uuid: Uuid) -> Self {
+        Self { start, end, uuid }
+    }
+
+    /// Handle of the service declaration, the first handle of the service.
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    /// Last handle of the service.
+    pub fn end(&self) -> u16 {
+        self.end
+    }
+
+    /// UUID of the service.
+    pub fn uuid(&self) -> &Uuid {
+        &self.uuid
+    }
+
+    fn contains(&self, handle: u16) -> bool {
+        self.start <= handle && handle <= self.end
+    }
+}
+
+/// An include declaration discovered in a service.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct IncludedService {
+    /// Handle of the include declaration.
+    pub handle: u16,
+    /// The included service.
+    pub service: ServiceHandle,
+}
+
+/// A characteristic discovered in a service.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct DiscoveredCharacteristic {
+    /// Handle of the characteristic declaration.
+    pub declaration_handle: u16,
+    /// Handle of the characteristic value.
+    pub handle: u16,
+    /// Last handle of the characteristic, including its descriptors.
+    pub end_handle: u16,
+    /// Properties of the characteristic.
+    pub props: CharacteristicProps,
+    /// UUID of the characteristic.
+    pub uuid: Uuid,
+}
+
+/// A descriptor discovered for a characteristic.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct DiscoveredDescriptor {
+    /// Handle of the descriptor.
+    pub handle: u16,
+    /// UUID of the descriptor.
+    pub uuid: Uuid,
+}
+
+/// Maximum number of attributes read with a single Read Multiple request.
+const READ_MULTIPLE_MAX: usize = 16;
+
+/// Encode the handles of a Read Multiple request.
+fn encode_handles<'b>(handles: &[u16], buf: &'b mut [u8; 2 * READ_MULTIPLE_MAX]) -> Result<&'b [u8], Error> {
+    if handles.len() < 2 || handles.len() > READ_MULTIPLE_MAX {
+        return Err(Error::InvalidValue);
+    }
+    for (dest, handle) in buf.chunks_exact_mut(2).zip(handles) {
+        dest.copy_from_slice(&handle.to_le_bytes());
+    }
+    Ok(&buf[..2 * handles.len()])
+}
+
+/// Returns the first handle of the next discovery request, following a response ending at `last`.
+///
+/// Returns `None` once the end of the range is reached. A response which does not move past `start` is
+/// rejected, as repeating the request would never finish.
+fn next_start(start: u16, last: u16, end: u16) -> Result<Option<u16>, Error> {
+    if last < start {
+        Err(Error::UnexpectedGattResponse)
+    } else if last >= end {
+        Ok(None)
+    } else {
+        Ok(Some(last + 1))
+    }
+}
+
+/// A tuple of characteristics which can be read with a single request, see [`GattClient::read_characteristics`].
+///
+/// Implemented for tuples of 2 to 8 characteristic references.
+pub trait CharacteristicTuple {
+    /// The values of the characteristics.
+    type Values;
+
+    /// Write the value handles of the characteristics into `handles`, returning their number.
+    fn handles(&self, handles: &mut [u16]) -> usize;
+
+    /// Decode the values from a Read Multiple Variable Length response.
+    fn decode(it: &mut ReadMultipleVariableIter<'_>) -> Result<Self::Values, Error>;
+}
+
+macro_rules! impl_characteristic_tuple {
+    ($($t:ident $idx:tt),+) => {
+        impl<'a, $($t: FromGatt),+> CharacteristicTuple for ($(&'a Characteristic<$t>,)+) {
+            type Values = ($($t,)+);
+
+            fn handles(&self, handles: &mut [u16]) -> usize {
+                let mut count = 0;
+                $(
+                    handles[count] = self.$idx.handle;
+                    count += 1;
+                )+
+                count
+            }
+
+            fn decode(it: &mut ReadMultipleVariableIter<'_>) -> Result<Self::Values, Error> {
+                Ok(($(
+                    {
+                        let value = it.next().ok_or(Error::InvalidValue)??;
+                        $t::from_gatt(value).map_err(|_| Error::InvalidValue)?
+                    },
+                )+))
+            }
+        }
+    };
+}
+
+impl_characteristic_tuple!(A 0, B 1);
+impl_characteristic_tuple!(A 0, B 1, C 2);
+impl_characteristic_tuple!(A 0, B 1, C 2, D 3);
+impl_characteristic_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_characteristic_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_characteristic_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_characteristic_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+/// The attribute database of a GATT server, as discovered by [`GattClient::discover`].
+///
+/// The model is bounded by the maximum number of services (including included services), characteristics and descriptors.
+/// Services, characteristics and descriptors are held in handle order.
+#[derive(Debug, Clone)]
+pub struct GattDatabase<const SERVICES: usize, const CHARACTERISTICS: usize, const DESCRIPTORS: usize> {
+    services: Vec<ServiceHandle, SERVICES>,
+    includes: Vec<IncludedService, SERVICES>,
+    characteristics: Vec<DiscoveredCharacteristic, CHARACTERISTICS>,
+    descriptors: Vec<DiscoveredDescriptor, DESCRIPTORS>,
+    hash: Option<[u8; 16]>,
+}
+
+impl<const SERVICES: usize, const CHARACTERISTICS: usize, const DESCRIPTORS: usize> Default
+    for GattDatabase<SERVICES, CHARACTERISTICS, DESCRIPTORS>
+{
+    fn default() -> Self {
+        Self {
+            services: Vec::new(),
+            includes: Vec::new(),
+            characteristics: Vec::new(),
+            descriptors: Vec::new(),
+            hash: None,
+        }
+    }
+}
+
+impl<const SERVICES: usize, const CHARACTERISTICS: usize, const DESCRIPTORS: usize>
+    GattDatabase<SERVICES, CHARACTERISTICS, DESCRIPTORS>
+{
+    /// Restore a database, for instance from a [`GattCache`].
+    ///
+    /// Entries are sorted into handle order.
+    pub fn from_parts(
+        mut services: Vec<ServiceHandle, SERVICES>,
+        mut includes: Vec<IncludedService, SERVICES>,
+        mut characteristics: Vec<DiscoveredCharacteristic, CHARACTERISTICS>,
+        mut descriptors: Vec<DiscoveredDescriptor, DESCRIPTORS>,
+        hash: Option<[u8; 16]>,
+    ) -> Self {
+        services.sort_unstable_by_key(|s| s.start);
+        includes.sort_unstable_by_key(|i| i.handle);
+        characteristics.sort_unstable_by_key(|c| c.declaration_handle);
+        descriptors.sort_unstable_by_key(|d| d.handle);
+        Self {
+            services,
+            includes,
+            characteristics,
+            descriptors,
+            hash,
+        }
+    }
+
+    /// The Database Hash of the server when the database was discovered, if the server exposes one.
+    pub fn hash(&self) -> Option<&[u8; 16]> {
+        self.hash.as_ref()
+    }
+
+    /// All include declarations.
+    pub fn all_includes(&self) -> impl Iterator<Item = &IncludedService> {
+        self.includes.iter()
+    }
+
+    /// All characteristics.
+    pub fn all_characteristics(&self) -> impl Iterator<Item = &DiscoveredCharacteristic> {
+        self.characteristics.iter()
+    }
+
+    /// All descriptors.
+    pub fn all_descriptors(&self) -> impl Iterator<Item = &DiscoveredDescriptor> {
+        self.descriptors.iter()
+    }
+
+    /// All discovered services, both primary services and services reached through an include.
+    pub fn services(&self) -> impl Iterator<Item = &ServiceHandle> {
+        self.services.iter()
+    }
+
+    /// The include declarations of a service.
+    pub fn includes<'m>(&'m self, service: &'m ServiceHandle) -> impl Iterator<Item = &'m IncludedService> {
+        self.includes.iter().filter(|i| service.contains(i.handle))
+    }
+
+    /// The characteristics of a service.
+    pub fn characteristics<'m>(
+        &'m self,
+        service: &'m ServiceHandle,
+    ) -> impl Iterator<Item = &'m DiscoveredCharacteristic> {
+        self.characteristics
+            .iter()
+            .filter(|c| service.contains(c.declaration_handle))
+    }
+
+    /// The descriptors of a characteristic.
+    pub fn descriptors<'m>(
+        &'m self,
+        characteristic: &'m DiscoveredCharacteristic,
+    ) -> impl Iterator<Item = &'m DiscoveredDescriptor> {
+        self.descriptors
+            .iter()
+            .filter(|d| characteristic.handle < d.handle && d.handle <= characteristic.end_handle)
+    }
+
+    /// Returns a characteristic handle which can be used for reading, writing and subscribing.
+    ///
+    /// The CCCD handle is taken from the discovered descriptors.
+    pub fn characteristic<T: AsGatt>(&self, characteristic: &DiscoveredCharacteristic) -> Characteristic<T> {
+        let cccd_handle = self
+            .descriptors(characteristic)
+            .find(|d| d.uuid == CLIENT_CHARACTERISTIC_CONFIGURATION.into())
+            .map(|d| d.handle);
+        Characteristic {
+            handle: characteristic.handle,
+            cccd_handle,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Find the first characteristic with the given UUID.
+    pub fn characteristic_by_uuid<T: AsGatt>(&self, uuid: &Uuid) -> Option<Characteristic<T>> {
+        self.characteristics
+            .iter()
+            .find(|c| c.uuid == *uuid)
+            .map(|c| self.characteristic(c))
+    }
+
+    /// Remove all entries of the services overlapping a handle range.
+    ///
+    /// Returns the range covered by the removed services, which must be rediscovered.
+    fn invalidate(&mut self, mut start: u16, mut end: u16) -> (u16, u16) {
+        loop {
+            let mut range = (start, end);
+            for service in self.services.iter() {
+                // Secondary services are only found through an include, so the services including
+                // an affected service must be rediscovered as well.
+                let includes_affected = self
+                    .includes
+                    .iter()
+                    .any(|i| service.contains(i.handle) && i.service.start <= end && i.service.end >= start);
+                if (service.start <= end && service.end >= start) || includes_affected {
+                    range = (range.0.min(service.start), range.1.max(service.end));
+                }
+            }
+            if range == (start, end) {
+                break;
+            }
+            (start, end) = range;
+        }
+        let range = start..=end;
+        self.services.retain(|s| !range.contains(&s.start));
+        self.includes.retain(|i| !range.contains(&i.handle));
+        self.characteristics.retain(|c| !range.contains(&c.declaration_handle));
+        self.descriptors.retain(|d| !range.contains(&d.handle));
+        self.hash = None;
+        (start, end)
+    }
+}
+
+/// Storage of discovered attribute databases across connections, keyed by the identity of the peer.
+///
+/// Used by [`GattClient::discover_cached`] to skip discovery when reconnecting to a known server.
+/// Implementations would typically only keep entries for bonded peers, and persist them along with the bond information.
+pub trait GattCache<const SERVICES: usize, const CHARACTERISTICS: usize, const DESCRIPTORS: usize> {
+    /// Load the database cached for a peer.
+    fn load(&mut self, peer: &Identity) -> Option<GattDatabase<SERVICES, CHARACTERISTICS, DESCRIPTORS>>;
+
+    /// Store the database of a peer, replacing any previous entry.
+    fn store(&mut self, peer: &Identity, database: &GattDatabase<SERVICES, CHARACTERISTICS, DESCRIPTORS>);
+
+    /// Remove the database cached for a peer.
+    fn remove(&mut self, peer: &Identity);
+}
+
 pub(crate) struct Response<P> {
     pdu: Pdu<P>,
     handle: ConnHandle,
@@ -537,14 +1219,21 @@ impl<'reference, T: Controller, P: PacketPool, const MAX_SERVICES: usize> Client
     for GattClient<'reference, T, P, MAX_SERVICES>
 {
     async fn request(&self, req: AttReq<'_>) -> Result<Response<P::Packet>, BleHostError<T::Error>> {
+        if !self.bearers.borrow().is_empty() {
+            return self.request_eatt(req).await;
+        }
+
         let data = Att::Client(AttClient::Request(req));
 
         self.send_att_data(data).await?;
 
-        let (h, pdu) = self.response_channel.receive().await;
-
-        assert_eq!(h, self.connection.handle());
-        Ok(Response { handle: h, pdu })
+        match self.response_channel.receive().with_timeout(self.timeout.get()).await {
+            Ok(pdu) => Ok(Response {
+                handle: self.connection.handle(),
+                pdu,
+            }),
+            Err(_) => Err(self.transaction_timeout()),
+        }
     }
 
     async fn command(&self, cmd: AttCmd<'_>) -> Result<(), BleHostError<T::Error>> {
@@ -558,12 +1247,15 @@ impl<'reference, T: Controller, P: PacketPool, const MAX_SERVICES: usize> Client
 
 impl<'reference, T: Controller, P: PacketPool, const MAX_SERVICES: usize> GattClient<'reference, T, P, MAX_SERVICES> {
     async fn send_att_data(&self, data: Att<'_>) -> Result<(), BleHostError<T::Error>> {
+        if self.failed.get() {
+            return Err(Error::Timeout.into());
+        }
         let header = L2capHeader {
             channel: crate::types::l2cap::L2CAP_CID_ATT,
             length: data.size() as u16,
         };
 
-        let mut buf = P::allocate().ok_or(Error::OutOfMemory)?;
+        let mut buf = P::allocate_async().await;
         let mut w = WriteCursor::new(buf.as_mut());
         w.write_hci(&header)?;
         w.write(data)?;
@@ -572,34 +1264,225 @@ impl<'reference, T: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         self.connection.send(Pdu::new(buf, len)).await;
         Ok(())
     }
+
+    /// Perform a request on a free Enhanced ATT bearer, waiting for one if all bearers are busy.
+    async fn request_eatt(&self, req: AttReq<'_>) -> Result<Response<P::Packet>, BleHostError<T::Error>> {
+        if self.failed.get() {
+            return Err(Error::Timeout.into());
+        }
+        let slot = BearerSlot::take(&self.free_bearers).await;
+        let index = self.bearers.borrow()[slot.slot as usize].index();
+        self.send_eatt(index, Att::Client(AttClient::Request(req))).await?;
+
+        let host = &self.stack.host;
+        let response = async {
+            loop {
+                let sdu = host.channels.receive_sdu(index, host).await?;
+                let len = sdu.len();
+                let pdu = Pdu::new(sdu.into_inner(), len);
+                // Notifications and indications may be sent on any bearer.
+                match pdu.as_ref().first() {
+                    Some(&opcode) if opcode == ATT_HANDLE_VALUE_NTF || opcode == ATT_HANDLE_VALUE_IND => {
+                        let indication = opcode == ATT_HANDLE_VALUE_IND;
+                        self.handle_notification_packet(pdu, indication)?;
+                        if indication && self.auto_confirm.get() {
+                            let cfm = Att::Client(AttClient::Confirmation(AttCfm::ConfirmIndication));
+                            self.send_eatt(index, cfm).await?;
+                        }
+                    }
+                    _ => return Ok::<_, BleHostError<T::Error>>(pdu),
+                }
+            }
+        }
+        .with_timeout(self.timeout.get())
+        .await;
+
+        match response {
+            Ok(pdu) => Ok(Response {
+                handle: self.connection.handle(),
+                pdu: pdu?,
+            }),
+            Err(_) => Err(self.transaction_timeout()),
+        }
+    }
+
+    async fn send_eatt(&self, index: ChannelIndex, data: Att<'_>) -> Result<(), BleHostError<T::Error>> {
+        let mut sdu = TxSdu::<P>::allocate_async().await;
+        let mut w = WriteCursor::new(sdu.buffer_mut());
+        w.write(data)?;
+        let len = w.len();
+        sdu.set_len(len)?;
+
+        let (packet, len) = sdu.raw_mut();
+        self.stack
+            .host
+            .channels
+            .send_in_place(index, packet, len, &self.stack.host)
+            .await
+    }
+
+    fn transaction_timeout(&self) -> BleHostError<T::Error> {
+        warn!(
+            "[gatt] att transaction timed out on connection {}",
+            self.connection.handle().raw()
+        );
+        // No further ATT traffic may be sent on the bearer after a transaction timeout.
+        self.failed.set(true);
+        if self.disconnect_on_timeout.get() {
+            self.connection.disconnect();
+        }
+        Error::Timeout.into()
+    }
 }
 
 impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattClient<'reference, C, P, MAX_SERVICES> {
     /// Creates a GATT client capable of processing the GATT protocol using the provided table of attributes.
+    ///
+    /// The ATT MTU is exchanged with the server, requesting the largest MTU supported by the packet pool, before returning.
     pub async fn new(
         stack: &'reference Stack<'reference, C, P>,
         connection: &Connection<'reference, P>,
     ) -> Result<GattClient<'reference, C, P, MAX_SERVICES>, BleHostError<C::Error>> {
-        let l2cap = L2capHeader { channel: 4, length: 3 };
-        let mut buf = P::allocate().ok_or(Error::OutOfMemory)?;
-        let mut w = WriteCursor::new(buf.as_mut());
-        w.write_hci(&l2cap)?;
-        w.write(att::Att::Client(att::AttClient::Request(att::AttReq::ExchangeMtu {
-            mtu: P::MTU as u16 - 4,
-        })))?;
+        Self::new_with_mtu(stack, connection, P::MTU as u16 - 4).await
+    }
 
-        let len = w.len();
-        connection.send(Pdu::new(buf, len)).await;
-        Ok(Self {
-            known_services: RefCell::new(heapless::Vec::new()),
-            rx: stack.host.att_client.receiver().into(),
-            stack,
+    /// Creates a GATT client, requesting the given ATT MTU in the exchange with the server.
+    ///
+    /// The requested MTU is limited to what the packet pool supports. The exchange is completed
+    /// before returning, and the negotiated MTU is available from [`GattClient::mtu`].
+    pub async fn new_with_mtu(
+        stack: &'reference Stack<'reference, C, P>,
+        connection: &Connection<'reference, P>,
+        mtu: u16,
+    ) -> Result<GattClient<'reference, C, P, MAX_SERVICES>, BleHostError<C::Error>> {
+        let client = Self {
+            stack,
             connection: connection.clone(),
 
             response_channel: Channel::new(),
+            auto_confirm: Cell::new(true),
+
+            notifications: NotificationListeners::new(),
+            timeout: Cell::new(ATT_TIMEOUT),
+            disconnect_on_timeout: Cell::new(false),
+            failed: Cell::new(false),
+            bearers: RefCell::new(Vec::new()),
+            free_bearers: Channel::new(),
+        };
 
-            notifications: PubSubChannel::new(),
-        })
+        let mtu = mtu.clamp(23, P::MTU as u16 - 4);
+        client
+            .send_att_data(Att::Client(AttClient::Request(AttReq::ExchangeMtu { mtu })))
+            .await?;
+
+        // The client task is not running yet, so wait for the response directly.
+        async {
+            loop {
+                let pdu = connection.next_gatt_client().await;
+                let opcode = pdu.as_ref()[0];
+                if opcode == ATT_HANDLE_VALUE_NTF || opcode == ATT_HANDLE_VALUE_IND {
+                    // The server may send these before answering, indications must still be confirmed.
+                    let indication = opcode == ATT_HANDLE_VALUE_IND;
+                    client.handle_notification_packet(pdu, indication)?;
+                    if indication {
+                        client.confirm_indication().await?;
+                    }
+                    continue;
+                }
+                match Att::decode(pdu.as_ref())? {
+                    Att::Server(AttServer::Response(AttRsp::ExchangeMtu { mtu: server_mtu })) => {
+                        let mtu = connection.exchange_att_mtu(mtu.min(server_mtu));
+                        info!("[gatt] agreed att MTU of {}", mtu);
+                        return Ok::<_, BleHostError<C::Error>>(());
+                    }
+                    Att::Server(AttServer::Response(AttRsp::Error { request, handle, code })) => {
+                        // The server does not support the exchange: keep the default MTU.
+                        warn!("[gatt] att MTU exchange failed: {:?}", code);
+                        return Ok(());
+                    }
+                    _ => {
+                        warn!("[gatt] dropping unexpected att pdu received before the MTU exchange completed");
+                    }
+                }
+            }
+        }
+        .with_timeout(ATT_TIMEOUT)
+        .await
+        .map_err(|_| Error::Timeout)??;
+
+        Ok(client)
+    }
+
+    /// The ATT MTU negotiated with the server.
+    pub fn mtu(&self) -> u16 {
+        self.connection.get_att_mtu()
+    }
+
+    /// Open Enhanced ATT bearers to the server, so that requests are processed in parallel.
+    ///
+    /// The server must advertise EATT support in its Server Supported Features. The EATT bit of the
+    /// Client Supported Features is written before the bearers are opened. Once opened, all requests use
+    /// the bearers, with one outstanding request per bearer. Commands keep using the unenhanced bearer.
+    ///
+    /// Returns the number of bearers accepted by the server.
+    pub async fn open_eatt(&self, count: usize) -> Result<usize, BleHostError<C::Error>> {
+        if !self.bearers.borrow().is_empty() {
+            return Err(Error::InvalidState.into());
+        }
+        match self.read_features(SERVER_SUPPORTED_FEATURES.into()).await? {
+            Some((_, features)) if features & SERVER_FEATURES_EATT != 0 => {}
+            _ => return Err(Error::NotSupported.into()),
+        }
+        if let Some((handle, features)) = self.read_features(CLIENT_SUPPORTED_FEATURES.into()).await? {
+            let value = [features | CLIENT_FEATURES_EATT];
+            let response = self.request(att::AttReq::Write { handle, data: &value }).await?;
+            match Self::response(response.pdu.as_ref())? {
+                AttRsp::Write => {}
+                AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
+                _ => return Err(Error::UnexpectedGattResponse.into()),
+            }
+        }
+
+        let config = L2capChannelConfig {
+            // Requests and responses are limited to the ATT MTU of the connection.
+            mtu: Some(self.mtu().max(64)),
+            ..Default::default()
+        };
+        let bearers = L2capChannel::create_enhanced(self.stack, &self.connection, EATT_PSM, count, &config).await?;
+        let opened = bearers.len();
+        *self.bearers.borrow_mut() = bearers;
+        for slot in 0..opened {
+            let _ = self.free_bearers.try_send(slot as u8);
+        }
+        info!("[gatt] opened {} eatt bearers", opened);
+        Ok(opened)
+    }
+
+    /// The number of Enhanced ATT bearers opened to the server.
+    pub fn eatt_bearers(&self) -> usize {
+        self.bearers.borrow().len()
+    }
+
+    /// Read the handle and first octet of a features characteristic of the GATT service.
+    async fn read_features(&self, uuid: Uuid) -> Result<Option<(u16, u8)>, BleHostError<C::Error>> {
+        let data = att::AttReq::ReadByType {
+            start: 0x0001,
+            end: 0xffff,
+            attribute_type: uuid,
+        };
+        let response = self.request(data).await?;
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ReadByType { mut it } => match it.next() {
+                Some(res) => {
+                    let (handle, value) = res?;
+                    Ok(Some((handle, value.first().copied().unwrap_or(0))))
+                }
+                None => Ok(None),
+            },
+            AttRsp::Error { code, .. } if code == AttErrorCode::ATTRIBUTE_NOT_FOUND => Ok(None),
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
     }
 
     /// Discover primary services associated with a UUID.
@@ -628,36 +1511,441 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
                     return Err(Error::Att(code).into());
                 }
                 AttRsp::FindByTypeValue { mut it } => {
-                    let mut end: u16 = 0;
+                    let mut end: u16 = 0xffff;
                     while let Some(res) = it.next() {
                         let (handle, e) = res?;
+                        if e < handle {
+                            return Err(Error::UnexpectedGattResponse.into());
+                        }
                         end = e;
                         let svc = ServiceHandle {
                             start: handle,
                             end,
                             uuid: uuid.clone(),
                         };
-                        result.push(svc.clone()).map_err(|_| Error::InsufficientSpace)?;
-                        self.known_services
-                            .borrow_mut()
-                            .push(svc)
-                            .map_err(|_| Error::InsufficientSpace)?;
+                        result.push(svc).map_err(|_| Error::InsufficientSpace)?;
                     }
-                    if end == 0xFFFF {
-                        break;
+                    match next_start(start, end, 0xffff)? {
+                        Some(next) => start = next,
+                        None => break,
+                    }
+                }
+                res => {
+                    trace!("[gatt client] response: {:?}", res);
+                    return Err(Error::UnexpectedGattResponse.into());
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Discover all primary services of the server.
+    pub async fn services(&self) -> Result<Vec<ServiceHandle, MAX_SERVICES>, BleHostError<C::Error>> {
+        let mut result = Vec::new();
+        self.discover_services_into(0x0001, 0xffff, &mut result).await?;
+        Ok(result)
+    }
+
+    async fn discover_services_into<const N: usize>(
+        &self,
+        mut start: u16,
+        range_end: u16,
+        result: &mut Vec<ServiceHandle, N>,
+    ) -> Result<(), BleHostError<C::Error>> {
+        while start <= range_end {
+            let data = att::AttReq::ReadByGroupType {
+                start,
+                end: range_end,
+                group_type: PRIMARY_SERVICE.into(),
+            };
+
+            let response = self.request(data).await?;
+            match Self::response(response.pdu.as_ref())? {
+                AttRsp::ReadByGroupType { mut it } => {
+                    let mut end = range_end;
+                    while let Some(res) = it.next() {
+                        let (handle, e, uuid) = res?;
+                        if e < handle {
+                            return Err(Error::UnexpectedGattResponse.into());
+                        }
+                        end = e;
+                        let svc = ServiceHandle {
+                            start: handle,
+                            end,
+                            uuid: Uuid::try_from(uuid)?,
+                        };
+                        result.push(svc).map_err(|_| Error::InsufficientSpace)?;
+                    }
+                    match next_start(start, end, range_end)? {
+                        Some(next) => start = next,
+                        None => break,
                     }
-                    start = end + 1;
                 }
+                AttRsp::Error { code, .. } if code == AttErrorCode::ATTRIBUTE_NOT_FOUND => break,
+                AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
                 res => {
                     trace!("[gatt client] response: {:?}", res);
                     return Err(Error::UnexpectedGattResponse.into());
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Find the services included by a service.
+    pub async fn included_services<const N: usize>(
+        &self,
+        service: &ServiceHandle,
+    ) -> Result<Vec<IncludedService, N>, BleHostError<C::Error>> {
+        let mut result = Vec::new();
+        self.included_services_into(service, &mut result).await?;
+        Ok(result)
+    }
+
+    async fn included_services_into<const N: usize>(
+        &self,
+        service: &ServiceHandle,
+        result: &mut Vec<IncludedService, N>,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let first = result.len();
+        let mut start = service.start;
+        while start <= service.end {
+            let data = att::AttReq::ReadByType {
+                start,
+                end: service.end,
+                attribute_type: INCLUDE.into(),
+            };
+
+            let response = self.request(data).await?;
+            match Self::response(response.pdu.as_ref())? {
+                AttRsp::ReadByType { mut it } => {
+                    let mut last = service.end;
+                    while let Some(res) = it.next() {
+                        let (handle, item) = res?;
+                        let mut r = ReadCursor::new(item);
+                        let included_start: u16 = r.read()?;
+                        let included_end: u16 = r.read()?;
+                        // 128-bit service UUIDs are not part of the declaration, they are read below
+                        let uuid = match r.remaining() {
+                            [] => Uuid::Uuid128([0; 16]),
+                            uuid => Uuid::try_from(uuid)?,
+                        };
+                        result
+                            .push(IncludedService {
+                                handle,
+                                service: ServiceHandle {
+                                    start: included_start,
+                                    end: included_end,
+                                    uuid,
+                                },
+                            })
+                            .map_err(|_| Error::InsufficientSpace)?;
+                        last = handle;
+                    }
+                    match next_start(start, last, service.end)? {
+                        Some(next) => start = next,
+                        None => break,
+                    }
+                }
+                AttRsp::Error { code, .. } if code == AttErrorCode::ATTRIBUTE_NOT_FOUND => break,
+                AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
+                _ => return Err(Error::UnexpectedGattResponse.into()),
+            }
+        }
+
+        for i in first..result.len() {
+            if result[i].service.uuid == Uuid::Uuid128([0; 16]) {
+                let data = att::AttReq::Read {
+                    handle: result[i].service.start,
+                };
+                let response = self.request(data).await?;
+                match Self::response(response.pdu.as_ref())? {
+                    AttRsp::Read { data } => result[i].service.uuid = Uuid::try_from(data)?,
+                    AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
+                    _ => return Err(Error::UnexpectedGattResponse.into()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Discover all characteristics of a service.
+    pub async fn characteristics<const N: usize>(
+        &self,
+        service: &ServiceHandle,
+    ) -> Result<Vec<DiscoveredCharacteristic, N>, BleHostError<C::Error>> {
+        let mut result = Vec::new();
+        self.characteristics_into(service, &mut result).await?;
+        Ok(result)
+    }
+
+    async fn characteristics_into<const N: usize>(
+        &self,
+        service: &ServiceHandle,
+        result: &mut Vec<DiscoveredCharacteristic, N>,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let first = result.len();
+        let mut start = service.start;
+        while start <= service.end {
+            let data = att::AttReq::ReadByType {
+                start,
+                end: service.end,
+                attribute_type: CHARACTERISTIC.into(),
+            };
+
+            let response = self.request(data).await?;
+            match Self::response(response.pdu.as_ref())? {
+                AttRsp::ReadByType { mut it } => {
+                    let mut last = service.end;
+                    while let Some(res) = it.next() {
+                        let (declaration_handle, item) = res?;
+                        if declaration_handle < start {
+                            return Err(Error::UnexpectedGattResponse.into());
+                        }
+                        if let AttributeData::Declaration { props, handle, uuid } =
+                            AttributeData::decode_declaration(item)?
+                        {
+                            // The previous characteristic ends right before this declaration
+                            if result.len() > first {
+                                if let Some(previous) = result.last_mut() {
+                                    previous.end_handle = declaration_handle - 1;
+                                }
+                            }
+                            result
+                                .push(DiscoveredCharacteristic {
+                                    declaration_handle,
+                                    handle,
+                                    end_handle: service.end,
+                                    props,
+                                    uuid,
+                                })
+                                .map_err(|_| Error::InsufficientSpace)?;
+                        } else {
+                            return Err(Error::InvalidCharacteristicDeclarationData.into());
+                        }
+                        last = declaration_handle;
+                    }
+                    match next_start(start, last, service.end)? {
+                        Some(next) => start = next,
+                        None => break,
+                    }
+                }
+                AttRsp::Error { code, .. } if code == AttErrorCode::ATTRIBUTE_NOT_FOUND => break,
+                AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
+                _ => return Err(Error::UnexpectedGattResponse.into()),
+            }
+        }
+        Ok(())
+    }
 
+    /// Discover all descriptors of a characteristic.
+    pub async fn descriptors<const N: usize>(
+        &self,
+        characteristic: &DiscoveredCharacteristic,
+    ) -> Result<Vec<DiscoveredDescriptor, N>, BleHostError<C::Error>> {
+        let mut result = Vec::new();
+        self.descriptors_into(characteristic, &mut result).await?;
         Ok(result)
     }
 
+    async fn descriptors_into<const N: usize>(
+        &self,
+        characteristic: &DiscoveredCharacteristic,
+        result: &mut Vec<DiscoveredDescriptor, N>,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let end = characteristic.end_handle;
+        let mut start = characteristic.handle.saturating_add(1);
+        while characteristic.handle < end && start <= end {
+            let data = att::AttReq::FindInformation {
+                start_handle: start,
+                end_handle: end,
+            };
+
+            let response = self.request(data).await?;
+            match Self::response(response.pdu.as_ref())? {
+                AttRsp::FindInformation { mut it } => {
+                    let mut last = end;
+                    while let Some(res) = it.next() {
+                        let (handle, uuid) = res?;
+                        result
+                            .push(DiscoveredDescriptor { handle, uuid })
+                            .map_err(|_| Error::InsufficientSpace)?;
+                        last = handle;
+                    }
+                    match next_start(start, last, end)? {
+                        Some(next) => start = next,
+                        None => break,
+                    }
+                }
+                AttRsp::Error { code, .. } if code == AttErrorCode::ATTRIBUTE_NOT_FOUND => break,
+                AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
+                _ => return Err(Error::UnexpectedGattResponse.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Discover the complete attribute database of the server.
+    ///
+    /// All primary services are discovered, along with the services they include and all characteristics
+    /// and descriptors. If the database does not fit in the bounds of the model, `Error::InsufficientSpace` is returned.
+    pub async fn discover<const SERVICES: usize, const CHARACTERISTICS: usize, const DESCRIPTORS: usize>(
+        &self,
+    ) -> Result<GattDatabase<SERVICES, CHARACTERISTICS, DESCRIPTORS>, BleHostError<C::Error>> {
+        let mut db = GattDatabase::default();
+        self.discover_range_into(&mut db, 0x0001, 0xffff).await?;
+        Ok(db)
+    }
+
+    /// Discover the services in a handle range, adding them to the database.
+    async fn discover_range_into<const SERVICES: usize, const CHARACTERISTICS: usize, const DESCRIPTORS: usize>(
+        &self,
+        db: &mut GattDatabase<SERVICES, CHARACTERISTICS, DESCRIPTORS>,
+        start: u16,
+        end: u16,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let first_service = db.services.len();
+        let first_characteristic = db.characteristics.len();
+        self.discover_services_into(start, end, &mut db.services).await?;
+
+        // Included services may be secondary services, which are only found through the include
+        let mut i = first_service;
+        while i < db.services.len() {
+            let service = db.services[i].clone();
+            let first = db.includes.len();
+            self.included_services_into(&service, &mut db.includes).await?;
+            for include in db.includes[first..].iter() {
+                if !db.services.iter().any(|s| s.start == include.service.start) {
+                    db.services
+                        .push(include.service.clone())
+                        .map_err(|_| Error::InsufficientSpace)?;
+                }
+            }
+            i += 1;
+        }
+
+        for service in db.services[first_service..].iter() {
+            self.characteristics_into(service, &mut db.characteristics).await?;
+        }
+        for characteristic in db.characteristics[first_characteristic..].iter() {
+            self.descriptors_into(characteristic, &mut db.descriptors).await?;
+        }
+
+        db.services.sort_unstable_by_key(|s| s.start);
+        db.includes.sort_unstable_by_key(|i| i.handle);
+        db.characteristics.sort_unstable_by_key(|c| c.declaration_handle);
+        db.descriptors.sort_unstable_by_key(|d| d.handle);
+        Ok(())
+    }
+
+    /// Read the Database Hash characteristic of the server.
+    ///
+    /// Returns `None` if the server does not expose a Database Hash.
+    pub async fn database_hash(&self) -> Result<Option<[u8; 16]>, BleHostError<C::Error>> {
+        let data = att::AttReq::ReadByType {
+            start: 0x0001,
+            end: 0xffff,
+            attribute_type: DATABASE_HASH.into(),
+        };
+        let response = self.request(data).await?;
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ReadByType { mut it } => match it.next() {
+                Some(res) => {
+                    let (_, value) = res?;
+                    let hash: [u8; 16] = value.try_into().map_err(|_| Error::InvalidValue)?;
+                    Ok(Some(hash))
+                }
+                None => Ok(None),
+            },
+            AttRsp::Error { code, .. } if code == AttErrorCode::ATTRIBUTE_NOT_FOUND => Ok(None),
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
+    /// Discover the attribute database of the server, using the database cached for the peer if it is still valid.
+    ///
+    /// The cached database is used when the Database Hash read from the server matches the hash it was
+    /// discovered with. Otherwise the database is discovered and stored in the cache. Servers without a
+    /// Database Hash are always discovered.
+    pub async fn discover_cached<
+        const SERVICES: usize,
+        const CHARACTERISTICS: usize,
+        const DESCRIPTORS: usize,
+        G: GattCache<SERVICES, CHARACTERISTICS, DESCRIPTORS>,
+    >(
+        &self,
+        cache: &mut G,
+    ) -> Result<GattDatabase<SERVICES, CHARACTERISTICS, DESCRIPTORS>, BleHostError<C::Error>> {
+        let peer = self.connection.peer_identity();
+        let hash = self.database_hash().await?;
+        if let (Some(hash), Some(db)) = (hash, cache.load(&peer)) {
+            if db.hash == Some(hash) {
+                debug!("[gatt] using cached database");
+                return Ok(db);
+            }
+        }
+
+        let mut db = self.discover().await?;
+        db.hash = hash;
+        if hash.is_some() {
+            cache.store(&peer, &db);
+        } else {
+            cache.remove(&peer);
+        }
+        Ok(db)
+    }
+
+    /// Subscribe to the Service Changed indications of the server.
+    ///
+    /// Returns `Error::NotFound` if the server has no Service Changed characteristic.
+    pub async fn subscribe_service_changed<
+        const SERVICES: usize,
+        const CHARACTERISTICS: usize,
+        const DESCRIPTORS: usize,
+    >(
+        &self,
+        database: &GattDatabase<SERVICES, CHARACTERISTICS, DESCRIPTORS>,
+    ) -> Result<NotificationListener<'_, P>, BleHostError<C::Error>> {
+        let characteristic = database
+            .characteristic_by_uuid::<[u8; 4]>(&SERVICE_CHANGED.into())
+            .ok_or(Error::NotFound)?;
+        self.subscribe(&characteristic, true).await
+    }
+
+    /// Handle a Service Changed indication.
+    ///
+    /// The services in the affected handle range are removed from the database and rediscovered,
+    /// and the database cached for the peer is updated.
+    pub async fn handle_service_changed<
+        const SERVICES: usize,
+        const CHARACTERISTICS: usize,
+        const DESCRIPTORS: usize,
+        G: GattCache<SERVICES, CHARACTERISTICS, DESCRIPTORS>,
+    >(
+        &self,
+        database: &mut GattDatabase<SERVICES, CHARACTERISTICS, DESCRIPTORS>,
+        indication: &Notification<P>,
+        cache: &mut G,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let mut r = ReadCursor::new(indication.as_ref());
+        let start: u16 = r.read()?;
+        let end: u16 = r.read()?;
+        let peer = self.connection.peer_identity();
+        cache.remove(&peer);
+
+        let (start, end) = database.invalidate(start, end);
+        debug!("[gatt] rediscovering handles {} to {}", start, end);
+        self.discover_range_into(database, start, end).await?;
+
+        database.hash = self.database_hash().await?;
+        if database.hash.is_some() {
+            cache.store(&peer, database);
+        }
+        Ok(())
+    }
+
     /// Discover characteristics in a given service using a UUID.
     pub async fn characteristic_by_uuid<T: AsGatt>(
         &self,
@@ -696,7 +1984,7 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
                                 // "notify" and "indicate" characteristic properties
                                 let cccd_handle =
                                     if props.any(&[CharacteristicProp::Indicate, CharacteristicProp::Notify]) {
-                                        Some(self.get_characteristic_cccd(handle).await?.0)
+                                        Some(self.get_characteristic_cccd(handle, service.end).await?.0)
                                     } else {
                                         None
                                     };
@@ -711,6 +1999,9 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
                             if handle == 0xFFFF {
                                 return Err(Error::NotFound.into());
                             }
+                            if handle < start {
+                                return Err(Error::UnexpectedGattResponse.into());
+                            }
                             start = handle + 1;
                         } else {
                             return Err(Error::InvalidCharacteristicDeclarationData.into());
@@ -725,10 +2016,11 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 
-    async fn get_characteristic_cccd(&self, char_handle: u16) -> Result<(u16, CCCD), BleHostError<C::Error>> {
+    /// Find the CCCD of a characteristic, which is the first CCCD following the characteristic value.
+    async fn get_characteristic_cccd(&self, char_handle: u16, end: u16) -> Result<(u16, CCCD), BleHostError<C::Error>> {
         let data = att::AttReq::ReadByType {
-            start: char_handle,
-            end: char_handle + 1,
+            start: char_handle + 1,
+            end,
             attribute_type: CLIENT_CHARACTERISTIC_CONFIGURATION.into(),
         };
 
@@ -762,6 +2054,9 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
 
     /// Read a characteristic described by a handle.
     ///
+    /// If the value fills the complete response and the provided buffer has room for more,
+    /// the rest of the value is read using the Read Long Characteristic Value procedure.
+    ///
     /// The number of bytes copied into the provided buffer is returned.
     pub async fn read_characteristic<T: AsGatt>(
         &self,
@@ -774,8 +2069,86 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
 
         let response = self.request(data).await?;
 
-        match Self::response(response.pdu.as_ref())? {
+        let (to_copy, is_long) = match Self::response(response.pdu.as_ref())? {
             AttRsp::Read { data } => {
+                let to_copy = data.len().min(dest.len());
+                dest[..to_copy].copy_from_slice(&data[..to_copy]);
+                (to_copy, data.len() >= self.connection.get_att_mtu() as usize - 1)
+            }
+            AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
+            _ => return Err(Error::UnexpectedGattResponse.into()),
+        };
+
+        if is_long && to_copy < dest.len() {
+            self.read_blobs(characteristic.handle, to_copy, dest).await
+        } else {
+            Ok(to_copy)
+        }
+    }
+
+    /// Read a characteristic described by a handle, using the Read Long Characteristic Value procedure.
+    ///
+    /// The value is read in parts until it ends or the provided buffer is full.
+    /// The number of bytes copied into the provided buffer is returned.
+    pub async fn read_characteristic_long<T: AsGatt>(
+        &self,
+        characteristic: &Characteristic<T>,
+        dest: &mut [u8],
+    ) -> Result<usize, BleHostError<C::Error>> {
+        self.read_blobs(characteristic.handle, 0, dest).await
+    }
+
+    /// Read the parts of a long attribute value from the offset on, until the value ends or the buffer is full.
+    async fn read_blobs(
+        &self,
+        handle: u16,
+        mut offset: usize,
+        dest: &mut [u8],
+    ) -> Result<usize, BleHostError<C::Error>> {
+        let part_len = self.connection.get_att_mtu() as usize - 1;
+        while offset < dest.len() && offset <= u16::MAX as usize {
+            let data = att::AttReq::ReadBlob {
+                handle,
+                offset: offset as u16,
+            };
+
+            let response = self.request(data).await?;
+            match Self::response(response.pdu.as_ref())? {
+                AttRsp::ReadBlob { data } => {
+                    let to_copy = data.len().min(dest.len() - offset);
+                    dest[offset..offset + to_copy].copy_from_slice(&data[..to_copy]);
+                    offset += to_copy;
+                    if data.len() < part_len {
+                        break;
+                    }
+                }
+                // The value ended exactly with the previous part
+                AttRsp::Error { code, .. }
+                    if offset > 0
+                        && (code == AttErrorCode::INVALID_OFFSET || code == AttErrorCode::ATTRIBUTE_NOT_LONG) =>
+                {
+                    break
+                }
+                AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
+                _ => return Err(Error::UnexpectedGattResponse.into()),
+            }
+        }
+        Ok(offset)
+    }
+
+    /// Read the values of several attributes with a single Read Multiple request.
+    ///
+    /// The values are concatenated into the provided buffer, so all but the last value must have a known fixed size.
+    /// The response is limited to the MTU. The number of bytes copied into the provided buffer is returned.
+    pub async fn read_multiple(&self, handles: &[u16], dest: &mut [u8]) -> Result<usize, BleHostError<C::Error>> {
+        let mut buf = [0; 2 * READ_MULTIPLE_MAX];
+        let data = att::AttReq::ReadMultiple {
+            handles: encode_handles(handles, &mut buf)?,
+        };
+
+        let response = self.request(data).await?;
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ReadMultiple { data } => {
                 let to_copy = data.len().min(dest.len());
                 dest[..to_copy].copy_from_slice(&data[..to_copy]);
                 Ok(to_copy)
@@ -785,6 +2158,68 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 
+    /// Read the values of several attributes with a single Read Multiple Variable Length request.
+    ///
+    /// The values are copied one after the other into the provided buffer, and the length of each value is returned.
+    /// The response is limited to the MTU, so the last value may be truncated and values may be missing.
+    pub async fn read_multiple_variable<const N: usize>(
+        &self,
+        handles: &[u16],
+        dest: &mut [u8],
+    ) -> Result<Vec<usize, N>, BleHostError<C::Error>> {
+        let mut buf = [0; 2 * READ_MULTIPLE_MAX];
+        let data = att::AttReq::ReadMultipleVariable {
+            handles: encode_handles(handles, &mut buf)?,
+        };
+
+        let response = self.request(data).await?;
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ReadMultipleVariable { mut it } => {
+                let mut lengths = Vec::new();
+                let mut offset = 0;
+                while let Some(value) = it.next() {
+                    let value = value?;
+                    if offset + value.len() > dest.len() {
+                        return Err(Error::InsufficientSpace.into());
+                    }
+                    dest[offset..offset + value.len()].copy_from_slice(value);
+                    offset += value.len();
+                    lengths.push(value.len()).map_err(|_| Error::InsufficientSpace)?;
+                }
+                Ok(lengths)
+            }
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
+    /// Read the values of a tuple of characteristics with a single request.
+    ///
+    /// The Read Multiple Variable Length procedure is used, so the values may have any size,
+    /// as long as the response fits in the MTU.
+    ///
+    /// ```rust,ignore
+    /// let (level, temperature) = client.read_characteristics((&level, &temperature)).await?;
+    /// ```
+    pub async fn read_characteristics<R: CharacteristicTuple>(
+        &self,
+        characteristics: R,
+    ) -> Result<R::Values, BleHostError<C::Error>> {
+        let mut handles = [0; READ_MULTIPLE_MAX];
+        let count = characteristics.handles(&mut handles);
+        let mut buf = [0; 2 * READ_MULTIPLE_MAX];
+        let data = att::AttReq::ReadMultipleVariable {
+            handles: encode_handles(&handles[..count], &mut buf)?,
+        };
+
+        let response = self.request(data).await?;
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ReadMultipleVariable { mut it } => Ok(R::decode(&mut it)?),
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
     /// Read a characteristic described by a UUID.
     ///
     /// The number of bytes copied into the provided buffer is returned.
@@ -818,11 +2253,18 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
     }
 
     /// Write to a characteristic described by a handle.
+    ///
+    /// Values which do not fit in a single write request are written using the
+    /// Write Long Characteristic Value procedure.
     pub async fn write_characteristic<T: FromGatt>(
         &self,
         handle: &Characteristic<T>,
         buf: &[u8],
     ) -> Result<(), BleHostError<C::Error>> {
+        if buf.len() > self.connection.get_att_mtu() as usize - 3 {
+            return self.write_characteristic_long(handle, buf).await;
+        }
+
         let data = att::AttReq::Write {
             handle: handle.handle,
             data: buf,
@@ -836,6 +2278,114 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 
+    /// Write to a characteristic described by a handle, using the Write Long Characteristic Value procedure.
+    ///
+    /// The value is queued on the server in parts, and written at once when all parts are queued.
+    /// If queueing fails, the queued parts are cancelled.
+    pub async fn write_characteristic_long<T: FromGatt>(
+        &self,
+        characteristic: &Characteristic<T>,
+        buf: &[u8],
+    ) -> Result<(), BleHostError<C::Error>> {
+        if let Err(e) = self.prepare_writes(characteristic.handle, 0, buf, false).await {
+            let _ = self.execute_writes(false).await;
+            return Err(e);
+        }
+        self.execute_writes(true).await
+    }
+
+    /// Write to a characteristic described by a handle, using the Reliable Writes procedure.
+    ///
+    /// Every queued part is verified against the value echoed by the server before the write is executed.
+    /// If queueing fails, the queued parts are cancelled.
+    pub async fn write_characteristic_reliable<T: FromGatt>(
+        &self,
+        characteristic: &Characteristic<T>,
+        buf: &[u8],
+    ) -> Result<(), BleHostError<C::Error>> {
+        self.prepare_write(characteristic, 0, buf).await?;
+        self.execute_write().await
+    }
+
+    /// Queue a reliable write of a value at the given offset of a characteristic.
+    ///
+    /// Writes to multiple characteristics can be queued, and are then written at once using [`GattClient::execute_write`]
+    /// or discarded using [`GattClient::cancel_write`]. Every queued part is verified against the value echoed by the
+    /// server, and `Error::ReliableWriteMismatch` is returned if the echo does not match.
+    ///
+    /// On any error, all queued writes are cancelled.
+    pub async fn prepare_write<T: FromGatt>(
+        &self,
+        characteristic: &Characteristic<T>,
+        offset: u16,
+        buf: &[u8],
+    ) -> Result<(), BleHostError<C::Error>> {
+        if let Err(e) = self
+            .prepare_writes(characteristic.handle, offset as usize, buf, true)
+            .await
+        {
+            let _ = self.execute_writes(false).await;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Write all queued writes.
+    pub async fn execute_write(&self) -> Result<(), BleHostError<C::Error>> {
+        self.execute_writes(true).await
+    }
+
+    /// Discard all queued writes.
+    pub async fn cancel_write(&self) -> Result<(), BleHostError<C::Error>> {
+        self.execute_writes(false).await
+    }
+
+    /// Queue a value in parts that fit in a prepare write request, optionally verifying the echoed parts.
+    async fn prepare_writes(
+        &self,
+        handle: u16,
+        offset: usize,
+        buf: &[u8],
+        verify: bool,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let part_len = self.connection.get_att_mtu() as usize - 5;
+        for (i, part) in buf.chunks(part_len).enumerate() {
+            let offset = u16::try_from(offset + i * part_len).map_err(|_| Error::InvalidValue)?;
+            let data = att::AttReq::PrepareWrite {
+                handle,
+                offset,
+                value: part,
+            };
+
+            let response = self.request(data).await?;
+            match Self::response(response.pdu.as_ref())? {
+                AttRsp::PrepareWrite {
+                    handle: echo_handle,
+                    offset: echo_offset,
+                    value,
+                } => {
+                    if verify && (echo_handle != handle || echo_offset != offset || value != part) {
+                        return Err(Error::ReliableWriteMismatch.into());
+                    }
+                }
+                AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
+                _ => return Err(Error::UnexpectedGattResponse.into()),
+            }
+        }
+        Ok(())
+    }
+
+    async fn execute_writes(&self, commit: bool) -> Result<(), BleHostError<C::Error>> {
+        let data = att::AttReq::ExecuteWrite { flags: commit as u8 };
+
+        let response = self.request(data).await?;
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ExecuteWrite => Ok(()),
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
     /// Write without waiting for a response to a characteristic described by a handle.
     pub async fn write_characteristic_without_response<T: FromGatt>(
         &self,
@@ -859,8 +2409,25 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         &self,
         characteristic: &Characteristic<T>,
         indication: bool,
-    ) -> Result<NotificationListener<'_, 512>, BleHostError<C::Error>> {
-        let properties = u16::to_le_bytes(if indication { 0x02 } else { 0x01 });
+    ) -> Result<NotificationListener<'_, P>, BleHostError<C::Error>> {
+        let flags = if indication {
+            CCCDFlag::Indicate
+        } else {
+            CCCDFlag::Notify
+        };
+        self.subscribe_with(characteristic, [flags].into()).await
+    }
+
+    /// Subscribe to a given Characteristic with the provided CCCD configuration
+    ///
+    /// This allows enabling both notifications and indications, i.e. `[CCCDFlag::Notify, CCCDFlag::Indicate].into()`.
+    /// A listener is returned, which has a `next()` method
+    pub async fn subscribe_with<T: AsGatt>(
+        &self,
+        characteristic: &Characteristic<T>,
+        cccd: CCCD,
+    ) -> Result<NotificationListener<'_, P>, BleHostError<C::Error>> {
+        let properties = u16::to_le_bytes(cccd.raw());
 
         let data = att::AttReq::Write {
             handle: characteristic.cccd_handle.ok_or(Error::NotSupported)?,
@@ -871,16 +2438,7 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         let response = self.request(data).await?;
 
         match Self::response(response.pdu.as_ref())? {
-            AttRsp::Write => match self.notifications.dyn_subscriber() {
-                Ok(listener) => Ok(NotificationListener {
-                    listener,
-                    handle: characteristic.handle,
-                }),
-                Err(embassy_sync::pubsub::Error::MaximumSubscribersReached) => {
-                    Err(Error::GattSubscriberLimitReached.into())
-                }
-                Err(_) => Err(Error::Other.into()),
-            },
+            AttRsp::Write => Ok(self.notifications.listen(characteristic.handle)?),
             AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
             _ => Err(Error::UnexpectedGattResponse.into()),
         }
@@ -907,37 +2465,85 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 
-    /// Handle a notification that was received.
-    async fn handle_notification_packet(&self, data: &[u8]) -> Result<(), BleHostError<C::Error>> {
-        let mut r = ReadCursor::new(data);
-        let value_handle: u16 = r.read()?;
-        let value_attr = r.remaining();
+    /// Set whether received indications are confirmed automatically, which is the default.
+    ///
+    /// If disabled, the application must call [`GattClient::confirm_indication`] after processing an indication.
+    /// The server will not send another indication until the previous one has been confirmed.
+    pub fn set_auto_confirm(&self, enabled: bool) {
+        self.auto_confirm.set(enabled);
+    }
+
+    /// Set the timeout of ATT transactions, which is 30 seconds by default.
+    ///
+    /// When a request is not answered within the timeout, it fails with [`Error::Timeout`] and
+    /// the client is considered failed: all further procedures fail with [`Error::Timeout`].
+    /// Use [`GattClient::with_timeout`] to change the timeout of a single procedure.
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.timeout.set(timeout);
+    }
+
+    /// Run a procedure of this client with a different ATT transaction timeout.
+    ///
+    /// The timeout applies to every request sent by `procedure`, for example
+    /// `client.with_timeout(Duration::from_secs(1), client.read_characteristic(&c, &mut buf))`.
+    /// Other procedures running concurrently keep using the timeout set with [`GattClient::set_timeout`].
+    pub async fn with_timeout<R>(&self, timeout: Duration, procedure: impl Future<Output = R>) -> R {
+        let mut procedure = pin!(procedure);
+        poll_fn(|cx| {
+            let default = self.timeout.replace(timeout);
+            let result = procedure.as_mut().poll(cx);
+            self.timeout.set(default);
+            result
+        })
+        .await
+    }
+
+    /// Set whether the connection is disconnected when an ATT transaction times out.
+    pub fn set_disconnect_on_timeout(&self, enabled: bool) {
+        self.disconnect_on_timeout.set(enabled);
+    }
+
+    /// Check if an ATT transaction has timed out, after which no further requests can be sent.
+    pub fn is_failed(&self) -> bool {
+        self.failed.get()
+    }
 
-        let handle = value_handle;
+    /// Confirm the last received indication.
+    pub async fn confirm_indication(&self) -> Result<(), BleHostError<C::Error>> {
+        self.send_att_data(Att::Client(AttClient::Confirmation(AttCfm::ConfirmIndication)))
+            .await
+    }
 
-        // TODO
-        let mut data = [0u8; 512];
-        let to_copy = data.len().min(value_attr.len());
-        data[..to_copy].copy_from_slice(&value_attr[..to_copy]);
-        let n = Notification {
+    /// Handle a notification or indication that was received.
+    fn handle_notification_packet(&self, pdu: Pdu<P::Packet>, indication: bool) -> Result<(), BleHostError<C::Error>> {
+        let mut r = ReadCursor::new(&pdu.as_ref()[1..]);
+        let handle: u16 = r.read()?;
+        self.notifications.publish(Notification {
             handle,
-            data,
-            len: to_copy,
-        };
-        self.notifications.immediate_publisher().publish_immediate(n);
+            pdu,
+            indication,
+        });
         Ok(())
     }
 
     /// Task which handles GATT rx data (needed for notifications to work)
     pub async fn task(&self) -> Result<(), BleHostError<C::Error>> {
         loop {
-            let (handle, pdu) = self.rx.receive().await;
+            let pdu = self.connection.next_gatt_client().await;
             let data = pdu.as_ref();
-            // handle notifications
-            if pdu.as_ref()[0] == ATT_HANDLE_VALUE_NTF {
-                self.handle_notification_packet(&pdu.as_ref()[1..]).await?;
+            // handle notifications and indications, which are not responses to a request
+            let opcode = pdu.as_ref()[0];
+            if opcode == ATT_HANDLE_VALUE_NTF || opcode == ATT_HANDLE_VALUE_IND {
+                let indication = opcode == ATT_HANDLE_VALUE_IND;
+                self.handle_notification_packet(pdu, indication)?;
+                if indication && self.auto_confirm.get() {
+                    self.confirm_indication().await?;
+                }
+            } else if self.failed.get() {
+                // A late response to a timed out request.
+                warn!("[gatt] dropping att response received after a transaction timeout");
             } else {
-                self.response_channel.send((handle, pdu)).await;
+                self.response_channel.send(pdu).await;
             }
         }
     }
@@ -950,3 +2556,755 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::task::Poll;
+    use std::boxed::Box;
+    use std::vec::Vec as StdVec;
+
+    use bt_hci::param::{AddrKind, BdAddr, LeConnRole};
+    use embassy_futures::block_on;
+    use embassy_futures::join::join;
+    use embassy_futures::select::{select3, Either3};
+
+    use super::*;
+    use crate::att::{
+        ATT_ERROR_RSP, ATT_EXCHANGE_MTU_REQ, ATT_EXCHANGE_MTU_RSP, ATT_EXECUTE_WRITE_REQ, ATT_EXECUTE_WRITE_RSP,
+        ATT_HANDLE_VALUE_CMF, ATT_PREPARE_WRITE_REQ, ATT_PREPARE_WRITE_RSP, ATT_READ_BY_GROUP_TYPE_REQ,
+        ATT_READ_BY_GROUP_TYPE_RSP, ATT_READ_BY_TYPE_REQ, ATT_READ_BY_TYPE_RSP, ATT_READ_MULTIPLE_VARIABLE_REQ,
+        ATT_READ_MULTIPLE_VARIABLE_RSP, ATT_READ_REQ, ATT_READ_RSP, ATT_WRITE_REQ, ATT_WRITE_RSP,
+    };
+    use crate::mock_controller::MockController;
+    use crate::prelude::DefaultPacketPool;
+    use crate::HostResources;
+
+    type TestStack = Stack<'static, MockController, DefaultPacketPool>;
+    type TestClient = GattClient<'static, MockController, DefaultPacketPool, 4>;
+
+    fn stack() -> &'static TestStack {
+        let resources: &'static mut HostResources<DefaultPacketPool, 2, 2> = Box::leak(Box::new(HostResources::new()));
+        Box::leak(Box::new(crate::new(MockController::new(), resources)))
+    }
+
+    fn connect(stack: &'static TestStack, handle: u16) -> Connection<'static, DefaultPacketPool> {
+        let connections = &stack.host.connections;
+        connections
+            .connect(
+                ConnHandle::new(handle),
+                AddrKind::RANDOM,
+                BdAddr::new([handle as u8; 6]),
+                LeConnRole::Central,
+            )
+            .unwrap();
+        let Poll::Ready(connection) = connections.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        connection
+    }
+
+    /// The server side of a connection, receiving the PDUs sent by the client and answering them.
+    struct Peer {
+        stack: &'static TestStack,
+        handle: ConnHandle,
+    }
+
+    impl Peer {
+        /// Receive the next ATT PDU sent by the client.
+        async fn receive(&self) -> StdVec<u8> {
+            let (handle, pdu) = self.stack.host.connections.outbound().await;
+            assert_eq!(handle, self.handle);
+            // Skip the L2CAP header
+            pdu.as_ref()[4..].to_vec()
+        }
+
+        /// Send an ATT PDU to the client.
+        fn send(&self, data: &[u8]) {
+            let mut packet = DefaultPacketPool::allocate().unwrap();
+            packet.as_mut()[..data.len()].copy_from_slice(data);
+            self.stack
+                .host
+                .connections
+                .post_gatt_client(self.handle, Pdu::new(packet, data.len()))
+                .unwrap();
+        }
+
+        /// Returns true if the client has not sent anything.
+        fn is_idle(&self) -> bool {
+            matches!(
+                block_on(select(self.stack.host.connections.outbound(), core::future::ready(()))),
+                Either::Second(())
+            )
+        }
+
+        /// Answer a request with an error response.
+        fn error(&self, request: &[u8], code: AttErrorCode) {
+            self.send(&[ATT_ERROR_RSP, request[0], request[1], request[2], code.value()]);
+        }
+    }
+
+    /// Create a client for a new connection, exchanging the default ATT MTU.
+    fn client(handle: u16) -> (TestClient, Peer) {
+        client_on(stack(), handle)
+    }
+
+    /// Create a client for a new connection of the stack, exchanging the default ATT MTU.
+    fn client_on(stack: &'static TestStack, handle: u16) -> (TestClient, Peer) {
+        let connection = connect(stack, handle);
+        let peer = Peer {
+            stack,
+            handle: ConnHandle::new(handle),
+        };
+        let (client, _) = block_on(join(TestClient::new_with_mtu(stack, &connection, 23), async {
+            let request = peer.receive().await;
+            assert_eq!(request[0], ATT_EXCHANGE_MTU_REQ);
+            peer.send(&[ATT_EXCHANGE_MTU_RSP, 23, 0]);
+        }));
+        (client.unwrap(), peer)
+    }
+
+    /// Run a client operation while the peer answers it, with the client task running.
+    fn run<T>(client: &TestClient, operation: impl Future<Output = T>, peer: impl Future<Output = ()>) -> T {
+        match block_on(select(client.task(), join(operation, peer))) {
+            Either::First(res) => panic!("client task stopped: {:?}", res),
+            Either::Second((output, ())) => output,
+        }
+    }
+
+    fn characteristic<T: AsGatt>(handle: u16) -> Characteristic<T> {
+        Characteristic {
+            handle,
+            cccd_handle: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Answer prepare write requests by echoing them, returning the queued value.
+    async fn echo_prepared(peer: &Peer, handle: u16, parts: usize) -> StdVec<u8> {
+        let mut value = StdVec::new();
+        for _ in 0..parts {
+            let mut request = peer.receive().await;
+            assert_eq!(request[0], ATT_PREPARE_WRITE_REQ);
+            assert_eq!(u16::from_le_bytes([request[1], request[2]]), handle);
+            assert_eq!(u16::from_le_bytes([request[3], request[4]]) as usize, value.len());
+            // A prepare write request can hold at most ATT_MTU - 5 bytes of the value
+            assert!(request.len() - 5 <= 18);
+            value.extend_from_slice(&request[5..]);
+            request[0] = ATT_PREPARE_WRITE_RSP;
+            peer.send(&request);
+        }
+        value
+    }
+
+    /// Expect an execute write request with the given flags, and answer it.
+    async fn expect_execute(peer: &Peer, flags: u8) {
+        let request = peer.receive().await;
+        assert_eq!(request, &[ATT_EXECUTE_WRITE_REQ, flags]);
+        peer.send(&[ATT_EXECUTE_WRITE_RSP]);
+    }
+
+    /// Expect a request from the client, and answer it.
+    async fn expect(peer: &Peer, request: &[u8], response: &[u8]) {
+        assert_eq!(peer.receive().await, request);
+        peer.send(response);
+    }
+
+    /// Expect a request from the client, and answer it with an Attribute Not Found error.
+    async fn expect_not_found(peer: &Peer, request: &[u8]) {
+        assert_eq!(peer.receive().await, request);
+        peer.error(request, AttErrorCode::ATTRIBUTE_NOT_FOUND);
+    }
+
+    const DATABASE_HASH_REQ: [u8; 7] = [ATT_READ_BY_TYPE_REQ, 0x01, 0x00, 0xff, 0xff, 0x2a, 0x2b];
+
+    fn database_hash_rsp(hash: [u8; 16]) -> StdVec<u8> {
+        let mut response = StdVec::from([ATT_READ_BY_TYPE_RSP, 18, 0x03, 0x00]);
+        response.extend_from_slice(&hash);
+        response
+    }
+
+    type TestDatabase = GattDatabase<4, 4, 4>;
+
+    /// A cache holding the database of a single peer.
+    struct TestCache {
+        entry: Option<(Identity, TestDatabase)>,
+    }
+
+    impl GattCache<4, 4, 4> for TestCache {
+        fn load(&mut self, peer: &Identity) -> Option<TestDatabase> {
+            self.entry.as_ref().filter(|(p, _)| p == peer).map(|(_, db)| db.clone())
+        }
+
+        fn store(&mut self, peer: &Identity, database: &TestDatabase) {
+            self.entry = Some((*peer, database.clone()));
+        }
+
+        fn remove(&mut self, peer: &Identity) {
+            if self.entry.as_ref().is_some_and(|(p, _)| p == peer) {
+                self.entry = None;
+            }
+        }
+    }
+
+    #[test]
+    fn mtu_exchange() {
+        let stack = stack();
+        let connection = connect(stack, 1);
+        let peer = Peer {
+            stack,
+            handle: ConnHandle::new(1),
+        };
+
+        // The update is reported even if the event queue is full
+        for _ in 0..config::CONNECTION_EVENT_QUEUE_SIZE {
+            let event = ConnectionEvent::PhyUpdated {
+                tx_phy: PhyKind::Le2M,
+                rx_phy: PhyKind::Le2M,
+            };
+            stack.host.connections.post_handle_event(peer.handle, event).unwrap();
+        }
+
+        let (client, _) = block_on(join(TestClient::new_with_mtu(stack, &connection, 100), async {
+            let request = peer.receive().await;
+            assert_eq!(request, &[ATT_EXCHANGE_MTU_REQ, 100, 0]);
+            // Indications sent before the response are confirmed
+            peer.send(&[ATT_HANDLE_VALUE_IND, 0x10, 0x00, 1]);
+            assert_eq!(peer.receive().await, &[ATT_HANDLE_VALUE_CMF]);
+            peer.send(&[ATT_HANDLE_VALUE_NTF, 0x10, 0x00, 2]);
+            peer.send(&[ATT_EXCHANGE_MTU_RSP, 50, 0]);
+        }));
+        assert_eq!(client.unwrap().mtu(), 50);
+
+        assert!(matches!(
+            block_on(connection.next()),
+            ConnectionEvent::AttMtuUpdated { mtu: 50 }
+        ));
+        assert!(matches!(
+            block_on(connection.next()),
+            ConnectionEvent::PhyUpdated { .. }
+        ));
+    }
+
+    #[test]
+    fn write_long() {
+        let (client, peer) = client(1);
+        let c = characteristic::<[u8; 40]>(0x0010);
+        let value: [u8; 40] = core::array::from_fn(|i| i as u8);
+
+        let res = run(&client, client.write_characteristic_long(&c, &value), async {
+            assert_eq!(echo_prepared(&peer, 0x0010, 3).await, value);
+            expect_execute(&peer, 1).await;
+        });
+        assert!(res.is_ok());
+
+        // Values longer than a write request use the long write procedure
+        let res = run(&client, client.write_characteristic(&c, &value), async {
+            assert_eq!(echo_prepared(&peer, 0x0010, 3).await, value);
+            expect_execute(&peer, 1).await;
+        });
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn write_long_cancelled_on_error() {
+        let (client, peer) = client(1);
+        let c = characteristic::<[u8; 40]>(0x0010);
+        let value = [0xaa; 40];
+
+        let res = run(&client, client.write_characteristic_long(&c, &value), async {
+            echo_prepared(&peer, 0x0010, 1).await;
+            let request = peer.receive().await;
+            peer.error(&request, AttErrorCode::PREPARE_QUEUE_FULL);
+            expect_execute(&peer, 0).await;
+        });
+        assert!(matches!(
+            res,
+            Err(BleHostError::BleHost(Error::Att(AttErrorCode::PREPARE_QUEUE_FULL)))
+        ));
+    }
+
+    #[test]
+    fn write_reliable() {
+        let (client, peer) = client(1);
+        let c = characteristic::<[u8; 20]>(0x0010);
+        let value = [0x55; 20];
+
+        let res = run(&client, client.write_characteristic_reliable(&c, &value), async {
+            assert_eq!(echo_prepared(&peer, 0x0010, 2).await, value);
+            expect_execute(&peer, 1).await;
+        });
+        assert!(res.is_ok());
+
+        // An echo which does not match the queued part cancels the write
+        let res = run(&client, client.write_characteristic_reliable(&c, &value), async {
+            let mut request = peer.receive().await;
+            request[0] = ATT_PREPARE_WRITE_RSP;
+            request[5] ^= 0xff;
+            peer.send(&request);
+            expect_execute(&peer, 0).await;
+        });
+        assert!(matches!(res, Err(BleHostError::BleHost(Error::ReliableWriteMismatch))));
+    }
+
+    #[test]
+    fn prepare_write() {
+        let (client, peer) = client(1);
+        let first = characteristic::<[u8; 4]>(0x0010);
+        let second = characteristic::<[u8; 4]>(0x0020);
+
+        // Writes to several characteristics are queued, then executed at once
+        let res = run(
+            &client,
+            async {
+                client.prepare_write(&first, 0, &[1, 2, 3, 4]).await?;
+                client.prepare_write(&second, 2, &[5, 6]).await?;
+                client.execute_write().await
+            },
+            async {
+                assert_eq!(echo_prepared(&peer, 0x0010, 1).await, [1, 2, 3, 4]);
+                let mut request = peer.receive().await;
+                assert_eq!(request, &[ATT_PREPARE_WRITE_REQ, 0x20, 0x00, 2, 0, 5, 6]);
+                request[0] = ATT_PREPARE_WRITE_RSP;
+                peer.send(&request);
+                expect_execute(&peer, 1).await;
+            },
+        );
+        assert!(res.is_ok());
+
+        // Queued writes can be discarded
+        let res = run(
+            &client,
+            async {
+                client.prepare_write(&first, 0, &[1, 2, 3, 4]).await?;
+                client.cancel_write().await
+            },
+            async {
+                echo_prepared(&peer, 0x0010, 1).await;
+                expect_execute(&peer, 0).await;
+            },
+        );
+        assert!(res.is_ok());
+
+        // An error response cancels all queued writes
+        let res = run(&client, client.prepare_write(&second, 0, &[1, 2, 3, 4]), async {
+            let request = peer.receive().await;
+            peer.error(&request, AttErrorCode::WRITE_NOT_PERMITTED);
+            expect_execute(&peer, 0).await;
+        });
+        assert!(matches!(
+            res,
+            Err(BleHostError::BleHost(Error::Att(AttErrorCode::WRITE_NOT_PERMITTED)))
+        ));
+    }
+
+    #[test]
+    fn indications() {
+        let (client, peer) = client(1);
+        let c = Characteristic::<u8> {
+            handle: 0x0010,
+            cccd_handle: Some(0x0011),
+            phantom: PhantomData,
+        };
+
+        let listener = run(
+            &client,
+            client.subscribe_with(&c, [CCCDFlag::Notify, CCCDFlag::Indicate].into()),
+            async {
+                let request = peer.receive().await;
+                assert_eq!(request, &[ATT_WRITE_REQ, 0x11, 0x00, 0x03, 0x00]);
+                peer.send(&[ATT_WRITE_RSP]);
+            },
+        );
+        let mut listener = listener.unwrap();
+        assert_eq!(listener.handle(), 0x0010);
+
+        // Indications are confirmed automatically
+        peer.send(&[ATT_HANDLE_VALUE_IND, 0x10, 0x00, 42]);
+        let notification = run(&client, listener.next(), async {
+            assert_eq!(peer.receive().await, &[ATT_HANDLE_VALUE_CMF]);
+        });
+        assert!(notification.is_indication());
+        assert_eq!(notification.handle(), 0x0010);
+        assert_eq!(notification.as_ref(), &[42]);
+
+        // Notifications are not confirmed
+        peer.send(&[ATT_HANDLE_VALUE_NTF, 0x10, 0x00, 43]);
+        let notification = run(&client, listener.next(), async {});
+        assert!(!notification.is_indication());
+        assert_eq!(notification.as_ref(), &[43]);
+        assert!(peer.is_idle());
+    }
+
+    #[test]
+    fn indications_confirmed_manually() {
+        let (client, peer) = client(1);
+        let c = Characteristic::<u8> {
+            handle: 0x0010,
+            cccd_handle: Some(0x0011),
+            phantom: PhantomData,
+        };
+        client.set_auto_confirm(false);
+
+        let listener = run(&client, client.subscribe(&c, true), async {
+            let request = peer.receive().await;
+            assert_eq!(request, &[ATT_WRITE_REQ, 0x11, 0x00, 0x02, 0x00]);
+            peer.send(&[ATT_WRITE_RSP]);
+        });
+        let mut listener = listener.unwrap();
+
+        peer.send(&[ATT_HANDLE_VALUE_IND, 0x10, 0x00, 42]);
+        let notification = run(&client, listener.next(), async {});
+        assert!(notification.is_indication());
+        assert_eq!(notification.as_ref(), &[42]);
+        // Nothing is sent until the application confirms the indication
+        assert!(peer.is_idle());
+
+        let res = run(&client, client.confirm_indication(), async {
+            assert_eq!(peer.receive().await, &[ATT_HANDLE_VALUE_CMF]);
+        });
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn clients_per_connection() {
+        let stack = stack();
+        let (first, first_peer) = client_on(stack, 1);
+        let (second, second_peer) = client_on(stack, 2);
+        let c = characteristic::<u8>(0x0010);
+
+        let reads = join(
+            async {
+                let mut data = [0; 1];
+                first.read_characteristic(&c, &mut data).await.map(|_| data[0])
+            },
+            async {
+                let mut data = [0; 1];
+                second.read_characteristic(&c, &mut data).await.map(|_| data[0])
+            },
+        );
+        let peer = async {
+            // Both requests are outstanding at the same time, one on each connection
+            let mut handles = StdVec::new();
+            for _ in 0..2 {
+                let (handle, pdu) = stack.host.connections.outbound().await;
+                assert_eq!(&pdu.as_ref()[4..], &[ATT_READ_REQ, 0x10, 0x00]);
+                handles.push(handle.raw());
+            }
+            handles.sort();
+            assert_eq!(handles, [1, 2]);
+
+            // Responses are delivered to the client of their connection, whatever the order
+            second_peer.send(&[ATT_READ_RSP, 2]);
+            first_peer.send(&[ATT_READ_RSP, 1]);
+        };
+
+        match block_on(select3(first.task(), second.task(), join(reads, peer))) {
+            Either3::Third(((first, second), ())) => {
+                assert_eq!(first.unwrap(), 1);
+                assert_eq!(second.unwrap(), 2);
+            }
+            _ => panic!("client task stopped"),
+        }
+    }
+
+    fn notification(handle: u16, value: &[u8], indication: bool) -> Notification<DefaultPacketPool> {
+        let mut packet = DefaultPacketPool::allocate().unwrap();
+        let opcode = if indication {
+            ATT_HANDLE_VALUE_IND
+        } else {
+            ATT_HANDLE_VALUE_NTF
+        };
+        let len = Notification::<DefaultPacketPool>::VALUE_OFFSET + value.len();
+        packet.as_mut()[0] = opcode;
+        packet.as_mut()[1..3].copy_from_slice(&handle.to_le_bytes());
+        packet.as_mut()[3..len].copy_from_slice(value);
+        Notification {
+            handle,
+            pdu: Pdu::new(packet, len),
+            indication,
+        }
+    }
+
+    #[test]
+    fn notification_queue() {
+        let listeners: NotificationListeners<DefaultPacketPool> = NotificationListeners::new();
+        let mut listener = listeners.listen(0x0010).unwrap();
+
+        // The oldest notification is dropped when the queue is full
+        for value in 0..=NOTIF_QSIZE as u8 {
+            listeners.publish(notification(0x0010, &[value], false));
+        }
+        for value in 1..=NOTIF_QSIZE as u8 {
+            assert_eq!(block_on(listener.next()).as_ref(), &[value]);
+        }
+
+        // Notifications of other characteristics are not delivered
+        listeners.publish(notification(0x0020, &[0], false));
+        assert!(listeners.queues[listener.slot].is_empty());
+
+        // The slot and its queue are released with the listener
+        listeners.publish(notification(0x0010, &[0], false));
+        drop(listener);
+        let listener = listeners.listen(0x0020).unwrap();
+        assert!(listeners.queues[listener.slot].is_empty());
+    }
+
+    #[test]
+    fn notification_fan_out() {
+        let listeners: NotificationListeners<DefaultPacketPool> = NotificationListeners::new();
+        let mut first = listeners.listen(0x0010).unwrap();
+        if MAX_NOTIF < 2 {
+            assert!(matches!(
+                listeners.listen(0x0010),
+                Err(Error::GattSubscriberLimitReached)
+            ));
+            return;
+        }
+        let mut second = listeners.listen(0x0010).unwrap();
+
+        // Every listener of the characteristic receives its own copy
+        listeners.publish(notification(0x0010, &[42], false));
+        assert_eq!(block_on(first.next()).as_ref(), &[42]);
+        assert_eq!(block_on(second.next()).as_ref(), &[42]);
+
+        // A listener which does not read does not hold up the others
+        for value in 0..=NOTIF_QSIZE as u8 {
+            listeners.publish(notification(0x0010, &[value], false));
+            assert_eq!(block_on(first.next()).as_ref(), &[value]);
+        }
+        assert_eq!(block_on(second.next()).as_ref(), &[1]);
+    }
+
+    #[test]
+    fn notification_long_value() {
+        let listeners: NotificationListeners<DefaultPacketPool> = NotificationListeners::new();
+        let mut listener = listeners.listen(0x0010).unwrap();
+
+        // Values up to the packet size are delivered whole, in the received packet
+        let value: StdVec<u8> = (0..DefaultPacketPool::MTU - Notification::<DefaultPacketPool>::VALUE_OFFSET)
+            .map(|i| i as u8)
+            .collect();
+        listeners.publish(notification(0x0010, &value, true));
+        let received = block_on(listener.next());
+        assert_eq!(received.as_ref(), &value[..]);
+        assert!(received.is_indication());
+    }
+
+    #[test]
+    fn discovery_pagination() {
+        assert_eq!(next_start(0x0001, 0x0005, 0xffff), Ok(Some(0x0006)));
+        assert_eq!(next_start(0x0001, 0x0001, 0xffff), Ok(Some(0x0002)));
+        assert_eq!(next_start(0x0001, 0x0010, 0x0010), Ok(None));
+        assert_eq!(next_start(0x0001, 0xffff, 0xffff), Ok(None));
+
+        // A response going backwards would repeat the same request forever
+        assert_eq!(next_start(0x0006, 0x0005, 0xffff), Err(Error::UnexpectedGattResponse));
+        assert_eq!(next_start(0x0010, 0x0000, 0x0020), Err(Error::UnexpectedGattResponse));
+    }
+
+    #[test]
+    fn request_timeout() {
+        let (client, peer) = client(1);
+        let c = characteristic::<u8>(0x0010);
+        let mut value = [0; 1];
+
+        // The peer never answers the request
+        let res = run(
+            &client,
+            client.with_timeout(Duration::from_millis(10), client.read_characteristic(&c, &mut value)),
+            async {
+                assert_eq!(peer.receive().await, &[ATT_READ_REQ, 0x10, 0x00]);
+            },
+        );
+        assert!(matches!(res, Err(BleHostError::BleHost(Error::Timeout))));
+        assert_eq!(client.timeout.get(), ATT_TIMEOUT);
+        assert!(client.is_failed());
+        assert!(peer.stack.host.connections.poll_disconnecting(None).is_pending());
+
+        // A late response is dropped, and nothing is sent on the failed bearer anymore
+        peer.send(&[ATT_READ_RSP, 1]);
+        let res = run(&client, client.read_characteristic(&c, &mut value), async {});
+        assert!(matches!(res, Err(BleHostError::BleHost(Error::Timeout))));
+        let res = run(&client, client.write_characteristic_without_response(&c, &[1]), async {
+        });
+        assert!(matches!(res, Err(BleHostError::BleHost(Error::Timeout))));
+        assert!(peer.is_idle());
+    }
+
+    #[test]
+    fn disconnect_on_timeout() {
+        let (client, peer) = client(1);
+        client.set_timeout(Duration::from_millis(10));
+        client.set_disconnect_on_timeout(true);
+        let c = characteristic::<u8>(0x0010);
+
+        let res = run(&client, client.write_characteristic(&c, &[1]), async {
+            assert_eq!(peer.receive().await, &[ATT_WRITE_REQ, 0x10, 0x00, 1]);
+        });
+        assert!(matches!(res, Err(BleHostError::BleHost(Error::Timeout))));
+        assert!(client.is_failed());
+
+        let Poll::Ready(request) = peer.stack.host.connections.poll_disconnecting(None) else {
+            panic!("expected the connection to be disconnected");
+        };
+        assert_eq!(request.handle(), ConnHandle::new(1));
+    }
+
+    #[test]
+    fn discover_cached() {
+        let (client, peer) = client(1);
+        let hash = [0xaa; 16];
+        let mut services = Vec::new();
+        services
+            .push(ServiceHandle::new(0x0001, 0x0005, Uuid::new_short(0x1800)))
+            .unwrap();
+        let cached = TestDatabase::from_parts(services, Vec::new(), Vec::new(), Vec::new(), Some(hash));
+        let mut cache = TestCache {
+            entry: Some((client.connection.peer_identity(), cached)),
+        };
+
+        // The cached database is used while the Database Hash of the server is unchanged
+        let db = run(&client, client.discover_cached(&mut cache), async {
+            expect(&peer, &DATABASE_HASH_REQ, &database_hash_rsp(hash)).await;
+        })
+        .unwrap();
+        assert_eq!(db.hash(), Some(&hash));
+        assert_eq!(db.services().count(), 1);
+        assert!(peer.is_idle());
+
+        // The database is discovered and cached again once the hash changed
+        let hash = [0xbb; 16];
+        let db = run(&client, client.discover_cached(&mut cache), async {
+            expect(&peer, &DATABASE_HASH_REQ, &database_hash_rsp(hash)).await;
+            expect_not_found(&peer, &[ATT_READ_BY_GROUP_TYPE_REQ, 0x01, 0x00, 0xff, 0xff, 0x00, 0x28]).await;
+        })
+        .unwrap();
+        assert_eq!(db.hash(), Some(&hash));
+        assert_eq!(db.services().count(), 0);
+        assert_eq!(cache.entry.as_ref().and_then(|(_, db)| db.hash()), Some(&hash));
+    }
+
+    #[test]
+    fn service_changed() {
+        let (client, peer) = client(1);
+        let primary = ServiceHandle::new(0x0001, 0x0005, Uuid::new_short(0x1800));
+        let including = ServiceHandle::new(0x0010, 0x0015, Uuid::new_short(0x180f));
+        let secondary = ServiceHandle::new(0x0030, 0x0032, Uuid::new_short(0x1234));
+        let include = IncludedService {
+            handle: 0x0011,
+            service: secondary.clone(),
+        };
+        let name = DiscoveredCharacteristic {
+            declaration_handle: 0x0002,
+            handle: 0x0003,
+            end_handle: 0x0005,
+            props: [CharacteristicProp::Read].into(),
+            uuid: Uuid::new_short(0x2a00),
+        };
+        let level = DiscoveredCharacteristic {
+            declaration_handle: 0x0031,
+            handle: 0x0032,
+            end_handle: 0x0032,
+            props: [CharacteristicProp::Read].into(),
+            uuid: Uuid::new_short(0x2a19),
+        };
+        let mut db = TestDatabase::from_parts(
+            Vec::from_slice(&[primary.clone(), including.clone(), secondary.clone()]).unwrap(),
+            Vec::from_slice(&[include.clone()]).unwrap(),
+            Vec::from_slice(&[name.clone(), level]).unwrap(),
+            Vec::new(),
+            Some([0xaa; 16]),
+        );
+        let mut cache = TestCache {
+            entry: Some((client.connection.peer_identity(), db.clone())),
+        };
+
+        // Only the secondary service changed, but it is rediscovered through the service including it
+        let indication = notification(0x0008, &[0x30, 0x00, 0x32, 0x00], true);
+        let hash = [0xbb; 16];
+        run(
+            &client,
+            async {
+                client
+                    .handle_service_changed(&mut db, &indication, &mut cache)
+                    .await
+                    .unwrap()
+            },
+            async {
+                expect(
+                    &peer,
+                    &[ATT_READ_BY_GROUP_TYPE_REQ, 0x10, 0x00, 0x32, 0x00, 0x00, 0x28],
+                    &[ATT_READ_BY_GROUP_TYPE_RSP, 6, 0x10, 0x00, 0x15, 0x00, 0x0f, 0x18],
+                )
+                .await;
+                expect_not_found(&peer, &[ATT_READ_BY_GROUP_TYPE_REQ, 0x16, 0x00, 0x32, 0x00, 0x00, 0x28]).await;
+                expect(
+                    &peer,
+                    &[ATT_READ_BY_TYPE_REQ, 0x10, 0x00, 0x15, 0x00, 0x02, 0x28],
+                    &[ATT_READ_BY_TYPE_RSP, 8, 0x11, 0x00, 0x30, 0x00, 0x32, 0x00, 0x34, 0x12],
+                )
+                .await;
+                expect_not_found(&peer, &[ATT_READ_BY_TYPE_REQ, 0x12, 0x00, 0x15, 0x00, 0x02, 0x28]).await;
+                expect_not_found(&peer, &[ATT_READ_BY_TYPE_REQ, 0x30, 0x00, 0x32, 0x00, 0x02, 0x28]).await;
+                expect_not_found(&peer, &[ATT_READ_BY_TYPE_REQ, 0x10, 0x00, 0x15, 0x00, 0x03, 0x28]).await;
+                // The characteristic of the secondary service changed
+                expect(
+                    &peer,
+                    &[ATT_READ_BY_TYPE_REQ, 0x30, 0x00, 0x32, 0x00, 0x03, 0x28],
+                    &[ATT_READ_BY_TYPE_RSP, 7, 0x31, 0x00, 0x02, 0x32, 0x00, 0x1a, 0x2a],
+                )
+                .await;
+                expect_not_found(&peer, &[ATT_READ_BY_TYPE_REQ, 0x32, 0x00, 0x32, 0x00, 0x03, 0x28]).await;
+                expect(&peer, &DATABASE_HASH_REQ, &database_hash_rsp(hash)).await;
+            },
+        );
+        assert!(peer.is_idle());
+
+        assert_eq!(
+            db.services().cloned().collect::<StdVec<_>>(),
+            [primary, including, secondary]
+        );
+        assert_eq!(db.all_includes().cloned().collect::<StdVec<_>>(), [include]);
+        let characteristics = db.all_characteristics().cloned().collect::<StdVec<_>>();
+        assert_eq!(characteristics.len(), 2);
+        assert_eq!(characteristics[0], name);
+        assert_eq!(characteristics[1].uuid, Uuid::new_short(0x2a1a));
+        assert_eq!(db.hash(), Some(&hash));
+        assert_eq!(cache.entry.as_ref().and_then(|(_, db)| db.hash()), Some(&hash));
+    }
+
+    #[test]
+    fn read_characteristics_truncated() {
+        let (client, peer) = client(1);
+        let level = characteristic::<u8>(0x0010);
+        let name = characteristic::<heapless::Vec<u8, 32>>(0x0020);
+        let request = [ATT_READ_MULTIPLE_VARIABLE_REQ, 0x10, 0x00, 0x20, 0x00];
+
+        // The 30 bytes long name does not fit in the MTU, only its first 17 bytes are sent
+        let mut response = StdVec::from([ATT_READ_MULTIPLE_VARIABLE_RSP, 1, 0, 42, 30, 0]);
+        response.extend(0..17u8);
+        assert_eq!(response.len(), 23);
+
+        let (first, second) = run(&client, client.read_characteristics((&level, &name)), async {
+            expect(&peer, &request, &response).await;
+        })
+        .unwrap();
+        assert_eq!(first, 42);
+        assert_eq!(&second[..], &response[6..]);
+
+        let mut values = [0; 32];
+        let lengths: Vec<usize, 2> = run(
+            &client,
+            client.read_multiple_variable(&[0x0010, 0x0020], &mut values),
+            async {
+                expect(&peer, &request, &response).await;
+            },
+        )
+        .unwrap();
+        assert_eq!(&lengths[..], &[1, 17]);
+        assert_eq!(values[0], 42);
+        assert_eq!(&values[1..18], &response[6..]);
+    }
+}
@@ -27,16 +27,14 @@ use bt_hci::param::{
     LeConnRole, LeEventMask, Status,
 };
 use bt_hci::{ControllerToHostPacket, FromHciBytes, WriteHci};
-use embassy_futures::select::{select3, select4, Either3, Either4};
+use embassy_futures::select::{select, select3, select4, Either, Either3, Either4};
 use embassy_sync::once_lock::OnceLock;
 use embassy_sync::waitqueue::WakerRegistration;
-#[cfg(feature = "gatt")]
-use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
 use embassy_time::Duration;
 use futures::pin_mut;
 
 use crate::att::{AttClient, AttServer};
-use crate::channel_manager::{ChannelManager, ChannelStorage};
+use crate::channel_manager::{ChannelManager, ChannelStorage, SignalQueue};
 use crate::command::CommandState;
 use crate::connection::ConnectionEvent;
 use crate::connection_manager::{ConnectionManager, ConnectionStorage, PacketGrant};
@@ -45,8 +43,8 @@ use crate::pdu::Pdu;
 #[cfg(feature = "security")]
 use crate::security_manager::SecurityEventData;
 use crate::types::l2cap::{
-    L2capHeader, L2capSignal, L2capSignalHeader, L2CAP_CID_ATT, L2CAP_CID_DYN_START, L2CAP_CID_LE_U_SECURITY_MANAGER,
-    L2CAP_CID_LE_U_SIGNAL,
+    L2capHeader, L2capSignal, L2capSignalHeader, L2capVarSignal, L2CAP_CID_ATT, L2CAP_CID_DYN_START,
+    L2CAP_CID_FIXED_END, L2CAP_CID_FIXED_START, L2CAP_CID_LE_U_SECURITY_MANAGER, L2CAP_CID_LE_U_SIGNAL,
 };
 use crate::{att, Address, BleHostError, Error, PacketPool, Stack};
 
@@ -64,8 +62,6 @@ pub(crate) struct BleHost<'d, T, P: PacketPool> {
     pub(crate) controller: T,
     pub(crate) connections: ConnectionManager<'d, P>,
     pub(crate) channels: ChannelManager<'d, P>,
-    #[cfg(feature = "gatt")]
-    pub(crate) att_client: Channel<NoopRawMutex, (ConnHandle, Pdu<P::Packet>), { crate::config::L2CAP_RX_QUEUE_SIZE }>,
     pub(crate) advertise_state: AdvState<'d>,
     pub(crate) advertise_command_state: CommandState<bool>,
     pub(crate) connect_command_state: CommandState<bool>,
@@ -195,6 +191,7 @@ where
         controller: T,
         connections: &'d mut [ConnectionStorage<P::Packet>],
         channels: &'d mut [ChannelStorage<P::Packet>],
+        signals: &'static SignalQueue,
         advertise_handles: &'d mut [AdvHandleState],
     ) -> Self {
         Self {
@@ -203,9 +200,7 @@ where
             metrics: RefCell::new(HostMetrics::default()),
             controller,
             connections: ConnectionManager::new(connections, P::MTU as u16 - 4),
-            channels: ChannelManager::new(channels),
-            #[cfg(feature = "gatt")]
-            att_client: Channel::new(),
+            channels: ChannelManager::new(channels, signals),
             advertise_state: AdvState::new(advertise_handles),
             advertise_command_state: CommandState::new(),
             scan_command_state: CommandState::new(),
@@ -292,15 +287,16 @@ where
                 if header.channel < L2CAP_CID_DYN_START
                     && !(&[L2CAP_CID_LE_U_SIGNAL, L2CAP_CID_ATT, L2CAP_CID_LE_U_SECURITY_MANAGER]
                         .contains(&header.channel))
+                    && !self.channels.is_fixed(handle, header.channel)
                 {
                     warn!("[host] unsupported l2cap channel id {}", header.channel);
                     return Err(Error::NotSupported);
                 }
 
-                // Avoids using the packet buffer for signalling packets
+                // Avoids using the packet buffer for signalling packets. Signals which do not fit in a single
+                // packet exceed the signaling MTU, and are rejected without reassembly.
                 if header.channel == L2CAP_CID_LE_U_SIGNAL {
-                    assert!(data.len() == header.length as usize);
-                    self.channels.signal(acl.handle(), data)?;
+                    self.channels.signal(acl.handle(), data, &self.connections)?;
                     return Ok(());
                 }
 
@@ -447,9 +443,6 @@ where
                     info!("[host] agreed att MTU of {}", mtu);
                     let len = w.len();
                     self.connections.try_outbound(acl.handle(), Pdu::new(packet, len))?;
-                } else if let Ok(att::Att::Server(AttServer::Response(att::AttRsp::ExchangeMtu { mtu }))) = a {
-                    info!("[host] remote agreed att MTU of {}", mtu);
-                    self.connections.exchange_att_mtu(acl.handle(), mtu);
                 } else {
                     #[cfg(feature = "gatt")]
                     match a {
@@ -457,9 +450,7 @@ where
                             self.connections.post_gatt(acl.handle(), pdu)?;
                         }
                         Ok(att::Att::Server(_)) => {
-                            if let Err(e) = self.att_client.try_send((acl.handle(), pdu)) {
-                                return Err(Error::OutOfMemory);
-                            }
+                            self.connections.post_gatt_client(acl.handle(), pdu)?;
                         }
                         Err(e) => {
                             warn!("Error decoding attribute payload: {:?}", e);
@@ -475,6 +466,12 @@ where
             L2CAP_CID_LE_U_SECURITY_MANAGER => {
                 self.connections.handle_security_channel(acl.handle(), pdu)?;
             }
+            other if (L2CAP_CID_FIXED_START..=L2CAP_CID_FIXED_END).contains(&other) => {
+                if let Err(e) = self.channels.dispatch_fixed(handle, other, pdu) {
+                    warn!("Error dispatching l2cap packet to fixed channel {}: {:?}", other, e);
+                    return Err(e);
+                }
+            }
             other if other >= L2CAP_CID_DYN_START => match self.channels.dispatch(header.channel, pdu) {
                 Ok(_) => {}
                 Err(e) => {
@@ -528,6 +525,42 @@ where
         Ok(())
     }
 
+    /// Send a signal with a variable length payload on the LE signalling channel.
+    pub(crate) async fn l2cap_signal_var<D: L2capVarSignal>(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        signal: &D,
+        p_buf: &mut [u8],
+    ) -> Result<(), BleHostError<T::Error>> {
+        let header = L2capSignalHeader {
+            identifier,
+            code: D::code(),
+            length: signal.size() as u16,
+        };
+        let l2cap = L2capHeader {
+            channel: L2CAP_CID_LE_U_SIGNAL,
+            length: header.size() as u16 + header.length,
+        };
+
+        let mut w = WriteCursor::new(p_buf);
+        w.write_hci(&l2cap)?;
+        w.write_hci(&header)?;
+        w.write_ref(signal)?;
+
+        let mut sender = self.l2cap(conn, w.len() as u16, 1).await?;
+        sender.send(w.finish()).await?;
+
+        Ok(())
+    }
+
+    /// Initialize the host without a controller, with the given ACL packet length and credits.
+    #[cfg(test)]
+    pub(crate) fn initialize(&self, acl_max: usize, credits: usize) {
+        let _ = self.initialized.init(InitialState { acl_max });
+        self.connections.set_link_credits(credits);
+    }
+
     // Request to an L2CAP payload of len to the HCI controller for a connection.
     //
     // This function will request the appropriate number of ACL packets to be sent and
@@ -1031,7 +1064,10 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
         loop {
             match select3(
                 poll_fn(|cx| host.connections.poll_disconnecting(Some(cx))),
-                poll_fn(|cx| host.channels.poll_disconnecting(Some(cx))),
+                select(
+                    poll_fn(|cx| host.channels.poll_disconnecting(Some(cx))),
+                    poll_fn(|cx| host.channels.poll_signal(cx)),
+                ),
                 select4(
                     poll_fn(|cx| host.connect_command_state.poll_cancelled(cx)),
                     poll_fn(|cx| host.advertise_command_state.poll_cancelled(cx)),
@@ -1059,7 +1095,7 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
                     }
                     request.confirm();
                 }
-                Either3::Second(request) => {
+                Either3::Second(Either::First(request)) => {
                     trace!("[host] poll disconnecting channels");
                     match request.send(host).await {
                         Ok(_) => {}
@@ -1071,6 +1107,17 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
                     }
                     request.confirm();
                 }
+                Either3::Second(Either::Second(signal)) => {
+                    trace!("[host] sending l2cap signal response");
+                    match signal.send(host).await {
+                        Ok(_) => {}
+                        Err(BleHostError::BleHost(Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => {}
+                        Err(BleHostError::BleHost(Error::NotFound)) => {}
+                        Err(e) => {
+                            return Err(e);
+                        }
+                    }
+                }
                 Either3::Third(states) => match states {
                     Either4::First(_) => {
                         trace!("[host] cancel connection create");
@@ -1,15 +1,22 @@
 //! L2CAP channels.
 use bt_hci::controller::{blocking, Controller};
+use bt_hci::param::ConnHandle;
+use heapless::Vec;
 
-pub use crate::channel_manager::CreditFlowPolicy;
 #[cfg(feature = "channel-metrics")]
 pub use crate::channel_manager::Metrics as ChannelMetrics;
-use crate::channel_manager::{ChannelIndex, ChannelManager};
+use crate::channel_manager::{ChannelIndex, ChannelManager, L2CAP_RTX_TIMEOUT};
+pub use crate::channel_manager::{CreditFlowPolicy, L2capSecurityLevel};
 use crate::connection::Connection;
-use crate::pdu::Sdu;
+use crate::pdu::{Sdu, TxSdu};
+pub use crate::types::l2cap::L2CAP_ECFC_MAX_CHANNELS;
 use crate::{BleHostError, Error, PacketPool, Stack};
 
 pub(crate) mod sar;
+#[cfg(feature = "l2cap-stream")]
+mod stream;
+#[cfg(feature = "l2cap-stream")]
+pub use stream::*;
 
 /// Handle representing an L2CAP channel.
 pub struct L2capChannel<'d, P: PacketPool> {
@@ -29,6 +36,29 @@ pub struct L2capChannelReader<'d, P: PacketPool> {
     manager: &'d ChannelManager<'d, P>,
 }
 
+/// Handle representing a registered L2CAP listener.
+///
+/// While a listener is registered, connection requests for PSMs without a listener are refused,
+/// as are requests not meeting the security required by the listener, or exceeding its backlog.
+/// Requests for Enhanced ATT bearers are still queued for the GATT server, unless a listener is
+/// registered for their PSM.
+/// Dropping the listener unregisters it.
+pub struct L2capListener<'d, P: PacketPool> {
+    psm: u16,
+    config: L2capChannelConfig,
+    manager: &'d ChannelManager<'d, P>,
+}
+
+/// Handle representing a user registered fixed L2CAP channel of a connection.
+///
+/// Fixed channels carry raw B-frames without any flow control besides that of the connection.
+/// Dropping the handle unregisters the channel.
+pub struct L2capFixedChannel<'d, P: PacketPool> {
+    conn: ConnHandle,
+    cid: u16,
+    manager: &'d ChannelManager<'d, P>,
+}
+
 /// Handle to an L2CAP channel for checking it's state.
 pub struct L2capChannelRef<'d, P: PacketPool> {
     index: ChannelIndex,
@@ -49,6 +79,18 @@ impl<P: PacketPool> Drop for L2capChannel<'_, P> {
     }
 }
 
+impl<P: PacketPool> Drop for L2capListener<'_, P> {
+    fn drop(&mut self) {
+        self.manager.unlisten(self.psm);
+    }
+}
+
+impl<P: PacketPool> Drop for L2capFixedChannel<'_, P> {
+    fn drop(&mut self) {
+        self.manager.unregister_fixed(self.conn, self.cid);
+    }
+}
+
 impl<P: PacketPool> Drop for L2capChannelRef<'_, P> {
     fn drop(&mut self) {
         self.manager.dec_ref(self.index);
@@ -96,16 +138,144 @@ pub struct L2capChannelConfig {
     pub initial_credits: Option<u16>,
 }
 
+/// Configuration for an L2CAP listener.
+#[derive(Default)]
+pub struct L2capListenerConfig {
+    /// Security required from the connection. Defaults to no security.
+    pub security: L2capSecurityLevel,
+    /// Configuration of the accepted channels.
+    pub channel: L2capChannelConfig,
+    /// Number of connection requests which can be pending until accepted. Defaults to 1.
+    pub backlog: Option<u8>,
+}
+
+impl<'d, P: PacketPool> L2capListener<'d, P> {
+    /// Register a listener for the provided PSM.
+    ///
+    /// If no PSM is provided, a free PSM is allocated from the dynamic range 0x80 - 0xFF.
+    pub fn register<T: Controller>(
+        stack: &'d Stack<'d, T, P>,
+        psm: Option<u16>,
+        config: L2capListenerConfig,
+    ) -> Result<Self, Error> {
+        let manager = &stack.host.channels;
+        let psm = manager.listen(psm, config.security, config.backlog.unwrap_or(1))?;
+        Ok(Self {
+            psm,
+            config: config.channel,
+            manager,
+        })
+    }
+
+    /// Get the PSM of this listener.
+    pub fn psm(&self) -> u16 {
+        self.psm
+    }
+
+    /// Await an incoming connection request for this listener.
+    pub async fn accept<T: Controller>(
+        &self,
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+    ) -> Result<L2capChannel<'d, P>, BleHostError<T::Error>> {
+        let handle = connection.handle();
+        stack
+            .host
+            .channels
+            .accept(handle, &[self.psm], &self.config, &stack.host)
+            .await
+    }
+
+    /// Await an incoming enhanced credit based connection request for this listener.
+    pub async fn accept_enhanced<T: Controller>(
+        &self,
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+    ) -> Result<Vec<L2capChannel<'d, P>, L2CAP_ECFC_MAX_CHANNELS>, BleHostError<T::Error>> {
+        let handle = connection.handle();
+        stack
+            .host
+            .channels
+            .accept_enhanced(handle, &[self.psm], &self.config, &stack.host)
+            .await
+    }
+}
+
+impl<'d, P: PacketPool> L2capFixedChannel<'d, P> {
+    /// Register a fixed channel with the provided CID on the connection.
+    ///
+    /// The CID must be in the range 0x0020 - 0x003E. Frames received on the CID are dropped
+    /// unless a fixed channel is registered for it.
+    pub fn register<T: Controller>(
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+        cid: u16,
+    ) -> Result<Self, Error> {
+        let manager = &stack.host.channels;
+        let conn = connection.handle();
+        manager.register_fixed(conn, cid)?;
+        Ok(Self { conn, cid, manager })
+    }
+
+    /// Get the CID of this channel.
+    pub fn cid(&self) -> u16 {
+        self.cid
+    }
+
+    /// Send the provided buffer as a single B-frame on this channel.
+    ///
+    /// The buffer must fit in a packet of the packet pool, including the 4 byte L2CAP header.
+    pub async fn send<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        buf: &[u8],
+    ) -> Result<(), BleHostError<T::Error>> {
+        let mut p_buf = P::allocate_async().await;
+        stack
+            .host
+            .channels
+            .send_fixed(self.conn, self.cid, buf, p_buf.as_mut(), &stack.host)
+            .await
+    }
+
+    /// Receive the payload of the next B-frame on this channel and copy it into the buffer.
+    ///
+    /// If the buffer is smaller than the payload, the remaining data is discarded.
+    pub async fn receive(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let pdu = self.manager.receive_fixed(self.conn, self.cid).await?;
+        let to_copy = pdu.len().min(buf.len());
+        buf[..to_copy].copy_from_slice(&pdu.as_ref()[..to_copy]);
+        Ok(to_copy)
+    }
+
+    /// Receive the payload of the next B-frame on this channel.
+    pub async fn receive_sdu(&mut self) -> Result<Sdu<P::Packet>, Error> {
+        let pdu = self.manager.receive_fixed(self.conn, self.cid).await?;
+        Ok(Sdu::from_pdu(pdu))
+    }
+}
+
 impl<'d, P: PacketPool> L2capChannel<'d, P> {
     pub(crate) fn new(index: ChannelIndex, manager: &'d ChannelManager<'d, P>) -> Self {
         Self { index, manager }
     }
 
+    pub(crate) fn index(&self) -> ChannelIndex {
+        self.index
+    }
+
     /// Disconnect this channel.
     pub fn disconnect(&mut self) {
         self.manager.disconnect(self.index);
     }
 
+    /// Disconnect this channel and wait for the peer to confirm the disconnection.
+    ///
+    /// Fails with `Error::Timeout` if the peer does not respond in time, in which case the channel is closed anyway.
+    pub async fn disconnect_and_wait(self) -> Result<(), Error> {
+        self.manager.disconnect_and_wait(self.index, L2CAP_RTX_TIMEOUT).await
+    }
+
     /// Get the PSM for this channel.
     pub fn psm(&self) -> u16 {
         self.manager.psm(self.index)
@@ -122,7 +292,7 @@ impl<'d, P: PacketPool> L2capChannel<'d, P> {
         stack: &Stack<'_, T, P>,
         buf: &[u8],
     ) -> Result<(), BleHostError<T::Error>> {
-        let mut p_buf = P::allocate().ok_or(Error::OutOfMemory)?;
+        let mut p_buf = P::allocate_async().await;
         stack
             .host
             .channels
@@ -130,6 +300,37 @@ impl<'d, P: PacketPool> L2capChannel<'d, P> {
             .await
     }
 
+    /// Send an SDU written in place, without copying its payload.
+    ///
+    /// The SDU must be equal to or smaller than the MTU agreed for the channel.
+    pub async fn send_sdu<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        mut sdu: TxSdu<P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let (packet, len) = sdu.raw_mut();
+        stack
+            .host
+            .channels
+            .send_in_place(self.index, packet, len, &stack.host)
+            .await
+    }
+
+    /// Send the provided buffers as a single SDU, in order.
+    ///
+    /// The total length must be equal to or smaller than the MTU agreed for the channel.
+    pub async fn send_vectored<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        bufs: &[&[u8]],
+    ) -> Result<(), BleHostError<T::Error>> {
+        let mut sdu = TxSdu::allocate_async().await;
+        for buf in bufs {
+            sdu.extend_from_slice(buf)?;
+        }
+        self.send_sdu(stack, sdu).await
+    }
+
     /// Send the provided buffer over this l2cap channel.
     ///
     /// The buffer must be equal to or smaller than the MTU agreed for the channel.
@@ -176,6 +377,9 @@ impl<'d, P: PacketPool> L2capChannel<'d, P> {
     }
 
     /// Await an incoming connection request matching the list of PSM.
+    ///
+    /// If any [`L2capListener`] is registered, only requests for the PSMs of registered listeners, and for
+    /// Enhanced ATT bearers, are queued.
     pub async fn accept<T: Controller>(
         stack: &'d Stack<'d, T, P>,
         connection: &Connection<'_, P>,
@@ -200,6 +404,114 @@ impl<'d, P: PacketPool> L2capChannel<'d, P> {
             .await
     }
 
+    /// Await an incoming enhanced credit based connection request matching the list of PSM.
+    ///
+    /// All channels opened by the request are accepted, in the order they were requested by the peer.
+    /// The MTU and MPS of enhanced credit based channels must be at least 64.
+    pub async fn accept_enhanced<T: Controller>(
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+        psm: &[u16],
+        config: &L2capChannelConfig,
+    ) -> Result<Vec<Self, L2CAP_ECFC_MAX_CHANNELS>, BleHostError<T::Error>> {
+        let handle = connection.handle();
+        stack
+            .host
+            .channels
+            .accept_enhanced(handle, psm, config, &stack.host)
+            .await
+    }
+
+    /// Create up to `L2CAP_ECFC_MAX_CHANNELS` channels with the provided PSM using a single enhanced
+    /// credit based connection request.
+    ///
+    /// The peer may refuse some of the channels, in which case only the accepted channels are returned.
+    /// The MTU and MPS of enhanced credit based channels must be at least 64.
+    pub async fn create_enhanced<T: Controller>(
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+        psm: u16,
+        count: usize,
+        config: &L2capChannelConfig,
+    ) -> Result<Vec<Self, L2CAP_ECFC_MAX_CHANNELS>, BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .create_enhanced(connection.handle(), psm, count, config, &stack.host)
+            .await
+    }
+
+    /// Number of frames which can be sent on this channel without waiting for more credits from the peer.
+    pub fn tx_credits(&self) -> u16 {
+        self.manager.tx_credits(self.index)
+    }
+
+    /// Number of frames the peer is currently allowed to send on this channel.
+    pub fn rx_credits(&self) -> u16 {
+        self.manager.rx_credits(self.index)
+    }
+
+    /// Change the policy used to issue credits to the peer.
+    ///
+    /// Credits owed according to the new policy are issued right away. Use [`CreditFlowPolicy::Manual`]
+    /// to stop issuing credits until granted with [`Self::grant_credits`].
+    pub async fn set_credit_flow_policy<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        policy: CreditFlowPolicy,
+    ) -> Result<(), BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .set_flow_policy(self.index, policy, &stack.host)
+            .await
+    }
+
+    /// Grant the peer credits to send more frames on this channel, regardless of the credit flow policy.
+    pub async fn grant_credits<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        credits: u16,
+    ) -> Result<(), BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .grant_credits(self.index, credits, &stack.host)
+            .await
+    }
+
+    /// Change the MTU and MPS this enhanced credit based channel can receive.
+    ///
+    /// The MTU can not be reduced.
+    pub async fn reconfigure<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        mtu: u16,
+        mps: u16,
+    ) -> Result<(), BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .reconfigure(&[self.index], mtu, mps, &stack.host)
+            .await
+    }
+
+    /// Change the MTU and MPS of several enhanced credit based channels of the same connection in a single request.
+    ///
+    /// Neither the MTU nor the MPS can be reduced when reconfiguring more than one channel.
+    pub async fn reconfigure_all<T: Controller>(
+        stack: &Stack<'_, T, P>,
+        channels: &[&Self],
+        mtu: u16,
+        mps: u16,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let mut indices: Vec<ChannelIndex, L2CAP_ECFC_MAX_CHANNELS> = Vec::new();
+        for channel in channels {
+            indices.push(channel.index).map_err(|_| Error::InvalidValue)?;
+        }
+        stack.host.channels.reconfigure(&indices, mtu, mps, &stack.host).await
+    }
+
     /// Split the channel into a writer and reader for concurrently
     /// writing to/reading from the channel.
     pub fn split(self) -> (L2capChannelWriter<'d, P>, L2capChannelReader<'d, P>) {
@@ -260,6 +572,56 @@ impl<'d, P: PacketPool> L2capChannelReader<'d, P> {
         stack.host.channels.receive_sdu(self.index, &stack.host).await
     }
 
+    /// Number of frames the peer is currently allowed to send on this channel.
+    pub fn rx_credits(&self) -> u16 {
+        self.manager.rx_credits(self.index)
+    }
+
+    /// Change the policy used to issue credits to the peer.
+    ///
+    /// Credits owed according to the new policy are issued right away. Use [`CreditFlowPolicy::Manual`]
+    /// to stop issuing credits until granted with [`Self::grant_credits`].
+    pub async fn set_credit_flow_policy<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        policy: CreditFlowPolicy,
+    ) -> Result<(), BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .set_flow_policy(self.index, policy, &stack.host)
+            .await
+    }
+
+    /// Grant the peer credits to send more frames on this channel, regardless of the credit flow policy.
+    pub async fn grant_credits<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        credits: u16,
+    ) -> Result<(), BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .grant_credits(self.index, credits, &stack.host)
+            .await
+    }
+
+    /// Change the MTU and MPS this enhanced credit based channel can receive.
+    ///
+    /// The MTU can not be reduced.
+    pub async fn reconfigure<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        mtu: u16,
+        mps: u16,
+    ) -> Result<(), BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .reconfigure(&[self.index], mtu, mps, &stack.host)
+            .await
+    }
+
     /// Read metrics of the l2cap channel.
     #[cfg(feature = "channel-metrics")]
     pub fn metrics<F: FnOnce(&ChannelMetrics) -> R, R>(&self, f: F) -> R {
@@ -301,7 +663,7 @@ impl<'d, P: PacketPool> L2capChannelWriter<'d, P> {
         stack: &Stack<'_, T, P>,
         buf: &[u8],
     ) -> Result<(), BleHostError<T::Error>> {
-        let mut p_buf = P::allocate().ok_or(Error::OutOfMemory)?;
+        let mut p_buf = P::allocate_async().await;
         stack
             .host
             .channels
@@ -309,6 +671,37 @@ impl<'d, P: PacketPool> L2capChannelWriter<'d, P> {
             .await
     }
 
+    /// Send an SDU written in place, without copying its payload.
+    ///
+    /// The SDU must be equal to or smaller than the MTU agreed for the channel.
+    pub async fn send_sdu<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        mut sdu: TxSdu<P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let (packet, len) = sdu.raw_mut();
+        stack
+            .host
+            .channels
+            .send_in_place(self.index, packet, len, &stack.host)
+            .await
+    }
+
+    /// Send the provided buffers as a single SDU, in order.
+    ///
+    /// The total length must be equal to or smaller than the MTU agreed for the channel.
+    pub async fn send_vectored<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        bufs: &[&[u8]],
+    ) -> Result<(), BleHostError<T::Error>> {
+        let mut sdu = TxSdu::allocate_async().await;
+        for buf in bufs {
+            sdu.extend_from_slice(buf)?;
+        }
+        self.send_sdu(stack, sdu).await
+    }
+
     /// Send the provided buffer over this l2cap channel.
     ///
     /// The buffer must be equal to or smaller than the MTU agreed for the channel.
@@ -327,6 +720,11 @@ impl<'d, P: PacketPool> L2capChannelWriter<'d, P> {
             .try_send(self.index, buf, p_buf.as_mut(), &stack.host)
     }
 
+    /// Number of frames which can be sent on this channel without waiting for more credits from the peer.
+    pub fn tx_credits(&self) -> u16 {
+        self.manager.tx_credits(self.index)
+    }
+
     /// Read metrics of the l2cap channel.
     #[cfg(feature = "channel-metrics")]
     pub fn metrics<F: FnOnce(&ChannelMetrics) -> R, R>(&self, f: F) -> R {
@@ -0,0 +1,478 @@
+//! Byte stream adapters for L2CAP channels.
+//!
+//! Reads are served from the last received SDU until it has been fully consumed. Writes are buffered
+//! and sent as a single SDU when the buffer reaches the channel MTU, or when the stream is flushed.
+use bt_hci::controller::Controller;
+use embedded_io_async::{BufRead, ErrorType, Read, Write};
+
+use super::{L2capChannel, L2capChannelReader, L2capChannelWriter};
+use crate::channel_manager::ChannelIndex;
+use crate::pdu::Sdu;
+use crate::{BleHostError, Error, PacketPool, Stack};
+
+/// Byte stream over an L2CAP channel.
+///
+/// Buffered data is only sent when the stream is flushed or the buffer is full, so make sure to
+/// call `flush` after writing.
+pub struct L2capStream<'d, T, P: PacketPool> {
+    stack: &'d Stack<'d, T, P>,
+    channel: L2capChannel<'d, P>,
+    rx: RxBuffer<P>,
+    tx: TxBuffer<P>,
+}
+
+/// Read half of a byte stream over an L2CAP channel.
+pub struct L2capStreamReader<'d, T, P: PacketPool> {
+    stack: &'d Stack<'d, T, P>,
+    reader: L2capChannelReader<'d, P>,
+    rx: RxBuffer<P>,
+}
+
+/// Write half of a byte stream over an L2CAP channel.
+///
+/// Buffered data is only sent when the stream is flushed or the buffer is full, so make sure to
+/// call `flush` after writing.
+pub struct L2capStreamWriter<'d, T, P: PacketPool> {
+    stack: &'d Stack<'d, T, P>,
+    writer: L2capChannelWriter<'d, P>,
+    tx: TxBuffer<P>,
+}
+
+impl<'d, T: Controller, P: PacketPool> L2capStream<'d, T, P> {
+    /// Create a byte stream over the provided channel.
+    pub fn new(stack: &'d Stack<'d, T, P>, channel: L2capChannel<'d, P>) -> Self {
+        Self {
+            stack,
+            channel,
+            rx: RxBuffer::new(),
+            tx: TxBuffer::new(),
+        }
+    }
+
+    /// Split the stream into a writer and reader for concurrently writing to/reading from the channel.
+    ///
+    /// Data buffered by the stream is kept by the respective halves.
+    pub fn split(self) -> (L2capStreamWriter<'d, T, P>, L2capStreamReader<'d, T, P>) {
+        let (writer, reader) = self.channel.split();
+        (
+            L2capStreamWriter {
+                stack: self.stack,
+                writer,
+                tx: self.tx,
+            },
+            L2capStreamReader {
+                stack: self.stack,
+                reader,
+                rx: self.rx,
+            },
+        )
+    }
+
+    /// Retrieve the underlying channel.
+    ///
+    /// Any data that has not been read or flushed is discarded.
+    pub fn into_inner(self) -> L2capChannel<'d, P> {
+        self.channel
+    }
+}
+
+impl<'d, T: Controller, P: PacketPool> L2capStreamReader<'d, T, P> {
+    /// Create a byte stream over the provided channel reader.
+    pub fn new(stack: &'d Stack<'d, T, P>, reader: L2capChannelReader<'d, P>) -> Self {
+        Self {
+            stack,
+            reader,
+            rx: RxBuffer::new(),
+        }
+    }
+
+    /// Retrieve the underlying channel reader.
+    ///
+    /// Any data that has not been read is discarded.
+    pub fn into_inner(self) -> L2capChannelReader<'d, P> {
+        self.reader
+    }
+}
+
+impl<'d, T: Controller, P: PacketPool> L2capStreamWriter<'d, T, P> {
+    /// Create a byte stream over the provided channel writer.
+    pub fn new(stack: &'d Stack<'d, T, P>, writer: L2capChannelWriter<'d, P>) -> Self {
+        Self {
+            stack,
+            writer,
+            tx: TxBuffer::new(),
+        }
+    }
+
+    /// Retrieve the underlying channel writer.
+    ///
+    /// Any data that has not been flushed is discarded.
+    pub fn into_inner(self) -> L2capChannelWriter<'d, P> {
+        self.writer
+    }
+}
+
+impl<T: Controller, P: PacketPool> ErrorType for L2capStream<'_, T, P> {
+    type Error = BleHostError<T::Error>;
+}
+
+impl<T: Controller, P: PacketPool> ErrorType for L2capStreamReader<'_, T, P> {
+    type Error = BleHostError<T::Error>;
+}
+
+impl<T: Controller, P: PacketPool> ErrorType for L2capStreamWriter<'_, T, P> {
+    type Error = BleHostError<T::Error>;
+}
+
+impl<T: Controller, P: PacketPool> Read for L2capStream<'_, T, P> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let data = self.fill_buf().await?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<T: Controller, P: PacketPool> BufRead for L2capStream<'_, T, P> {
+    async fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+        while self.rx.is_empty() {
+            match self.channel.receive_sdu(self.stack).await {
+                Ok(sdu) => self.rx.set(sdu),
+                // A closed channel is the end of the stream.
+                Err(BleHostError::BleHost(Error::ChannelClosed)) => return Ok(&[]),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.rx.data())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.rx.consume(amt);
+    }
+}
+
+impl<T: Controller, P: PacketPool> Read for L2capStreamReader<'_, T, P> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let data = self.fill_buf().await?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<T: Controller, P: PacketPool> BufRead for L2capStreamReader<'_, T, P> {
+    async fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+        while self.rx.is_empty() {
+            match self.reader.receive_sdu(self.stack).await {
+                Ok(sdu) => self.rx.set(sdu),
+                // A closed channel is the end of the stream.
+                Err(BleHostError::BleHost(Error::ChannelClosed)) => return Ok(&[]),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.rx.data())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.rx.consume(amt);
+    }
+}
+
+impl<T: Controller, P: PacketPool> Write for L2capStream<'_, T, P> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let index = self.channel.index;
+        if self.tx.is_full(self.stack, index)? {
+            self.flush().await?;
+        }
+        Ok(self.tx.push(self.stack, index, buf).await?)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        if let Some(data) = self.tx.data() {
+            self.channel.send(self.stack, data).await?;
+            self.tx.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<T: Controller, P: PacketPool> Write for L2capStreamWriter<'_, T, P> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let index = self.writer.index;
+        if self.tx.is_full(self.stack, index)? {
+            self.flush().await?;
+        }
+        Ok(self.tx.push(self.stack, index, buf).await?)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        if let Some(data) = self.tx.data() {
+            self.writer.send(self.stack, data).await?;
+            self.tx.clear();
+        }
+        Ok(())
+    }
+}
+
+/// The SDU currently being read.
+struct RxBuffer<P: PacketPool> {
+    sdu: Option<Sdu<P::Packet>>,
+    pos: usize,
+}
+
+impl<P: PacketPool> RxBuffer<P> {
+    fn new() -> Self {
+        Self { sdu: None, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data().is_empty()
+    }
+
+    fn set(&mut self, sdu: Sdu<P::Packet>) {
+        self.sdu = Some(sdu);
+        self.pos = 0;
+    }
+
+    fn data(&self) -> &[u8] {
+        match &self.sdu {
+            Some(sdu) => &sdu.as_ref()[self.pos..],
+            None => &[],
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Some(sdu) = &self.sdu {
+            self.pos = (self.pos + amt).min(sdu.len());
+            if self.pos == sdu.len() {
+                // Release the packet as soon as possible.
+                self.sdu = None;
+                self.pos = 0;
+            }
+        }
+    }
+}
+
+/// The SDU currently being written.
+///
+/// The packet is only allocated while there is data buffered.
+struct TxBuffer<P: PacketPool> {
+    packet: Option<P::Packet>,
+    len: usize,
+}
+
+impl<P: PacketPool> TxBuffer<P> {
+    fn new() -> Self {
+        Self { packet: None, len: 0 }
+    }
+
+    /// Size of the largest SDU that can be buffered for the channel.
+    fn capacity<T>(stack: &Stack<'_, T, P>, index: ChannelIndex) -> Result<usize, Error> {
+        Ok((stack.host.channels.send_mtu(index)? as usize).min(P::MTU))
+    }
+
+    fn is_full<T>(&self, stack: &Stack<'_, T, P>, index: ChannelIndex) -> Result<bool, Error> {
+        Ok(self.len >= Self::capacity(stack, index)?)
+    }
+
+    /// Append data to the buffer, waiting for a packet to be available if none is allocated yet.
+    async fn push<T>(&mut self, stack: &Stack<'_, T, P>, index: ChannelIndex, buf: &[u8]) -> Result<usize, Error> {
+        let capacity = Self::capacity(stack, index)?;
+        let packet = match self.packet.take() {
+            Some(packet) => packet,
+            None => P::allocate_async().await,
+        };
+        let packet = self.packet.insert(packet);
+        let n = buf.len().min(capacity.saturating_sub(self.len));
+        packet.as_mut()[self.len..self.len + n].copy_from_slice(&buf[..n]);
+        self.len += n;
+        Ok(n)
+    }
+
+    fn data(&self) -> Option<&[u8]> {
+        match &self.packet {
+            Some(packet) if self.len > 0 => Some(&packet.as_ref()[..self.len]),
+            _ => None,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.packet = None;
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::task::Poll;
+    use std::boxed::Box;
+
+    use bt_hci::param::{AddrKind, BdAddr, ConnHandle, LeConnRole};
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock_controller::MockController;
+    use crate::pdu::Pdu;
+    use crate::prelude::DefaultPacketPool;
+    use crate::types::l2cap::L2capSignalCode;
+    use crate::HostResources;
+
+    type TestStream = L2capStream<'static, MockController, DefaultPacketPool>;
+
+    /// Create a stream over a channel with the given MTU, and a larger MPS so that every SDU is a single K-frame.
+    fn stream(mtu: u16) -> TestStream {
+        let resources: &'static mut HostResources<DefaultPacketPool, 1, 1> = Box::leak(Box::new(HostResources::new()));
+        let stack: &'static Stack<'static, MockController, DefaultPacketPool> =
+            Box::leak(Box::new(crate::new(MockController::new(), resources)));
+        stack.host.initialize(251, 32);
+        stack
+            .host
+            .connections
+            .connect(
+                ConnHandle::new(1),
+                AddrKind::PUBLIC,
+                BdAddr::new([0; 6]),
+                LeConnRole::Central,
+            )
+            .unwrap();
+        let Poll::Ready(connection) = stack.host.connections.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        // The connection stays open for the lifetime of the stream.
+        Box::leak(Box::new(connection));
+        let channel = stack.host.channels.open(ConnHandle::new(1), mtu, 100, 10);
+        L2capStream::new(stack, channel)
+    }
+
+    /// Receive an SDU from the peer.
+    fn receive(stream: &TestStream, data: &[u8]) {
+        #[cfg(not(feature = "l2cap-sdu-reassembly-optimization"))]
+        let header: &[u8] = &(data.len() as u16).to_le_bytes();
+        #[cfg(feature = "l2cap-sdu-reassembly-optimization")]
+        let header: &[u8] = &[];
+        let mut packet = DefaultPacketPool::allocate().unwrap();
+        packet.as_mut()[..header.len()].copy_from_slice(header);
+        packet.as_mut()[header.len()..header.len() + data.len()].copy_from_slice(data);
+        let pdu = Pdu::new(packet, header.len() + data.len());
+        stream.stack.host.channels.dispatch(0x40, pdu).unwrap();
+    }
+
+    /// The SDUs sent to the peer.
+    fn sent(stream: &TestStream) -> std::vec::Vec<std::vec::Vec<u8>> {
+        let frames = stream.stack.host.controller.take_acl();
+        frames
+            .into_iter()
+            .map(|frame| {
+                // L2CAP header of the peer channel, followed by the SDU length.
+                assert_eq!(&frame[2..4], &[0x50, 0]);
+                assert_eq!(frame[4..6], ((frame.len() - 6) as u16).to_le_bytes());
+                frame[6..].to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn partial_reads() {
+        let mut stream = stream(64);
+        receive(&stream, &[1, 2, 3, 4, 5]);
+        receive(&stream, &[6, 7]);
+
+        let mut buf = [0; 8];
+        assert_eq!(block_on(stream.read(&mut buf[..2])).unwrap(), 2);
+        assert_eq!(&buf[..2], &[1, 2]);
+        assert_eq!(block_on(stream.read(&mut buf[..2])).unwrap(), 2);
+        assert_eq!(&buf[..2], &[3, 4]);
+        // A read does not span SDUs.
+        assert_eq!(block_on(stream.read(&mut buf)).unwrap(), 1);
+        assert_eq!(&buf[..1], &[5]);
+        assert_eq!(block_on(stream.read(&mut buf)).unwrap(), 2);
+        assert_eq!(&buf[..2], &[6, 7]);
+        assert_eq!(block_on(stream.read(&mut [])).unwrap(), 0);
+    }
+
+    #[test]
+    fn buf_read_consume() {
+        let mut stream = stream(64);
+        receive(&stream, &[1, 2, 3]);
+
+        assert_eq!(block_on(stream.fill_buf()).unwrap(), &[1, 2, 3]);
+        stream.consume(1);
+        assert_eq!(block_on(stream.fill_buf()).unwrap(), &[2, 3]);
+        // Filling again does not consume anything.
+        assert_eq!(block_on(stream.fill_buf()).unwrap(), &[2, 3]);
+        stream.consume(2);
+        // The packet is released as soon as the SDU is consumed.
+        assert!(stream.rx.sdu.is_none());
+
+        receive(&stream, &[4]);
+        assert_eq!(block_on(stream.fill_buf()).unwrap(), &[4]);
+        // Consuming more than available stops at the end of the SDU.
+        stream.consume(5);
+        assert!(stream.rx.is_empty());
+    }
+
+    #[test]
+    fn full_buffer_flush() {
+        let mut stream = stream(30);
+        let data: std::vec::Vec<u8> = (0..40).collect();
+
+        // Writes are buffered up to the channel MTU.
+        assert_eq!(block_on(stream.write(&data[..20])).unwrap(), 20);
+        assert_eq!(block_on(stream.write(&data[20..])).unwrap(), 10);
+        assert!(sent(&stream).is_empty());
+
+        // The full buffer is sent before buffering more data.
+        assert_eq!(block_on(stream.write(&data[30..])).unwrap(), 10);
+        assert_eq!(sent(&stream), [data[..30].to_vec()]);
+
+        block_on(stream.flush()).unwrap();
+        assert_eq!(sent(&stream), [data[30..].to_vec()]);
+
+        // Flushing an empty buffer sends nothing.
+        block_on(stream.flush()).unwrap();
+        assert!(sent(&stream).is_empty());
+    }
+
+    #[test]
+    fn eof_on_close() {
+        let mut stream = stream(64);
+        receive(&stream, &[1, 2]);
+
+        let mut buf = [0; 8];
+        assert_eq!(block_on(stream.read(&mut buf[..1])).unwrap(), 1);
+
+        // The peer disconnects the channel.
+        stream
+            .stack
+            .host
+            .channels
+            .signal(
+                ConnHandle::new(1),
+                &[L2capSignalCode::DisconnectionReq as u8, 1, 4, 0, 0x40, 0, 0x50, 0],
+                &stream.stack.host.connections,
+            )
+            .unwrap();
+
+        // Data already read from the channel is still available, then the end of the stream is reached.
+        assert_eq!(block_on(stream.read(&mut buf)).unwrap(), 1);
+        assert_eq!(&buf[..1], &[2]);
+        assert_eq!(block_on(stream.read(&mut buf)).unwrap(), 0);
+        assert!(block_on(stream.fill_buf()).unwrap().is_empty());
+    }
+}
@@ -5,19 +5,25 @@
 #![doc = include_str!(concat!("../", env!("CARGO_PKG_README")))]
 #![warn(missing_docs)]
 
+use core::cell::RefCell;
+use core::future::{poll_fn, Future};
 use core::mem::MaybeUninit;
+use core::task::{Context, Poll};
 
 use advertise::AdvertisementDataError;
 use bt_hci::cmd::status::ReadRssi;
 use bt_hci::cmd::{AsyncCmd, SyncCmd};
 use bt_hci::param::{AddrKind, BdAddr};
 use bt_hci::FromHciBytesError;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::waitqueue::MultiWakerRegistration;
 #[cfg(feature = "security")]
 use heapless::Vec;
 use rand_core::{CryptoRng, RngCore};
 
 use crate::att::AttErrorCode;
-use crate::channel_manager::ChannelStorage;
+use crate::channel_manager::{ChannelStorage, SignalQueue};
 use crate::connection_manager::ConnectionStorage;
 #[cfg(feature = "security")]
 pub use crate::security_manager::{BondInformation, IdentityResolvingKey, LongTermKey};
@@ -79,7 +85,7 @@ pub mod prelude {
     pub use trouble_host_macros::*;
 
     pub use super::att::AttErrorCode;
-    pub use super::{BleHostError, Controller, Error, Host, HostResources, Packet, PacketPool, Stack};
+    pub use super::{packet_freed, BleHostError, Controller, Error, Host, HostResources, Packet, PacketPool, Stack};
     #[cfg(feature = "peripheral")]
     pub use crate::advertise::*;
     #[cfg(feature = "gatt")]
@@ -97,7 +103,7 @@ pub mod prelude {
     pub use crate::l2cap::*;
     #[cfg(feature = "default-packet-pool")]
     pub use crate::packet_pool::DefaultPacketPool;
-    pub use crate::pdu::Sdu;
+    pub use crate::pdu::{Sdu, TxSdu, TX_SDU_HEADROOM};
     #[cfg(feature = "peripheral")]
     pub use crate::peripheral::*;
     #[cfg(feature = "scan")]
@@ -246,6 +252,24 @@ pub enum BleHostError<E> {
     BleHost(Error),
 }
 
+#[cfg(feature = "l2cap-stream")]
+impl<E: embedded_io::Error> embedded_io::Error for BleHostError<E> {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            Self::Controller(e) => e.kind(),
+            Self::BleHost(Error::ChannelClosed) | Self::BleHost(Error::Disconnected) => {
+                embedded_io::ErrorKind::NotConnected
+            }
+            Self::BleHost(Error::OutOfMemory) => embedded_io::ErrorKind::OutOfMemory,
+            Self::BleHost(Error::Timeout) => embedded_io::ErrorKind::TimedOut,
+            Self::BleHost(Error::InsufficientSpace) | Self::BleHost(Error::InvalidValue) => {
+                embedded_io::ErrorKind::InvalidInput
+            }
+            Self::BleHost(_) => embedded_io::ErrorKind::Other,
+        }
+    }
+}
+
 /// How many bytes of invalid data to capture in the error variants before truncating.
 pub const MAX_INVALID_DATA_LEN: usize = 16;
 
@@ -302,6 +326,11 @@ pub enum Error {
     /// Invalid CCCD handle length.
     InvalidCccdHandleLength(usize),
 
+    /// The value echoed by the server for a reliable write did not match the value which was sent.
+    ///
+    /// The queued writes have been cancelled.
+    ReliableWriteMismatch,
+
     /// Failed to finalize the packet.
     FailedToFinalize {
         /// Expected length.
@@ -343,6 +372,10 @@ pub enum Error {
     NoPermits,
     /// Connection is disconnected.
     Disconnected,
+    /// The request was rejected by the peer.
+    Rejected,
+    /// The peer rejected an L2CAP signaling command, with the given reason.
+    CommandRejected(u16),
     /// Connection limit has been reached.
     ConnectionLimitReached,
     /// GATT subscriber limit has been reached.
@@ -507,10 +540,40 @@ pub trait PacketPool: 'static {
     /// amount of bytes it has received.
     fn allocate() -> Option<Self::Packet>;
 
+    /// Poll for a new buffer with space for `MTU` bytes.
+    ///
+    /// When no buffer is available, the waker from `cx` is woken once a packet is freed. The default
+    /// implementation registers the waker to be woken by [`packet_freed`], so pools relying on it must
+    /// call [`packet_freed`] whenever a packet is returned to the pool.
+    fn poll_allocate(cx: &mut Context<'_>) -> Poll<Self::Packet> {
+        // Registering before allocating ensures a packet freed in between is not missed
+        ALLOC_WAITERS.lock(|waiters| waiters.borrow_mut().register(cx.waker()));
+        match Self::allocate() {
+            Some(packet) => Poll::Ready(packet),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Allocate a new buffer with space for `MTU` bytes, waiting until one is available.
+    fn allocate_async() -> impl Future<Output = Self::Packet> {
+        poll_fn(Self::poll_allocate)
+    }
+
     /// Capacity of this pool in the number of packets.
     fn capacity() -> usize;
 }
 
+/// Tasks waiting in the default [`PacketPool::poll_allocate`].
+static ALLOC_WAITERS: Mutex<CriticalSectionRawMutex, RefCell<MultiWakerRegistration<4>>> =
+    Mutex::new(RefCell::new(MultiWakerRegistration::new()));
+
+/// Wake the tasks waiting for a packet in the default [`PacketPool::poll_allocate`].
+///
+/// Packet pools relying on the default implementation call this when a packet is returned to the pool.
+pub fn packet_freed() {
+    ALLOC_WAITERS.lock(|waiters| waiters.borrow_mut().wake());
+}
+
 /// HostResources holds the resources used by the host.
 ///
 /// The l2cap packet pool is used by the host to handle inbound data, by allocating space for
@@ -519,6 +582,7 @@ pub struct HostResources<P: PacketPool, const CONNS: usize, const CHANNELS: usiz
     connections: MaybeUninit<[ConnectionStorage<P::Packet>; CONNS]>,
     channels: MaybeUninit<[ChannelStorage<P::Packet>; CHANNELS]>,
     advertise_handles: MaybeUninit<[AdvHandleState; ADV_SETS]>,
+    signals: SignalQueue,
 }
 
 impl<P: PacketPool, const CONNS: usize, const CHANNELS: usize, const ADV_SETS: usize> Default
@@ -538,6 +602,7 @@ impl<P: PacketPool, const CONNS: usize, const CHANNELS: usize, const ADV_SETS: u
             connections: MaybeUninit::uninit(),
             channels: MaybeUninit::uninit(),
             advertise_handles: MaybeUninit::uninit(),
+            signals: SignalQueue::new(),
         }
     }
 }
@@ -559,6 +624,10 @@ pub fn new<
         unsafe { core::mem::transmute(x) }
     }
 
+    unsafe fn transmute_ref<T>(x: &T) -> &'static T {
+        unsafe { core::mem::transmute(x) }
+    }
+
     // Safety:
     // - HostResources has the exceeding lifetime as the returned Stack.
     // - Internal lifetimes are elided (made 'static) to simplify API usage
@@ -574,7 +643,11 @@ pub fn new<
 
     let advertise_handles = &mut *resources.advertise_handles.write([AdvHandleState::None; ADV_SETS]);
     let advertise_handles: &'static mut [AdvHandleState] = unsafe { transmute_slice(advertise_handles) };
-    let host: BleHost<'_, C, P> = BleHost::new(controller, connections, channels, advertise_handles);
+    // Responses queued by a previous stack are not sent.
+    resources.signals.clear();
+    let signals: &'static SignalQueue = unsafe { transmute_ref(&resources.signals) };
+
+    let host: BleHost<'_, C, P> = BleHost::new(controller, connections, channels, signals, advertise_handles);
 
     Stack { host }
 }
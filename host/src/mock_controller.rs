@@ -1,14 +1,27 @@
+extern crate std;
+
+use core::cell::RefCell;
 use core::convert::Infallible;
 use core::future::Future;
+use std::vec::Vec;
 
 use bt_hci::cmd::{self, AsyncCmd, SyncCmd};
 use bt_hci::controller::{ControllerCmdAsync, ControllerCmdSync};
 
-pub struct MockController {}
+pub struct MockController {
+    acl: RefCell<Vec<Vec<u8>>>,
+}
 
 impl MockController {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            acl: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Take the payloads of the ACL packets written so far.
+    pub fn take_acl(&self) -> Vec<Vec<u8>> {
+        self.acl.take()
     }
 }
 
@@ -64,7 +77,8 @@ impl bt_hci::controller::blocking::Controller for MockController {
 
 impl bt_hci::controller::Controller for MockController {
     fn write_acl_data(&self, packet: &bt_hci::data::AclPacket) -> impl Future<Output = Result<(), Self::Error>> {
-        async { todo!() }
+        self.acl.borrow_mut().push(packet.data().to_vec());
+        async { Ok(()) }
     }
 
     fn write_sync_data(&self, packet: &bt_hci::data::SyncPacket) -> impl Future<Output = Result<(), Self::Error>> {
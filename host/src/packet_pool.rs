@@ -1,8 +1,10 @@
 //! A packet pool for allocating and freeing packet buffers with quality of service policy.
 use core::cell::RefCell;
+use core::task::{Context, Poll};
 
 use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, RawMutex};
 use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::waitqueue::MultiWakerRegistration;
 
 use crate::{config, Packet, PacketPool};
 
@@ -22,14 +24,19 @@ impl<const MTU: usize> PacketBuf<MTU> {
     }
 }
 
+/// Number of tasks that can wait for a packet to be freed before all of them are woken.
+const ALLOC_WAITERS: usize = 4;
+
 struct State<const MTU: usize, const N: usize> {
     packets: [PacketBuf<MTU>; N],
+    waiters: MultiWakerRegistration<ALLOC_WAITERS>,
 }
 
 impl<const MTU: usize, const N: usize> State<MTU, N> {
     pub(crate) const fn new() -> Self {
         Self {
             packets: [PacketBuf::NEW; N],
+            waiters: MultiWakerRegistration::new(),
         }
     }
 
@@ -51,6 +58,7 @@ impl<const MTU: usize, const N: usize> State<MTU, N> {
     fn free(&mut self, p_ref: &PacketRef<MTU>) {
         // info!("[{}] free {}", id.0, p_ref.idx);
         self.packets[p_ref.idx].free = true;
+        self.waiters.wake();
     }
 
     fn available(&mut self) -> usize {
@@ -85,6 +93,19 @@ impl<M: RawMutex, const MTU: usize, const N: usize> StaticPacketPool<M, MTU, N>
         })
     }
 
+    fn poll_alloc(&self, cx: &mut Context<'_>) -> Poll<PacketRef<MTU>> {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            match state.alloc() {
+                Some(p_ref) => Poll::Ready(p_ref),
+                None => {
+                    state.waiters.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+
     fn free(&self, p_ref: &PacketRef<MTU>) {
         self.state.lock(|state| {
             let mut state = state.borrow_mut();
@@ -133,6 +154,13 @@ impl PacketPool for DefaultPacketPool {
             pool: &DEFAULT_POOL,
         })
     }
+
+    fn poll_allocate(cx: &mut Context<'_>) -> Poll<DefaultPacket> {
+        DEFAULT_POOL.poll_alloc(cx).map(|p| DefaultPacket {
+            p_ref: p,
+            pool: &DEFAULT_POOL,
+        })
+    }
 }
 
 /// Type representing the packet from the default packet pool.
@@ -162,10 +190,29 @@ impl Drop for DefaultPacket {
 
 #[cfg(test)]
 mod tests {
+    extern crate std;
+
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+
     use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 
     use super::*;
 
+    /// Waker counting how many times it was woken.
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
     #[test]
     fn test_none_qos() {
         let pool: StaticPacketPool<NoopRawMutex, 27, 8> = StaticPacketPool::new();
@@ -191,4 +238,23 @@ mod tests {
         let b2 = pool.alloc();
         assert!(b2.is_none());
     }
+
+    #[test]
+    fn test_poll_alloc() {
+        let pool: StaticPacketPool<NoopRawMutex, 27, 1> = StaticPacketPool::new();
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = core::task::Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let a1 = pool.poll_alloc(&mut cx);
+        let Poll::Ready(a1) = a1 else {
+            panic!("expected a packet");
+        };
+        assert!(pool.poll_alloc(&mut cx).is_pending());
+        assert_eq!(counter.0.load(Ordering::Relaxed), 0);
+
+        pool.free(&a1);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+        assert!(pool.poll_alloc(&mut cx).is_ready());
+    }
 }
@@ -1,4 +1,4 @@
-use crate::Packet;
+use crate::{Error, Packet, PacketPool};
 
 pub(crate) struct Pdu<P> {
     packet: P,
@@ -75,3 +75,94 @@ impl<P: Packet> AsMut<[u8]> for Sdu<P> {
         self.pdu.as_mut()
     }
 }
+
+/// Number of bytes reserved in front of the payload of a [`TxSdu`] for the L2CAP basic header
+/// and the SDU length field.
+pub const TX_SDU_HEADROOM: usize = 6;
+
+/// Service Data Unit to be sent over an L2CAP channel.
+///
+/// The SDU is written in place into a packet of the pool, with room reserved in front of the
+/// payload for the L2CAP headers, so that it can be sent without being copied.
+pub struct TxSdu<P: PacketPool> {
+    packet: P::Packet,
+    len: usize,
+}
+
+impl<P: PacketPool> TxSdu<P> {
+    /// Allocate an empty SDU from the packet pool.
+    ///
+    /// Returns `None` if no packet is available.
+    pub fn allocate() -> Option<Self> {
+        Some(Self {
+            packet: P::allocate()?,
+            len: 0,
+        })
+    }
+
+    /// Allocate an empty SDU from the packet pool, waiting until a packet is available.
+    pub async fn allocate_async() -> Self {
+        Self {
+            packet: P::allocate_async().await,
+            len: 0,
+        }
+    }
+
+    /// Maximum payload length.
+    pub fn capacity(&self) -> usize {
+        self.packet.as_ref().len() - TX_SDU_HEADROOM
+    }
+
+    /// Payload length.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no payload has been written.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Set the payload length, after writing the payload using [`TxSdu::buffer_mut`].
+    pub fn set_len(&mut self, len: usize) -> Result<(), Error> {
+        if len > self.capacity() {
+            return Err(Error::InsufficientSpace);
+        }
+        self.len = len;
+        Ok(())
+    }
+
+    /// Append data to the payload.
+    pub fn extend_from_slice(&mut self, data: &[u8]) -> Result<(), Error> {
+        let start = TX_SDU_HEADROOM + self.len;
+        let end = start + data.len();
+        if end > self.packet.as_ref().len() {
+            return Err(Error::InsufficientSpace);
+        }
+        self.packet.as_mut()[start..end].copy_from_slice(data);
+        self.len += data.len();
+        Ok(())
+    }
+
+    /// The whole payload area, regardless of the current payload length.
+    pub fn buffer_mut(&mut self) -> &mut [u8] {
+        &mut self.packet.as_mut()[TX_SDU_HEADROOM..]
+    }
+
+    /// The packet including the reserved headroom, and the payload length.
+    pub(crate) fn raw_mut(&mut self) -> (&mut [u8], usize) {
+        (self.packet.as_mut(), self.len)
+    }
+}
+
+impl<P: PacketPool> AsRef<[u8]> for TxSdu<P> {
+    fn as_ref(&self) -> &[u8] {
+        &self.packet.as_ref()[TX_SDU_HEADROOM..TX_SDU_HEADROOM + self.len]
+    }
+}
+
+impl<P: PacketPool> AsMut<[u8]> for TxSdu<P> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.packet.as_mut()[TX_SDU_HEADROOM..TX_SDU_HEADROOM + self.len]
+    }
+}
@@ -27,12 +27,12 @@ pub use types::Reason;
 use types::{AuthReq, BondingFlag, Command, IoCapabilities, PairingFeatures};
 
 use crate::codec::{Decode, Encode};
-use crate::connection_manager::{ConnectionManager, ConnectionStorage};
+use crate::connection_manager::ConnectionStorage;
 use crate::pdu::Pdu;
 use crate::prelude::Connection;
 use crate::security_manager::types::UseOutOfBand;
 use crate::types::l2cap::L2CAP_CID_LE_U_SECURITY_MANAGER;
-use crate::{Address, Error, Identity, PacketPool};
+use crate::{Address, Error, Identity, Packet, PacketPool};
 
 /// Events of interest to the security manager
 pub(crate) enum SecurityEventData {
@@ -44,6 +44,8 @@ pub(crate) enum SecurityEventData {
     Timeout,
     /// Oairing timer changed
     TimerChange,
+    /// Send a security manager protocol packet
+    SendPacket(ConnHandle, TxPacket),
 }
 
 /// Bond Information
@@ -98,25 +100,30 @@ impl<const BOND_COUNT: usize> SecurityManagerData<BOND_COUNT> {
 }
 
 /// Packet structure for sending security manager protocol (SMP) commands
-struct TxPacket<P: PacketPool> {
-    /// Underlying packet
-    packet: P::Packet,
+///
+/// The packet is built in a fixed buffer and copied into a packet from the pool when it is sent,
+/// so that commands can be prepared without waiting for the pool.
+pub(crate) struct TxPacket {
+    /// Packet data, including the L2CAP header
+    data: [u8; Self::MAX_SIZE],
     /// Command to send
     command: Command,
 }
 
-impl<P: PacketPool> TxPacket<P> {
+impl TxPacket {
     /// Size of L2CAP header and command
     const HEADER_SIZE: usize = 5;
+    /// Size of the largest command, the public key
+    const MAX_SIZE: usize = Self::HEADER_SIZE + Command::PairingPublicKey.payload_size() as usize;
 
-    /// Get a packet from the pool
-    pub fn new(mut packet: P::Packet, command: Command) -> Result<Self, Error> {
-        let packet_data = packet.as_mut();
+    /// Create a packet for a command
+    pub fn new(command: Command) -> Self {
+        let mut data = [0; Self::MAX_SIZE];
         let smp_size = command.payload_size() + 1;
-        packet_data[..2].copy_from_slice(&(smp_size).to_le_bytes());
-        packet_data[2..4].copy_from_slice(&L2CAP_CID_LE_U_SECURITY_MANAGER.to_le_bytes());
-        packet_data[4] = command.into();
-        Ok(Self { packet, command })
+        data[..2].copy_from_slice(&(smp_size).to_le_bytes());
+        data[2..4].copy_from_slice(&L2CAP_CID_LE_U_SECURITY_MANAGER.to_le_bytes());
+        data[4] = command.into();
+        Self { data, command }
     }
     /// Packet command
     pub fn command(&self) -> Command {
@@ -125,20 +132,22 @@ impl<P: PacketPool> TxPacket<P> {
 
     /// Packet payload
     pub fn payload(&self) -> &[u8] {
-        &self.packet.as_ref()[Self::HEADER_SIZE..Self::HEADER_SIZE + usize::from(self.command.payload_size())]
+        &self.data[Self::HEADER_SIZE..Self::HEADER_SIZE + usize::from(self.command.payload_size())]
     }
     /// Package mutable payload
     pub fn payload_mut(&mut self) -> &mut [u8] {
-        &mut self.packet.as_mut()[Self::HEADER_SIZE..Self::HEADER_SIZE + usize::from(self.command.payload_size())]
+        &mut self.data[Self::HEADER_SIZE..Self::HEADER_SIZE + usize::from(self.command.payload_size())]
     }
     /// Package size
     pub fn total_size(&self) -> usize {
         usize::from(self.command.payload_size()) + Self::HEADER_SIZE
     }
-    /// Create a PDU from the packet
-    pub fn into_pdu(self) -> Pdu<P::Packet> {
+    /// Create a PDU from the packet, waiting for a packet from the pool
+    pub async fn into_pdu<P: PacketPool>(self) -> Pdu<P::Packet> {
         let len = self.total_size();
-        Pdu::new(self.packet, len)
+        let mut packet = P::allocate_async().await;
+        packet.as_mut()[..len].copy_from_slice(&self.data[..len]);
+        Pdu::new(packet, len)
     }
 }
 
@@ -314,7 +323,7 @@ pub struct SecurityManager<const BOND_COUNT: usize> {
     /// Current state of the pairing
     pairing_state: RefCell<PairingData>,
     /// Received events
-    events: Channel<NoopRawMutex, SecurityEventData, 2>,
+    events: Channel<NoopRawMutex, SecurityEventData, 4>,
     result_signal: Signal<NoopRawMutex, Reason>,
     /// Timer
     timer_expires: RefCell<Instant>,
@@ -420,12 +429,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     }
 
     /// Handle packet
-    pub(crate) fn handle<P: PacketPool>(
-        &self,
-        pdu: Pdu<P::Packet>,
-        connections: &ConnectionManager<P>,
-        storage: &ConnectionStorage<P::Packet>,
-    ) -> Result<(), Error> {
+    pub(crate) fn handle<T: Packet>(&self, pdu: Pdu<T>, storage: &ConnectionStorage<T>) -> Result<(), Error> {
         // Should it be possible to handle multiple concurrent pairings?
         let role = storage.role.ok_or(Error::InvalidValue)?;
         let handle = storage.handle.ok_or(Error::InvalidValue)?;
@@ -505,12 +509,12 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
             trace!("Security Manager Protocol command {}", command);
 
             match command {
-                Command::PairingRequest => self.handle_pairing_request(payload, connections, handle),
-                Command::PairingResponse => self.handle_pairing_response(payload, connections, handle),
-                Command::PairingPublicKey => self.handle_pairing_public_key(payload, connections, handle),
-                Command::PairingConfirm => self.handle_pairing_confirm(payload, connections, handle),
-                Command::PairingRandom => self.handle_pairing_random(payload, connections, handle, storage),
-                Command::PairingDhKeyCheck => self.handle_pairing_dhkey_check(payload, connections, handle, storage),
+                Command::PairingRequest => self.handle_pairing_request(payload, handle),
+                Command::PairingResponse => self.handle_pairing_response(payload, handle),
+                Command::PairingPublicKey => self.handle_pairing_public_key(payload, handle),
+                Command::PairingConfirm => self.handle_pairing_confirm(payload, handle),
+                Command::PairingRandom => self.handle_pairing_random(payload, handle, storage),
+                Command::PairingDhKeyCheck => self.handle_pairing_dhkey_check(payload, handle, storage),
                 Command::PairingFailed => self.handle_pairing_failed(payload),
                 Command::IdentityInformation => self.handle_identity_information(payload, handle),
                 Command::IdentityAddressInformation => self.handle_identity_address_information(payload),
@@ -531,11 +535,11 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
 
             // Cease sending security manager messages on timeout
             if *error != Error::Timeout {
-                let mut packet = self.prepare_packet(Command::PairingFailed, connections)?;
+                let mut packet = self.prepare_packet(Command::PairingFailed);
                 let payload = packet.payload_mut();
                 payload[0] = u8::from(reason);
 
-                match self.try_send_packet(packet, connections, handle) {
+                match self.try_send_packet(packet, handle) {
                     Ok(()) => (),
                     Err(error) => {
                         error!("[security manager] Failed to send pairing failed {:?}", error);
@@ -549,7 +553,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     }
 
     /// Initiate pairing
-    pub fn initiate<P: PacketPool>(&self, connection: &Connection<P>) -> Result<(), Error> {
+    pub async fn initiate<P: PacketPool>(&self, connection: &Connection<'_, P>) -> Result<(), Error> {
         if connection.role() == LeConnRole::Central {
             let peer_identity = connection.peer_identity();
             if let Some(ltk) = self.get_peer_long_term_key(&peer_identity) {
@@ -572,20 +576,13 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                     ..Default::default()
                 };
 
-                let mut packet: TxPacket<P> =
-                    TxPacket::new(P::allocate().ok_or(Error::OutOfMemory)?, Command::PairingRequest)?;
+                let mut packet = TxPacket::new(Command::PairingRequest);
 
                 let payload = packet.payload_mut();
 
                 local_features.encode(payload).map_err(|_| Error::InvalidValue)?;
 
-                match connection.try_send(packet.into_pdu()) {
-                    Ok(()) => (),
-                    Err(error) => {
-                        error!("[security manager] Failed to respond to request {:?}", error);
-                        return Err(error);
-                    }
-                }
+                connection.send(packet.into_pdu::<P>().await).await;
 
                 {
                     let mut pairing_state = self.pairing_state.borrow_mut();
@@ -602,20 +599,13 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
             // Send sequrity request to central
             let auth_req = AuthReq::new(BondingFlag::Bonding);
 
-            let mut packet: TxPacket<P> =
-                TxPacket::new(P::allocate().ok_or(Error::OutOfMemory)?, Command::SecurityRequest)?;
+            let mut packet = TxPacket::new(Command::SecurityRequest);
 
             let response = packet.payload_mut();
 
             response[0] = auth_req.into();
 
-            match connection.try_send(packet.into_pdu()) {
-                Ok(()) => (),
-                Err(error) => {
-                    error!("[security manager] Failed to send security request {:?}", error);
-                    return Err(error);
-                }
-            }
+            connection.send(packet.into_pdu::<P>().await).await;
 
             {
                 let mut pairing_state = self.pairing_state.borrow_mut();
@@ -671,12 +661,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     }
 
     /// Handle pairing request command
-    fn handle_pairing_request<P: PacketPool>(
-        &self,
-        payload: &[u8],
-        connections: &ConnectionManager<P>,
-        handle: ConnHandle,
-    ) -> Result<(), Error> {
+    fn handle_pairing_request(&self, payload: &[u8], handle: ConnHandle) -> Result<(), Error> {
         let peer_features = PairingFeatures::decode(payload).map_err(|_| Error::Security(Reason::InvalidParameters))?;
         {
             let pairing_state = self.pairing_state.borrow();
@@ -708,12 +693,12 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 return Err(Error::InvalidState);
             }
 
-            let mut packet = self.prepare_packet(Command::PairingResponse, connections)?;
+            let mut packet = self.prepare_packet(Command::PairingResponse);
 
             let response = packet.payload_mut();
             local_features.encode(response).map_err(|_| Error::InvalidValue)?;
 
-            match self.try_send_packet(packet, connections, handle) {
+            match self.try_send_packet(packet, handle) {
                 Ok(()) => (),
                 Err(error) => {
                     error!("[security manager] Failed to respond to request {:?}", error);
@@ -736,12 +721,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     }
 
     /// Handle pairing response command
-    fn handle_pairing_response<P: PacketPool>(
-        &self,
-        payload: &[u8],
-        connections: &ConnectionManager<P>,
-        handle: ConnHandle,
-    ) -> Result<(), Error> {
+    fn handle_pairing_response(&self, payload: &[u8], handle: ConnHandle) -> Result<(), Error> {
         let peer_features = PairingFeatures::decode(payload).map_err(|_| Error::Security(Reason::InvalidParameters))?;
         {
             let pairing_state = self.pairing_state.borrow();
@@ -756,7 +736,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         let secret_key = SecretKey::new(rng);
         let public_key = secret_key.public_key();
 
-        let mut packet = self.prepare_packet(Command::PairingPublicKey, connections)?;
+        let mut packet = self.prepare_packet(Command::PairingPublicKey);
 
         let response = packet.payload_mut();
 
@@ -770,7 +750,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         response[..x.len()].copy_from_slice(&x);
         response[x.len()..y.len() + x.len()].copy_from_slice(&y);
 
-        match self.try_send_packet(packet, connections, handle) {
+        match self.try_send_packet(packet, handle) {
             Ok(()) => (),
             Err(error) => {
                 error!("[security manager] Failed to respond to request {:?}", error);
@@ -790,12 +770,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     }
 
     /// Handle pairing public key command
-    fn handle_pairing_public_key<P: PacketPool>(
-        &self,
-        payload: &[u8],
-        connections: &ConnectionManager<P>,
-        handle: ConnHandle,
-    ) -> Result<(), Error> {
+    fn handle_pairing_public_key(&self, payload: &[u8], handle: ConnHandle) -> Result<(), Error> {
         let role = {
             let pairing_state = self.pairing_state.borrow();
             if (pairing_state.role == LeConnRole::Central && pairing_state.state == PairingState::CentralPublicKey)
@@ -849,14 +824,14 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
             x.reverse();
             y.reverse();
 
-            let mut packet = self.prepare_packet(Command::PairingPublicKey, connections)?;
+            let mut packet = self.prepare_packet(Command::PairingPublicKey);
 
             let response = packet.payload_mut();
 
             response[..x.len()].copy_from_slice(&x);
             response[x.len()..y.len() + x.len()].copy_from_slice(&y);
 
-            match self.try_send_packet(packet, connections, handle) {
+            match self.try_send_packet(packet, handle) {
                 Ok(()) => (),
                 Err(error) => {
                     error!("[security manager] Failed to send public key {:?}", error);
@@ -874,13 +849,13 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
             let local_nonce = Nonce::new(rng);
             let confirm = local_nonce.f4(public_key.x(), peer_public_key.x(), 0);
 
-            let mut packet = self.prepare_packet(Command::PairingConfirm, connections)?;
+            let mut packet = self.prepare_packet(Command::PairingConfirm);
 
             let response = packet.payload_mut();
 
             response.copy_from_slice(&confirm.0.to_le_bytes());
 
-            match self.try_send_packet(packet, connections, handle) {
+            match self.try_send_packet(packet, handle) {
                 Ok(()) => (),
                 Err(error) => {
                     error!("[security manager] Failed to send confirm {:?}", error);
@@ -902,12 +877,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     }
 
     /// Handle pairing confirm command
-    fn handle_pairing_confirm<P: PacketPool>(
-        &self,
-        payload: &[u8],
-        connections: &ConnectionManager<P>,
-        handle: ConnHandle,
-    ) -> Result<(), Error> {
+    fn handle_pairing_confirm(&self, payload: &[u8], handle: ConnHandle) -> Result<(), Error> {
         let confirm = Confirm(u128::from_le_bytes(
             payload.try_into().map_err(|_| Error::InvalidValue)?,
         ));
@@ -922,13 +892,13 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 }
             }?;
 
-            let mut packet = self.prepare_packet(Command::PairingRandom, connections)?;
+            let mut packet = self.prepare_packet(Command::PairingRandom);
 
             let response = packet.payload_mut();
 
             response.copy_from_slice(&local_nonce.0.to_le_bytes());
 
-            match self.try_send_packet(packet, connections, handle) {
+            match self.try_send_packet(packet, handle) {
                 Ok(()) => (),
                 Err(error) => {
                     error!("[security manager] Failed to send random {:?}", error);
@@ -947,12 +917,11 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     }
 
     /// Handle pairing random command
-    fn handle_pairing_random<P: PacketPool>(
+    fn handle_pairing_random<T>(
         &self,
         payload: &[u8],
-        connections: &ConnectionManager<P>,
         handle: ConnHandle,
-        storage: &ConnectionStorage<P::Packet>,
+        storage: &ConnectionStorage<T>,
     ) -> Result<(), Error> {
         let peer_nonce = Nonce(u128::from_le_bytes(
             payload
@@ -975,13 +944,13 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 return Err(Error::Security(Reason::ConfirmValueFailed));
             }
         } else {
-            let mut packet = self.prepare_packet(Command::PairingRandom, connections)?;
+            let mut packet = self.prepare_packet(Command::PairingRandom);
 
             let response = packet.payload_mut();
 
             response.copy_from_slice(&local_nonce.0.to_le_bytes());
 
-            match self.try_send_packet(packet, connections, handle) {
+            match self.try_send_packet(packet, handle) {
                 Ok(()) => (),
                 Err(error) => {
                     error!("[security manager] Failed to send random {:?}", error);
@@ -1055,13 +1024,13 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         }
         if role == LeConnRole::Central {
             // Send DH check
-            let mut packet = self.prepare_packet(Command::PairingDhKeyCheck, connections)?;
+            let mut packet = self.prepare_packet(Command::PairingDhKeyCheck);
 
             let response = packet.payload_mut();
 
             response.copy_from_slice(&local_check.0.to_le_bytes());
 
-            match self.try_send_packet(packet, connections, handle) {
+            match self.try_send_packet(packet, handle) {
                 Ok(()) => (),
                 Err(error) => {
                     error!("[security manager] Failed to send DH check {:?}", error);
@@ -1074,12 +1043,11 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     }
 
     /// Handle pairing DH key check
-    fn handle_pairing_dhkey_check<P: PacketPool>(
+    fn handle_pairing_dhkey_check<T>(
         &self,
         payload: &[u8],
-        connections: &ConnectionManager<P>,
         handle: ConnHandle,
-        storage: &ConnectionStorage<P::Packet>,
+        storage: &ConnectionStorage<T>,
     ) -> Result<(), Error> {
         let (role, local_check) = {
             let pairing_state = self.pairing_state.borrow();
@@ -1124,13 +1092,13 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
             let bond_info = self.store_pairing()?;
             self.try_send_event(SecurityEventData::EnableEncryption(handle, bond_info))?;
         } else {
-            let mut packet = self.prepare_packet(Command::PairingDhKeyCheck, connections)?;
+            let mut packet = self.prepare_packet(Command::PairingDhKeyCheck);
 
             let response = packet.payload_mut();
 
             response.copy_from_slice(&local_check.0.to_le_bytes());
 
-            match self.try_send_packet(packet, connections, handle) {
+            match self.try_send_packet(packet, handle) {
                 Ok(()) => (),
                 Err(error) => {
                     error!("[security manager] Failed to send DH check {:?}", error);
@@ -1274,25 +1242,17 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     }
 
     /// Prepare a packet for sending
-    fn prepare_packet<P: PacketPool>(
-        &self,
-        command: Command,
-        connections: &ConnectionManager<P>,
-    ) -> Result<TxPacket<P>, Error> {
-        let packet = P::allocate().ok_or(Error::OutOfMemory)?;
-        TxPacket::new(packet, command)
+    fn prepare_packet(&self, command: Command) -> TxPacket {
+        TxPacket::new(command)
     }
 
     /// Send a packet
-    fn try_send_packet<P: PacketPool>(
-        &self,
-        packet: TxPacket<P>,
-        connections: &ConnectionManager<P>,
-        handle: ConnHandle,
-    ) -> Result<(), Error> {
+    ///
+    /// The packet is queued for the control runner, which copies it into a packet from the pool.
+    fn try_send_packet(&self, packet: TxPacket, handle: ConnHandle) -> Result<(), Error> {
         let len = packet.total_size();
         trace!("[security manager] Send {} {}", packet.command, len);
-        connections.try_outbound(handle, packet.into_pdu())
+        self.try_send_event(SecurityEventData::SendPacket(handle, packet))
     }
 
     /// Send a packet
@@ -1,12 +1,23 @@
 use bt_hci::{FixedSizeValue, WriteHci};
+use heapless::Vec;
 
-use crate::codec::Error;
+use crate::codec::{Decode, Encode, Error, Type};
+use crate::cursor::{ReadCursor, WriteCursor};
 
 pub(crate) const L2CAP_CID_ATT: u16 = 0x0004;
 pub(crate) const L2CAP_CID_LE_U_SIGNAL: u16 = 0x0005;
 pub(crate) const L2CAP_CID_LE_U_SECURITY_MANAGER: u16 = 0x0006;
+pub(crate) const L2CAP_CID_FIXED_START: u16 = 0x0020;
+pub(crate) const L2CAP_CID_FIXED_END: u16 = 0x003E;
 pub(crate) const L2CAP_CID_DYN_START: u16 = 0x0040;
 
+/// Maximum number of channels in a single enhanced credit based connection or reconfigure request.
+pub const L2CAP_ECFC_MAX_CHANNELS: usize = 5;
+/// Minimum MTU and MPS allowed for enhanced credit based channels.
+pub(crate) const L2CAP_ECFC_MIN_MTU: u16 = 64;
+/// Largest signaling command accepted on the LE signaling channel (MTU_sig).
+pub(crate) const L2CAP_LE_SIG_MTU: usize = 23;
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -52,6 +63,11 @@ pub trait L2capSignal: WriteHci + FixedSizeValue + defmt::Format {
     fn code() -> L2capSignalCode;
 }
 
+/// Signal with a variable length payload, encoded using the host codec.
+pub trait L2capVarSignal: Encode {
+    fn code() -> L2capSignalCode;
+}
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum L2capSignalCode {
@@ -189,19 +205,193 @@ impl L2capSignal for LeCreditFlowInd {
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u16)]
+pub enum CommandRejectReason {
+    NotUnderstood = 0x0000,
+    MtuExceeded = 0x0001,
+    InvalidCid = 0x0002,
+}
+
+#[derive(Debug, Clone)]
 pub struct CommandRejectRes {
     pub reason: u16,
-    // TODO: Optional fields pub data: u16,
+    pub data: Vec<u8, 4>,
+}
+
+impl CommandRejectRes {
+    pub fn not_understood() -> Self {
+        Self {
+            reason: CommandRejectReason::NotUnderstood as u16,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn mtu_exceeded(mtu: u16) -> Self {
+        Self {
+            reason: CommandRejectReason::MtuExceeded as u16,
+            data: unwrap!(Vec::from_slice(&mtu.to_le_bytes())),
+        }
+    }
+
+    /// Reject a request for a channel, identified by the local and the remote CID of the rejecting side.
+    pub fn invalid_cid(local_cid: u16, remote_cid: u16) -> Self {
+        let mut data = Vec::new();
+        let _ = data.extend_from_slice(&local_cid.to_le_bytes());
+        let _ = data.extend_from_slice(&remote_cid.to_le_bytes());
+        Self {
+            reason: CommandRejectReason::InvalidCid as u16,
+            data,
+        }
+    }
+}
+
+impl Type for CommandRejectRes {
+    fn size(&self) -> usize {
+        2 + self.data.len()
+    }
+}
+
+impl Encode for CommandRejectRes {
+    fn encode(&self, dest: &mut [u8]) -> Result<(), Error> {
+        let mut w = WriteCursor::new(dest);
+        w.write(self.reason)?;
+        w.append(&self.data)?;
+        Ok(())
+    }
+}
+
+impl Decode<'_> for CommandRejectRes {
+    fn decode(src: &[u8]) -> Result<Self, Error> {
+        let mut r = ReadCursor::new(src);
+        let reason = r.read()?;
+        // Unknown reasons may carry data we don't know about, which is not needed.
+        let data = Vec::from_slice(r.remaining()).unwrap_or_default();
+        Ok(Self { reason, data })
+    }
+}
+
+impl L2capVarSignal for CommandRejectRes {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::CommandRejectRes
+    }
+}
+
+/// Largest echo payload which fits in a signaling command.
+pub(crate) const L2CAP_ECHO_MAX_DATA: usize = L2CAP_LE_SIG_MTU - 4;
+
+#[derive(Debug, Clone)]
+pub struct EchoRes {
+    pub data: Vec<u8, L2CAP_ECHO_MAX_DATA>,
+}
+
+impl Type for EchoRes {
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl Encode for EchoRes {
+    fn encode(&self, dest: &mut [u8]) -> Result<(), Error> {
+        let mut w = WriteCursor::new(dest);
+        w.append(&self.data)?;
+        Ok(())
+    }
+}
+
+impl L2capVarSignal for EchoRes {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::EchoRes
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InformationReq {
+    pub info_type: u16,
 }
 
-unsafe impl FixedSizeValue for CommandRejectRes {
+unsafe impl FixedSizeValue for InformationReq {
     fn is_valid(data: &[u8]) -> bool {
         true
     }
 }
 
+impl L2capSignal for InformationReq {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::InformationReq
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u16)]
+pub enum InformationType {
+    ConnectionlessMtu = 0x0001,
+    ExtendedFeatures = 0x0002,
+    FixedChannels = 0x0003,
+}
+
+/// Extended features supported by the host: fixed channels and enhanced credit based flow control.
+pub(crate) const L2CAP_EXTENDED_FEATURES: u32 = (1 << 7) | (1 << 10);
+
+/// Fixed channels supported by the host: ATT, LE signaling and the security manager.
+pub(crate) const L2CAP_FIXED_CHANNELS: u64 =
+    (1 << L2CAP_CID_ATT) | (1 << L2CAP_CID_LE_U_SIGNAL) | (1 << L2CAP_CID_LE_U_SECURITY_MANAGER);
+
+#[derive(Debug, Clone)]
+pub struct InformationRes {
+    pub info_type: u16,
+    pub result: u16,
+    pub data: Vec<u8, 8>,
+}
+
+impl InformationRes {
+    pub fn new(info_type: u16) -> Self {
+        let mut data = Vec::new();
+        let result = match info_type {
+            t if t == InformationType::ExtendedFeatures as u16 => {
+                let _ = data.extend_from_slice(&L2CAP_EXTENDED_FEATURES.to_le_bytes());
+                0x0000
+            }
+            t if t == InformationType::FixedChannels as u16 => {
+                let _ = data.extend_from_slice(&L2CAP_FIXED_CHANNELS.to_le_bytes());
+                0x0000
+            }
+            // Not supported
+            _ => 0x0001,
+        };
+        Self {
+            info_type,
+            result,
+            data,
+        }
+    }
+}
+
+impl Type for InformationRes {
+    fn size(&self) -> usize {
+        4 + self.data.len()
+    }
+}
+
+impl Encode for InformationRes {
+    fn encode(&self, dest: &mut [u8]) -> Result<(), Error> {
+        let mut w = WriteCursor::new(dest);
+        w.write(self.info_type)?;
+        w.write(self.result)?;
+        w.append(&self.data)?;
+        Ok(())
+    }
+}
+
+impl L2capVarSignal for InformationRes {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::InformationRes
+    }
+}
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -264,6 +454,14 @@ impl L2capSignal for ConnParamUpdateReq {
     }
 }
 
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u16)]
+pub enum ConnParamUpdateResultCode {
+    Accepted = 0x0000,
+    Rejected = 0x0001,
+}
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -282,3 +480,252 @@ impl L2capSignal for ConnParamUpdateRes {
         L2capSignalCode::ConnParamUpdateRes
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct CreditConnReq {
+    pub spsm: u16,
+    pub mtu: u16,
+    pub mps: u16,
+    pub credits: u16,
+    pub scids: Vec<u16, L2CAP_ECFC_MAX_CHANNELS>,
+}
+
+impl Type for CreditConnReq {
+    fn size(&self) -> usize {
+        8 + 2 * self.scids.len()
+    }
+}
+
+impl Encode for CreditConnReq {
+    fn encode(&self, dest: &mut [u8]) -> Result<(), Error> {
+        let mut w = WriteCursor::new(dest);
+        w.write(self.spsm)?;
+        w.write(self.mtu)?;
+        w.write(self.mps)?;
+        w.write(self.credits)?;
+        for scid in self.scids.iter() {
+            w.write(*scid)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decode<'_> for CreditConnReq {
+    fn decode(src: &[u8]) -> Result<Self, Error> {
+        let mut r = ReadCursor::new(src);
+        Ok(Self {
+            spsm: r.read()?,
+            mtu: r.read()?,
+            mps: r.read()?,
+            credits: r.read()?,
+            scids: decode_cids(r)?,
+        })
+    }
+}
+
+impl L2capVarSignal for CreditConnReq {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::CreditConnReq
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CreditConnRes {
+    pub mtu: u16,
+    pub mps: u16,
+    pub credits: u16,
+    pub result: u16,
+    pub dcids: Vec<u16, L2CAP_ECFC_MAX_CHANNELS>,
+}
+
+impl Type for CreditConnRes {
+    fn size(&self) -> usize {
+        8 + 2 * self.dcids.len()
+    }
+}
+
+impl Encode for CreditConnRes {
+    fn encode(&self, dest: &mut [u8]) -> Result<(), Error> {
+        let mut w = WriteCursor::new(dest);
+        w.write(self.mtu)?;
+        w.write(self.mps)?;
+        w.write(self.credits)?;
+        w.write(self.result)?;
+        for dcid in self.dcids.iter() {
+            w.write(*dcid)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decode<'_> for CreditConnRes {
+    fn decode(src: &[u8]) -> Result<Self, Error> {
+        let mut r = ReadCursor::new(src);
+        Ok(Self {
+            mtu: r.read()?,
+            mps: r.read()?,
+            credits: r.read()?,
+            result: r.read()?,
+            dcids: decode_cids(r)?,
+        })
+    }
+}
+
+impl L2capVarSignal for CreditConnRes {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::CreditConnRes
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CreditConnReconfigReq {
+    pub mtu: u16,
+    pub mps: u16,
+    pub dcids: Vec<u16, L2CAP_ECFC_MAX_CHANNELS>,
+}
+
+impl Type for CreditConnReconfigReq {
+    fn size(&self) -> usize {
+        4 + 2 * self.dcids.len()
+    }
+}
+
+impl Encode for CreditConnReconfigReq {
+    fn encode(&self, dest: &mut [u8]) -> Result<(), Error> {
+        let mut w = WriteCursor::new(dest);
+        w.write(self.mtu)?;
+        w.write(self.mps)?;
+        for dcid in self.dcids.iter() {
+            w.write(*dcid)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decode<'_> for CreditConnReconfigReq {
+    fn decode(src: &[u8]) -> Result<Self, Error> {
+        let mut r = ReadCursor::new(src);
+        Ok(Self {
+            mtu: r.read()?,
+            mps: r.read()?,
+            dcids: decode_cids(r)?,
+        })
+    }
+}
+
+impl L2capVarSignal for CreditConnReconfigReq {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::CreditConnReconfigReq
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CommandRejectRes {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "reason = {}, data = {:?}", self.reason, self.data.as_slice());
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for EchoRes {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "data = {:?}", self.data.as_slice());
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for InformationRes {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "info_type = {}, result = {}, data = {:?}",
+            self.info_type,
+            self.result,
+            self.data.as_slice()
+        );
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CreditConnReq {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "spsm = {}, mtu = {}, mps = {}, credits = {}, scids = {:?}",
+            self.spsm,
+            self.mtu,
+            self.mps,
+            self.credits,
+            self.scids.as_slice()
+        );
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CreditConnRes {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "mtu = {}, mps = {}, credits = {}, result = {}, dcids = {:?}",
+            self.mtu,
+            self.mps,
+            self.credits,
+            self.result,
+            self.dcids.as_slice()
+        );
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CreditConnReconfigReq {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "mtu = {}, mps = {}, dcids = {:?}",
+            self.mtu,
+            self.mps,
+            self.dcids.as_slice()
+        );
+    }
+}
+
+fn decode_cids(mut r: ReadCursor<'_>) -> Result<Vec<u16, L2CAP_ECFC_MAX_CHANNELS>, Error> {
+    if r.available() == 0 || r.available() % 2 != 0 {
+        return Err(Error::InvalidValue);
+    }
+    let mut cids = Vec::new();
+    while r.available() > 0 {
+        cids.push(r.read()?).map_err(|_| Error::InvalidValue)?;
+    }
+    Ok(cids)
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u16)]
+pub enum CreditConnReconfigResultCode {
+    Success = 0x0000,
+    MtuReductionNotAllowed = 0x0001,
+    MpsReductionNotAllowed = 0x0002,
+    InvalidDestinationCid = 0x0003,
+    UnacceptableParameters = 0x0004,
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CreditConnReconfigRes {
+    pub result: u16,
+}
+
+unsafe impl FixedSizeValue for CreditConnReconfigRes {
+    fn is_valid(data: &[u8]) -> bool {
+        true
+    }
+}
+
+impl L2capSignal for CreditConnReconfigRes {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::CreditConnReconfigRes
+    }
+}
@@ -161,6 +161,8 @@ async fn gatt_client_server() {
 
                 println!("[central] creating gatt client");
                 let client = GattClient::<common::Controller, DefaultPacketPool, 10>::new(&stack, &conn).await.unwrap();
+                assert!(client.mtu() > 23);
+                assert_eq!(client.mtu(), conn.att_mtu());
 
                 select! {
                     r = async {
@@ -177,6 +179,19 @@ async fn gatt_client_server() {
                         println!("[central] service discovered successfully");
                         let c: Characteristic<u8> = client.characteristic_by_uuid(&service, &VALUE_UUID).await.unwrap();
 
+                        let db: GattDatabase<8, 16, 16> = client.discover().await.unwrap();
+                        let discovered = db.services().find(|s| *s.uuid() == SERVICE_UUID).unwrap();
+                        let value = db.characteristics(discovered).find(|c| c.uuid == VALUE_UUID).unwrap();
+                        assert_eq!(db.characteristic::<u8>(value), c);
+                        println!("[central] database discovered successfully");
+
+                        // The server has no Database Hash, so the cache is never used
+                        let mut cache = MemoryCache(None);
+                        let cached: GattDatabase<8, 16, 16> = client.discover_cached(&mut cache).await.unwrap();
+                        assert!(cached.hash().is_none());
+                        assert!(cache.0.is_none());
+                        assert!(cached.services().eq(db.services()));
+
                         let mut data = [0; 1];
                         client.read_characteristic(&c, &mut data[..]).await.unwrap();
                         println!("[central] read value: {}", data[0]);
@@ -223,3 +238,19 @@ async fn gatt_client_server() {
         }
     }
 }
+
+struct MemoryCache(Option<GattDatabase<8, 16, 16>>);
+
+impl GattCache<8, 16, 16> for MemoryCache {
+    fn load(&mut self, _peer: &Identity) -> Option<GattDatabase<8, 16, 16>> {
+        self.0.clone()
+    }
+
+    fn store(&mut self, _peer: &Identity, database: &GattDatabase<8, 16, 16>) {
+        self.0 = Some(database.clone());
+    }
+
+    fn remove(&mut self, _peer: &Identity) {
+        self.0 = None;
+    }
+}
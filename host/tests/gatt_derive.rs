@@ -16,8 +16,11 @@ const SERVICE_UUID: Uuid = Uuid::new_long([
 const VALUE_UUID: Uuid = Uuid::new_long([
     0x00, 0x00, 0x10, 0x01, 0xb0, 0xcd, 0x11, 0xec, 0x87, 0x1f, 0xd4, 0x5d, 0xdf, 0x13, 0x88, 0x40,
 ]);
+const THIRD_UUID: Uuid = Uuid::new_long([
+    0x00, 0x00, 0x10, 0x01, 0xb0, 0xcd, 0x11, 0xec, 0x87, 0x1f, 0xd4, 0x5d, 0xdf, 0x15, 0x88, 0x40,
+]);
 
-#[gatt_server(connections_max = CONNECTIONS_MAX, mutex_type = NoopRawMutex, attribute_table_size = 31)]
+#[gatt_server(connections_max = CONNECTIONS_MAX, mutex_type = NoopRawMutex, attribute_table_size = 35)]
 struct Server {
     service: CustomService,
     bas: BatteryService,
@@ -47,6 +50,7 @@ struct CustomService {
     pub fifth: heapless::Vec<u8, 33>,
 }
 
+#[gatt_client_service]
 #[gatt_service(uuid = service::BATTERY)]
 struct BatteryService {
     /// Battery Level
@@ -56,6 +60,72 @@ struct BatteryService {
     level: u8,
 }
 
+/// Client side definition of `CustomService`, the struct itself is kept by the macro.
+#[gatt_client_service(uuid = "408813df-5dd4-1f87-ec11-cdb000100000")]
+struct CustomServiceDefinition {
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001100000", read, write, notify)]
+    value: u8,
+}
+
+#[cfg(feature = "eatt")]
+const EATT_SERVICE_UUID: Uuid = Uuid::new_long([
+    0x00, 0x00, 0x20, 0x00, 0xb0, 0xcd, 0x11, 0xec, 0x87, 0x1f, 0xd4, 0x5d, 0xdf, 0x13, 0x88, 0x40,
+]);
+#[cfg(feature = "eatt")]
+const EATT_VALUE_UUID: Uuid = Uuid::new_long([
+    0x00, 0x00, 0x20, 0x01, 0xb0, 0xcd, 0x11, 0xec, 0x87, 0x1f, 0xd4, 0x5d, 0xdf, 0x13, 0x88, 0x40,
+]);
+
+// A second server in the same module would clash with the constants generated for `Server`.
+#[cfg(feature = "eatt")]
+mod eatt {
+    use super::*;
+
+    #[gatt_server(connections_max = CONNECTIONS_MAX, mutex_type = NoopRawMutex, attribute_table_size = 30)]
+    pub struct EattServer {
+        pub service: EattService,
+    }
+
+    #[gatt_service(uuid = "408813df-5dd4-1f87-ec11-cdb000200000")]
+    pub struct EattService {
+        #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001200000", value = 42, read, write, notify)]
+        pub value: u8,
+    }
+}
+#[cfg(feature = "eatt")]
+use eatt::EattServer;
+
+/// Serve an Enhanced ATT bearer, notifying the value on the bearer before answering each read of it.
+#[cfg(feature = "eatt")]
+async fn serve_eatt_bearer<'s>(
+    server: &EattServer<'_>,
+    conn: &GattConnection<'s, '_, DefaultPacketPool>,
+    stack: &'s Stack<'s, common::Controller, DefaultPacketPool>,
+    bearer: &mut L2capChannel<'s, DefaultPacketPool>,
+) {
+    let value = &server.service.value;
+    while let Some(event) = conn.next_eatt(stack, bearer).await.unwrap() {
+        match event.event() {
+            GattEvent::Write(write) if write.handle() == value.handle => {
+                assert_eq!(write.data(), &[7]);
+                println!("[peripheral] value written on eatt bearer");
+            }
+            GattEvent::Read(read) if read.handle() == value.handle => {
+                let current = server.get(value).unwrap();
+                value.notify_eatt(conn, stack, bearer, &current).await.unwrap();
+            }
+            _ => {}
+        }
+        event.accept().await.unwrap();
+    }
+}
+
+#[test]
+fn gatt_client_service_keeps_struct() {
+    let definition = CustomServiceDefinition { value: 42 };
+    assert_eq!(definition.value, 42);
+}
+
 #[tokio::test]
 async fn gatt_client_server() {
     let _ = env_logger::try_init();
@@ -228,6 +298,211 @@ async fn gatt_client_server() {
                             panic!();
                         }
                         println!("[central] write done");
+
+                        let third: Characteristic<[u8; 2]> = client.characteristic_by_uuid(&service, &THIRD_UUID).await.unwrap();
+                        let mut value = [0; 1];
+                        client.read_characteristic(&c, &mut value[..]).await.unwrap();
+                        let (first, second): (u8, [u8; 2]) = client.read_characteristics((&c, &third)).await.unwrap();
+                        assert_eq!(first, value[0]);
+                        assert_eq!(second, [0, 1]);
+                        let mut values = [0; 3];
+                        let lengths: heapless::Vec<usize, 2> = client.read_multiple_variable(&[third.handle, c.handle], &mut values).await.unwrap();
+                        assert_eq!(&lengths[..], &[2, 1]);
+                        assert_eq!(values, [0, 1, first]);
+                        println!("[central] read multiple done");
+
+                        let battery = BatteryServiceClient::discover(&client).await.unwrap();
+                        assert_eq!(battery.read_level().await.unwrap(), 10);
+                        println!("[central] read battery level through client proxy");
+                        Ok(())
+                    } => {
+                        r
+                    }
+                }
+            } => {
+                r
+            }
+        }
+    });
+
+    match tokio::time::timeout(Duration::from_secs(30), local).await {
+        Ok(_) => match tokio::join!(central, peripheral) {
+            (Err(e1), Err(e2)) => {
+                println!("Central error: {:?}", e1);
+                println!("Peripheral error: {:?}", e2);
+                panic!();
+            }
+            (Err(e), _) => {
+                println!("Central error: {:?}", e);
+                panic!();
+            }
+            (_, Err(e)) => {
+                println!("Peripheral error: {:?}", e);
+                panic!();
+            }
+            _ => {
+                println!("Test completed successfully");
+            }
+        },
+        Err(e) => {
+            println!("Test timed out: {:?}", e);
+            panic!();
+        }
+    }
+}
+
+#[cfg(feature = "eatt")]
+#[tokio::test]
+async fn gatt_client_server_eatt() {
+    let _ = env_logger::try_init();
+    let adapters = common::find_controllers();
+    let peripheral = adapters[0].clone();
+    let central = adapters[1].clone();
+
+    let name = std::env::var("DEVICE_NAME").unwrap_or("TrouBLE".into());
+
+    let peripheral_address: Address = Address::random([0xfe, 0x9f, 0x1a, 0x05, 0xe4, 0xff]);
+
+    let local = tokio::task::LocalSet::new();
+
+    // Spawn peripheral
+    let peripheral = local.spawn_local(async move {
+        let controller_peripheral = common::create_controller(&peripheral).await;
+
+        let mut resources: HostResources<DefaultPacketPool, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX> = HostResources::new();
+        let stack = trouble_host::new(controller_peripheral, &mut resources)
+            .set_random_address(peripheral_address);
+        let Host {
+            mut peripheral,
+            mut runner,
+            ..
+        } = stack.build();
+
+        let gap = GapConfig::Peripheral(PeripheralConfig {
+            name: &name,
+            appearance: &appearance::power_device::GENERIC_POWER_DEVICE,
+        });
+        let server: EattServer = EattServer::new_with_config(
+            gap,
+        ).unwrap();
+
+        select! {
+            r = runner.run() => {
+                r
+            }
+            r = async {
+                let mut adv_data = [0; 31];
+                let adv_data_len = AdStructure::encode_slice(
+                    &[AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED)],
+                    &mut adv_data[..],
+                ).unwrap();
+
+                println!("[peripheral] advertising");
+                let acceptor = peripheral.advertise(&Default::default(), Advertisement::ConnectableScannableUndirected {
+                    adv_data: &adv_data[..adv_data_len],
+                    scan_data: &[],
+                }).await?;
+                let conn = acceptor.accept().await?.with_attribute_server(&server)?;
+                println!("[peripheral] connected");
+
+                select! {
+                    _ = async {
+                        let mut bearers = conn.accept_eatt(&stack).await.unwrap();
+                        assert_eq!(bearers.len(), 2);
+                        println!("[peripheral] accepted {} eatt bearers", bearers.len());
+                        let (first, second) = bearers.split_at_mut(1);
+                        tokio::join!(
+                            serve_eatt_bearer(&server, &conn, &stack, &mut first[0]),
+                            serve_eatt_bearer(&server, &conn, &stack, &mut second[0]),
+                        );
+                    } => {}
+                    _ = async {
+                        loop {
+                            if let GattConnectionEvent::Disconnected { reason } = conn.next().await {
+                                println!("Disconnected: {:?}", reason);
+                                break;
+                            }
+                        }
+                    } => {}
+                }
+                println!("[peripheral] done");
+                Ok(())
+            } => {
+                r
+            }
+        }
+    });
+
+    // Spawn central
+    let central = local.spawn_local(async move {
+        let controller_central = common::create_controller(&central).await;
+        let mut resources: HostResources<DefaultPacketPool, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX> = HostResources::new();
+        let stack = trouble_host::new(controller_central, &mut resources);
+        let Host {
+            mut central,
+            mut runner,
+            ..
+        } = stack.build();
+
+        select! {
+            r = runner.run() => {
+                r
+            }
+            r = async {
+                let config = ConnectConfig {
+                    connect_params: Default::default(),
+                    scan_config: ScanConfig {
+                        active: true,
+                        filter_accept_list: &[(peripheral_address.kind, &peripheral_address.addr)],
+                        ..Default::default()
+                    },
+                };
+
+                println!("[central] connecting");
+                let conn = central.connect(&config).await.unwrap();
+                println!("[central] connected");
+                tokio::time::sleep(Duration::from_secs(5)).await;
+
+                let client = GattClient::<common::Controller, DefaultPacketPool, 10>::new(&stack, &conn).await.unwrap();
+
+                select! {
+                    r = async {
+                        client.task().await
+                    } => {
+                        r
+                    }
+                    r = async {
+                        let opened = client.open_eatt(2).await.unwrap();
+                        assert_eq!(opened, 2);
+                        assert_eq!(client.eatt_bearers(), 2);
+                        println!("[central] opened {} eatt bearers", opened);
+
+                        let services = client.services_by_uuid(&EATT_SERVICE_UUID).await.unwrap();
+                        let service = services.first().unwrap().clone();
+                        let c: Characteristic<u8> = client.characteristic_by_uuid(&service, &EATT_VALUE_UUID).await.unwrap();
+                        let mut listener = client.subscribe(&c, false).await.unwrap();
+
+                        // The write produces an event on the peripheral.
+                        client.write_characteristic(&c, &[7]).await.unwrap();
+
+                        // Both reads are in flight at the same time, one on each bearer.
+                        let mut first = [0; 1];
+                        let mut second = [0; 1];
+                        let (r1, r2) = tokio::join!(
+                            client.read_characteristic(&c, &mut first[..]),
+                            client.read_characteristic(&c, &mut second[..]),
+                        );
+                        r1.unwrap();
+                        r2.unwrap();
+                        assert_eq!(first, [7]);
+                        assert_eq!(second, [7]);
+                        println!("[central] parallel reads done");
+
+                        // Each read was preceded by a notification on its bearer.
+                        let notification = listener.next().await;
+                        assert_eq!(notification.handle(), c.handle);
+                        assert_eq!(notification.as_ref(), &[7]);
+                        println!("[central] notified on eatt bearer");
                         Ok(())
                     } => {
                         r
@@ -11,12 +11,70 @@ struct CustomService {
     long_uuid: f32,
     #[characteristic(uuid = "2a38", read, notify)]
     notify: [u8; 8],
+    #[characteristic(uuid = "2a39", read, write, provider = &PROVIDER)]
+    provided: u32,
+    #[characteristic(uuid = "2a6e", read, write, reliable_write, user_description = "Temperature", writable_auxiliaries, valid_range = (-40, 85))]
+    #[descriptor(uuid = "2b20", read, write, value = [0u8; 4], capacity = 8)]
+    temperature: i16,
+    #[characteristic(uuid = "2a6f", read, presentation_format = [PresentationFormat::new(0x06, -2, 0x27ad, 0x01, 0), PresentationFormat::new(0x06, 0, 0x2728, 0x01, 0)])]
+    humidity: u16,
+    #[characteristic(uuid = "2a9f", read, write, value = 7, per_connection)]
+    session: u8,
+    #[characteristic(uuid = "2a9d", write, on_write = check_control_point)]
+    control_point: u8,
     non_characteristic_field: u8,
 }
 
+enum ControlPointError {
+    OpCodeNotSupported,
+}
+
+impl From<ControlPointError> for AttErrorCode {
+    fn from(error: ControlPointError) -> Self {
+        match error {
+            ControlPointError::OpCodeNotSupported => AttErrorCode::application(0x80).unwrap(),
+        }
+    }
+}
+
+fn check_control_point(_ctx: &ConnectionContext, _offset: usize, data: &[u8]) -> Result<(), ControlPointError> {
+    match data {
+        [0x01] => Ok(()),
+        _ => Err(ControlPointError::OpCodeNotSupported),
+    }
+}
+
+struct Provider;
+
+impl AttributeProvider for Provider {
+    fn read(&self, _ctx: &ConnectionContext, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
+        let value = 42u32.to_le_bytes();
+        if offset > value.len() {
+            return Err(AttErrorCode::INVALID_OFFSET);
+        }
+        let len = data.len().min(value.len() - offset);
+        data[..len].copy_from_slice(&value[offset..offset + len]);
+        Ok(len)
+    }
+}
+
+static PROVIDER: Provider = Provider;
+
+#[gatt_service(uuid = "180f", secondary)]
+struct SecondaryService {
+    #[characteristic(uuid = "2a19", read)]
+    level: u8,
+}
+
+#[gatt_service(uuid = "7e702cf1-b1df-42a1-bb5f-6a1028c793b0")]
+struct IncludingService {
+    #[characteristic(uuid = "2a3d", read)]
+    value: u8,
+}
+
 #[tokio::test]
 async fn gatt_service_derive() {
-    let mut table: AttributeTable<NoopRawMutex, 10> = AttributeTable::new();
+    let mut table: AttributeTable<NoopRawMutex, 27> = AttributeTable::new();
     let service = CustomService::new(&mut table);
 
     // Check all fields of service have been generated and are accessible
@@ -25,4 +83,46 @@ async fn gatt_service_derive() {
     let _characteristic_short_uuid = service.short_uuid;
     let _characteristic_long_uuid = service.long_uuid;
     let _notify = service.notify;
+    let _provided = service.provided;
+    let _temperature = service.temperature;
+    let _humidity = service.humidity;
+    let _control_point = service.control_point;
+
+    // Without a connection the initial value of a per-connection characteristic is used
+    assert_eq!(table.get(&service.session).unwrap(), 7);
+    table.set(&service.session, &9).unwrap();
+    assert_eq!(table.get(&service.session).unwrap(), 9);
+}
+
+#[tokio::test]
+async fn gatt_service_per_connection_store() {
+    // Only the session characteristic holds values per connection
+    assert_eq!(
+        CustomService::per_connection_store_size(3),
+        per_connection_store_size(1, 3)
+    );
+    assert_eq!(IncludingService::per_connection_store_size(3), 0);
+
+    let mut store = [0; 4];
+    let mut table: AttributeTable<NoopRawMutex, 27> = AttributeTable::new();
+    let result = CustomService::new_with_store(&mut table, &[], 3, &mut store);
+    assert!(matches!(result, Err(Error::InsufficientSpace)));
+}
+
+#[tokio::test]
+async fn gatt_service_include() {
+    let mut table: AttributeTable<NoopRawMutex, 8> = AttributeTable::new();
+    let secondary = SecondaryService::new(&mut table);
+
+    // Only service declarations can be included, and nothing is added for a failed service
+    let result = IncludingService::new_with_includes(&mut table, &[secondary.level.handle]);
+    assert!(matches!(result, Err(Error::NotFound)));
+    let result = IncludingService::new_with_includes(&mut table, &[0x1234]);
+    assert!(matches!(result, Err(Error::NotFound)));
+
+    let service = IncludingService::new_with_includes(&mut table, &[secondary.handle()]).unwrap();
+    assert_ne!(secondary.handle(), service.handle());
+    assert!(table.is_service(secondary.handle()));
+    assert!(table.is_service(service.handle()));
+    assert!(!table.is_service(service.value.handle));
 }